@@ -0,0 +1,43 @@
+/*
+ * C interface to libxcsv: open a workbook, list its sheets and export them to CSV.
+ *
+ * Functions return NULL or -1 on failure; xcsv_last_error then describes what went wrong.
+ * Link against libxcsv.so / libxcsv.a from `cargo build --release` in bindings/c.
+ */
+#ifndef XCSV_H
+#define XCSV_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* An open workbook */
+typedef struct XcsvWorkbook XcsvWorkbook;
+
+/* Open the workbook at path (a file, or "-" for stdin); free it with xcsv_close */
+XcsvWorkbook *xcsv_open(const char *path);
+
+/* Number of sheets in the workbook, of any kind */
+int32_t xcsv_sheet_count(const XcsvWorkbook *workbook);
+
+/* Name of the sheet at index (from 0), owned by the workbook until xcsv_close */
+const char *xcsv_sheet_name(const XcsvWorkbook *workbook, int32_t index);
+
+/* Export the sheet at index (from 0) to out_path as CSV separated by delimiter, which must be
+ * ASCII; returns the number of rows written */
+int64_t xcsv_export_csv(XcsvWorkbook *workbook, int32_t index, const char *out_path,
+                        char delimiter);
+
+/* Message of the last failure on this thread, or NULL; valid until the next failure */
+const char *xcsv_last_error(void);
+
+/* Close a workbook; NULL is ignored */
+void xcsv_close(XcsvWorkbook *workbook);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* XCSV_H */
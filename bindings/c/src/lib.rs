@@ -0,0 +1,236 @@
+//! C interface to libxcsv, declared in include/xcsv.h
+//! Every function reports failure through its return value (NULL or -1) and leaves the message
+//! for xcsv_last_error; no Rust panic or error crosses the boundary.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::io::BufReader;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use libxcsv::{
+    ExportOptions, SheetInfo, StyleInfo, WorkbookReader, export_sheet_xml_to_csv, open_zip,
+    read_package_parts, read_strings_and_styles, read_workbook,
+};
+use zip::ZipArchive;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// An open workbook with its shared strings and styles; opaque to C
+pub struct XcsvWorkbook {
+    zip: ZipArchive<WorkbookReader>,
+    sheets: Vec<SheetInfo>,
+    /// Sheet names as C strings, handed out by xcsv_sheet_name
+    names: Vec<CString>,
+    is_1904: bool,
+    shared_strings: Vec<String>,
+    styles: Vec<StyleInfo>,
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).expect("nul bytes were replaced");
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Run f, turning an error or panic into the last error and the value failed
+fn guard<T>(failed: T, f: impl FnOnce() -> Result<T>) -> T {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(e)) => {
+            set_last_error(format!("{:#}", e));
+            failed
+        }
+        Err(_) => {
+            set_last_error("internal error (panic) in xcsv".to_string());
+            failed
+        }
+    }
+}
+
+/// The UTF-8 string at ptr; name is used in the error for NULL or invalid strings
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str> {
+    if ptr.is_null() {
+        anyhow::bail!("{} is NULL", name);
+    }
+    // SAFETY: the caller passes a nul terminated string that outlives the call
+    unsafe { CStr::from_ptr(ptr) }
+        .to_str()
+        .with_context(|| format!("{} is not valid UTF-8", name))
+}
+
+/// The delimiter as a byte; a char past ASCII would be one byte of some other character
+fn delimiter_arg(delimiter: c_char) -> Result<u8> {
+    let byte = delimiter as u8;
+    if !byte.is_ascii() {
+        anyhow::bail!("delimiter {:#04x} is not an ASCII character", byte);
+    }
+    Ok(byte)
+}
+
+fn open(path: &str) -> Result<XcsvWorkbook> {
+    let mut zip = open_zip(Path::new(path))?;
+    let parts = read_package_parts(&mut zip)?;
+    let (sheets, is_1904) = read_workbook(&mut zip, &parts)?;
+    let (shared_strings, styles) = read_strings_and_styles(&mut zip, &parts)?;
+    let names = sheets
+        .iter()
+        .map(|sheet| CString::new(sheet.name.replace('\0', " ")).expect("nul bytes were replaced"))
+        .collect();
+    Ok(XcsvWorkbook {
+        zip,
+        sheets,
+        names,
+        is_1904,
+        shared_strings,
+        styles,
+    })
+}
+
+/// Open the workbook at path (a file, or "-" for stdin)
+/// Returns NULL on failure. Free the workbook with xcsv_close.
+///
+/// # Safety
+/// path must be NULL or a nul terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xcsv_open(path: *const c_char) -> *mut XcsvWorkbook {
+    guard(std::ptr::null_mut(), || {
+        let path = unsafe { str_arg(path, "path") }?;
+        Ok(Box::into_raw(Box::new(open(path)?)))
+    })
+}
+
+/// Number of sheets in the workbook, of any kind, or -1 if workbook is NULL
+///
+/// # Safety
+/// workbook must be NULL or returned by xcsv_open and not yet closed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xcsv_sheet_count(workbook: *const XcsvWorkbook) -> i32 {
+    guard(-1, || {
+        // SAFETY: see the function docs
+        let workbook = unsafe { workbook.as_ref() }.context("workbook is NULL")?;
+        Ok(workbook.sheets.len() as i32)
+    })
+}
+
+/// Name of the sheet at index (from 0), or NULL if there is none
+/// The string belongs to the workbook and lives until xcsv_close.
+///
+/// # Safety
+/// workbook must be NULL or returned by xcsv_open and not yet closed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xcsv_sheet_name(
+    workbook: *const XcsvWorkbook,
+    index: i32,
+) -> *const c_char {
+    guard(std::ptr::null(), || {
+        // SAFETY: see the function docs
+        let workbook = unsafe { workbook.as_ref() }.context("workbook is NULL")?;
+        let name = usize::try_from(index)
+            .ok()
+            .and_then(|i| workbook.names.get(i))
+            .with_context(|| format!("no sheet at index {}", index))?;
+        Ok(name.as_ptr())
+    })
+}
+
+/// Export the sheet at index (from 0) to out_path as CSV separated by delimiter (e.g. ',')
+/// Returns the number of rows written, or -1 on failure. Chartsheets and other sheets without
+/// cells are an error, as is a delimiter that isn't ASCII.
+///
+/// # Safety
+/// workbook must be NULL or returned by xcsv_open and not yet closed; out_path must be NULL or a
+/// nul terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xcsv_export_csv(
+    workbook: *mut XcsvWorkbook,
+    index: i32,
+    out_path: *const c_char,
+    delimiter: c_char,
+) -> i64 {
+    guard(-1, || {
+        // SAFETY: see the function docs
+        let workbook = unsafe { workbook.as_mut() }.context("workbook is NULL")?;
+        let out_path = unsafe { str_arg(out_path, "out_path") }?;
+        let delimiter = delimiter_arg(delimiter)?;
+        let sheet = usize::try_from(index)
+            .ok()
+            .and_then(|i| workbook.sheets.get(i))
+            .with_context(|| format!("no sheet at index {}", index))?;
+        if sheet.kind != libxcsv::SheetKind::Worksheet {
+            anyhow::bail!(
+                "sheet '{}' is a {}, not a worksheet",
+                sheet.name,
+                sheet.kind
+            );
+        }
+        let options = ExportOptions {
+            delimiter,
+            ..ExportOptions::default()
+        };
+        let f = workbook
+            .zip
+            .by_name(&sheet.path_in_zip)
+            .with_context(|| format!("missing {}", sheet.path_in_zip))?;
+        let summary = export_sheet_xml_to_csv(
+            BufReader::new(f),
+            &workbook.shared_strings,
+            &workbook.styles,
+            workbook.is_1904,
+            Path::new(out_path),
+            &options,
+        )
+        .with_context(|| format!("export {}", sheet.name))?;
+        Ok(summary.rows_written as i64)
+    })
+}
+
+/// The message of the last failure on this thread, or NULL if nothing failed yet
+/// The string lives until the next failing call on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn xcsv_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Close a workbook returned by xcsv_open; NULL is ignored
+///
+/// # Safety
+/// workbook must be NULL or returned by xcsv_open and not yet closed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn xcsv_close(workbook: *mut XcsvWorkbook) {
+    if !workbook.is_null() {
+        // SAFETY: see the function docs
+        drop(unsafe { Box::from_raw(workbook) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffi_reports_errors_through_last_error() {
+        let path = CString::new("/does/not/exist.xlsx").unwrap();
+        let workbook = unsafe { xcsv_open(path.as_ptr()) };
+        assert!(workbook.is_null());
+        let message = unsafe { CStr::from_ptr(xcsv_last_error()) };
+        assert!(message.to_str().unwrap().contains("exist.xlsx"));
+
+        assert_eq!(unsafe { xcsv_sheet_count(std::ptr::null()) }, -1);
+        let message = unsafe { CStr::from_ptr(xcsv_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "workbook is NULL");
+        unsafe { xcsv_close(std::ptr::null_mut()) };
+
+        assert_eq!(delimiter_arg(b';' as c_char).unwrap(), b';');
+        assert_eq!(
+            delimiter_arg(0xE9_u8 as c_char).unwrap_err().to_string(),
+            "delimiter 0xe9 is not an ASCII character"
+        );
+    }
+}
@@ -0,0 +1,463 @@
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+
+use anyhow::Context;
+use libxcsv::{
+    CellValue, ExportOptions, GeneralPrecision, NameTemplate, OutputFormat, RowSink, SheetKind,
+    SheetState, StyleInfo, WorkbookReader, convert_xlsx_bytes_to_csv, export_sheet_to_sink,
+    export_sheet_xml_to_csv, open_zip, read_package_parts, read_strings_and_styles, read_workbook,
+};
+use napi::bindgen_prelude::Buffer;
+use napi_derive::napi;
+use zip::ZipArchive;
+
+/// A sheet of a workbook, as listed by listSheets
+/// kind: worksheet, chartsheet, dialogsheet or macrosheet; state: visible, hidden or veryHidden
+#[napi(object)]
+pub struct Sheet {
+    pub name: String,
+    pub kind: String,
+    pub state: String,
+}
+
+/// Options of convertFile, convertBuffer and SheetReader; like the flags of `xcsv export`
+/// delimiter: a single character [default: ,]
+/// header: synthetic header row, letters or index [default: none]
+/// format: csv, markdown, html or pgcopy [default: csv]
+/// nameTemplate: file names of convertFile, like --name-template [default: {slug}.<format>]
+#[napi(object)]
+#[derive(Default)]
+pub struct ConvertOptions {
+    pub delimiter: Option<String>,
+    pub header: Option<String>,
+    pub trim: Option<bool>,
+    pub skip_blank_rows: Option<bool>,
+    pub row_numbers: Option<bool>,
+    pub empty_as: Option<String>,
+    pub format: Option<String>,
+    pub split_rows: Option<u32>,
+    pub max_rows: Option<u32>,
+    pub name_template: Option<String>,
+}
+
+/// A file converted by convertBuffer
+#[napi(object)]
+pub struct ConvertedFile {
+    pub name: String,
+    pub data: Buffer,
+}
+
+/// A sheet written by convertFile: its rows (including the header row) and files
+#[napi(object)]
+pub struct ExportedSheet {
+    pub sheet: String,
+    pub rows: i64,
+    pub files: Vec<String>,
+}
+
+/// Errors reach JavaScript as an Error with the whole context chain as message
+fn js_error(e: anyhow::Error) -> napi::Error {
+    napi::Error::from_reason(format!("{:#}", e))
+}
+
+fn export_options(options: Option<ConvertOptions>) -> anyhow::Result<ExportOptions> {
+    let options = options.unwrap_or_default();
+    let delimiter = match options.delimiter.as_deref().map(str::as_bytes) {
+        None => b',',
+        Some([b]) => *b,
+        Some(_) => anyhow::bail!("delimiter must be a single character"),
+    };
+    Ok(ExportOptions {
+        delimiter,
+        header: options
+            .header
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(anyhow::Error::msg)?
+            .unwrap_or_default(),
+        trim: options.trim.unwrap_or(false),
+        skip_blank_rows: options.skip_blank_rows.unwrap_or(false),
+        row_numbers: options.row_numbers.unwrap_or(false),
+        empty_as: options.empty_as,
+        format: options
+            .format
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(anyhow::Error::msg)?
+            .unwrap_or_default(),
+        split_rows: options.split_rows.map(u64::from),
+        max_rows: options.max_rows.map(u64::from),
+        ..ExportOptions::default()
+    })
+}
+
+/// The opened workbook with its shared strings and styles
+struct Workbook {
+    zip: ZipArchive<WorkbookReader>,
+    sheets: Vec<libxcsv::SheetInfo>,
+    is_1904: bool,
+    shared_strings: Vec<String>,
+    styles: Vec<StyleInfo>,
+}
+
+fn open_workbook(path: &str) -> anyhow::Result<Workbook> {
+    let mut zip = open_zip(Path::new(path))?;
+    let parts = read_package_parts(&mut zip)?;
+    let (sheets, is_1904) = read_workbook(&mut zip, &parts)?;
+    let (shared_strings, styles) = read_strings_and_styles(&mut zip, &parts)?;
+    Ok(Workbook {
+        zip,
+        sheets,
+        is_1904,
+        shared_strings,
+        styles,
+    })
+}
+
+/// List the sheets of the workbook at path (a file, or an http(s) URL with the http feature)
+#[napi]
+pub fn list_sheets(path: String) -> napi::Result<Vec<Sheet>> {
+    let workbook = open_workbook(&path).map_err(js_error)?;
+    Ok(workbook
+        .sheets
+        .into_iter()
+        .map(|sheet| Sheet {
+            name: sheet.name,
+            kind: sheet.kind.to_string(),
+            state: match sheet.state {
+                SheetState::Visible => "visible",
+                SheetState::Hidden => "hidden",
+                SheetState::VeryHidden => "veryHidden",
+            }
+            .to_string(),
+        })
+        .collect())
+}
+
+/// Convert every worksheet of an uploaded workbook in memory, e.g. the body of a request
+/// Returns the file name (like `xcsv export` writes it) and contents of every file.
+#[napi]
+pub fn convert_buffer(
+    data: Buffer,
+    options: Option<ConvertOptions>,
+) -> napi::Result<Vec<ConvertedFile>> {
+    let options = export_options(options).map_err(js_error)?;
+    let files = convert_xlsx_bytes_to_csv(&data, &options).map_err(js_error)?;
+    Ok(files
+        .into_iter()
+        .map(|(name, data)| ConvertedFile {
+            name,
+            data: data.into(),
+        })
+        .collect())
+}
+
+/// Export every worksheet of the workbook at path into outDir as <sheet>.csv, like `xcsv export`
+#[napi]
+pub fn convert_file(
+    path: String,
+    out_dir: String,
+    options: Option<ConvertOptions>,
+) -> napi::Result<Vec<ExportedSheet>> {
+    let template = options
+        .as_ref()
+        .and_then(|o| o.name_template.as_deref())
+        .map(str::parse)
+        .transpose()
+        .map_err(|e: String| js_error(anyhow::Error::msg(e)))?;
+    convert(
+        &path,
+        Path::new(&out_dir),
+        export_options(options).map_err(js_error)?,
+        template,
+    )
+    .map_err(js_error)
+}
+
+fn convert(
+    path: &str,
+    out_dir: &Path,
+    options: ExportOptions,
+    template: Option<NameTemplate>,
+) -> anyhow::Result<Vec<ExportedSheet>> {
+    let mut workbook = open_workbook(path)?;
+    std::fs::create_dir_all(out_dir).context("create output directory")?;
+    let template = match (template, options.format) {
+        (Some(template), _) => template,
+        (None, OutputFormat::Csv) => NameTemplate::default(),
+        (None, format) => format!("{{slug}}.{}", format.extension())
+            .parse()
+            .map_err(anyhow::Error::msg)?,
+    };
+    // {workbook} is the file stem of the input, as `xcsv export` renders it
+    let stem = match Path::new(path).file_stem().and_then(|s| s.to_str()) {
+        Some("-") | None => "stdin",
+        Some(stem) => stem,
+    };
+    let mut exported = Vec::new();
+    for (position, sheet) in workbook.sheets.iter().enumerate() {
+        if sheet.kind != SheetKind::Worksheet {
+            continue;
+        }
+        let out_path = out_dir.join(template.render(&sheet.name, position + 1, stem));
+        let f = workbook
+            .zip
+            .by_name(&sheet.path_in_zip)
+            .with_context(|| format!("missing {}", sheet.path_in_zip))?;
+        let summary = export_sheet_xml_to_csv(
+            BufReader::new(f),
+            &workbook.shared_strings,
+            &workbook.styles,
+            workbook.is_1904,
+            &out_path,
+            &options,
+        )
+        .with_context(|| format!("export {}", sheet.name))?;
+        exported.push(ExportedSheet {
+            sheet: sheet.name.clone(),
+            rows: summary.rows_written as i64,
+            files: summary
+                .files
+                .iter()
+                .map(|f| f.display().to_string())
+                .collect(),
+        });
+    }
+    Ok(exported)
+}
+
+/// Rows read ahead of nextRow by the reading thread of a SheetReader
+const READ_AHEAD_ROWS: usize = 1024;
+
+/// Read the rows of one sheet one at a time, without holding the sheet in memory
+/// A thread reads the sheet as nextRow takes its rows, at most READ_AHEAD_ROWS ahead; it stops
+/// when the reader is dropped. Of the options only trim, skipBlankRows and maxRows apply.
+#[napi]
+pub struct SheetReader {
+    rows: Receiver<anyhow::Result<Vec<String>>>,
+}
+
+#[napi]
+impl SheetReader {
+    /// Open the sheet named sheet of the workbook at path
+    #[napi(factory)]
+    pub fn open(
+        path: String,
+        sheet: String,
+        options: Option<ConvertOptions>,
+    ) -> napi::Result<Self> {
+        Self::open_sheet(&path, &sheet, options).map_err(js_error)
+    }
+
+    /// The next row as an array of strings, or null after the last one
+    #[napi]
+    pub fn next_row(&mut self) -> napi::Result<Option<Vec<String>>> {
+        match self.rows.recv() {
+            Ok(row) => row.map(Some).map_err(js_error),
+            // The reading thread is done
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+impl SheetReader {
+    fn open_sheet(
+        path: &str,
+        sheet: &str,
+        options: Option<ConvertOptions>,
+    ) -> anyhow::Result<Self> {
+        let options = options.unwrap_or_default();
+        for (name, set) in [
+            ("delimiter", options.delimiter.is_some()),
+            ("header", options.header.is_some()),
+            ("rowNumbers", options.row_numbers.is_some()),
+            ("emptyAs", options.empty_as.is_some()),
+            ("format", options.format.is_some()),
+            ("splitRows", options.split_rows.is_some()),
+        ] {
+            if set {
+                anyhow::bail!("SheetReader reads rows, {} does not apply", name);
+            }
+        }
+        let options = export_options(Some(options))?;
+        let mut workbook = open_workbook(path)?;
+        let info = workbook
+            .sheets
+            .iter()
+            .find(|s| s.name == sheet)
+            .with_context(|| format!("no sheet named '{}'", sheet))?
+            .clone();
+        // Fail here rather than on the first row when the part is missing
+        workbook
+            .zip
+            .by_name(&info.path_in_zip)
+            .with_context(|| format!("missing {}", info.path_in_zip))?;
+        let (sender, rows) = mpsc::sync_channel(READ_AHEAD_ROWS);
+        std::thread::spawn(move || {
+            let mut sink = ChannelSink {
+                sender: sender.clone(),
+                precision: options.general_precision,
+                closed: false,
+            };
+            let result = workbook
+                .zip
+                .by_name(&info.path_in_zip)
+                .map_err(anyhow::Error::from)
+                .and_then(|f| {
+                    export_sheet_to_sink(
+                        BufReader::new(f),
+                        &info,
+                        &workbook.shared_strings,
+                        &workbook.styles,
+                        workbook.is_1904,
+                        &options,
+                        &mut sink,
+                    )
+                });
+            if let Err(e) = result {
+                let _ = sender.send(Err(e.context(format!("read {}", info.name))));
+            }
+        });
+        Ok(Self { rows })
+    }
+}
+
+/// Sends the rows of a sheet to a SheetReader as the exporter writes their fields
+struct ChannelSink {
+    sender: SyncSender<anyhow::Result<Vec<String>>>,
+    precision: GeneralPrecision,
+    /// Whether the SheetReader is gone, so reading can stop
+    closed: bool,
+}
+
+impl RowSink for ChannelSink {
+    fn write_row(&mut self, row: &[CellValue]) -> anyhow::Result<()> {
+        let fields = row.iter().map(|v| v.to_field(self.precision)).collect();
+        if self.sender.send(Ok(fields)).is_err() {
+            self.closed = true;
+        }
+        Ok(())
+    }
+
+    fn done(&self) -> bool {
+        self.closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::ZipWriter;
+    use zip::write::FileOptions;
+
+    /// A workbook with one sheet, Orders, of 3000 rows
+    fn write_workbook() -> tempfile::NamedTempFile {
+        let parts = [
+            (
+                "[Content_Types].xml",
+                r#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/></Types>"#.to_string(),
+            ),
+            (
+                "_rels/.rels",
+                r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#.to_string(),
+            ),
+            (
+                "xl/workbook.xml",
+                r#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="Orders" sheetId="1" r:id="rId1"/></sheets></workbook>"#.to_string(),
+            ),
+            (
+                "xl/_rels/workbook.xml.rels",
+                r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/></Relationships>"#.to_string(),
+            ),
+            (
+                "xl/worksheets/sheet1.xml",
+                format!(
+                    r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>{}</sheetData></worksheet>"#,
+                    (1..=3000)
+                        .map(|i| format!(
+                            r#"<row r="{i}"><c r="A{i}"><v>{i}</v></c><c r="B{i}" t="inlineStr"><is><t>row {i}</t></is></c><c r="C{i}" t="b"><v>1</v></c></row>"#
+                        ))
+                        .collect::<String>()
+                ),
+            ),
+        ];
+        let file = tempfile::Builder::new()
+            .prefix("upload")
+            .suffix(".xlsx")
+            .tempfile()
+            .unwrap();
+        let mut writer = ZipWriter::new(file.reopen().unwrap());
+        for (name, contents) in parts {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+        file
+    }
+
+    #[test]
+    fn test_sheet_reader_streams_rows() {
+        let file = write_workbook();
+        let path = file.path().to_str().unwrap();
+
+        let mut reader = SheetReader::open_sheet(path, "Orders", None).unwrap();
+        assert_eq!(
+            reader.next_row().unwrap(),
+            Some(vec![
+                "1".to_string(),
+                "row 1".to_string(),
+                "TRUE".to_string()
+            ])
+        );
+        let mut count = 1;
+        while reader.next_row().unwrap().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 3000);
+
+        // Dropping a reader part way stops its thread, which blocks on the full channel
+        let mut reader = SheetReader::open_sheet(path, "Orders", None).unwrap();
+        reader.next_row().unwrap();
+        drop(reader);
+
+        let options = ConvertOptions {
+            max_rows: Some(2),
+            ..ConvertOptions::default()
+        };
+        let mut reader = SheetReader::open_sheet(path, "Orders", Some(options)).unwrap();
+        assert!(reader.next_row().unwrap().is_some());
+        assert!(reader.next_row().unwrap().is_some());
+        assert_eq!(reader.next_row().unwrap(), None);
+
+        let options = ConvertOptions {
+            format: Some("html".to_string()),
+            ..ConvertOptions::default()
+        };
+        assert!(SheetReader::open_sheet(path, "Orders", Some(options)).is_err());
+        assert!(SheetReader::open_sheet(path, "Missing", None).is_err());
+    }
+
+    #[test]
+    fn test_convert_file_names_by_workbook() {
+        let file = write_workbook();
+        let out_dir = tempfile::tempdir().unwrap();
+        let options = ConvertOptions {
+            name_template: Some("{workbook}-{slug}.csv".to_string()),
+            ..ConvertOptions::default()
+        };
+        let exported = convert_file(
+            file.path().to_str().unwrap().to_string(),
+            out_dir.path().to_str().unwrap().to_string(),
+            Some(options),
+        )
+        .unwrap();
+        let stem = file.path().file_stem().unwrap().to_str().unwrap();
+        let expected = out_dir.path().join(format!("{}-orders.csv", stem));
+        assert_eq!(exported[0].files, vec![expected.display().to_string()]);
+        assert_eq!(exported[0].rows, 3000);
+    }
+}
@@ -0,0 +1,140 @@
+//! Benchmarks of the export loop on synthetic workbooks (see fixtures).
+//!
+//!     cargo bench --bench export
+//!     XCSV_BENCH_SCALE=10 cargo bench --bench export -- tall
+//!
+//! Sheets are exported with dry_run, so rows are parsed and encoded but not written to disk.
+
+use std::io::{BufReader, Cursor, Read};
+use std::path::Path;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use libxcsv::{
+    ExportOptions, StyleInfo, WorkbookReader, export_sheet_xml_to_csv, parse_styles,
+    read_package_parts, read_shared_strings, read_workbook,
+};
+use zip::ZipArchive;
+
+mod fixtures;
+
+use fixtures::Shape;
+
+/// A generated workbook with its shared strings and styles read
+struct Fixture {
+    zip: ZipArchive<WorkbookReader>,
+    sheet_part: String,
+    sheet_xml: Vec<u8>,
+    shared_strings: Vec<String>,
+    styles: Vec<StyleInfo>,
+}
+
+fn scale() -> usize {
+    std::env::var("XCSV_BENCH_SCALE")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(1)
+}
+
+fn load(shape: Shape) -> Fixture {
+    let bytes = fixtures::generate(shape, scale());
+    let mut zip = ZipArchive::new(WorkbookReader::Memory(Cursor::new(bytes))).unwrap();
+    let parts = read_package_parts(&mut zip).unwrap();
+    let shared_strings = match &parts.shared_strings {
+        Some(part) => read_shared_strings(BufReader::new(zip.by_name(part).unwrap())).unwrap(),
+        None => Vec::new(),
+    };
+    let styles = match &parts.styles {
+        Some(part) => parse_styles(BufReader::new(zip.by_name(part).unwrap())).unwrap(),
+        None => Vec::new(),
+    };
+    let (sheets, _) = read_workbook(&mut zip, &parts).unwrap();
+    let sheet_part = sheets[0].path_in_zip.clone();
+    let mut sheet_xml = Vec::new();
+    zip.by_name(&sheet_part)
+        .unwrap()
+        .read_to_end(&mut sheet_xml)
+        .unwrap();
+    Fixture {
+        zip,
+        sheet_part,
+        sheet_xml,
+        shared_strings,
+        styles,
+    }
+}
+
+/// Parsing and encoding a sheet that is already decompressed
+fn export_sheet(c: &mut Criterion) {
+    let mut group = c.benchmark_group("export_sheet");
+    let options = ExportOptions {
+        dry_run: true,
+        ..ExportOptions::default()
+    };
+    for shape in Shape::ALL {
+        let fixture = load(shape);
+        group.throughput(Throughput::Bytes(fixture.sheet_xml.len() as u64));
+        group.bench_function(BenchmarkId::from_parameter(shape.name()), |b| {
+            b.iter(|| {
+                export_sheet_xml_to_csv(
+                    fixture.sheet_xml.as_slice(),
+                    &fixture.shared_strings,
+                    &fixture.styles,
+                    false,
+                    Path::new("bench.csv"),
+                    &options,
+                )
+                .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+/// The same, reading the sheet straight from the zip archive, so inflating counts too
+fn export_sheet_from_zip(c: &mut Criterion) {
+    let mut group = c.benchmark_group("export_sheet_from_zip");
+    let options = ExportOptions {
+        dry_run: true,
+        ..ExportOptions::default()
+    };
+    for shape in [Shape::Tall, Shape::Strings] {
+        let mut fixture = load(shape);
+        group.throughput(Throughput::Bytes(fixture.sheet_xml.len() as u64));
+        group.bench_function(BenchmarkId::from_parameter(shape.name()), |b| {
+            b.iter(|| {
+                let part = fixture.zip.by_name(&fixture.sheet_part).unwrap();
+                export_sheet_xml_to_csv(
+                    BufReader::new(part),
+                    &fixture.shared_strings,
+                    &fixture.styles,
+                    false,
+                    Path::new("bench.csv"),
+                    &options,
+                )
+                .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Reading the shared string table of the string-heavy workbook
+fn shared_strings(c: &mut Criterion) {
+    let mut fixture = load(Shape::Strings);
+    let mut xml = Vec::new();
+    fixture
+        .zip
+        .by_name("xl/sharedStrings.xml")
+        .unwrap()
+        .read_to_end(&mut xml)
+        .unwrap();
+    let mut group = c.benchmark_group("read_shared_strings");
+    group.throughput(Throughput::Bytes(xml.len() as u64));
+    group.bench_function("strings", |b| {
+        b.iter(|| read_shared_strings(xml.as_slice()).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, export_sheet, export_sheet_from_zip, shared_strings);
+criterion_main!(benches);
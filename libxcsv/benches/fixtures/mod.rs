@@ -0,0 +1,76 @@
+//! Synthetic workbooks for the benchmarks, written with WorkbookWriter.
+//!
+//! Every shape stresses a different part of the export loop; `scale` multiplies the rows so
+//! the same shapes serve quick runs and long profiling sessions.
+
+use std::io::Cursor;
+
+use chrono::NaiveDate;
+use libxcsv::{CellValue, WorkbookWriter};
+
+/// A kind of workbook to generate
+#[derive(Debug, Clone, Copy)]
+pub enum Shape {
+    /// 200 columns of numbers and short strings, few rows
+    Wide,
+    /// 6 columns of numbers, many rows
+    Tall,
+    /// Mostly unique strings, so the shared string table is large
+    Strings,
+    /// Date and date-time cells, which go through number formats and the date system
+    Dates,
+}
+
+impl Shape {
+    pub const ALL: [Shape; 4] = [Shape::Wide, Shape::Tall, Shape::Strings, Shape::Dates];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Shape::Wide => "wide",
+            Shape::Tall => "tall",
+            Shape::Strings => "strings",
+            Shape::Dates => "dates",
+        }
+    }
+
+    /// Columns and rows (besides the header) of the shape at scale 1
+    fn size(self) -> (usize, usize) {
+        match self {
+            Shape::Wide => (200, 500),
+            Shape::Tall => (6, 20_000),
+            Shape::Strings => (4, 10_000),
+            Shape::Dates => (6, 10_000),
+        }
+    }
+}
+
+/// The .xlsx bytes of a workbook with a single sheet "Data" of the given shape
+pub fn generate(shape: Shape, scale: usize) -> Vec<u8> {
+    let (columns, rows) = shape.size();
+    let mut book = WorkbookWriter::new(Cursor::new(Vec::new()));
+    book.start_sheet("Data").unwrap();
+    let header: Vec<CellValue> = (0..columns)
+        .map(|c| CellValue::Text(format!("column_{}", c)))
+        .collect();
+    book.write_row(&header).unwrap();
+    let start = NaiveDate::from_ymd_opt(2000, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    let mut row = Vec::with_capacity(columns);
+    for r in 0..rows * scale {
+        row.clear();
+        row.extend((0..columns).map(|c| match shape {
+            Shape::Wide if c % 4 == 0 => CellValue::Text(format!("code {}", (r + c) % 50)),
+            Shape::Wide | Shape::Tall => CellValue::Number((r * columns + c) as f64 * 0.25),
+            Shape::Strings => CellValue::Text(format!("customer {} note {}", r, c)),
+            Shape::Dates if c % 2 == 0 => CellValue::Number(r as f64),
+            Shape::Dates => {
+                let minutes = (r * 97 + c * 13) as i64;
+                CellValue::Date(start + chrono::Duration::minutes(minutes))
+            }
+        }));
+        book.write_row(&row).unwrap();
+    }
+    book.finish().unwrap().into_inner()
+}
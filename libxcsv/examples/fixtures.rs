@@ -0,0 +1,26 @@
+//! Write the benchmark workbooks (see benches/fixtures) to a directory, to profile the export
+//! or try them with xcsv:
+//!
+//!     cargo run --release --example fixtures -- DIR [SCALE]
+
+use std::path::PathBuf;
+
+#[path = "../benches/fixtures/mod.rs"]
+mod fixtures;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let Some(dir) = args.next().map(PathBuf::from) else {
+        eprintln!("usage: fixtures DIR [SCALE]");
+        std::process::exit(2);
+    };
+    let scale = args
+        .next()
+        .map_or(1, |s| s.parse().expect("SCALE must be a number"));
+    std::fs::create_dir_all(&dir).expect("create output directory");
+    for shape in fixtures::Shape::ALL {
+        let path = dir.join(format!("{}.xlsx", shape.name()));
+        std::fs::write(&path, fixtures::generate(shape, scale)).expect("write workbook");
+        println!("wrote {}", path.display());
+    }
+}
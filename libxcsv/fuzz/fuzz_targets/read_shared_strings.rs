@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = libxcsv::read_shared_strings(data);
+    // A tiny budget, so the strings spill to temp files after the first few
+    let _ = libxcsv::read_shared_strings_within(data, 256);
+});
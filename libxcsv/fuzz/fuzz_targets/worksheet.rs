@@ -0,0 +1,46 @@
+#![no_main]
+
+//! The worksheet parser on arbitrary sheet XML, packed into an in-memory workbook with a few
+//! shared strings and a date style so `t="s"` cells and date formats are reached too.
+
+use std::io::{Cursor, Write};
+
+use libfuzzer_sys::fuzz_target;
+use libxcsv::{ExportOptions, convert_xlsx_bytes_to_csv};
+use zip::CompressionMethod;
+use zip::write::{FileOptions, ZipWriter};
+
+const PARTS: [(&str, &str); 4] = [
+    (
+        "xl/workbook.xml",
+        r#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="Fuzz" sheetId="1" r:id="rId1"/></sheets></workbook>"#,
+    ),
+    (
+        "xl/_rels/workbook.xml.rels",
+        r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/sharedStrings" Target="sharedStrings.xml"/><Relationship Id="rId3" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/></Relationships>"#,
+    ),
+    (
+        "xl/sharedStrings.xml",
+        r#"<sst xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" uniqueCount="2"><si><t>id</t></si><si><t>a &amp; b</t></si></sst>"#,
+    ),
+    (
+        "xl/styles.xml",
+        r#"<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><numFmts count="1"><numFmt numFmtId="164" formatCode="yyyy-mm-dd hh:mm"/></numFmts><cellXfs count="3"><xf numFmtId="0"/><xf numFmtId="14"/><xf numFmtId="164"/></cellXfs></styleSheet>"#,
+    ),
+];
+
+fn workbook(sheet_xml: &[u8]) -> Vec<u8> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    for (name, xml) in PARTS {
+        zip.start_file(name, options).unwrap();
+        zip.write_all(xml.as_bytes()).unwrap();
+    }
+    zip.start_file("xl/worksheets/sheet1.xml", options).unwrap();
+    zip.write_all(sheet_xml).unwrap();
+    zip.finish().unwrap().into_inner()
+}
+
+fuzz_target!(|data: &[u8]| {
+    let _ = convert_xlsx_bytes_to_csv(&workbook(data), &ExportOptions::default());
+});
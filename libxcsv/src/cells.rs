@@ -0,0 +1,547 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use crate::{
+    CellKind, CellRef, CellValue, ExportOptions, ExportSummary, OutputWriter, ReadCell, RowTarget,
+    StringTable, StyleInfo, UnitOutput, index_to_col, open_output, read_sheet,
+};
+
+/// A line of OutputFormat::CellsJson: one cell holding a value or a formula
+/// reference: the cell reference, e.g. "B3"; row and column: its 1-based position
+/// raw: the value as stored, i.e. the `<v>` text (a shared string index for t="s") or the
+///   inline string
+/// value: the decoded value as the export writes it: a string, number or boolean (formatted
+///   numbers, units and dates as strings); null for a cell that decodes to nothing, e.g. a
+///   shared string that doesn't exist
+/// kind: what the cell holds, see CellKind
+/// style: the cell's style, its own or its row's or column's; num_fmt: the format code of that
+///   style
+/// formula: without the leading '='
+/// fill and font_color: with ExportOptions::colors, the colors of the cell's style as
+///   "#RRGGBB"; left out for a cell without a fill or with the automatic font color
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct CellRecord<'a> {
+    #[serde(rename = "ref")]
+    reference: String,
+    row: u32,
+    column: u32,
+    raw: &'a str,
+    value: Value,
+    #[serde(rename = "type")]
+    kind: CellKind,
+    style: Option<u32>,
+    num_fmt: Option<&'a str>,
+    formula: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fill: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    font_color: Option<String>,
+}
+
+/// Write every cell of a sheet with a value or a formula to out_path as a line of JSON (see
+/// CellRecord), counting them as rows_written
+/// The cells are read and decoded by read_sheet like those of any other export; options that
+/// shape rows rather than cells are refused (see check_options).
+/// Returns the summary and with in_memory the contents of the file.
+pub(crate) fn export_cells<R: BufRead, S: StringTable + ?Sized>(
+    reader: R,
+    shared_strings: &S,
+    styles: &[StyleInfo],
+    is_1904: bool,
+    out_path: &Path,
+    options: &ExportOptions,
+    in_memory: bool,
+) -> Result<(ExportSummary, Vec<Vec<u8>>)> {
+    check_options(options)?;
+    let mut wtr = CellsWriter {
+        out: open_output(out_path, options, in_memory)?,
+        out_path,
+        options,
+        styles,
+        row: 0,
+        pending: Vec::new(),
+        pending_cells: 0,
+        rows: 0,
+    };
+    let mut summary = ExportSummary::default();
+    let progress = match read_sheet(
+        reader,
+        shared_strings,
+        styles,
+        is_1904,
+        options,
+        &mut wtr,
+        &mut summary,
+    ) {
+        Ok(progress) => progress,
+        Err(e) => {
+            drop(wtr.out);
+            if !options.dry_run && !in_memory {
+                let _ = std::fs::remove_file(out_path);
+            }
+            return Err(e);
+        }
+    };
+    summary.rows_written = progress.rows_written;
+    summary.files = vec![out_path.to_path_buf()];
+    match wtr.out {
+        OutputWriter::Memory(bytes) => Ok((summary, vec![bytes])),
+        out => out.finish().map(|_| (summary, Vec::new())),
+    }
+}
+
+/// Refuse the options that shape rows, which CellsJson doesn't write
+fn check_options(options: &ExportOptions) -> Result<()> {
+    let row_options = [
+        ("delimiter", options.delimiter != b','),
+        ("pad_to_columns", options.pad_to_columns.is_some()),
+        ("trailing_delimiter", options.trailing_delimiter),
+        ("a synthetic header", options.header.is_synthetic()),
+        ("row_numbers", options.row_numbers),
+        ("decimal_comma", options.decimal_comma),
+        ("empty_as", options.empty_as.is_some()),
+        ("UnitOutput::Column", options.units == UnitOutput::Column),
+        ("split_rows", options.split_rows.is_some()),
+        ("split_size", options.split_size.is_some()),
+        ("row_filter", options.row_filter.is_some()),
+        ("column_types", options.column_types.is_some()),
+        ("column_mapping", options.column_mapping.is_some()),
+        ("dedupe", options.dedupe.is_some()),
+        ("sample", options.sample.is_some()),
+        ("pipeline", options.pipeline),
+    ];
+    match row_options.iter().find(|(_, set)| *set) {
+        Some((name, _)) => {
+            anyhow::bail!("cells-json writes cells, not rows; {} does not apply", name)
+        }
+        None => Ok(()),
+    }
+}
+
+/// The RowTarget of CellsJson: writes the cells read_sheet passes to it as CellRecord lines
+/// The records of a row are held back until read_sheet writes the row, so rows it leaves out
+/// (dropped by cell_transform, above header_row) leave no cells behind.
+struct CellsWriter<'a> {
+    out: OutputWriter,
+    out_path: &'a Path,
+    options: &'a ExportOptions,
+    styles: &'a [StyleInfo],
+    /// The row of the records in pending, and how many there are
+    row: u32,
+    pending: Vec<u8>,
+    pending_cells: u64,
+    /// Rows written, for max_rows
+    rows: u64,
+}
+
+impl RowTarget for CellsWriter<'_> {
+    fn set_sheet_width(&mut self, _width: usize) {}
+
+    fn write(
+        &mut self,
+        _row: &mut Vec<String>,
+        _present: &[bool],
+        _values: &mut Vec<CellValue>,
+        row_number: u32,
+    ) -> Result<u64> {
+        let mut written = 0;
+        if self.row == row_number && self.pending_cells > 0 {
+            self.out
+                .write_all(&self.pending)
+                .with_context(|| format!("write {}", self.out_path.display()))?;
+            written = self.pending_cells;
+        }
+        self.pending.clear();
+        self.pending_cells = 0;
+        self.rows += 1;
+        Ok(written)
+    }
+
+    fn limit_reached(&self) -> bool {
+        self.options.max_rows.is_some_and(|n| self.rows >= n)
+    }
+
+    fn wants_cells(&self) -> bool {
+        true
+    }
+
+    fn cell(&mut self, cell: &ReadCell) -> Result<()> {
+        if cell.row != self.row {
+            self.row = cell.row;
+            self.pending.clear();
+            self.pending_cells = 0;
+        }
+        let style = cell.style.and_then(|s| self.styles.get(s as usize));
+        let mut record = CellRecord {
+            reference: format!("{}{}", index_to_col(cell.column), cell.row),
+            row: cell.row,
+            column: cell.column,
+            raw: cell.raw,
+            value: json_value(cell, self.options),
+            kind: cell.kind,
+            style: cell.style,
+            num_fmt: style.and_then(|s| s.format_code.as_deref()),
+            formula: cell.formula,
+            fill: None,
+            font_color: None,
+        };
+        // A cell without a style has the first one
+        let colors = self.styles.get(cell.style.unwrap_or(0) as usize);
+        if let (Some(theme), Some(style)) = (&self.options.colors, colors) {
+            record.fill = style.fill.and_then(|color| theme.resolve(&color));
+            record.font_color = style.font_color.and_then(|color| theme.resolve(&color));
+        }
+        serde_json::to_writer(&mut self.pending, &record)?;
+        self.pending.push(b'\n');
+        self.pending_cells += 1;
+        Ok(())
+    }
+}
+
+/// The value of a CellRecord: the field read_sheet decoded, typed by the kind of the cell
+/// Errors keep their code as stored (#DIV/0!) rather than the #ERROR: field.
+fn json_value(cell: &ReadCell, options: &ExportOptions) -> Value {
+    let field = cell.field;
+    match cell.kind {
+        _ if cell.is_text => {
+            let mut text = field.to_string();
+            options.newlines.apply(&mut text);
+            Value::String(text)
+        }
+        CellKind::Empty if field.is_empty() => Value::Null,
+        CellKind::Bool => Value::Bool(field == "TRUE"),
+        CellKind::Error => Value::String(cell.raw.to_string()),
+        CellKind::Number => field
+            .parse()
+            .map_or_else(|_| Value::String(field.to_string()), number),
+        _ => Value::String(field.to_string()),
+    }
+}
+
+/// A number as JSON, whole numbers without a fraction (1 rather than 1.0)
+fn number(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < 2f64.powi(53) {
+        Value::from(n as i64)
+    } else {
+        Value::from(n)
+    }
+}
+
+/// A formula cell of a sheet, see for_each_formula
+/// formula: without the leading '=', shared formulas translated to the cell
+/// value: the cached result as the export writes it
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormulaCell {
+    pub cell: CellRef,
+    pub formula: String,
+    pub value: String,
+}
+
+/// Hand every formula cell of a sheet to f in sheet order, its cached result decoded by
+/// read_sheet like the cells of an export with options (date system, timezone, date output,
+/// number formats)
+pub fn for_each_formula<R: BufRead, S: StringTable + ?Sized>(
+    reader: R,
+    shared_strings: &S,
+    styles: &[StyleInfo],
+    is_1904: bool,
+    options: &ExportOptions,
+    f: impl FnMut(FormulaCell) -> Result<()>,
+) -> Result<()> {
+    read_sheet(
+        reader,
+        shared_strings,
+        styles,
+        is_1904,
+        options,
+        &mut FormulaCells(f),
+        &mut ExportSummary::default(),
+    )?;
+    Ok(())
+}
+
+/// The RowTarget of for_each_formula: passes on the formula cells and writes no rows
+struct FormulaCells<F>(F);
+
+impl<F: FnMut(FormulaCell) -> Result<()>> RowTarget for FormulaCells<F> {
+    fn set_sheet_width(&mut self, _width: usize) {}
+
+    fn write(
+        &mut self,
+        _row: &mut Vec<String>,
+        _present: &[bool],
+        _values: &mut Vec<CellValue>,
+        _row_number: u32,
+    ) -> Result<u64> {
+        Ok(0)
+    }
+
+    fn limit_reached(&self) -> bool {
+        false
+    }
+
+    fn wants_cells(&self) -> bool {
+        true
+    }
+
+    fn cell(&mut self, cell: &ReadCell) -> Result<()> {
+        let Some(formula) = cell.formula else {
+            return Ok(());
+        };
+        (self.0)(FormulaCell {
+            cell: CellRef {
+                col: cell.column,
+                row: cell.row,
+            },
+            formula: formula.to_string(),
+            value: cell.field.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OutputFormat, export_sheet_xml_to_csv};
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_cells_json() {
+        let xml_data = r#"
+        <worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">
+            <sheetData>
+                <row r="1"><c r="A1" t="s"><v>0</v></c><c r="C1" t="s"><v>7</v></c></row>
+                <row r="2">
+                    <c r="A2" s="0"><v>45108.5</v></c>
+                    <c r="B2"><f>A2*2</f><v>2.5</v></c>
+                    <c r="C2" s="1"/>
+                    <c r="D2" t="b"><v>1</v></c>
+                    <c r="E2" t="e"><v>#DIV/0!</v></c>
+                </row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let styles = [
+            StyleInfo {
+                num_fmt_id: 14,
+                format_code: Some("yyyy-mm-dd".to_string()),
+                is_date: true,
+                is_text: false,
+                ..StyleInfo::default()
+            },
+            StyleInfo::default(),
+        ];
+        let temp_file = NamedTempFile::new().unwrap();
+        let options = ExportOptions {
+            format: OutputFormat::CellsJson,
+            ..ExportOptions::default()
+        };
+        let summary = export_sheet_xml_to_csv(
+            xml_data.as_bytes(),
+            &["name".to_string()],
+            &styles,
+            false,
+            temp_file.path(),
+            &options,
+        )
+        .unwrap();
+
+        let lines: Vec<Value> = std::fs::read_to_string(temp_file.path())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(summary.rows_written, 6);
+        assert_eq!(summary.cells.get(&CellKind::Empty), Some(&2));
+        assert_eq!(
+            lines[0],
+            serde_json::json!({"ref": "A1", "row": 1, "column": 1, "raw": "0", "value": "name",
+                "type": "text", "style": null, "num_fmt": null, "formula": null})
+        );
+        // A shared string that doesn't exist
+        assert_eq!(lines[1]["value"], Value::Null);
+        assert_eq!(lines[1]["type"], "empty");
+        assert_eq!(lines[2]["value"], "2023-07-01T12:00:00.000Z");
+        assert_eq!(lines[2]["type"], "date");
+        assert_eq!(lines[2]["num_fmt"], "yyyy-mm-dd");
+        assert_eq!(lines[3]["value"], 2.5);
+        assert_eq!(lines[3]["formula"], "A2*2");
+        assert_eq!(lines[4]["value"], true);
+        assert_eq!(lines[5]["value"], "#DIV/0!");
+        assert_eq!(lines[5]["type"], "error");
+    }
+
+    #[test]
+    fn test_cells_json_decodes_like_rows() {
+        let xml_data = r#"
+        <worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">
+            <sheetData>
+                <row r="1"><c r="A1" t="inlineStr"><is><t>Title</t></is></c></row>
+                <row r="2">
+                    <c r="A2" s="1"><v>0.125</v></c>
+                    <c r="B2"><f t="shared" ref="B2:B3" si="0">A2*2</f><v>0.25</v></c>
+                    <c r="C2" t="s"><v>9</v></c>
+                </row>
+                <row r="3"><c r="B3"><f t="shared" si="0"/><v>0</v></c></row>
+                <row r="4"><c r="A4" t="inlineStr"><is><t>a
+b</t></is></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let styles = [
+            StyleInfo::default(),
+            StyleInfo {
+                num_fmt_id: 10,
+                format_code: Some("0.00%".to_string()),
+                ..StyleInfo::default()
+            },
+        ];
+        let export = |options: ExportOptions| {
+            let temp_file = NamedTempFile::new().unwrap();
+            let options = ExportOptions {
+                format: OutputFormat::CellsJson,
+                ..options
+            };
+            export_sheet_xml_to_csv(
+                xml_data.as_bytes(),
+                &[],
+                &styles,
+                false,
+                temp_file.path(),
+                &options,
+            )
+            .map(|summary| {
+                let lines: Vec<Value> = std::fs::read_to_string(temp_file.path())
+                    .unwrap()
+                    .lines()
+                    .map(|line| serde_json::from_str(line).unwrap())
+                    .collect();
+                (summary, lines)
+            })
+        };
+
+        let (summary, lines) = export(ExportOptions {
+            header_row: Some(2),
+            max_rows: Some(2),
+            units: UnitOutput::Suffix,
+            ..ExportOptions::default()
+        })
+        .unwrap();
+        // Row 1 is above the header row and row 4 past max_rows
+        assert_eq!(summary.rows_written, 4);
+        assert_eq!(lines[0]["value"], "12.5 %");
+        assert_eq!(lines[0]["raw"], "0.125");
+        assert_eq!(lines[1]["value"], 0.25);
+        assert_eq!(lines[2]["value"], Value::Null);
+        assert_eq!(lines[3]["formula"], "A3*2");
+        assert_eq!(
+            summary.warnings[&crate::WarningKind::MissingSharedString],
+            1
+        );
+
+        let (_, lines) = export(ExportOptions {
+            newlines: crate::Newlines::Escape,
+            ..ExportOptions::default()
+        })
+        .unwrap();
+        assert_eq!(lines[5]["value"], "a\\nb");
+
+        let err = export(ExportOptions {
+            on_missing_string: crate::MissingString::Error,
+            ..ExportOptions::default()
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("C2: shared string index '9'"));
+
+        let err = export(ExportOptions {
+            row_numbers: true,
+            ..ExportOptions::default()
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cells-json writes cells, not rows; row_numbers does not apply"
+        );
+    }
+
+    #[test]
+    fn test_cells_json_colors() {
+        let styles_xml = r#"
+        <styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">
+            <fonts>
+                <font><sz val="11"/><color theme="1"/></font>
+                <font><b/><color indexed="10"/></font>
+                <font><color auto="1"/></font>
+            </fonts>
+            <fills>
+                <fill><patternFill patternType="none"/></fill>
+                <fill><patternFill patternType="gray125"/></fill>
+                <fill><patternFill patternType="solid"><fgColor theme="4" tint="0.7999816888943144"/><bgColor indexed="64"/></patternFill></fill>
+                <fill><patternFill patternType="solid"><fgColor rgb="FFFFFF00"/></patternFill></fill>
+            </fills>
+            <cellXfs>
+                <xf numFmtId="0" fontId="0" fillId="0"/>
+                <xf numFmtId="0" fontId="1" fillId="2"/>
+                <xf numFmtId="0" fontId="2" fillId="3"/>
+            </cellXfs>
+            <dxfs><dxf><font><color rgb="FF9C0006"/></font></dxf></dxfs>
+            <colors><indexedColors>
+                <rgbColor rgb="FF000000"/><rgbColor rgb="FFFFFFFF"/><rgbColor rgb="FFFF0000"/>
+                <rgbColor rgb="FF00FF00"/><rgbColor rgb="FF0000FF"/><rgbColor rgb="FFFFFF00"/>
+                <rgbColor rgb="FFFF00FF"/><rgbColor rgb="FF00FFFF"/><rgbColor rgb="FF000000"/>
+                <rgbColor rgb="FFFFFFFF"/><rgbColor rgb="FF123456"/>
+            </indexedColors></colors>
+        </styleSheet>
+        "#;
+        let styles = crate::parse_styles(styles_xml.as_bytes()).unwrap();
+        let xml_data = r#"
+        <worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">
+            <sheetData>
+                <row r="1">
+                    <c r="A1"><v>1</v></c>
+                    <c r="B1" s="1"><v>2</v></c>
+                    <c r="C1" s="2"><v>3</v></c>
+                </row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let export = |colors| {
+            let temp_file = NamedTempFile::new().unwrap();
+            let options = ExportOptions {
+                format: OutputFormat::CellsJson,
+                colors,
+                ..ExportOptions::default()
+            };
+            export_sheet_xml_to_csv(
+                xml_data.as_bytes(),
+                &[],
+                &styles,
+                false,
+                temp_file.path(),
+                &options,
+            )
+            .unwrap();
+            std::fs::read_to_string(temp_file.path())
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect::<Vec<Value>>()
+        };
+
+        let lines = export(Some(crate::ThemeColors::default()));
+        assert_eq!(lines[0].get("fill"), None);
+        assert_eq!(lines[0]["font_color"], "#000000");
+        assert_eq!(lines[1]["fill"], "#DAE3F3");
+        // Indexed colors come from the palette of styles.xml
+        assert_eq!(lines[1]["font_color"], "#123456");
+        assert_eq!(lines[2]["fill"], "#FFFF00");
+        assert_eq!(lines[2].get("font_color"), None);
+
+        let lines = export(None);
+        assert_eq!(lines[1].get("fill"), None);
+        assert_eq!(lines[1].get("font_color"), None);
+    }
+}
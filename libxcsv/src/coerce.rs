@@ -0,0 +1,307 @@
+use anyhow::Result;
+use std::str::FromStr;
+
+use super::dates::split_zone;
+use super::filter::{column_position, find_column};
+use super::{format_iso_datetime, parse_iso_datetime};
+
+/// The type a column is coerced to, see ColumnTypes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetType {
+    Int,
+    Float,
+    /// A number rounded (half away from zero) to this many decimal places
+    Decimal(u32),
+    Bool,
+    Date,
+    Datetime,
+    Text,
+}
+
+impl FromStr for TargetType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        if let Some(places) = s
+            .strip_prefix("decimal(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            return places
+                .trim()
+                .parse()
+                .map(TargetType::Decimal)
+                .map_err(|_| format!("invalid decimal places in '{}'", s));
+        }
+        match s.as_str() {
+            "int" | "integer" => Ok(TargetType::Int),
+            "float" | "number" => Ok(TargetType::Float),
+            "bool" | "boolean" => Ok(TargetType::Bool),
+            "date" => Ok(TargetType::Date),
+            "datetime" | "timestamp" => Ok(TargetType::Datetime),
+            "text" | "string" => Ok(TargetType::Text),
+            _ => Err(format!(
+                "invalid type '{}', expected int, float, decimal(N), bool, date, datetime or text",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for TargetType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TargetType::Int => f.write_str("int"),
+            TargetType::Float => f.write_str("float"),
+            TargetType::Decimal(places) => write!(f, "decimal({})", places),
+            TargetType::Bool => f.write_str("bool"),
+            TargetType::Date => f.write_str("date"),
+            TargetType::Datetime => f.write_str("datetime"),
+            TargetType::Text => f.write_str("text"),
+        }
+    }
+}
+
+impl TargetType {
+    /// The normalized value, or None when value is not of this type
+    /// int: 42 (42.0 too); float: plain decimal notation (1e3 becomes 1000); decimal(2): 2.50;
+    /// bool: true/false (from TRUE/FALSE, 1/0, yes/no); date: 2024-01-31 (a datetime must be
+    /// at midnight); datetime: 2024-01-31T08:00:00.000Z like exported dates, keeping the zone
+    /// designator of the value (none, Z or an offset, see Timezone).
+    pub fn coerce(self, value: &str) -> Option<String> {
+        let trimmed = value.trim();
+        match self {
+            TargetType::Int => trimmed
+                .parse::<i64>()
+                .ok()
+                .map(|i| i.to_string())
+                .or_else(|| {
+                    trimmed
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|f| f.fract() == 0.0 && f.abs() < i64::MAX as f64)
+                        .map(|f| (f as i64).to_string())
+                }),
+            TargetType::Float => trimmed
+                .parse::<f64>()
+                .ok()
+                .filter(|f| f.is_finite())
+                .map(|f| f.to_string()),
+            TargetType::Decimal(places) => round_decimal(trimmed, places as usize),
+            TargetType::Bool => match trimmed.to_ascii_lowercase().as_str() {
+                "true" | "1" | "yes" => Some("true".to_string()),
+                "false" | "0" | "no" => Some("false".to_string()),
+                _ => None,
+            },
+            TargetType::Date => parse_iso_datetime(trimmed)
+                .filter(|dt| dt.time() == chrono::NaiveTime::MIN)
+                .map(|dt| dt.format("%Y-%m-%d").to_string()),
+            TargetType::Datetime => {
+                let (datetime, zone) = split_zone(trimmed);
+                parse_iso_datetime(datetime).map(|dt| match zone {
+                    // A date alone is midnight UTC, like before zones were written
+                    "" if !datetime.contains('T') => format_iso_datetime(&dt),
+                    _ => format!("{}{}", dt.format("%Y-%m-%dT%H:%M:%S%.3f"), zone),
+                })
+            }
+            TargetType::Text => Some(value.to_string()),
+        }
+    }
+}
+
+/// Round a number to places decimals, half away from zero
+/// Plain decimal notation is rounded on its digits, so 2.675 becomes 2.68 and not 2.67 like
+/// its nearest f64 would.
+fn round_decimal(s: &str, places: usize) -> Option<String> {
+    let f = s.parse::<f64>().ok().filter(|f| f.is_finite())?;
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+    if !(int_part.bytes().chain(frac_part.bytes())).all(|b| b.is_ascii_digit()) {
+        // Exponent notation: round the f64
+        return Some(format!("{:.*}", places, f));
+    }
+    let mut kept: Vec<u8> = int_part.bytes().collect();
+    if kept.is_empty() {
+        kept.push(b'0');
+    }
+    kept.extend(
+        frac_part
+            .bytes()
+            .chain(std::iter::repeat(b'0'))
+            .take(places),
+    );
+    if frac_part.as_bytes().get(places).is_some_and(|&d| d >= b'5') {
+        // Carry the rounding up through the kept digits
+        let mut i = kept.len();
+        loop {
+            if i == 0 {
+                kept.insert(0, b'1');
+                break;
+            }
+            i -= 1;
+            if kept[i] == b'9' {
+                kept[i] = b'0';
+            } else {
+                kept[i] += 1;
+                break;
+            }
+        }
+    }
+    let split = kept.len() - places;
+    let mut out = String::new();
+    let int_digits = std::str::from_utf8(&kept[..split]).ok()?;
+    let int_digits = int_digits.trim_start_matches('0');
+    out.push_str(if int_digits.is_empty() {
+        "0"
+    } else {
+        int_digits
+    });
+    if places > 0 {
+        out.push('.');
+        out.push_str(std::str::from_utf8(&kept[split..]).ok()?);
+    }
+    if negative && out.bytes().any(|b| (b'1'..=b'9').contains(&b)) {
+        out.insert(0, '-');
+    }
+    Some(out)
+}
+
+/// Types to coerce columns to while exporting, like `id:int,price:decimal(2),created:date`
+/// Columns are named like in a RowFilter: by the header row, or by letter or number with a
+/// synthetic header. Empty cells stay empty; see TargetType::coerce for the rest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnTypes {
+    columns: Vec<(String, TargetType)>,
+}
+
+impl FromStr for ColumnTypes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut columns = Vec::new();
+        for item in s.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (name, target) = item
+                .rsplit_once(':')
+                .ok_or_else(|| format!("invalid column type '{}', expected NAME:TYPE", item))?;
+            columns.push((name.trim().to_string(), target.parse()?));
+        }
+        if columns.is_empty() {
+            return Err("no column types given".to_string());
+        }
+        Ok(Self { columns })
+    }
+}
+
+impl ColumnTypes {
+    /// Whether a column is coerced to a date or datetime, which read ISO dates
+    pub fn has_dates(&self) -> bool {
+        self.columns
+            .iter()
+            .any(|(_, target)| matches!(target, TargetType::Date | TargetType::Datetime))
+    }
+
+    /// Resolve the columns against a header row
+    pub fn bind(&self, header: &[String]) -> Result<BoundColumnTypes> {
+        self.bind_with(|name| find_column(header, name), || header.join(", "))
+    }
+
+    /// Resolve the columns as letters (A, B, ...) or 1-based numbers, see RowFilter::bind_positions
+    pub fn bind_positions(&self, letters: bool) -> Result<BoundColumnTypes> {
+        let expected = if letters { "A, B, ..." } else { "1, 2, ..." };
+        self.bind_with(
+            |name| column_position(name, letters),
+            || expected.to_string(),
+        )
+    }
+
+    fn bind_with(
+        &self,
+        find: impl Fn(&str) -> Option<usize>,
+        columns: impl Fn() -> String,
+    ) -> Result<BoundColumnTypes> {
+        let mut bound = Vec::new();
+        for (name, target) in &self.columns {
+            let index = find(name).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "unknown column '{}' in types (columns: {})",
+                    name,
+                    columns()
+                )
+            })?;
+            bound.push((index, name.clone(), *target));
+        }
+        Ok(BoundColumnTypes { columns: bound })
+    }
+}
+
+/// ColumnTypes with the columns resolved to field positions
+#[derive(Debug, Clone)]
+pub struct BoundColumnTypes {
+    columns: Vec<(usize, String, TargetType)>,
+}
+
+/// A value that could not be coerced: its field position and what is wrong with it
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoercionError {
+    pub index: usize,
+    pub message: String,
+}
+
+impl BoundColumnTypes {
+    /// Coerce the typed fields of row in place, returning the fields that failed
+    pub fn apply(&self, row: &mut [String]) -> Vec<CoercionError> {
+        let mut errors = Vec::new();
+        for (index, name, target) in &self.columns {
+            let Some(value) = row.get_mut(*index).filter(|v| !v.is_empty()) else {
+                continue;
+            };
+            match target.coerce(value) {
+                Some(coerced) => *value = coerced,
+                None => errors.push(CoercionError {
+                    index: *index,
+                    message: format!("'{}' in column {} is not a valid {}", value, name, target),
+                }),
+            }
+        }
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_types_coerce_and_report() {
+        let types: ColumnTypes = "id:int, price:decimal(2), created:date, ok:bool"
+            .parse()
+            .unwrap();
+        let header: Vec<String> = ["ID", "price", "created", "ok"].map(String::from).to_vec();
+        let bound = types.bind(&header).unwrap();
+
+        let mut row: Vec<String> = ["7.0", "2.675", "2024-01-31T00:00:00.000Z", "TRUE"]
+            .map(String::from)
+            .to_vec();
+        assert!(bound.apply(&mut row).is_empty());
+        assert_eq!(row, vec!["7", "2.68", "2024-01-31", "true"]);
+
+        let mut row: Vec<String> = ["7.5", "", "2024-01-31T08:00:00.000Z"]
+            .map(String::from)
+            .to_vec();
+        let errors = bound.apply(&mut row);
+        assert_eq!(
+            errors.iter().map(|e| e.index).collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+        assert_eq!(errors[0].message, "'7.5' in column id is not a valid int");
+
+        assert_eq!(round_decimal("-0.004", 2).as_deref(), Some("0.00"));
+        assert_eq!(round_decimal("99.995", 2).as_deref(), Some("100.00"));
+        assert_eq!(round_decimal("1e3", 1).as_deref(), Some("1000.0"));
+        assert!("id:uuid".parse::<ColumnTypes>().is_err());
+        assert!(types.bind(&header[..2]).is_err());
+    }
+}
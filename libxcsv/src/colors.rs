@@ -0,0 +1,320 @@
+use anyhow::Result;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::NsReader;
+use std::io::BufRead;
+
+use crate::ns::{DRAWINGML, read_event};
+
+/// Excel's default palette of indexed colors, used when styles.xml has no `<indexedColors>`
+/// 64 and 65 are the system foreground and background.
+const INDEXED_COLORS: [[u8; 3]; 66] = [
+    [0x00, 0x00, 0x00],
+    [0xFF, 0xFF, 0xFF],
+    [0xFF, 0x00, 0x00],
+    [0x00, 0xFF, 0x00],
+    [0x00, 0x00, 0xFF],
+    [0xFF, 0xFF, 0x00],
+    [0xFF, 0x00, 0xFF],
+    [0x00, 0xFF, 0xFF],
+    [0x00, 0x00, 0x00],
+    [0xFF, 0xFF, 0xFF],
+    [0xFF, 0x00, 0x00],
+    [0x00, 0xFF, 0x00],
+    [0x00, 0x00, 0xFF],
+    [0xFF, 0xFF, 0x00],
+    [0xFF, 0x00, 0xFF],
+    [0x00, 0xFF, 0xFF],
+    [0x80, 0x00, 0x00],
+    [0x00, 0x80, 0x00],
+    [0x00, 0x00, 0x80],
+    [0x80, 0x80, 0x00],
+    [0x80, 0x00, 0x80],
+    [0x00, 0x80, 0x80],
+    [0xC0, 0xC0, 0xC0],
+    [0x80, 0x80, 0x80],
+    [0x99, 0x99, 0xFF],
+    [0x99, 0x33, 0x66],
+    [0xFF, 0xFF, 0xCC],
+    [0xCC, 0xFF, 0xFF],
+    [0x66, 0x00, 0x66],
+    [0xFF, 0x80, 0x80],
+    [0x00, 0x66, 0xCC],
+    [0xCC, 0xCC, 0xFF],
+    [0x00, 0x00, 0x80],
+    [0xFF, 0x00, 0xFF],
+    [0xFF, 0xFF, 0x00],
+    [0x00, 0xFF, 0xFF],
+    [0x80, 0x00, 0x80],
+    [0x80, 0x00, 0x00],
+    [0x00, 0x80, 0x80],
+    [0x00, 0x00, 0xFF],
+    [0x00, 0xCC, 0xFF],
+    [0xCC, 0xFF, 0xFF],
+    [0xCC, 0xFF, 0xCC],
+    [0xFF, 0xFF, 0x99],
+    [0x99, 0xCC, 0xFF],
+    [0xFF, 0x99, 0xCC],
+    [0xCC, 0x99, 0xFF],
+    [0xFF, 0xCC, 0x99],
+    [0x33, 0x66, 0xFF],
+    [0x33, 0xCC, 0xCC],
+    [0x99, 0xCC, 0x00],
+    [0xFF, 0xCC, 0x00],
+    [0xFF, 0x99, 0x00],
+    [0xFF, 0x66, 0x00],
+    [0x66, 0x66, 0x99],
+    [0x96, 0x96, 0x96],
+    [0x00, 0x33, 0x66],
+    [0x33, 0x99, 0x66],
+    [0x00, 0x33, 0x00],
+    [0x33, 0x33, 0x00],
+    [0x99, 0x33, 0x00],
+    [0x99, 0x33, 0x66],
+    [0x33, 0x33, 0x99],
+    [0x33, 0x33, 0x33],
+    [0x00, 0x00, 0x00],
+    [0xFF, 0xFF, 0xFF],
+];
+
+/// A color of styles.xml as written, e.g. `<fgColor theme="4" tint="0.5999"/>`
+/// base: the color it starts from
+/// tint: lightens (up to 1.0) or darkens (down to -1.0) it; 0.0 leaves it as is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub base: ColorBase,
+    pub tint: f64,
+}
+
+/// Where a Color comes from
+/// Rgb: the color itself (the alpha channel of ARGB dropped)
+/// Theme: an index into the ThemeColors of the workbook
+/// Indexed: an index into the palette of styles.xml, or Excel's default one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorBase {
+    Rgb([u8; 3]),
+    Theme(u32),
+    Indexed(u32),
+}
+
+/// The colors of a workbook theme (theme1.xml) by theme index: lt1, dk1, lt2, dk2,
+/// accent1 to accent6, hlink and folHlink
+/// The index swaps the light and dark colors of the scheme, which lists dk1 first. The
+/// default is the Office theme, for workbooks without a theme part.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThemeColors(pub Vec<[u8; 3]>);
+
+impl Default for ThemeColors {
+    fn default() -> Self {
+        ThemeColors(vec![
+            [0xFF, 0xFF, 0xFF],
+            [0x00, 0x00, 0x00],
+            [0xE7, 0xE6, 0xE6],
+            [0x44, 0x54, 0x6A],
+            [0x44, 0x72, 0xC4],
+            [0xED, 0x7D, 0x31],
+            [0xA5, 0xA5, 0xA5],
+            [0xFF, 0xC0, 0x00],
+            [0x5B, 0x9B, 0xD5],
+            [0x70, 0xAD, 0x47],
+            [0x05, 0x63, 0xC1],
+            [0x95, 0x4F, 0x72],
+        ])
+    }
+}
+
+impl ThemeColors {
+    /// A color as "#RRGGBB", tint applied; None for a theme or palette index that doesn't exist
+    pub fn resolve(&self, color: &Color) -> Option<String> {
+        let rgb = match color.base {
+            ColorBase::Rgb(rgb) => rgb,
+            ColorBase::Theme(index) => *self.0.get(index as usize)?,
+            ColorBase::Indexed(index) => *INDEXED_COLORS.get(index as usize)?,
+        };
+        let [r, g, b] = apply_tint(rgb, color.tint);
+        Some(format!("#{:02X}{:02X}{:02X}", r, g, b))
+    }
+}
+
+/// The theme index of a color of `<a:clrScheme>`, e.g. 1 for dk1
+fn theme_index(name: &[u8]) -> Option<usize> {
+    Some(match name {
+        b"lt1" => 0,
+        b"dk1" => 1,
+        b"lt2" => 2,
+        b"dk2" => 3,
+        b"accent1" => 4,
+        b"accent2" => 5,
+        b"accent3" => 6,
+        b"accent4" => 7,
+        b"accent5" => 8,
+        b"accent6" => 9,
+        b"hlink" => 10,
+        b"folHlink" => 11,
+        _ => return None,
+    })
+}
+
+/// Parse the color scheme of a theme part (xl/theme/theme1.xml)
+/// Colors the scheme leaves out keep those of the Office theme.
+pub fn parse_theme<R: BufRead>(reader: R) -> Result<ThemeColors> {
+    let mut xml = NsReader::from_reader(reader);
+    let mut buf = Vec::new();
+    let mut colors = ThemeColors::default();
+    let mut in_scheme = false;
+    // The theme index of the scheme color being read
+    let mut slot = None;
+    loop {
+        match read_event(&mut xml, &mut buf, DRAWINGML) {
+            Ok((true, Event::Start(e) | Event::Empty(e))) => match e.local_name().as_ref() {
+                b"clrScheme" => in_scheme = true,
+                b"srgbClr" | b"sysClr" if in_scheme => {
+                    // sysClr holds the system color it was saved with in lastClr
+                    let key: &[u8] = match e.local_name().as_ref() {
+                        b"srgbClr" => b"val",
+                        _ => b"lastClr",
+                    };
+                    let rgb = e
+                        .attributes()
+                        .flatten()
+                        .find(|a| a.key.as_ref() == key)
+                        .and_then(|a| parse_rgb(&String::from_utf8_lossy(&a.value)));
+                    if let (Some(index), Some(rgb)) = (slot.take(), rgb) {
+                        colors.0[index] = rgb;
+                    }
+                }
+                name if in_scheme => slot = theme_index(name),
+                _ => {}
+            },
+            // Only the first scheme counts
+            Ok((true, Event::End(e))) if e.local_name().as_ref() == b"clrScheme" => break,
+            Ok((_, Event::Eof)) => break,
+            Err(e) => return Err(anyhow::anyhow!("XML error in theme: {}", e)),
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(colors)
+}
+
+/// Parse a color element of styles.xml (`<color>`, `<fgColor>`, ...)
+/// Returns None for an automatic color and for one without a color.
+pub(crate) fn parse_color(e: &BytesStart) -> Option<Color> {
+    let mut base = None;
+    let mut tint = 0.0;
+    for a in e.attributes().flatten() {
+        let value = String::from_utf8_lossy(&a.value);
+        match a.key.as_ref() {
+            b"rgb" => base = parse_rgb(&value).map(ColorBase::Rgb).or(base),
+            b"theme" => base = base.or(value.parse().ok().map(ColorBase::Theme)),
+            b"indexed" => base = base.or(value.parse().ok().map(ColorBase::Indexed)),
+            b"tint" => tint = value.parse().unwrap_or(0.0),
+            b"auto" if value == "1" || value == "true" => return None,
+            _ => {}
+        }
+    }
+    Some(Color { base: base?, tint })
+}
+
+/// The RGB of a hex RRGGBB or AARRGGBB value
+pub(crate) fn parse_rgb(value: &str) -> Option<[u8; 3]> {
+    let hex = value.get(value.len().checked_sub(6)?..)?;
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Lighten (tint > 0) or darken (tint < 0) a color the way Excel does, on its HSL lightness
+fn apply_tint(rgb: [u8; 3], tint: f64) -> [u8; 3] {
+    if tint == 0.0 {
+        return rgb;
+    }
+    let [r, g, b] = rgb.map(|c| f64::from(c) / 255.0);
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let lightness = (max + min) / 2.0;
+    let (hue, saturation) = if max == min {
+        (0.0, 0.0)
+    } else {
+        let d = max - min;
+        let saturation = if lightness > 0.5 {
+            d / (2.0 - max - min)
+        } else {
+            d / (max + min)
+        };
+        let hue = if max == r {
+            (g - b) / d + if g < b { 6.0 } else { 0.0 }
+        } else if max == g {
+            (b - r) / d + 2.0
+        } else {
+            (r - g) / d + 4.0
+        };
+        (hue / 6.0, saturation)
+    };
+    let lightness = if tint < 0.0 {
+        lightness * (1.0 + tint)
+    } else {
+        lightness * (1.0 - tint) + tint
+    }
+    .clamp(0.0, 1.0);
+
+    let q = if lightness < 0.5 {
+        lightness * (1.0 + saturation)
+    } else {
+        lightness + saturation - lightness * saturation
+    };
+    let p = 2.0 * lightness - q;
+    let channel = |t: f64| {
+        let t = t.rem_euclid(1.0);
+        let c = if t < 1.0 / 6.0 {
+            p + (q - p) * 6.0 * t
+        } else if t < 0.5 {
+            q
+        } else if t < 2.0 / 3.0 {
+            p + (q - p) * (2.0 / 3.0 - t) * 6.0
+        } else {
+            p
+        };
+        (c * 255.0).round() as u8
+    };
+    [
+        channel(hue + 1.0 / 3.0),
+        channel(hue),
+        channel(hue - 1.0 / 3.0),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_theme_colors() {
+        let xml = r#"<a:theme xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main">
+            <a:themeElements>
+                <a:clrScheme name="Custom">
+                    <a:dk1><a:sysClr val="windowText" lastClr="111111"/></a:dk1>
+                    <a:lt1><a:sysClr val="window" lastClr="FEFEFE"/></a:lt1>
+                    <a:dk2><a:srgbClr val="1F497D"/></a:dk2>
+                    <a:accent1><a:srgbClr val="C00000"/></a:accent1>
+                </a:clrScheme>
+            </a:themeElements>
+        </a:theme>"#;
+        let theme = parse_theme(xml.as_bytes()).unwrap();
+        let color = |base, tint| theme.resolve(&Color { base, tint });
+        assert_eq!(color(ColorBase::Theme(0), 0.0).unwrap(), "#FEFEFE");
+        assert_eq!(color(ColorBase::Theme(1), 0.0).unwrap(), "#111111");
+        assert_eq!(color(ColorBase::Theme(3), 0.0).unwrap(), "#1F497D");
+        assert_eq!(color(ColorBase::Theme(4), 0.0).unwrap(), "#C00000");
+        // Left out of the scheme: the Office theme's
+        assert_eq!(color(ColorBase::Theme(5), 0.0).unwrap(), "#ED7D31");
+        assert_eq!(color(ColorBase::Theme(12), 0.0), None);
+
+        assert_eq!(color(ColorBase::Indexed(10), 0.0).unwrap(), "#FF0000");
+        assert_eq!(
+            color(ColorBase::Rgb([0x44, 0x72, 0xC4]), 0.0).unwrap(),
+            "#4472C4"
+        );
+        // Excel's "Blue, Accent 1, Lighter 80%" and "Darker 50%"
+        let accent = ColorBase::Rgb([0x44, 0x72, 0xC4]);
+        assert_eq!(color(accent, 0.7999816888943144).unwrap(), "#DAE3F3");
+        assert_eq!(color(accent, -0.499984740745262).unwrap(), "#203864");
+    }
+}
@@ -0,0 +1,276 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+
+use crate::{CellValue, GeneralPrecision, RowSink, column_names};
+
+/// The values of a Column, one per row; rows without a value hold a placeholder ("", 0.0,
+/// false or 1970-01-01) and are marked in the validity
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnData {
+    Text(Vec<String>),
+    Number(Vec<f64>),
+    Bool(Vec<bool>),
+    Timestamp(Vec<NaiveDateTime>),
+}
+
+impl ColumnData {
+    pub fn len(&self) -> usize {
+        match self {
+            ColumnData::Text(v) => v.len(),
+            ColumnData::Number(v) => v.len(),
+            ColumnData::Bool(v) => v.len(),
+            ColumnData::Timestamp(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Append the placeholder for a row without a value
+    fn push_null(&mut self) {
+        match self {
+            ColumnData::Text(v) => v.push(String::new()),
+            ColumnData::Number(v) => v.push(0.0),
+            ColumnData::Bool(v) => v.push(false),
+            ColumnData::Timestamp(v) => v.push(NaiveDateTime::default()),
+        }
+    }
+
+    /// Append value if it has the type of the column; false if it doesn't
+    fn push(&mut self, value: &CellValue) -> bool {
+        match (self, value) {
+            (ColumnData::Number(v), CellValue::Number(n)) => v.push(*n),
+            (ColumnData::Bool(v), CellValue::Bool(b)) => v.push(*b),
+            (ColumnData::Timestamp(v), CellValue::Date(d)) => v.push(*d),
+            (ColumnData::Text(v), value) => v.push(value.to_field(GeneralPrecision::Exact)),
+            _ => return false,
+        }
+        true
+    }
+
+    /// The values as text, as they would be exported; placeholders become ""
+    fn into_text(self, validity: &[bool]) -> Vec<String> {
+        let values: Vec<CellValue> = match self {
+            ColumnData::Text(v) => return v,
+            ColumnData::Number(v) => v.into_iter().map(CellValue::Number).collect(),
+            ColumnData::Bool(v) => v.into_iter().map(CellValue::Bool).collect(),
+            ColumnData::Timestamp(v) => v.into_iter().map(CellValue::Date).collect(),
+        };
+        values
+            .iter()
+            .zip(validity)
+            .map(|(value, valid)| match valid {
+                true => value.to_field(GeneralPrecision::Exact),
+                false => String::new(),
+            })
+            .collect()
+    }
+}
+
+/// A typed column of a sheet, as returned by Sheet::to_columns
+/// name: the header field, or column_N when it is empty or repeated
+/// data: the values of the rows below the header
+/// validity: whether each row has a value in the column (false for empty cells)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub data: ColumnData,
+    pub validity: Vec<bool>,
+}
+
+impl Column {
+    /// Number of rows, with or without a value
+    pub fn len(&self) -> usize {
+        self.validity.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.validity.is_empty()
+    }
+
+    /// Number of rows without a value
+    pub fn null_count(&self) -> usize {
+        self.validity.iter().filter(|valid| !**valid).count()
+    }
+}
+
+/// A column being filled; the type is set by the first value and falls back to text when a
+/// value of another type follows
+#[derive(Debug, Default)]
+struct ColumnBuilder {
+    data: Option<ColumnData>,
+    validity: Vec<bool>,
+}
+
+impl ColumnBuilder {
+    fn push(&mut self, value: &CellValue) {
+        if value.is_empty() {
+            self.push_null();
+            return;
+        }
+        let data = self.data.get_or_insert_with(|| {
+            let mut data = match value {
+                CellValue::Number(_) => ColumnData::Number(Vec::new()),
+                CellValue::Bool(_) => ColumnData::Bool(Vec::new()),
+                CellValue::Date(_) => ColumnData::Timestamp(Vec::new()),
+                _ => ColumnData::Text(Vec::new()),
+            };
+            // The rows before the first value
+            for _ in 0..self.validity.len() {
+                data.push_null();
+            }
+            data
+        });
+        if !data.push(value) {
+            let text = std::mem::replace(data, ColumnData::Text(Vec::new()));
+            *data = ColumnData::Text(text.into_text(&self.validity));
+            data.push(value);
+        }
+        self.validity.push(true);
+    }
+
+    fn push_null(&mut self) {
+        if let Some(data) = &mut self.data {
+            data.push_null();
+        }
+        self.validity.push(false);
+    }
+
+    fn finish(mut self, name: String, rows: usize) -> Column {
+        while self.validity.len() < rows {
+            self.push_null();
+        }
+        let data = self
+            .data
+            .unwrap_or_else(|| ColumnData::Text(vec![String::new(); rows]));
+        Column {
+            name,
+            data,
+            validity: self.validity,
+        }
+    }
+}
+
+/// Collects the rows of a sheet into Columns; the first non-blank row is the header
+#[derive(Debug, Default)]
+pub(crate) struct ColumnsSink {
+    header: Option<Vec<String>>,
+    columns: Vec<ColumnBuilder>,
+    rows: usize,
+}
+
+impl ColumnsSink {
+    pub(crate) fn finish(self) -> Vec<Column> {
+        let header = self.header.unwrap_or_default();
+        let width = header.len().max(self.columns.len());
+        let mut builders = self.columns;
+        builders.resize_with(width, ColumnBuilder::default);
+        column_names(&header, width)
+            .into_iter()
+            .zip(builders)
+            .map(|(name, builder)| builder.finish(name, self.rows))
+            .collect()
+    }
+}
+
+impl RowSink for ColumnsSink {
+    fn write_row(&mut self, values: &[CellValue]) -> Result<()> {
+        if self.header.is_none() {
+            if !values.iter().all(CellValue::is_empty) {
+                self.header = Some(
+                    values
+                        .iter()
+                        .map(|value| value.to_field(GeneralPrecision::Exact))
+                        .collect(),
+                );
+            }
+            return Ok(());
+        }
+        // A column first seen in this row has no value in the rows before
+        while self.columns.len() < values.len() {
+            self.columns.push(ColumnBuilder {
+                data: None,
+                validity: vec![false; self.rows],
+            });
+        }
+        for (i, column) in self.columns.iter_mut().enumerate() {
+            match values.get(i) {
+                Some(value) => column.push(value),
+                None => column.push_null(),
+            }
+        }
+        self.rows += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_columns_sink_types_columns() {
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 2, 29)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let mut sink = ColumnsSink::default();
+        for row in [
+            vec![],
+            vec![
+                CellValue::Text("id".into()),
+                CellValue::Text("ok".into()),
+                CellValue::Text("when".into()),
+                CellValue::Text("mixed".into()),
+            ],
+            vec![
+                CellValue::Number(1.0),
+                CellValue::Empty,
+                CellValue::Date(date),
+                CellValue::Empty,
+                CellValue::Empty,
+            ],
+            vec![
+                CellValue::Number(2.5),
+                CellValue::Bool(true),
+                CellValue::Empty,
+                CellValue::Number(7.0),
+            ],
+            vec![
+                CellValue::Empty,
+                CellValue::Empty,
+                CellValue::Empty,
+                CellValue::Text("x".into()),
+                CellValue::Empty,
+                CellValue::Bool(true),
+            ],
+        ] {
+            sink.write_row(&row).unwrap();
+        }
+        let columns = sink.finish();
+        let names: Vec<&str> = columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["id", "ok", "when", "mixed", "column_5", "column_6"]);
+        assert_eq!(columns[0].data, ColumnData::Number(vec![1.0, 2.5, 0.0]));
+        assert_eq!(columns[0].validity, [true, true, false]);
+        assert_eq!(columns[1].data, ColumnData::Bool(vec![false, true, false]));
+        assert_eq!(
+            columns[2].data,
+            ColumnData::Timestamp(vec![
+                date,
+                NaiveDateTime::default(),
+                NaiveDateTime::default()
+            ])
+        );
+        // A number then text: the column falls back to text
+        assert_eq!(
+            columns[3].data,
+            ColumnData::Text(vec!["".into(), "7".into(), "x".into()])
+        );
+        assert_eq!(columns[3].null_count(), 1);
+        assert_eq!(columns[4].data, ColumnData::Text(vec![String::new(); 3]));
+        assert_eq!(columns[4].len(), 3);
+        assert_eq!(columns[5].data, ColumnData::Bool(vec![false, false, true]));
+        assert_eq!(columns[5].validity, [false, false, true]);
+    }
+}
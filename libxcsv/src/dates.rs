@@ -0,0 +1,512 @@
+#[cfg(feature = "tz")]
+use chrono::TimeZone;
+use chrono::{FixedOffset, NaiveDate, NaiveDateTime, TimeDelta};
+#[cfg(feature = "tz")]
+use chrono_tz::Tz;
+use std::str::FromStr;
+
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+/// Converts Excel serial numbers (days since an epoch, with the time as fraction) to datetimes
+/// Implement this to support serials written by systems with their own epoch or calendar.
+pub trait DateSystem: Send + Sync {
+    /// Convert a serial number to a naive (timezone-less) datetime
+    /// Returns None if the serial is out of the representable range.
+    fn to_datetime(&self, serial: f64) -> Option<NaiveDateTime>;
+
+    /// Whether serial falls on 1900-02-29, which the system counts although it doesn't exist
+    /// (see LeapBug)
+    fn is_leap_bug_day(&self, _serial: f64) -> bool {
+        false
+    }
+}
+
+/// What serial 60 of the 1900 date system becomes: Excel shows it as 1900-02-29, a day Lotus
+/// 1-2-3 invented and that doesn't exist
+/// Feb28: 1900-02-28, the day the 1900 system's epoch gives it
+/// Mar1: 1900-03-01, the same day as serial 61
+/// Error: fail the sheet, for audits that must not guess
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LeapBug {
+    #[default]
+    Feb28,
+    Mar1,
+    Error,
+}
+
+impl FromStr for LeapBug {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1900-02-28" => Ok(Self::Feb28),
+            "1900-03-01" => Ok(Self::Mar1),
+            "error" => Ok(Self::Error),
+            _ => Err(format!(
+                "invalid mode '{}', expected error, 1900-02-28 or 1900-03-01",
+                s
+            )),
+        }
+    }
+}
+
+/// A date system with serial 60 converted as policy says; LeapBug::Error is up to the caller,
+/// as to_datetime can't fail
+pub(crate) struct WithLeapBug<'a> {
+    pub system: &'a dyn DateSystem,
+    pub policy: LeapBug,
+}
+
+impl DateSystem for WithLeapBug<'_> {
+    fn to_datetime(&self, serial: f64) -> Option<NaiveDateTime> {
+        if self.policy == LeapBug::Mar1 && self.system.is_leap_bug_day(serial) {
+            self.system.to_datetime(serial + 1.0)
+        } else {
+            self.system.to_datetime(serial)
+        }
+    }
+
+    fn is_leap_bug_day(&self, serial: f64) -> bool {
+        self.system.is_leap_bug_day(serial)
+    }
+}
+
+/// The default Excel date system (Windows), counting from 1899-12-30
+/// Excel counts the phantom 1900-02-29, so serials before 60 count from 1899-12-31 instead and
+/// match what Excel shows (1 is 1900-01-01); serial 60 (Excel's 1900-02-29) is 1900-02-28.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Excel1900;
+
+/// The Excel 1904 date system (old Mac workbooks, `date1904="1"`), counting from 1904-01-01
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Excel1904;
+
+/// A user supplied epoch: serial 0 is the epoch itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CustomEpoch {
+    pub epoch: NaiveDateTime,
+}
+
+impl DateSystem for Excel1900 {
+    fn to_datetime(&self, serial: f64) -> Option<NaiveDateTime> {
+        let epoch = if serial < 60.0 {
+            NaiveDate::from_ymd_opt(1899, 12, 31)?
+        } else {
+            NaiveDate::from_ymd_opt(1899, 12, 30)?
+        };
+        offset_from_epoch(epoch.and_hms_opt(0, 0, 0)?, serial)
+    }
+
+    fn is_leap_bug_day(&self, serial: f64) -> bool {
+        serial.floor() == 60.0
+    }
+}
+
+impl DateSystem for Excel1904 {
+    fn to_datetime(&self, serial: f64) -> Option<NaiveDateTime> {
+        offset_from_epoch(
+            NaiveDate::from_ymd_opt(1904, 1, 1)?.and_hms_opt(0, 0, 0)?,
+            serial,
+        )
+    }
+}
+
+impl DateSystem for CustomEpoch {
+    fn to_datetime(&self, serial: f64) -> Option<NaiveDateTime> {
+        offset_from_epoch(self.epoch, serial)
+    }
+}
+
+impl FromStr for CustomEpoch {
+    type Err = String;
+
+    /// Parse "YYYY-MM-DD" or "YYYY-MM-DDTHH:MM:SS"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let epoch = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S")
+            .or_else(|_| {
+                NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                    .map(|d| d.and_hms_opt(0, 0, 0).unwrap_or_default())
+            })
+            .map_err(|_| format!("invalid epoch '{}', expected YYYY-MM-DD[THH:MM:SS]", s))?;
+        Ok(Self { epoch })
+    }
+}
+
+/// Add a serial number of days (fraction = time of day, rounded to the second) to an epoch
+fn offset_from_epoch(epoch: NaiveDateTime, serial: f64) -> Option<NaiveDateTime> {
+    if !serial.is_finite() {
+        return None;
+    }
+    let days = serial.floor();
+    let seconds = ((serial - days) * SECONDS_PER_DAY).round();
+    let total = days * SECONDS_PER_DAY + seconds;
+    if total.abs() > i64::MAX as f64 / 2.0 {
+        return None;
+    }
+    epoch.checked_add_signed(TimeDelta::try_seconds(total as i64)?)
+}
+
+/// The serial number of a datetime in the 1900 date system, as Excel shows it
+/// Excel counts the phantom 1900-02-29, so dates before 1900-03-01 are a day lower than the
+/// count from 1899-12-30 (1900-01-01 is 1). Returns None before serial 0 (Excel's 1900-01-00,
+/// 1899-12-31), which Excel cannot show as a date.
+pub fn to_excel_serial(datetime: &NaiveDateTime) -> Option<f64> {
+    let epoch = NaiveDate::from_ymd_opt(1899, 12, 30)?.and_hms_opt(0, 0, 0)?;
+    let leap_bug_day = NaiveDate::from_ymd_opt(1900, 3, 1)?.and_hms_opt(0, 0, 0)?;
+    let mut since = datetime.signed_duration_since(epoch);
+    if *datetime < leap_bug_day {
+        since -= TimeDelta::days(1);
+    }
+    let millis = since.num_milliseconds();
+    (millis >= 0).then(|| millis as f64 / (SECONDS_PER_DAY * 1000.0))
+}
+
+/// Format a datetime the way exported dates are written (ISO 8601 with a `Z` suffix)
+pub fn format_iso_datetime(datetime: &NaiveDateTime) -> String {
+    datetime.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+/// What timezone the naive dates of a workbook are taken to be in, and so how they are written
+/// Utc: with a `Z` suffix, 2023-01-01T12:00:00.000Z (the default)
+/// Naive: without a zone, 2023-01-01T12:00:00.000, for consumers that read it as local time
+/// Offset: with a fixed UTC offset, 2023-01-01T12:00:00.000+02:00
+/// Zone: with the offset an IANA zone like Europe/Amsterdam has at that time (tz feature)
+/// Only the zone designator changes; the wall clock time is the one stored in the workbook.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Timezone {
+    #[default]
+    Utc,
+    Naive,
+    Offset(FixedOffset),
+    #[cfg(feature = "tz")]
+    Zone(Tz),
+}
+
+impl FromStr for Timezone {
+    type Err = String;
+
+    /// Parse utc (or Z), naive, an offset like +02:00, -0530 or +02, or with the tz feature an
+    /// IANA zone name
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "utc" | "UTC" | "Z" => Ok(Timezone::Utc),
+            "naive" => Ok(Timezone::Naive),
+            _ if s.starts_with(['+', '-']) => parse_offset(s)
+                .map(Timezone::Offset)
+                .ok_or_else(|| format!("invalid offset '{}', expected +HH:MM", s)),
+            #[cfg(feature = "tz")]
+            _ => s.parse::<Tz>().map(Timezone::Zone).map_err(|_| {
+                format!(
+                    "invalid timezone '{}', expected utc, naive, an offset like +02:00 or an IANA zone like Europe/Amsterdam",
+                    s
+                )
+            }),
+            #[cfg(not(feature = "tz"))]
+            _ => Err(format!(
+                "invalid timezone '{}', expected utc, naive or an offset like +02:00 (IANA zone names need the tz feature)",
+                s
+            )),
+        }
+    }
+}
+
+impl Timezone {
+    /// Format a datetime as an exported date: ISO 8601 with milliseconds and the zone designator
+    /// Wall clock times that a zone skips (a DST gap) get the offset in effect before the gap;
+    /// repeated ones the earlier offset.
+    pub fn format(&self, datetime: &NaiveDateTime) -> String {
+        const LOCAL: &str = "%Y-%m-%dT%H:%M:%S%.3f";
+        match (self, self.offset(datetime)) {
+            (Timezone::Naive, _) => datetime.format(LOCAL).to_string(),
+            (_, Some(offset)) if offset.local_minus_utc() != 0 => {
+                format!("{}{}", datetime.format(LOCAL), offset)
+            }
+            _ => format_iso_datetime(datetime),
+        }
+    }
+
+    /// The UTC time of a wall clock time in this zone; naive times are taken as UTC
+    pub fn to_utc(&self, datetime: &NaiveDateTime) -> NaiveDateTime {
+        match self.offset(datetime) {
+            Some(offset) => *datetime - TimeDelta::seconds(offset.local_minus_utc() as i64),
+            None => *datetime,
+        }
+    }
+
+    /// The offset of a wall clock time, None for Utc and Naive
+    #[cfg_attr(not(feature = "tz"), allow(unused_variables))]
+    fn offset(&self, datetime: &NaiveDateTime) -> Option<FixedOffset> {
+        match self {
+            Timezone::Utc | Timezone::Naive => None,
+            Timezone::Offset(offset) => Some(*offset),
+            #[cfg(feature = "tz")]
+            Timezone::Zone(zone) => {
+                let earlier = *datetime - TimeDelta::hours(3);
+                zone.offset_from_local_datetime(datetime)
+                    .earliest()
+                    .or_else(|| zone.offset_from_local_datetime(&earlier).earliest())
+                    .map(|offset| chrono::Offset::fix(&offset))
+            }
+        }
+    }
+}
+
+/// How dates are written
+/// Iso: ISO 8601 with the zone of Timezone, 2023-01-01T12:00:00.000Z (the default)
+/// UnixSeconds, UnixMillis: whole seconds or milliseconds since 1970-01-01T00:00:00Z of the
+///   date in its Timezone (naive dates are taken as UTC)
+/// Serial: the Excel serial number in the 1900 date system at full precision (45108.5), also
+///   for 1904 workbooks; dates before 1899-12-31, which have none, are written as Iso
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DateOutput {
+    #[default]
+    Iso,
+    UnixSeconds,
+    UnixMillis,
+    Serial,
+}
+
+impl FromStr for DateOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "iso" => Ok(Self::Iso),
+            "unix-seconds" => Ok(Self::UnixSeconds),
+            "unix-millis" => Ok(Self::UnixMillis),
+            "serial" => Ok(Self::Serial),
+            _ => Err(format!(
+                "invalid mode '{}', expected iso, unix-seconds, unix-millis or serial",
+                s
+            )),
+        }
+    }
+}
+
+impl DateOutput {
+    /// Write a datetime of a workbook in timezone
+    pub fn format(self, datetime: &NaiveDateTime, timezone: &Timezone) -> String {
+        match self {
+            DateOutput::Iso => timezone.format(datetime),
+            DateOutput::UnixSeconds => timezone.to_utc(datetime).and_utc().timestamp().to_string(),
+            DateOutput::UnixMillis => timezone
+                .to_utc(datetime)
+                .and_utc()
+                .timestamp_millis()
+                .to_string(),
+            DateOutput::Serial => match to_excel_serial(datetime) {
+                Some(serial) => serial.to_string(),
+                None => timezone.format(datetime),
+            },
+        }
+    }
+}
+
+/// Parse a UTC offset: +HH:MM, +HHMM or +HH (or with a -)
+fn parse_offset(s: &str) -> Option<FixedOffset> {
+    let sign = match s.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits = s[1..].replace(':', "");
+    if !matches!(digits.len(), 2 | 4) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits
+        .get(2..)
+        .filter(|m| !m.is_empty())
+        .map_or(Some(0), |m| m.parse().ok())?;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Split the zone designator (Z or a UTC offset) off an ISO 8601 datetime, e.g.
+/// "2023-01-01T12:00:00+02:00" -> ("2023-01-01T12:00:00", "+02:00"); dates alone have none
+pub(crate) fn split_zone(s: &str) -> (&str, &str) {
+    let Some(t) = s.find('T') else {
+        return (s, "");
+    };
+    match s[t..].rfind(['Z', '+', '-']) {
+        Some(i) => s.split_at(t + i),
+        None => (s, ""),
+    }
+}
+
+/// Parse an ISO 8601 date or datetime as stored in `t="d"` cells (e.g. by strict OOXML files)
+/// Accepts YYYY-MM-DD and YYYY-MM-DDTHH:MM:SS[.fff] with an optional `Z` or UTC offset suffix;
+/// the wall clock time is returned, as written with Timezone.
+pub fn parse_iso_datetime(s: &str) -> Option<NaiveDateTime> {
+    let (s, _) = split_zone(s);
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_date_systems() {
+        let iso = |system: &dyn DateSystem, serial: f64| {
+            system.to_datetime(serial).map(|d| format_iso_datetime(&d))
+        };
+        assert_eq!(
+            iso(&Excel1900, 44197.0).as_deref(),
+            Some("2021-01-01T00:00:00.000Z")
+        );
+        assert_eq!(
+            iso(&Excel1904, 0.5).as_deref(),
+            Some("1904-01-01T12:00:00.000Z")
+        );
+
+        let unix: CustomEpoch = "1970-01-01".parse().unwrap();
+        assert_eq!(
+            iso(&unix, 1.25).as_deref(),
+            Some("1970-01-02T06:00:00.000Z")
+        );
+        assert!("01/01/1970".parse::<CustomEpoch>().is_err());
+        assert_eq!(iso(&unix, f64::MAX), None);
+
+        let date = parse_iso_datetime("2021-01-01T12:00:00").unwrap();
+        assert_eq!(to_excel_serial(&date), Some(44197.5));
+        assert_eq!(
+            iso(&Excel1900, 44197.5).as_deref(),
+            Some("2021-01-01T12:00:00.000Z")
+        );
+        assert_eq!(
+            to_excel_serial(&parse_iso_datetime("1800-01-01").unwrap()),
+            None
+        );
+        // Before the phantom 1900-02-29 Excel's serials are a day lower
+        let serial = |s: &str| to_excel_serial(&parse_iso_datetime(s).unwrap());
+        assert_eq!(serial("1900-01-01"), Some(1.0));
+        assert_eq!(serial("1900-02-28T12:00:00"), Some(59.5));
+        assert_eq!(serial("1900-03-01"), Some(61.0));
+        assert_eq!(serial("1899-12-31"), Some(0.0));
+        assert_eq!(serial("1899-12-30"), None);
+        // and read back as the same day
+        for s in [0.0, 1.0, 59.5, 61.0] {
+            let date = Excel1900.to_datetime(s).unwrap();
+            assert_eq!(to_excel_serial(&date), Some(s));
+        }
+        assert_eq!(
+            iso(&Excel1900, 1.0).as_deref(),
+            Some("1900-01-01T00:00:00.000Z")
+        );
+    }
+
+    #[test]
+    fn test_timezones() {
+        let noon = parse_iso_datetime("2023-07-01T12:00:00").unwrap();
+        let format =
+            |tz: &str, datetime: &NaiveDateTime| tz.parse::<Timezone>().unwrap().format(datetime);
+        assert_eq!(format("utc", &noon), "2023-07-01T12:00:00.000Z");
+        assert_eq!(format("naive", &noon), "2023-07-01T12:00:00.000");
+        assert_eq!(format("+02:00", &noon), "2023-07-01T12:00:00.000+02:00");
+        assert_eq!(format("-0530", &noon), "2023-07-01T12:00:00.000-05:30");
+        assert_eq!(format("+00", &noon), "2023-07-01T12:00:00.000Z");
+        #[cfg(feature = "tz")]
+        {
+            assert_eq!(
+                format("Europe/Amsterdam", &noon),
+                "2023-07-01T12:00:00.000+02:00"
+            );
+            let winter = parse_iso_datetime("2023-01-01T12:00:00").unwrap();
+            assert_eq!(
+                format("Europe/Amsterdam", &winter),
+                "2023-01-01T12:00:00.000+01:00"
+            );
+            // 02:30 doesn't exist on the day clocks go forward
+            let gap = parse_iso_datetime("2023-03-26T02:30:00").unwrap();
+            assert_eq!(
+                format("Europe/Amsterdam", &gap),
+                "2023-03-26T02:30:00.000+01:00"
+            );
+        }
+        #[cfg(not(feature = "tz"))]
+        assert!("Europe/Amsterdam".parse::<Timezone>().is_err());
+        assert!("Mars/Olympus".parse::<Timezone>().is_err());
+        assert!("+25:00".parse::<Timezone>().is_err());
+
+        // Written dates read back as the same wall clock time
+        for written in ["2023-07-01T12:00:00.000+02:00", "2023-07-01T12:00:00.000"] {
+            assert_eq!(parse_iso_datetime(written), Some(noon));
+        }
+        assert_eq!(split_zone("2023-07-01"), ("2023-07-01", ""));
+        assert_eq!(
+            split_zone("2023-07-01T12:00:00-05:30"),
+            ("2023-07-01T12:00:00", "-05:30")
+        );
+    }
+
+    #[test]
+    fn test_date_outputs() {
+        let noon = parse_iso_datetime("2023-07-01T12:00:00.250").unwrap();
+        let plus_two: Timezone = "+02:00".parse().unwrap();
+        let format = |output: &str, timezone: &Timezone| {
+            output
+                .parse::<DateOutput>()
+                .unwrap()
+                .format(&noon, timezone)
+        };
+        assert_eq!(format("iso", &Timezone::Utc), "2023-07-01T12:00:00.250Z");
+        assert_eq!(format("unix-seconds", &Timezone::Utc), "1688212800");
+        assert_eq!(format("unix-millis", &Timezone::Naive), "1688212800250");
+        assert_eq!(format("unix-seconds", &plus_two), "1688205600");
+        // At full precision, the 250 ms included
+        assert_eq!(format("serial", &plus_two), "45108.50000289352");
+        let old = parse_iso_datetime("1850-01-01").unwrap();
+        assert_eq!(
+            DateOutput::Serial.format(&old, &Timezone::Utc),
+            "1850-01-01T00:00:00.000Z"
+        );
+        assert_eq!(
+            DateOutput::UnixSeconds.format(&old, &Timezone::Utc),
+            "-3786825600"
+        );
+        assert!("epoch".parse::<DateOutput>().is_err());
+    }
+
+    #[test]
+    fn test_leap_bug_policies() {
+        let iso = |policy: LeapBug, serial: f64| {
+            let system = WithLeapBug {
+                system: &Excel1900,
+                policy,
+            };
+            system.to_datetime(serial).map(|d| format_iso_datetime(&d))
+        };
+        assert_eq!(
+            iso(LeapBug::Feb28, 60.5).as_deref(),
+            Some("1900-02-28T12:00:00.000Z")
+        );
+        assert_eq!(
+            iso(LeapBug::Mar1, 60.5).as_deref(),
+            Some("1900-03-01T12:00:00.000Z")
+        );
+        for policy in [LeapBug::Feb28, LeapBug::Mar1] {
+            assert_eq!(
+                iso(policy, 59.0).as_deref(),
+                Some("1900-02-28T00:00:00.000Z")
+            );
+            assert_eq!(
+                iso(policy, 61.0).as_deref(),
+                Some("1900-03-01T00:00:00.000Z")
+            );
+        }
+        assert!(Excel1900.is_leap_bug_day(60.99));
+        assert!(!Excel1900.is_leap_bug_day(61.0));
+        assert!(!Excel1904.is_leap_bug_day(60.0));
+        assert_eq!("1900-03-01".parse(), Ok(LeapBug::Mar1));
+        assert!("1900-02-29".parse::<LeapBug>().is_err());
+    }
+}
@@ -0,0 +1,360 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use super::col_to_index;
+
+/// A row filter expression like `amount > 100 && status == 'open'`
+/// Operands are column names (bare words, or `backticks` for names with spaces), 'strings' or
+/// "strings", and numbers. Comparisons (== != < <= > >=) are numeric when both sides are numbers
+/// and textual when neither is; a number and text only compare unequal.
+/// Conditions combine with && (and), || (or), ! (not) and parentheses; a lone column is true
+/// when the cell is not empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowFilter {
+    expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, CompareOp, Operand),
+    NotEmpty(Operand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Column(String),
+    Literal(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Column(String),
+    Literal(String),
+    Op(&'static str),
+}
+
+impl FromStr for RowFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s).map_err(|e| format!("invalid filter '{}': {}", s, e))?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser
+            .or()
+            .and_then(|expr| match parser.tokens.get(parser.pos) {
+                None => Ok(expr),
+                Some(token) => Err(format!("unexpected {}", describe(token))),
+            })
+            .map_err(|e| format!("invalid filter '{}': {}", s, e))?;
+        Ok(Self { expr })
+    }
+}
+
+impl RowFilter {
+    /// Resolve the column names of the filter against a header row
+    /// Names match exactly, or else case-insensitively. With synthetic headers the columns are
+    /// the letters (or numbers) of the header row.
+    pub fn bind(&self, header: &[String]) -> Result<BoundFilter<'_>> {
+        let mut columns = HashMap::new();
+        let mut names = Vec::new();
+        self.expr.column_names(&mut names);
+        for name in names {
+            let index = find_column(header, name).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "unknown column '{}' in filter (columns: {})",
+                    name,
+                    header.join(", ")
+                )
+            })?;
+            columns.insert(name.to_string(), index);
+        }
+        Ok(BoundFilter {
+            filter: self,
+            columns,
+        })
+    }
+
+    /// Resolve the column names as column letters (A, B, ...) or 1-based numbers, for sheets
+    /// written with a synthetic header row
+    pub fn bind_positions(&self, letters: bool) -> Result<BoundFilter<'_>> {
+        let mut columns = HashMap::new();
+        let mut names = Vec::new();
+        self.expr.column_names(&mut names);
+        for name in names {
+            let index = column_position(name, letters)
+                .ok_or_else(|| anyhow::anyhow!("unknown column '{}' in filter", name))?;
+            columns.insert(name.to_string(), index);
+        }
+        Ok(BoundFilter {
+            filter: self,
+            columns,
+        })
+    }
+}
+
+/// The position of the header field called name, matched exactly or else case-insensitively
+pub(crate) fn find_column(header: &[String], name: &str) -> Option<usize> {
+    header
+        .iter()
+        .position(|h| h == name)
+        .or_else(|| header.iter().position(|h| h.eq_ignore_ascii_case(name)))
+}
+
+/// The 0-based position of a column named by its letters (A, b, AA) or 1-based number
+pub(crate) fn column_position(name: &str, letters: bool) -> Option<usize> {
+    let index = if letters {
+        col_to_index(&name.to_ascii_uppercase())
+    } else {
+        name.parse::<u32>().ok()
+    };
+    index.filter(|&i| i > 0).map(|i| i as usize - 1)
+}
+
+/// A RowFilter with its columns resolved to field positions
+#[derive(Debug, Clone)]
+pub struct BoundFilter<'a> {
+    filter: &'a RowFilter,
+    columns: HashMap<String, usize>,
+}
+
+impl BoundFilter<'_> {
+    /// Whether row passes the filter; missing fields are empty
+    pub fn matches(&self, row: &[String]) -> bool {
+        self.eval(&self.filter.expr, row)
+    }
+
+    fn eval(&self, expr: &Expr, row: &[String]) -> bool {
+        match expr {
+            Expr::Or(a, b) => self.eval(a, row) || self.eval(b, row),
+            Expr::And(a, b) => self.eval(a, row) && self.eval(b, row),
+            Expr::Not(e) => !self.eval(e, row),
+            Expr::NotEmpty(operand) => !self.value(operand, row).is_empty(),
+            Expr::Compare(a, op, b) => {
+                let (a, b) = (self.value(a, row), self.value(b, row));
+                let ordering = match (a.trim().parse::<f64>(), b.trim().parse::<f64>()) {
+                    (Ok(x), Ok(y)) => x.partial_cmp(&y),
+                    (Err(_), Err(_)) => Some(a.cmp(b)),
+                    // A number and text (or an empty cell) are only ever unequal
+                    _ => None,
+                };
+                let Some(o) = ordering else {
+                    return *op == CompareOp::Ne;
+                };
+                match op {
+                    CompareOp::Eq => o.is_eq(),
+                    CompareOp::Ne => o.is_ne(),
+                    CompareOp::Lt => o.is_lt(),
+                    CompareOp::Le => o.is_le(),
+                    CompareOp::Gt => o.is_gt(),
+                    CompareOp::Ge => o.is_ge(),
+                }
+            }
+        }
+    }
+
+    fn value<'r>(&self, operand: &'r Operand, row: &'r [String]) -> &'r str {
+        match operand {
+            Operand::Literal(s) => s,
+            Operand::Column(name) => self
+                .columns
+                .get(name)
+                .and_then(|&i| row.get(i))
+                .map_or("", String::as_str),
+        }
+    }
+}
+
+impl Expr {
+    fn column_names<'a>(&'a self, names: &mut Vec<&'a str>) {
+        let mut operand = |o: &'a Operand| {
+            if let Operand::Column(name) = o {
+                names.push(name);
+            }
+        };
+        match self {
+            Expr::Or(a, b) | Expr::And(a, b) => {
+                a.column_names(names);
+                b.column_names(names);
+            }
+            Expr::Not(e) => e.column_names(names),
+            Expr::NotEmpty(o) => operand(o),
+            Expr::Compare(a, _, b) => {
+                operand(a);
+                operand(b);
+            }
+        }
+    }
+}
+
+/// Operators, longest first so that e.g. "<=" wins over "<"
+const OPERATORS: &[&str] = &[
+    "&&", "||", "==", "!=", "<=", ">=", "<", ">", "!", "(", ")", "=",
+];
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            // A single '=' reads as '=='
+            tokens.push(Token::Op(if *op == "=" { "==" } else { op }));
+            rest = &rest[op.len()..];
+        } else if c == '\'' || c == '"' || c == '`' {
+            let end = rest[1..]
+                .find(c)
+                .ok_or_else(|| format!("unterminated {}", c))?;
+            let text = rest[1..end + 1].to_string();
+            tokens.push(if c == '`' {
+                Token::Column(text)
+            } else {
+                Token::Literal(text)
+            });
+            rest = &rest[end + 2..];
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || "&|=!<>()'\"`".contains(c))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return Err(format!("unexpected '{}'", c));
+            }
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(w) => format!("'{}'", w),
+        Token::Column(c) => format!("`{}`", c),
+        Token::Literal(l) => format!("'{}'", l),
+        Token::Op(op) => format!("'{}'", op),
+    }
+}
+
+/// Recursive descent over the tokens: or > and > not > comparison
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_is(&self, op: &str, keyword: &str) -> bool {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(o)) => *o == op,
+            Some(Token::Word(w)) => w.eq_ignore_ascii_case(keyword),
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.peek_is("||", "or") {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.peek_is("&&", "and") {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.peek_is("!", "not") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        if self.peek_is("(", "") {
+            self.pos += 1;
+            let expr = self.or()?;
+            if !self.peek_is(")", "") {
+                return Err("missing ')'".to_string());
+            }
+            self.pos += 1;
+            return Ok(expr);
+        }
+        let left = self.operand()?;
+        let op = match self.tokens.get(self.pos) {
+            Some(Token::Op("==")) => CompareOp::Eq,
+            Some(Token::Op("!=")) => CompareOp::Ne,
+            Some(Token::Op("<")) => CompareOp::Lt,
+            Some(Token::Op("<=")) => CompareOp::Le,
+            Some(Token::Op(">")) => CompareOp::Gt,
+            Some(Token::Op(">=")) => CompareOp::Ge,
+            _ => return Ok(Expr::NotEmpty(left)),
+        };
+        self.pos += 1;
+        Ok(Expr::Compare(left, op, self.operand()?))
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Column(name)) => Ok(Operand::Column(name)),
+            Some(Token::Literal(text)) => Ok(Operand::Literal(text)),
+            Some(Token::Word(word)) if word.parse::<f64>().is_ok() => Ok(Operand::Literal(word)),
+            Some(Token::Word(word)) => Ok(Operand::Column(word)),
+            Some(token) => Err(format!("unexpected {}", describe(&token))),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(fields: &[&str]) -> Vec<String> {
+        fields.iter().map(|f| f.to_string()).collect()
+    }
+
+    #[test]
+    fn test_row_filter() {
+        let header = row(&["amount", "status", "Due Date"]);
+        let filter: RowFilter = "amount > 100 && (status == 'open' or not `due date`)"
+            .parse()
+            .unwrap();
+        let bound = filter.bind(&header).unwrap();
+        assert!(bound.matches(&row(&["150", "open", "2024-01-01"])));
+        assert!(bound.matches(&row(&["150.5", "closed"])));
+        assert!(!bound.matches(&row(&["99", "open"])));
+        assert!(!bound.matches(&row(&["1e3", "closed", "2024-01-01"])));
+        assert!(!bound.matches(&row(&["", "open"])));
+        assert!(!bound.matches(&row(&["n/a", "open"])));
+
+        assert!(
+            "unknown > 1"
+                .parse::<RowFilter>()
+                .unwrap()
+                .bind(&header)
+                .is_err()
+        );
+        assert!("amount >".parse::<RowFilter>().is_err());
+        assert!("(amount".parse::<RowFilter>().is_err());
+        assert!("status == 'open".parse::<RowFilter>().is_err());
+    }
+}
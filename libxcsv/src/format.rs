@@ -0,0 +1,143 @@
+use std::str::FromStr;
+
+/// File format of exported sheets
+/// Csv: delimited text (the default)
+/// Markdown: a GitHub flavored Markdown table; the first row becomes the table header
+/// Html: an HTML `<table>`; the first row is written as `<thead>`
+/// PgCopy: PostgreSQL's text COPY format, tab separated with `\N` for cells without a value;
+///   the first row only names the columns and is not written
+/// CellsJson: JSON lines with one record per cell holding a value or a formula, not a table:
+///   its reference, position, raw and decoded value, type, style, format code and formula
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    Markdown,
+    Html,
+    PgCopy,
+    CellsJson,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "markdown" | "md" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            "pgcopy" => Ok(Self::PgCopy),
+            "cells-json" => Ok(Self::CellsJson),
+            _ => Err(format!(
+                "invalid format '{}', expected csv, markdown, html, pgcopy or cells-json",
+                s
+            )),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// File extension (without the dot), e.g. "md" for sheet.md
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Markdown => "md",
+            OutputFormat::Html => "html",
+            OutputFormat::PgCopy => "tsv",
+            OutputFormat::CellsJson => "jsonl",
+        }
+    }
+}
+
+/// A Markdown table row, padded with empty cells to width; header additionally writes the
+/// `| --- |` separator line below it
+/// Pipes are escaped and line breaks become `<br>`, so every row stays on one line.
+pub(crate) fn markdown_row(fields: &[String], width: usize, header: bool) -> String {
+    let mut line = String::from("|");
+    for field in fields {
+        line.push(' ');
+        line.push_str(
+            &field
+                .replace('\\', "\\\\")
+                .replace('|', "\\|")
+                .replace("\r\n", "<br>")
+                .replace(['\r', '\n'], "<br>"),
+        );
+        line.push_str(" |");
+    }
+    for _ in fields.len()..width.max(1) {
+        line.push_str("  |");
+    }
+    line.push('\n');
+    if header {
+        line.push('|');
+        for _ in 0..fields.len().max(width).max(1) {
+            line.push_str(" --- |");
+        }
+        line.push('\n');
+    }
+    line
+}
+
+/// A line of PostgreSQL text COPY data; fields flagged in nulls are written as `\N`
+/// Backslashes, tabs and line breaks are escaped so they survive the load.
+pub(crate) fn pgcopy_row(fields: &[String], nulls: &[bool]) -> String {
+    let mut line = String::new();
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            line.push('\t');
+        }
+        if nulls.get(i).copied().unwrap_or(false) {
+            line.push_str("\\N");
+            continue;
+        }
+        for c in field.chars() {
+            match c {
+                '\\' => line.push_str("\\\\"),
+                '\t' => line.push_str("\\t"),
+                '\n' => line.push_str("\\n"),
+                '\r' => line.push_str("\\r"),
+                c => line.push(c),
+            }
+        }
+    }
+    line.push('\n');
+    line
+}
+
+/// Closes the table opened by the first html_row
+pub(crate) const HTML_END: &str = "</tbody>\n</table>\n";
+
+/// An HTML table row; header opens the table and writes the row as `<thead>`
+pub(crate) fn html_row(fields: &[String], header: bool) -> String {
+    let cell = if header { "th" } else { "td" };
+    let mut row = String::from("<tr>");
+    for field in fields {
+        row.push_str(&format!(
+            "<{}>{}</{}>",
+            cell,
+            escape_html(field).replace('\n', "<br>"),
+            cell
+        ));
+    }
+    row.push_str("</tr>\n");
+    if header {
+        format!("<table>\n<thead>\n{}</thead>\n<tbody>\n", row)
+    } else {
+        row
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,174 @@
+use crate::{col_to_index, index_to_col};
+
+/// Last row and column a reference can point to
+const MAX_ROW: i64 = 1_048_576;
+const MAX_COL: i64 = 16_384;
+
+/// Move the relative references of formula by rows and cols, as Excel does when it fills a
+/// shared formula from its first cell into the others
+/// References fixed with $ keep their row or column; string literals, quoted sheet names,
+/// structured references ([...]) and function names are left alone. A reference moved off the
+/// sheet becomes #REF!.
+/// Examples:
+///   ("SUM(A1:B2)*$C$1", 1, 1) -> "SUM(B2:C3)*$C$1"
+///   ("'My Sheet'!A$1&\"A1\"", 2, 0) -> "'My Sheet'!A$1&\"A1\""
+pub fn translate_formula(formula: &str, rows: i64, cols: i64) -> String {
+    let chars: Vec<char> = formula.chars().collect();
+    let mut out = String::with_capacity(formula.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '"' | '\'' => {
+                // Copy the literal or quoted name; a doubled quote is an escaped one
+                let start = i;
+                i += 1;
+                while i < chars.len() {
+                    if chars[i] == c && chars.get(i + 1) == Some(&c) {
+                        i += 2;
+                    } else if chars[i] == c {
+                        i += 1;
+                        break;
+                    } else {
+                        i += 1;
+                    }
+                }
+                out.extend(&chars[start..i]);
+            }
+            '[' => {
+                let start = i;
+                let mut depth = 0;
+                while i < chars.len() {
+                    match chars[i] {
+                        '[' => depth += 1,
+                        ']' => depth -= 1,
+                        _ => {}
+                    }
+                    i += 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                out.extend(&chars[start..i]);
+            }
+            c if is_word_char(c) => {
+                let start = i;
+                while i < chars.len() && is_word_char(chars[i]) {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                let next = chars.get(i).copied();
+                let in_range = next == Some(':') || (start > 0 && chars[start - 1] == ':');
+                let moved = match next {
+                    // A function or sheet name
+                    Some('(') | Some('!') => None,
+                    _ => translate_reference(&word, rows, cols, in_range),
+                };
+                out.push_str(moved.as_deref().unwrap_or(&word));
+            }
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '$' | '_' | '.' | '\\')
+}
+
+/// The moved reference if word is a cell (A1), or with in_range a column (A) or row (1) of a
+/// range like A:C or 1:3; None for any other word
+fn translate_reference(word: &str, rows: i64, cols: i64, in_range: bool) -> Option<String> {
+    let (mut col_fixed, rest) = match word.strip_prefix('$') {
+        Some(rest) => (true, rest),
+        None => (false, word),
+    };
+    let letters = rest.chars().take_while(char::is_ascii_alphabetic).count();
+    let (col, rest) = rest.split_at(letters);
+    let (mut row_fixed, row) = match rest.strip_prefix('$') {
+        Some(row) => (true, row),
+        None => (false, rest),
+    };
+    // In a row range like $2:$3 the $ fixes the row
+    if col.is_empty() && col_fixed {
+        if row_fixed {
+            return None;
+        }
+        (col_fixed, row_fixed) = (false, true);
+    }
+    if !row.bytes().all(|b| b.is_ascii_digit()) || letters > 3 {
+        return None;
+    }
+    // A column beyond XFD makes the word a name rather than a reference
+    let col = match col {
+        "" => None,
+        col => Some(i64::from(col_to_index(&col.to_ascii_uppercase())?)),
+    };
+    let row = (!row.is_empty()).then(|| row.parse::<i64>().ok()).flatten();
+    // A whole column or row is only a reference as part of a range
+    let whole = col.is_none() || row.is_none();
+    if (col.is_none() && row.is_none()) || (whole && !in_range) {
+        return None;
+    }
+    if col.is_some_and(|c| c > MAX_COL) || row.is_some_and(|r| r == 0 || r > MAX_ROW) {
+        return None;
+    }
+    let col = col.map(|c| if col_fixed { c } else { c + cols });
+    let row = row.map(|r| if row_fixed { r } else { r + rows });
+    if col.is_some_and(|c| !(1..=MAX_COL).contains(&c))
+        || row.is_some_and(|r| !(1..=MAX_ROW).contains(&r))
+    {
+        return Some("#REF!".to_string());
+    }
+    let mut moved = String::new();
+    if let Some(col) = col {
+        if col_fixed {
+            moved.push('$');
+        }
+        moved.push_str(&index_to_col(col as u32));
+    }
+    if let Some(row) = row {
+        if row_fixed {
+            moved.push('$');
+        }
+        moved.push_str(&row.to_string());
+    }
+    Some(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate_formula() {
+        for (formula, rows, cols, expected) in [
+            ("SUM(A1:B2)*$C$1", 1, 1, "SUM(B2:C3)*$C$1"),
+            ("A$1+$A1", 2, 3, "D$1+$A3"),
+            (
+                "'My Sheet'!A1&\"A1\"&Sheet2!B2",
+                1,
+                0,
+                "'My Sheet'!A2&\"A1\"&Sheet2!B3",
+            ),
+            (
+                "SUM(A:$A)+SUM($2:3)+LOG10(A1)",
+                1,
+                1,
+                "SUM(B:$A)+SUM($2:4)+LOG10(B2)",
+            ),
+            ("Table1[Col1]*rate+TRUE", 1, 1, "Table1[Col1]*rate+TRUE"),
+            ("A1-1.5E3", -1, 0, "#REF!-1.5E3"),
+        ] {
+            assert_eq!(
+                translate_formula(formula, rows, cols),
+                expected,
+                "{}",
+                formula
+            );
+        }
+    }
+}
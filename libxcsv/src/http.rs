@@ -0,0 +1,132 @@
+use anyhow::{Context, Result};
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use std::io::{self, Read};
+use std::ops::Range;
+
+use crate::input::{SPOOL_MEMORY_LIMIT, WorkbookReader, spool_reader};
+use crate::remote::{RangeSource, RemoteReader};
+
+/// A workbook on a web server, read with HTTP range requests
+#[derive(Debug)]
+struct HttpSource {
+    client: Client,
+    url: String,
+}
+
+/// Open the workbook at an http(s) URL
+/// Servers answering the first range request with 206 Partial Content are read with a
+/// RemoteReader; the body of any other successful response is downloaded like stdin.
+pub fn open_url(url: &str) -> Result<WorkbookReader> {
+    let client = Client::new();
+    let response = get(&client, url, 0, 0)?;
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        tracing::debug!("{} does not support range requests, downloading it", url);
+        return spool_reader(response, SPOOL_MEMORY_LIMIT);
+    }
+    let len = response
+        .headers()
+        .get(CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit_once('/'))
+        .and_then(|(_, total)| total.parse::<u64>().ok())
+        .with_context(|| format!("{} sent no Content-Range length", url))?;
+    let source = HttpSource {
+        client,
+        url: url.to_string(),
+    };
+    Ok(WorkbookReader::Remote(RemoteReader::new(
+        Box::new(source),
+        len,
+    )))
+}
+
+/// GET bytes start..=end of url, failing on an error status
+fn get(client: &Client, url: &str, start: u64, end: u64) -> Result<Response> {
+    client
+        .get(url)
+        .header(RANGE, format!("bytes={}-{}", start, end))
+        .send()
+        .and_then(Response::error_for_status)
+        .with_context(|| format!("Failed to fetch {}", url))
+}
+
+impl RangeSource for HttpSource {
+    fn fetch(&self, range: Range<u64>) -> io::Result<Vec<u8>> {
+        let mut response =
+            get(&self.client, &self.url, range.start, range.end - 1).map_err(io::Error::other)?;
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(io::Error::other(format!(
+                "{} stopped answering range requests",
+                self.url
+            )));
+        }
+        let mut data = Vec::with_capacity((range.end - range.start) as usize);
+        response.read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+    use std::net::TcpListener;
+
+    /// Serve data on a local port, answering Range requests, and return its URL
+    fn serve(data: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/book.xlsx", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut range = None;
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(r) = line.to_lowercase().strip_prefix("range: bytes=") {
+                        let (start, end) = r.split_once('-').unwrap();
+                        range = Some((
+                            start.parse::<usize>().unwrap(),
+                            end.parse::<usize>().unwrap(),
+                        ));
+                    }
+                }
+                let (start, end) = range.unwrap();
+                write!(
+                    stream,
+                    "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    start,
+                    end,
+                    data.len(),
+                    end - start + 1
+                )
+                .unwrap();
+                stream.write_all(&data[start..=end]).unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_open_url_reads_ranges() {
+        let data: Vec<u8> = (0..=255).cycle().take(1024 * 1024 + 100).collect();
+        let WorkbookReader::Remote(mut reader) = open_url(&serve(data.clone())).unwrap() else {
+            panic!("expected range requests to be used");
+        };
+        assert_eq!(reader.len(), data.len() as u64);
+
+        reader.seek(SeekFrom::End(-150)).unwrap();
+        let mut tail = Vec::new();
+        reader.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, data[data.len() - 150..]);
+
+        reader.seek(SeekFrom::Start(10)).unwrap();
+        let mut head = [0; 20];
+        reader.read_exact(&mut head).unwrap();
+        assert_eq!(head, data[10..30]);
+    }
+}
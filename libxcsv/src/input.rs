@@ -0,0 +1,190 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Inputs up to this size are kept in memory, larger ones are spooled to a temp file
+pub const SPOOL_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+/// A seekable workbook source: a file on disk, or a non-seekable stream (stdin)
+/// that has been buffered in memory or spooled to an anonymous temp file.
+/// With the http or object-store feature, also a workbook on a web server or in an object store
+/// read with range requests. With the mmap feature, a file mapped into memory.
+#[derive(Debug)]
+pub enum WorkbookReader {
+    File(BufReader<File>),
+    Memory(Cursor<Vec<u8>>),
+    #[cfg(feature = "mmap")]
+    Mapped(Cursor<memmap2::Mmap>),
+    #[cfg(any(feature = "http", feature = "object-store"))]
+    Remote(crate::remote::RemoteReader),
+}
+
+impl Read for WorkbookReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            WorkbookReader::File(r) => r.read(buf),
+            WorkbookReader::Memory(r) => r.read(buf),
+            #[cfg(feature = "mmap")]
+            WorkbookReader::Mapped(r) => r.read(buf),
+            #[cfg(any(feature = "http", feature = "object-store"))]
+            WorkbookReader::Remote(r) => r.read(buf),
+        }
+    }
+}
+
+impl Seek for WorkbookReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            WorkbookReader::File(r) => r.seek(pos),
+            WorkbookReader::Memory(r) => r.seek(pos),
+            #[cfg(feature = "mmap")]
+            WorkbookReader::Mapped(r) => r.seek(pos),
+            #[cfg(any(feature = "http", feature = "object-store"))]
+            WorkbookReader::Remote(r) => r.seek(pos),
+        }
+    }
+}
+
+/// Make a stream seekable so it can be read as a zip archive
+/// Up to memory_limit bytes are buffered in memory; anything larger is copied
+/// to a temp file that is removed when the reader is dropped.
+pub fn spool_reader<R: Read>(mut reader: R, memory_limit: usize) -> Result<WorkbookReader> {
+    let mut head = Vec::new();
+    reader
+        .by_ref()
+        .take(memory_limit as u64 + 1)
+        .read_to_end(&mut head)
+        .context("Failed to read input")?;
+    if head.len() <= memory_limit {
+        return Ok(WorkbookReader::Memory(Cursor::new(head)));
+    }
+
+    let mut file = tempfile::tempfile().context("Failed to create temp file for input")?;
+    file.write_all(&head)
+        .and_then(|_| io::copy(&mut reader, &mut file))
+        .context("Failed to spool input to temp file")?;
+    file.rewind()?;
+    Ok(WorkbookReader::File(BufReader::new(file)))
+}
+
+/// Map the workbook file at path into memory, so reads come from the page cache without a
+/// read call each; needs the mmap feature
+/// Reading goes through a Cursor, so the bytes are still copied out of the map into the zip
+/// reader's buffers; what is saved is the system calls and the BufReader copy.
+/// The file must not be changed or truncated while it is mapped.
+pub fn map_file(path: &Path) -> Result<WorkbookReader> {
+    #[cfg(feature = "mmap")]
+    {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        // SAFETY: memmap2 needs the file to stay unchanged while it is mapped: a concurrent
+        // write changes bytes behind the slice, a truncation makes reading past the new end
+        // fault. Nothing here can rule that out, so mapping is opt-in (the mmap feature, then
+        // open_zip_mmap) and documented as requiring a file nobody else writes.
+        let map = unsafe { memmap2::Mmap::map(&file) }
+            .with_context(|| format!("Failed to map {}", path.display()))?;
+        Ok(WorkbookReader::Mapped(Cursor::new(map)))
+    }
+    #[cfg(not(feature = "mmap"))]
+    anyhow::bail!(
+        "Cannot map {}: memory-mapping needs libxcsv built with the mmap feature",
+        path.display()
+    )
+}
+
+/// Whether an input path is an http(s) URL rather than a file
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Open the workbook at an http(s) URL; needs the http feature
+pub fn open_url(url: &str) -> Result<WorkbookReader> {
+    #[cfg(feature = "http")]
+    return crate::http::open_url(url);
+    #[cfg(not(feature = "http"))]
+    anyhow::bail!(
+        "Cannot read {}: reading URLs needs xcsv built with the http feature",
+        url
+    )
+}
+
+/// Whether an input or output path is an object store URL: s3://bucket/key, gs://..., az://...
+/// or abfs(s)://...
+pub fn is_object_url(path: &str) -> bool {
+    [
+        "s3://", "s3a://", "gs://", "az://", "azure://", "abfs://", "abfss://",
+    ]
+    .iter()
+    .any(|scheme| path.starts_with(scheme))
+}
+
+/// The URL of a file below an object store prefix, e.g. (s3://bucket/out/, a/b.csv) ->
+/// s3://bucket/out/a/b.csv
+pub fn object_url(prefix: &str, relative: &Path) -> String {
+    let mut url = prefix.trim_end_matches('/').to_string();
+    for part in relative {
+        url.push('/');
+        url.push_str(&part.to_string_lossy());
+    }
+    url
+}
+
+/// Open the workbook at an object store URL; needs the object-store feature
+pub fn open_object(url: &str) -> Result<WorkbookReader> {
+    #[cfg(feature = "object-store")]
+    return crate::object::open_object(url);
+    #[cfg(not(feature = "object-store"))]
+    anyhow::bail!(
+        "Cannot read {}: object store URLs need xcsv built with the object-store feature",
+        url
+    )
+}
+
+/// Upload the files under dir to an object store prefix; needs the object-store feature
+pub fn upload_dir(dir: &Path, url: &str) -> Result<Vec<String>> {
+    #[cfg(feature = "object-store")]
+    return crate::object::upload_dir(dir, url);
+    #[cfg(not(feature = "object-store"))]
+    anyhow::bail!(
+        "Cannot upload {} to {}: object store URLs need xcsv built with the object-store feature",
+        dir.display(),
+        url
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spool_reader_switches_to_file() {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+
+        let mut small = spool_reader(data.as_slice(), 4096).unwrap();
+        assert!(matches!(small, WorkbookReader::Memory(_)));
+        let mut out = Vec::new();
+        small.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+
+        let mut large = spool_reader(data.as_slice(), 100).unwrap();
+        assert!(matches!(large, WorkbookReader::File(_)));
+        large.seek(SeekFrom::Start(10)).unwrap();
+        out.clear();
+        large.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data[10..]);
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn test_map_file_reads_and_seeks() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"PK0123456789").unwrap();
+        let mut mapped = map_file(file.path()).unwrap();
+        assert!(matches!(mapped, WorkbookReader::Mapped(_)));
+        mapped.seek(SeekFrom::End(-4)).unwrap();
+        let mut out = String::new();
+        mapped.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "6789");
+    }
+}
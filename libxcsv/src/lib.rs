@@ -1,54 +1,831 @@
 use anyhow::{Context, Result};
-use chrono;
 
-use quick_xml::events::Event;
-use quick_xml::reader::Reader;
-use std::collections::BTreeMap;
+use quick_xml::events::attributes::Attribute;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::{NsReader, Reader};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::io::{BufRead, BufReader, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use zip::ZipArchive;
+use zip::result::ZipError;
+
+mod cells;
+mod coerce;
+mod colors;
+mod columns;
+mod dates;
+mod filter;
+mod format;
+mod formula;
+#[cfg(feature = "http")]
+mod http;
+mod input;
+mod mapping;
+mod memory;
+mod naming;
+mod ns;
+mod numfmt;
+#[cfg(feature = "object-store")]
+mod object;
+mod output;
+mod package;
+mod pipeline;
+#[cfg(any(feature = "http", feature = "object-store"))]
+mod remote;
+mod repair;
+mod salvage;
+mod sample;
+mod schema;
+mod sink;
+mod sniff;
+mod sst;
+mod tables;
+mod units;
+mod validate;
+mod validations;
+mod workbook;
+mod writer;
+
+use colors::{parse_color, parse_rgb};
+use dates::WithLeapBug;
+use filter::{column_position, find_column};
+use format::{HTML_END, html_row, markdown_row, pgcopy_row};
+use ns::{OFFICE_RELATIONSHIPS, SPREADSHEETML, in_namespace, read_event};
+use pipeline::read_sheet_pipelined;
+use repair::{
+    WorkbookSheet, add_unclaimed_worksheets, assign_orphan_sheets, read_workbook_rels_lenient,
+};
+use sample::Reservoir;
+use units::{place_units, unit_columns};
+
+pub use cells::{FormulaCell, for_each_formula};
+pub use coerce::{BoundColumnTypes, CoercionError, ColumnTypes, TargetType};
+pub use colors::{Color, ColorBase, ThemeColors, parse_theme};
+pub use columns::{Column, ColumnData};
+pub use dates::{
+    CustomEpoch, DateOutput, DateSystem, Excel1900, Excel1904, LeapBug, Timezone,
+    format_iso_datetime, parse_iso_datetime, to_excel_serial,
+};
+pub use filter::{BoundFilter, RowFilter};
+pub use format::OutputFormat;
+pub use formula::translate_formula;
+pub use input::{
+    SPOOL_MEMORY_LIMIT, WorkbookReader, is_object_url, is_url, map_file, object_url, open_object,
+    open_url, spool_reader, upload_dir,
+};
+pub use mapping::{
+    BoundMapping, ColumnMapping, ColumnRename, RenameTo, parse_column_mapping, snake_case,
+};
+pub use memory::convert_xlsx_bytes_to_csv;
+pub use naming::{DEFAULT_NAME_TEMPLATE, NameTemplate};
+pub use numfmt::{
+    DateToken, FormatLocale, GeneralPrecision, NumberLocale, NumberOutput, builtin_format_code,
+    format_general, format_number, is_date_format, parse_date_format, parse_date_text,
+    parse_format_locale, window_year,
+};
+pub use output::{Compression, OutputWriter, part_path};
+pub use package::{
+    PackageParts, Relationship, parse_content_types, parse_relationships, read_package_parts,
+    read_strings_and_styles, rels_part_name, resolve_target, write_pretty_xml,
+};
+#[cfg(any(feature = "http", feature = "object-store"))]
+pub use remote::RemoteReader;
+pub use repair::repair_workbook_rels;
+pub use salvage::{SalvagedPart, salvage_zip};
+pub use schema::{
+    ColumnSchema, ColumnType, DominantFormat, SchemaBuilder, SheetSchema, column_names,
+    infer_sheet_schema,
+};
+pub use sink::{CellContext, CellTransform, CellValue, RowSink, export_sheet_to_sink};
+pub use sniff::{InputFormat, sniff_format};
+pub use sst::{SharedStrings, StringTable, read_shared_strings, read_shared_strings_within};
+pub use tables::find_header_row;
+pub use units::{Unit, UnitOutput, format_unit};
+pub use validate::{ValidationIssue, validate_workbook};
+pub use validations::{DataValidation, list_values, read_data_validations};
+pub use workbook::{Sheet, Workbook};
+pub use writer::WorkbookWriter;
 
 /// Information about a sheet in the workbook
 /// name: The name of the sheet
 /// path_in_zip: The path to the sheet XML inside the zip archive
-#[derive(Debug, Clone)]
+/// sheet_id: The sheetId attribute of the sheet, if present
+/// state: The visibility of the sheet tab
+/// kind: What kind of sheet part the sheet points at (worksheet, chartsheet, ...)
+#[derive(Debug, Clone, Serialize)]
 pub struct SheetInfo {
     pub name: String,
     pub path_in_zip: String,
+    pub sheet_id: Option<u32>,
+    pub state: SheetState,
+    pub kind: SheetKind,
+}
+
+/// Visibility of a sheet tab, from the state attribute in workbook.xml
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SheetState {
+    #[default]
+    Visible,
+    Hidden,
+    VeryHidden,
+}
+
+/// The kind of part a sheet points at, from its relationship type in workbook.xml.rels
+/// Only worksheets hold cell data; the other kinds are exported as nothing or a placeholder.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SheetKind {
+    #[default]
+    Worksheet,
+    Chartsheet,
+    Dialogsheet,
+    Macrosheet,
+}
+
+impl SheetKind {
+    /// The sheet kind for a relationship Type, or None if it doesn't point at a sheet
+    /// Compared by the last URI segment, so transitional and strict type URIs both match.
+    pub fn from_relationship_type(rel_type: &str) -> Option<Self> {
+        match rel_type.rsplit('/').next()? {
+            "worksheet" => Some(SheetKind::Worksheet),
+            "chartsheet" => Some(SheetKind::Chartsheet),
+            "dialogsheet" => Some(SheetKind::Dialogsheet),
+            "xlMacrosheet" | "xlIntlMacrosheet" => Some(SheetKind::Macrosheet),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SheetKind::Worksheet => "worksheet",
+            SheetKind::Chartsheet => "chartsheet",
+            SheetKind::Dialogsheet => "dialogsheet",
+            SheetKind::Macrosheet => "macrosheet",
+        }
+    }
+}
+
+impl std::fmt::Display for SheetKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Metadata from the head of a worksheet part (everything before `<sheetData>`)
+/// dimension: The used range of the sheet (e.g. "A1:Q50000"), if declared
+/// tab_color: The sheet tab color as "#RRGGBB", or "theme:N" / "indexed:N" for palette colors
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SheetMeta {
+    pub dimension: Option<String>,
+    pub tab_color: Option<String>,
+}
+
+impl SheetMeta {
+    /// Number of rows and columns covered by the dimension, if declared
+    pub fn dimension_size(&self) -> Option<(u32, u32)> {
+        let (start, end) = parse_range_ref(self.dimension.as_deref()?)?;
+        Some((
+            end.row.saturating_sub(start.row) + 1,
+            end.col.saturating_sub(start.col) + 1,
+        ))
+    }
+
+    /// The last column (1-based) covered by the dimension, if declared
+    pub fn last_column(&self) -> Option<u32> {
+        parse_range_ref(self.dimension.as_deref()?).map(|(_, end)| end.col)
+    }
+
+    /// Estimated number of cells (rows x columns) covered by the dimension, if declared
+    pub fn cell_count(&self) -> Option<u64> {
+        self.dimension_size()
+            .map(|(rows, cols)| rows as u64 * cols as u64)
+    }
 }
 
 /// Information about a cell style
+/// num_fmt_id: The numFmtId of the cell format (0 = General)
+/// format_code: The format code for num_fmt_id, from `<numFmts>` or the built-in table;
+///   None for unknown ids and locale dependent built-ins
+/// is_date: the number format shows a date and/or time
+/// is_text: the number format is Text (`@`); the stored value is written as is
+/// fill: The color of the cell's fill (the foreground of its pattern), if it has one
+/// font_color: The color of the cell's font, None for the automatic color
 #[derive(Debug, Clone, Default)]
 pub struct StyleInfo {
+    pub num_fmt_id: u32,
+    pub format_code: Option<String>,
     pub is_date: bool,
+    pub is_text: bool,
+    pub fill: Option<Color>,
+    pub font_color: Option<Color>,
+}
+
+/// Number of columns of an Excel worksheet (A to XFD); cells beyond it are invalid
+pub const MAX_COLUMNS: u32 = 16_384;
+
+/// Number of rows of an Excel worksheet; rows beyond it are invalid
+pub const MAX_ROWS: u32 = 1_048_576;
+
+/// Progress of a sheet export, reported through ExportOptions::progress
+/// rows_written: number of CSV rows written so far
+/// bytes_read: number of (uncompressed) sheet XML bytes consumed so far
+/// estimated_rows: last row of the sheet's `<dimension>`, if the sheet declares one
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExportProgress {
+    pub rows_written: u64,
+    pub bytes_read: u64,
+    pub estimated_rows: Option<u32>,
+}
+
+impl ExportProgress {
+    /// Percentage of estimated_rows written so far (capped at 100), if the sheet declares a dimension
+    pub fn percent(&self) -> Option<f64> {
+        let total = self.estimated_rows.filter(|&rows| rows > 0)?;
+        Some((self.rows_written as f64 / total as f64 * 100.0).min(100.0))
+    }
+}
+
+/// Callback invoked with the current ExportProgress after every written row
+/// Shared by the clones of the ExportOptions holding it.
+pub type ProgressCallback = Arc<dyn Fn(&ExportProgress) + Send + Sync>;
+
+/// Kind of a non-fatal problem found while exporting a sheet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WarningKind {
+    /// A `t="s"` cell whose shared string index is invalid or out of range
+    MissingSharedString,
+    /// A cell or row reference beyond the worksheet limits (e.g. column past XFD); the cell is skipped
+    InvalidReference,
+    /// A value that does not fit the type given for its column in column_types; the row is left out
+    TypeMismatch,
+}
+
+impl WarningKind {
+    /// Stable machine-readable code of the warning
+    pub fn code(&self) -> &'static str {
+        match self {
+            WarningKind::MissingSharedString => "missing-shared-string",
+            WarningKind::InvalidReference => "invalid-reference",
+            WarningKind::TypeMismatch => "type-mismatch",
+        }
+    }
+}
+
+/// Kind of a cell read from a sheet, counted in ExportSummary::cells
+/// Numbers with a date format count as dates, numbers with the text (@) format as text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CellKind {
+    Number,
+    Date,
+    Text,
+    Bool,
+    Error,
+    /// A cell without a value, e.g. one that only carries formatting
+    Empty,
+}
+
+/// A non-fatal problem found while exporting a sheet
+/// kind: What went wrong
+/// cell: The cell reference (e.g. "B7") the warning refers to, if any
+/// message: Human readable description
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportWarning {
+    pub kind: WarningKind,
+    pub cell: Option<String>,
+    pub message: String,
+}
+
+/// Callback invoked for every ExportWarning as it happens
+/// Shared by the clones of the ExportOptions holding it.
+pub type WarningCallback = Arc<dyn Fn(&ExportWarning) + Send + Sync>;
+
+/// Result of a sheet export
+/// rows_written: number of CSV rows written
+/// files: the files written, in order; several part files with split_rows/split_size
+/// schema: with the pgcopy format, the schema of the rows written (named by the first row)
+/// columns: number of fields of the widest row written
+/// duplicates: rows skipped by dedupe
+/// cells: number of cells read from the sheet per kind, including those of rows left out
+/// warnings: number of warnings per kind
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExportSummary {
+    pub rows_written: u64,
+    pub files: Vec<PathBuf>,
+    pub schema: Option<SheetSchema>,
+    pub columns: usize,
+    pub duplicates: u64,
+    pub cells: BTreeMap<CellKind, u64>,
+    pub warnings: BTreeMap<WarningKind, u64>,
+}
+
+impl ExportSummary {
+    /// Total number of warnings of all kinds
+    pub fn warning_count(&self) -> u64 {
+        self.warnings.values().sum()
+    }
+}
+
+/// Document properties from docProps/core.xml and docProps/app.xml
+/// Every field is None when the part or the element is missing.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DocProperties {
+    pub title: Option<String>,
+    pub subject: Option<String>,
+    pub creator: Option<String>,
+    pub last_modified_by: Option<String>,
+    pub created: Option<String>,
+    pub modified: Option<String>,
+    pub application: Option<String>,
+    pub app_version: Option<String>,
+    pub company: Option<String>,
+}
+
+/// Synthetic header row written before the first row of a sheet
+/// None: no extra row
+/// Letters: the column letters, A,B,C,...
+/// Index: the 1-based column numbers, 1,2,3,...
+/// Auto: no extra row; the sheet's own header is located by its autoFilter or table (see
+///   find_header_row) and the title rows above it are left out with ExportOptions::header_row
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HeaderRow {
+    #[default]
+    None,
+    Letters,
+    Index,
+    Auto,
+}
+
+impl std::str::FromStr for HeaderRow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "letters" => Ok(Self::Letters),
+            "index" => Ok(Self::Index),
+            "auto" => Ok(Self::Auto),
+            _ => Err(format!(
+                "invalid header '{}', expected letters, index, auto or none",
+                s
+            )),
+        }
+    }
+}
+
+impl HeaderRow {
+    /// Whether a synthetic row is written, i.e. the mode is Letters or Index
+    pub fn is_synthetic(self) -> bool {
+        matches!(self, HeaderRow::Letters | HeaderRow::Index)
+    }
+
+    /// The header fields for a sheet of width columns; empty for None and Auto
+    pub fn fields(self, width: usize) -> Vec<String> {
+        (1..=width as u32)
+            .filter_map(|col| match self {
+                HeaderRow::None | HeaderRow::Auto => None,
+                HeaderRow::Letters => Some(index_to_col(col)),
+                HeaderRow::Index => Some(col.to_string()),
+            })
+            .collect()
+    }
+}
+
+/// What to write for a `t="s"` cell whose shared string is missing (index out of range or no
+/// sharedStrings.xml); it is counted as a MissingSharedString warning either way
+/// Empty: an empty cell (or the debug_cells sentinel)
+/// Error: fail the export of the sheet, after reporting the warning
+/// Index: the shared string index as stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingString {
+    #[default]
+    Empty,
+    Error,
+    Index,
+}
+
+impl std::str::FromStr for MissingString {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "empty" => Ok(Self::Empty),
+            "error" => Ok(Self::Error),
+            "index" => Ok(Self::Index),
+            _ => Err(format!(
+                "invalid mode '{}', expected empty, error or index",
+                s
+            )),
+        }
+    }
+}
+
+/// What to do with a cell or row reference beyond the sheet: past the worksheet limits (column
+/// XFD, row 1048576) or past the sheet's `<dimension>`, unless that is just "A1"; it is counted
+/// as an InvalidReference warning unless the export fails
+/// Clamp: skip the cell, or read the row as the one after the previous row (the default, so a
+///   stray reference can't blow a sheet up to millions of rows or columns)
+/// Keep: keep cells and rows past the dimension, for writers that leave it stale; past the
+///   worksheet limits they are treated like with Clamp
+/// Error: fail the export of the sheet
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutOfBounds {
+    #[default]
+    Clamp,
+    Keep,
+    Error,
+}
+
+impl std::str::FromStr for OutOfBounds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(Self::Clamp),
+            "keep" => Ok(Self::Keep),
+            "error" => Ok(Self::Error),
+            _ => Err(format!(
+                "invalid mode '{}', expected clamp, keep or error",
+                s
+            )),
+        }
+    }
+}
+
+/// What to write for line breaks (`\n`, `\r` or `\r\n`) inside a cell value
+/// Keep: write them as they are; CSV fields holding one are quoted, which many consumers can't read
+/// Space: replace every line break with a space
+/// Escape: write them as the two characters `\n` or `\r`, and a backslash as `\\`, so the
+///   escapes can be told apart from text that has them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Newlines {
+    #[default]
+    Keep,
+    Space,
+    Escape,
+}
+
+impl std::str::FromStr for Newlines {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(Self::Keep),
+            "space" => Ok(Self::Space),
+            "escape" => Ok(Self::Escape),
+            _ => Err(format!(
+                "invalid mode '{}', expected keep, space or escape",
+                s
+            )),
+        }
+    }
+}
+
+impl Newlines {
+    /// Rewrite the line breaks of field as this mode says
+    pub fn apply(self, field: &mut String) {
+        *field = match self {
+            Newlines::Keep => return,
+            Newlines::Escape if !field.contains(['\n', '\r', '\\']) => return,
+            Newlines::Space if !field.contains(['\n', '\r']) => return,
+            Newlines::Space => field.replace("\r\n", " ").replace(['\r', '\n'], " "),
+            // Backslashes first, or the escapes would be escaped again
+            Newlines::Escape => field
+                .replace('\\', "\\\\")
+                .replace('\r', "\\r")
+                .replace('\n', "\\n"),
+        };
+    }
+}
+
+/// Options controlling how a sheet is written to CSV
+/// delimiter: CSV delimiter character (e.g., b',' or b';')
+/// pad_to_columns: pad or truncate every row to exactly this many fields
+/// trailing_delimiter: end every row with a delimiter (an extra empty field)
+/// header: synthetic header row (column letters or numbers) written before the first row; it is
+///   as wide as pad_to_columns, the sheet's `<dimension>` or else the first row
+/// header_row: the sheet row (1-based) holding the sheet's own header; the rows above it, e.g.
+///   a report title, are left out as if the sheet started there. Set per sheet, typically from
+///   find_header_row with HeaderRow::Auto
+/// row_numbers: prepend the 1-based sheet row number to every row ("row" in the header row)
+/// trim: drop trailing empty fields of every row and blank rows at the end of the sheet
+/// skip_blank_rows: leave out rows without any non-empty cell, including the rows written for
+///   gaps in the row numbers
+/// date_system: convert date serials with this DateSystem instead of the workbook's 1900/1904 flag
+/// leap_bug: what serial 60 of the 1900 date system, Excel's nonexistent 1900-02-29, becomes
+///   (see LeapBug); LeapBug::Error fails the sheet
+/// timezone: the zone dates are written in; they are naive in the workbook, so only the zone
+///   designator changes: Z by default, none, or an offset (see Timezone)
+/// date_output: write dates as ISO 8601 in timezone, unix seconds or milliseconds, or Excel
+///   serials (see DateOutput); typed outputs get the date either way
+/// debug_cells: write sentinels like `⟨missing-ss:1234⟩` instead of empty cells for broken references
+/// on_missing_string: what to write for cells referring to a shared string that doesn't exist
+/// on_out_of_bounds: what to do with cell and row references beyond the sheet (see OutOfBounds)
+/// numbers: write numbers canonically (Raw, see general_precision) or the way their number
+///   format displays them (Formatted, e.g. "1,234.50 €", see format_number); dates are not
+///   affected. row_filter and column_types see the formatted text
+/// year_window: read text in date formatted cells (e.g. "05/03/34" typed into a dd/mm/yy cell)
+///   as the date Excel displays, two-digit years falling in the 100 years from this one (1930
+///   in Excel, see parse_date_text); None leaves such text as it is
+/// units: write currency and percent formatted numbers as their value in the unit (12.5 for
+///   0.125 in a % format) plus the unit as a suffix or in a column of its own (see UnitOutput);
+///   takes precedence over Formatted numbers for these cells, their typed values stay the
+///   stored numbers. row_filter and column_types see the fields as written; unit columns are
+///   added after column_mapping, so they don't shift the positions of columns
+/// decimal_comma: write the decimal point of numbers as a comma (3,14), as European consumers
+///   expect; dates, text and booleans are left alone. Pairs with a `;` delimiter. With
+///   Formatted numbers the formats are rendered with a decimal comma and `.` grouping
+/// newlines: keep line breaks inside cell values, or replace or escape them (see Newlines)
+/// empty_as: write this marker (e.g. "NULL" or "\N") for cells without a value; cells holding
+///   an empty string stay empty
+/// general_precision: write plain numbers as stored (Exact) or as Excel's General format shows them
+/// format: write CSV, a Markdown table or an HTML table; delimiter only applies to CSV
+/// colors: with OutputFormat::CellsJson, add the fill and font color of every cell to its
+///   record, theme colors taken from these ThemeColors (see parse_theme)
+/// compression: write the CSV gzip or zstd compressed (out_path should carry the extension)
+/// split_rows: start a new part file after this many rows (see part_path); the first row of the
+///   sheet (its header) is repeated at the top of every part
+/// split_size: start a new part file before it grows past this many bytes of (uncompressed) CSV;
+///   a part always holds at least one row after the header
+/// max_rows: stop reading the sheet once this many rows (including any header row) are written
+/// row_filter: only write the rows matching this RowFilter; its columns are named by the first
+///   non-blank row of the sheet (always written), or by the synthetic header
+/// column_types: coerce the values of these columns (named like for row_filter); rows with a value
+///   that does not fit are left out and reported as TypeMismatch warnings
+/// column_mapping: rename, reorder and drop columns (named like for row_filter) before writing
+/// dedupe: skip rows whose fields equal an earlier row's; an empty list compares whole rows,
+///   else only these columns (named like for row_filter). Applied after column_types
+/// sample: only write a random sample of this many rows, in sheet order; rows are sampled after
+///   row_filter, column_types and dedupe. The header row is always written, blank rows never
+/// sample_seed: seed for sample; the same seed and sheet always give the same rows
+/// dry_run: read and convert the sheet as usual but write no files; the summary still lists
+///   the files that would be written
+/// cell_transform: optional callback rewriting every cell value read (e.g. trimming, redacting or
+///   remapping codes) before rows are filtered and written; missing cells are not passed. An
+///   error from it fails the export and removes the files written so far
+/// progress: optional callback receiving ExportProgress updates
+/// on_warning: optional callback receiving every ExportWarning
+/// pipeline: parse the sheet and encode and write its rows on two threads, connected by a
+///   bounded queue, so reading and writing overlap (a win on slow disks and network filesystems)
+#[derive(Clone)]
+pub struct ExportOptions {
+    pub delimiter: u8,
+    pub pad_to_columns: Option<usize>,
+    pub trailing_delimiter: bool,
+    pub header: HeaderRow,
+    pub header_row: Option<u32>,
+    pub row_numbers: bool,
+    pub trim: bool,
+    pub skip_blank_rows: bool,
+    pub date_system: Option<Arc<dyn DateSystem>>,
+    pub leap_bug: LeapBug,
+    pub timezone: Timezone,
+    pub date_output: DateOutput,
+    pub debug_cells: bool,
+    pub on_missing_string: MissingString,
+    pub on_out_of_bounds: OutOfBounds,
+    pub numbers: NumberOutput,
+    pub units: UnitOutput,
+    pub year_window: Option<i32>,
+    pub decimal_comma: bool,
+    pub newlines: Newlines,
+    pub empty_as: Option<String>,
+    pub general_precision: GeneralPrecision,
+    pub format: OutputFormat,
+    pub colors: Option<ThemeColors>,
+    pub compression: Compression,
+    pub split_rows: Option<u64>,
+    pub split_size: Option<u64>,
+    pub max_rows: Option<u64>,
+    pub row_filter: Option<RowFilter>,
+    pub column_types: Option<ColumnTypes>,
+    pub column_mapping: Option<ColumnMapping>,
+    pub dedupe: Option<Vec<String>>,
+    pub sample: Option<usize>,
+    pub sample_seed: u64,
+    pub dry_run: bool,
+    pub cell_transform: Option<CellTransform>,
+    pub progress: Option<ProgressCallback>,
+    pub on_warning: Option<WarningCallback>,
+    pub pipeline: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            pad_to_columns: None,
+            trailing_delimiter: false,
+            header: HeaderRow::None,
+            header_row: None,
+            row_numbers: false,
+            trim: false,
+            skip_blank_rows: false,
+            date_system: None,
+            leap_bug: LeapBug::Feb28,
+            timezone: Timezone::Utc,
+            date_output: DateOutput::Iso,
+            debug_cells: false,
+            on_missing_string: MissingString::Empty,
+            on_out_of_bounds: OutOfBounds::Clamp,
+            numbers: NumberOutput::Raw,
+            units: UnitOutput::Off,
+            year_window: None,
+            decimal_comma: false,
+            newlines: Newlines::Keep,
+            empty_as: None,
+            general_precision: GeneralPrecision::default(),
+            format: OutputFormat::Csv,
+            colors: None,
+            compression: Compression::None,
+            split_rows: None,
+            split_size: None,
+            max_rows: None,
+            row_filter: None,
+            column_types: None,
+            column_mapping: None,
+            dedupe: None,
+            sample: None,
+            sample_seed: 0,
+            dry_run: false,
+            cell_transform: None,
+            progress: None,
+            on_warning: None,
+            pipeline: false,
+        }
+    }
+}
+
+impl std::fmt::Debug for ExportOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExportOptions")
+            .field("delimiter", &self.delimiter)
+            .field("pad_to_columns", &self.pad_to_columns)
+            .field("trailing_delimiter", &self.trailing_delimiter)
+            .field("header", &self.header)
+            .field("header_row", &self.header_row)
+            .field("row_numbers", &self.row_numbers)
+            .field("trim", &self.trim)
+            .field("skip_blank_rows", &self.skip_blank_rows)
+            .field("date_system", &self.date_system.is_some())
+            .field("leap_bug", &self.leap_bug)
+            .field("timezone", &self.timezone)
+            .field("date_output", &self.date_output)
+            .field("debug_cells", &self.debug_cells)
+            .field("on_missing_string", &self.on_missing_string)
+            .field("on_out_of_bounds", &self.on_out_of_bounds)
+            .field("numbers", &self.numbers)
+            .field("units", &self.units)
+            .field("year_window", &self.year_window)
+            .field("decimal_comma", &self.decimal_comma)
+            .field("newlines", &self.newlines)
+            .field("empty_as", &self.empty_as)
+            .field("general_precision", &self.general_precision)
+            .field("format", &self.format)
+            .field("colors", &self.colors)
+            .field("compression", &self.compression)
+            .field("split_rows", &self.split_rows)
+            .field("split_size", &self.split_size)
+            .field("max_rows", &self.max_rows)
+            .field("row_filter", &self.row_filter)
+            .field("column_types", &self.column_types)
+            .field("column_mapping", &self.column_mapping)
+            .field("dedupe", &self.dedupe)
+            .field("sample", &self.sample)
+            .field("sample_seed", &self.sample_seed)
+            .field("dry_run", &self.dry_run)
+            .field("cell_transform", &self.cell_transform.is_some())
+            .field("progress", &self.progress.is_some())
+            .field("on_warning", &self.on_warning.is_some())
+            .field("pipeline", &self.pipeline)
+            .finish()
+    }
 }
 
 /// Open the XLSX file as a ZipArchive
-/// Returns a new ZipArchive<BufReader<File>>
+/// The file is sniffed first so that inputs which are clearly not .xlsx
+/// (CSV, legacy .xls, encrypted workbooks, ...) fail with an actionable error.
+/// A path of "-" reads the workbook from stdin (see spool_reader), an http(s) URL from the
+/// web (see open_url) and an object store URL like s3://bucket/key.xlsx from there (see
+/// open_object).
+/// Returns a new ZipArchive<WorkbookReader>
 ///
 /// Examples
 /// let zip = open_zip(Path::new("example.xlsx"))?;
-pub fn open_zip(path: &Path) -> Result<ZipArchive<BufReader<File>>> {
-    let file = File::open(path)?;
+/// let zip = open_zip(Path::new("-"))?; // e.g. `curl ... | xcsv - export`
+/// let zip = open_zip(Path::new("https://example.com/report.xlsx"))?;
+pub fn open_zip(path: &Path) -> Result<ZipArchive<WorkbookReader>> {
+    let (reader, name) = open_input(path, false)?;
+    zip_archive(reader, &name)
+}
+
+/// Like open_zip, but a local file is memory-mapped (see map_file, which has the caveats) rather
+/// than read through a buffer; stdin and URLs are read as usual; needs the mmap feature
+/// Worth it for multi-gigabyte workbooks when there is RAM to spare for the page cache.
+pub fn open_zip_mmap(path: &Path) -> Result<ZipArchive<WorkbookReader>> {
+    let (reader, name) = open_input(path, true)?;
+    zip_archive(reader, &name)
+}
+
+/// Like open_zip, but an archive the zip reader rejects (e.g. with a damaged or cut off central
+/// directory) is rebuilt from the local file headers of its parts with salvage_zip
+/// Returns the archive and the parts salvage found, none if the archive opened as is.
+pub fn open_zip_salvage(path: &Path) -> Result<(ZipArchive<WorkbookReader>, Vec<SalvagedPart>)> {
+    let (mut reader, name) = open_input(path, false)?;
+    if ZipArchive::new(&mut reader).is_ok() {
+        reader.rewind()?;
+        return Ok((zip_archive(reader, &name)?, Vec::new()));
+    }
+    reader.rewind()?;
+    salvage_zip(reader).with_context(|| format!("Cannot salvage {}", name))
+}
+
+/// Open the input at path (see open_zip), a local file mapped into memory with mmap, and reject
+/// what clearly isn't a workbook
+/// Returns the reader and the name to use in messages.
+fn open_input(path: &Path, mmap: bool) -> Result<(WorkbookReader, String)> {
+    let is_stdin = path == Path::new("-");
+    let mut reader = if is_stdin {
+        spool_reader(std::io::stdin().lock(), SPOOL_MEMORY_LIMIT)?
+    } else if is_url(&path.to_string_lossy()) {
+        open_url(&path.to_string_lossy())?
+    } else if is_object_url(&path.to_string_lossy()) {
+        open_object(&path.to_string_lossy())?
+    } else if mmap {
+        map_file(path)?
+    } else {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        WorkbookReader::File(BufReader::new(file))
+    };
+
+    let format = sniff_format(&mut reader)?;
+    let name = if is_stdin {
+        "stdin".into()
+    } else {
+        path.display().to_string()
+    };
+    if let Some(reason) = format.rejection() {
+        anyhow::bail!("Cannot read {}: {}", name, reason);
+    }
+    Ok((reader, name))
+}
 
-    let reader = BufReader::new(file);
-    let zip = ZipArchive::new(reader).context("Failed to read XLSX (zip) archive")?;
+/// Read the zip archive of reader, naming why it can't be read
+fn zip_archive(mut reader: WorkbookReader, name: &str) -> Result<ZipArchive<WorkbookReader>> {
+    let size = reader.seek(std::io::SeekFrom::End(0))?;
+    reader.rewind()?;
+    // Zip64 archives (over 4 GB or 65,535 entries) are read like any other; what the zip
+    // reader rejects is named, as its own messages don't say which file or why
+    let zip = ZipArchive::new(reader).map_err(|e| {
+        let reason = match &e {
+            ZipError::UnsupportedArchive(detail) => format!(
+                "unsupported zip archive ({}); split or multi-disk archives can't be read",
+                detail
+            ),
+            ZipError::InvalidArchive(detail) if size > u64::from(u32::MAX) => format!(
+                "invalid zip archive of {} bytes ({}); an archive over 4 GB needs zip64 \
+                 records, so it was probably written by a tool without zip64 support or cut off",
+                size, detail
+            ),
+            ZipError::InvalidArchive(detail) => format!(
+                "invalid zip archive ({}); the file is damaged or incomplete (export --salvage \
+                 recovers what it can)",
+                detail
+            ),
+            _ => e.to_string(),
+        };
+        anyhow::Error::new(e).context(format!("Cannot read {}: {}", name, reason))
+    })?;
     Ok(zip)
 }
 
 /// Parse the styles.xml to extract cell styles and identify date formats
+/// The fill and font colors of each style are kept as written; indexed colors are looked up in
+/// the palette of `<indexedColors>` if there is one.
 /// Returns a vector of StyleInfo
 pub fn parse_styles<R: BufRead>(reader: R) -> Result<Vec<StyleInfo>> {
-    let mut xml = Reader::from_reader(reader);
+    let mut xml = NsReader::from_reader(reader);
     let mut buf = Vec::new();
     let mut styles = Vec::new();
     let mut num_fmts = BTreeMap::new();
     let mut in_cell_xfs = false;
+    // The colors of `<fills>` and `<fonts>` by index, and the custom palette of `<indexedColors>`
+    let (mut fills, mut fonts, mut palette) = (Vec::new(), Vec::new(), Vec::new());
+    let (mut in_fills, mut in_fonts, mut in_palette) = (false, false, false);
+    // Whether the pattern of the current fill shows its foreground color
+    let mut patterned = false;
 
     // Helper closure to process attributes of an <xf> tag
     let process_xf = |attrs: quick_xml::events::attributes::Attributes,
-                      num_fmts: &BTreeMap<u32, String>|
+                      num_fmts: &BTreeMap<u32, String>,
+                      fills: &[Option<Color>],
+                      fonts: &[Option<Color>]|
      -> Result<StyleInfo> {
         let mut style = StyleInfo::default();
         let mut num_fmt_id_attr = None;
@@ -61,9 +838,28 @@ pub fn parse_styles<R: BufRead>(reader: R) -> Result<Vec<StyleInfo>> {
             b"applyNumberFormat" => {
                 apply_num_fmt = String::from_utf8_lossy(&a.value).parse::<u32>().ok() == Some(1);
             }
+            b"fillId" => {
+                style.fill = String::from_utf8_lossy(&a.value)
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|id| fills.get(id).copied().flatten());
+            }
+            b"fontId" => {
+                style.font_color = String::from_utf8_lossy(&a.value)
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|id| fonts.get(id).copied().flatten());
+            }
             _ => {}
         });
 
+        if let Some(id) = num_fmt_id_attr {
+            style.num_fmt_id = id;
+            style.format_code = num_fmts
+                .get(&id)
+                .cloned()
+                .or_else(|| builtin_format_code(id).map(str::to_string));
+        }
         if apply_num_fmt {
             if let Some(id) = num_fmt_id_attr {
                 // Check built-in formats
@@ -71,14 +867,12 @@ pub fn parse_styles<R: BufRead>(reader: R) -> Result<Vec<StyleInfo>> {
                     matches!(id, 14..=22 | 27..=36 | 45..=47 | 50..=58 | 67..=71 | 75..=81);
                 if is_builtin_date {
                     style.is_date = true;
+                } else if id == 49 {
+                    style.is_text = true;
                 } else if let Some(format_code) = num_fmts.get(&id) {
                     // Check custom formats
-                    let lower = format_code.to_lowercase();
-                    if (lower.contains('y') || lower.contains('d') || lower.contains('m'))
-                        && !lower.contains('#')
-                    {
-                        style.is_date = true;
-                    }
+                    style.is_text = format_code.trim() == "@";
+                    style.is_date = is_date_format(format_code);
                 }
             }
         }
@@ -86,8 +880,51 @@ pub fn parse_styles<R: BufRead>(reader: R) -> Result<Vec<StyleInfo>> {
     };
 
     loop {
-        match xml.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) => match e.name().as_ref() {
+        match read_event(&mut xml, &mut buf, SPREADSHEETML) {
+            Ok((true, Event::Start(e) | Event::Empty(e)))
+                if matches!(
+                    e.local_name().as_ref(),
+                    b"fill" | b"patternFill" | b"fgColor" | b"font" | b"color" | b"rgbColor"
+                ) =>
+            {
+                match e.local_name().as_ref() {
+                    b"fill" if in_fills => fills.push(None),
+                    b"patternFill" if in_fills => {
+                        patterned = e.attributes().flatten().any(|a| {
+                            a.key.as_ref() == b"patternType" && a.value.as_ref() != b"none"
+                        });
+                    }
+                    b"fgColor" if in_fills && patterned => {
+                        if let Some(fill) = fills.last_mut() {
+                            *fill = parse_color(&e);
+                        }
+                    }
+                    // The first stop of a gradient fill
+                    b"color" if in_fills => {
+                        if let Some(fill @ None) = fills.last_mut() {
+                            *fill = parse_color(&e);
+                        }
+                    }
+                    b"font" if in_fonts => fonts.push(None),
+                    b"color" if in_fonts => {
+                        if let Some(font) = fonts.last_mut() {
+                            *font = parse_color(&e);
+                        }
+                    }
+                    b"rgbColor" if in_palette => palette.push(
+                        e.attributes()
+                            .flatten()
+                            .find(|a| a.key.as_ref() == b"rgb")
+                            .and_then(|a| parse_rgb(&String::from_utf8_lossy(&a.value)))
+                            .unwrap_or_default(),
+                    ),
+                    _ => {}
+                }
+            }
+            Ok((true, Event::Start(e))) => match e.local_name().as_ref() {
+                b"fills" => in_fills = true,
+                b"fonts" => in_fonts = true,
+                b"indexedColors" => in_palette = true,
                 b"numFmt" => {
                     let mut num_fmt_id = None;
                     let mut format_code = None;
@@ -109,12 +946,12 @@ pub fn parse_styles<R: BufRead>(reader: R) -> Result<Vec<StyleInfo>> {
                 }
                 b"cellXfs" => in_cell_xfs = true,
                 b"xf" if in_cell_xfs => {
-                    styles.push(process_xf(e.attributes(), &num_fmts)?);
+                    styles.push(process_xf(e.attributes(), &num_fmts, &fills, &fonts)?);
                     xml.read_to_end_into(e.name(), &mut Vec::new())?;
                 }
                 _ => {}
             },
-            Ok(Event::Empty(e)) => match e.name().as_ref() {
+            Ok((true, Event::Empty(e))) => match e.local_name().as_ref() {
                 b"numFmt" => {
                     let mut num_fmt_id = None;
                     let mut format_code = None;
@@ -134,115 +971,155 @@ pub fn parse_styles<R: BufRead>(reader: R) -> Result<Vec<StyleInfo>> {
                     }
                 }
                 b"xf" if in_cell_xfs => {
-                    styles.push(process_xf(e.attributes(), &num_fmts)?);
+                    styles.push(process_xf(e.attributes(), &num_fmts, &fills, &fonts)?);
                 }
                 _ => {}
             },
-            Ok(Event::End(e)) => {
-                if e.name().as_ref() == b"cellXfs" {
-                    in_cell_xfs = false;
-                }
-            }
-            Ok(Event::Eof) => break,
+            Ok((true, Event::End(e))) => match e.local_name().as_ref() {
+                b"cellXfs" => in_cell_xfs = false,
+                b"fills" => in_fills = false,
+                b"fonts" => in_fonts = false,
+                b"indexedColors" => in_palette = false,
+                _ => {}
+            },
+            Ok((_, Event::Eof)) => break,
             Err(e) => return Err(anyhow::anyhow!("XML error in styles: {}", e)),
             _ => {}
         }
         buf.clear();
     }
+    // The palette comes last in styles.xml, after the styles using it
+    if !palette.is_empty() {
+        for style in &mut styles {
+            for color in [&mut style.fill, &mut style.font_color]
+                .into_iter()
+                .flatten()
+            {
+                if let ColorBase::Indexed(index) = color.base
+                    && let Some(&rgb) = palette.get(index as usize)
+                {
+                    color.base = ColorBase::Rgb(rgb);
+                }
+            }
+        }
+    }
     Ok(styles)
 }
 
-fn tag_eq_ignore_case(actual: &[u8], expect: &str) -> bool {
-    actual.eq_ignore_ascii_case(expect.as_bytes())
-        || actual.ends_with(expect.as_bytes())
-        || actual.ends_with(expect.to_ascii_lowercase().as_bytes())
-        || actual.ends_with(expect.to_ascii_uppercase().as_bytes())
+/// Whether an attribute is the relationship id (`r:id`) of an element
+fn is_relationship_id<R>(xml: &NsReader<R>, attr: &Attribute) -> bool {
+    let (resolved, local) = xml.resolve_attribute(attr.key);
+    local.as_ref() == b"id" && in_namespace(&resolved, OFFICE_RELATIONSHIPS)
 }
 
 /// Parse the workbook rels to make sure to find what sheet matches what data and the cell matching
 /// per row and sheet.
-pub fn parse_workbook_rels<R: BufRead>(reader: R) -> Result<BTreeMap<String, String>> {
-    // Map r:Id -> full path inside zip (xl/worksheets/sheet1.xml)
-    let mut xml = Reader::from_reader(reader);
-    // xml.config_mut().trim_text(true);
-    let mut buf = Vec::new();
-    let mut map = BTreeMap::new();
-    loop {
-        match xml.read_event_into(&mut buf) {
-            Ok(Event::Empty(e)) | Ok(Event::Start(e)) => {
-                if tag_eq_ignore_case(e.name().as_ref(), "Relationship") {
-                    let mut id = None;
-                    let mut target = None;
-
-                    e.attributes().flatten().for_each(|a| match a.key.as_ref() {
-                        b"Id" | b"r:Id" => {
-                            id = Some(String::from_utf8_lossy(&a.value).into_owned())
-                        }
-                        b"Target" => target = Some(String::from_utf8_lossy(&a.value).into_owned()),
-                        _ => {}
-                    });
-
-                    if let (Some(id), Some(target)) = (id, target) {
-                        map.insert(id, format!("xl/{}", target.trim_start_matches('/')));
-                    }
-                }
-            }
-            Ok(Event::Eof) => break,
-            Err(e) => return Err(anyhow::anyhow!("XML error in workbook.rels: {}", e)),
-            _ => {}
-        }
-        buf.clear();
-    }
-    Ok(map)
+/// Maps each relationship id to the resolved part path and the kind of sheet it points at.
+/// Only sheet relationships (worksheets, chartsheets, dialog and macro sheets) are kept; external
+/// targets are skipped. Targets are resolved against workbook_part, the name of the workbook part.
+pub fn parse_workbook_rels<R: BufRead>(
+    reader: R,
+    workbook_part: &str,
+) -> Result<BTreeMap<String, (String, SheetKind)>> {
+    // Map r:Id -> (full path inside zip (xl/worksheets/sheet1.xml), kind)
+    let relationships = parse_relationships(reader).context("invalid workbook.rels")?;
+    Ok(relationships
+        .into_iter()
+        .filter(|r| !r.external)
+        .filter_map(|r| {
+            let kind = SheetKind::from_relationship_type(&r.rel_type)?;
+            Some((r.id, (resolve_target(workbook_part, &r.target), kind)))
+        })
+        .collect())
 }
 
 /// Parse the workbook itself
 /// Returns a vector of SheetInfo and a boolean indicating if the 1904 date system is used
 pub fn parse_workbook<R: BufRead>(
     reader: R,
-    rels: &BTreeMap<String, String>,
+    rels: &BTreeMap<String, (String, SheetKind)>,
 ) -> Result<(Vec<SheetInfo>, bool)> {
-    let mut xml = Reader::from_reader(reader);
+    let (sheets, is_1904) = parse_workbook_sheets(reader, rels)?;
+    Ok((
+        sheets
+            .into_iter()
+            .map(|sheet| sheet.info)
+            .filter(|info| !info.path_in_zip.is_empty())
+            .collect(),
+        is_1904,
+    ))
+}
+
+/// Like parse_workbook, but keeps the sheets whose r:id is missing or not in rels, with an
+/// empty path_in_zip, so their part can be looked for
+pub(crate) fn parse_workbook_sheets<R: BufRead>(
+    reader: R,
+    rels: &BTreeMap<String, (String, SheetKind)>,
+) -> Result<(Vec<WorkbookSheet>, bool)> {
+    let mut xml = NsReader::from_reader(reader);
     // xml.config_mut().trim_text(true);
     let mut buf = Vec::new();
     let mut sheets = Vec::new();
     let mut is_1904 = false;
     loop {
-        match xml.read_event_into(&mut buf) {
-            Ok(Event::Empty(e)) | Ok(Event::Start(e)) => match e.name().as_ref() {
-                b"sheet" => {
-                    let mut name = None;
-                    let mut r_id = None;
+        match read_event(&mut xml, &mut buf, SPREADSHEETML) {
+            Ok((true, Event::Empty(e))) | Ok((true, Event::Start(e))) => {
+                match e.local_name().as_ref() {
+                    b"sheet" => {
+                        let mut name = None;
+                        let mut r_id = None;
+                        let mut sheet_id = None;
+                        let mut state = SheetState::Visible;
 
-                    e.attributes().flatten().for_each(|a| match a.key.as_ref() {
-                        b"name" => name = Some(String::from_utf8_lossy(&a.value).into_owned()),
-                        b"id" | b"r:id" => {
-                            r_id = Some(String::from_utf8_lossy(&a.value).into_owned())
-                        }
-                        _ => {}
-                    });
+                        e.attributes().flatten().for_each(|a| match a.key.as_ref() {
+                            b"name" => name = Some(String::from_utf8_lossy(&a.value).into_owned()),
+                            _ if is_relationship_id(&xml, &a) => {
+                                r_id = Some(String::from_utf8_lossy(&a.value).into_owned())
+                            }
+                            b"sheetId" => {
+                                sheet_id = String::from_utf8_lossy(&a.value).parse::<u32>().ok()
+                            }
+                            b"state" => {
+                                state = match a.value.as_ref() {
+                                    b"hidden" => SheetState::Hidden,
+                                    b"veryHidden" => SheetState::VeryHidden,
+                                    _ => SheetState::Visible,
+                                }
+                            }
+                            _ => {}
+                        });
 
-                    if let (Some(name), Some(rid)) = (name, r_id) {
-                        if let Some(target) = rels.get(&rid) {
-                            sheets.push(SheetInfo {
-                                name,
-                                path_in_zip: target.clone(),
+                        if let Some(name) = name {
+                            let (path_in_zip, kind) = r_id
+                                .as_ref()
+                                .and_then(|rid| rels.get(rid))
+                                .cloned()
+                                .unwrap_or((String::new(), SheetKind::Worksheet));
+                            sheets.push(WorkbookSheet {
+                                info: SheetInfo {
+                                    name,
+                                    path_in_zip,
+                                    sheet_id,
+                                    state,
+                                    kind,
+                                },
+                                r_id,
                             });
                         }
                     }
-                }
-                b"workbookPr" => {
-                    e.attributes().flatten().into_iter().for_each(|a| {
-                        if a.key.as_ref() == b"date1904" {
-                            if let Ok(val) = a.decode_and_unescape_value(&xml) {
-                                is_1904 = val == "1" || val == "true";
+                    b"workbookPr" => {
+                        e.attributes().flatten().into_iter().for_each(|a| {
+                            if a.key.as_ref() == b"date1904" {
+                                if let Ok(val) = a.decode_and_unescape_value(&xml) {
+                                    is_1904 = val == "1" || val == "true";
+                                }
                             }
-                        }
-                    });
+                        });
+                    }
+                    _ => {}
                 }
-                _ => {}
-            },
-            Ok(Event::Eof) => break,
+            }
+            Ok((_, Event::Eof)) => break,
             Err(e) => return Err(anyhow::anyhow!("XML error in workbook.xml: {}", e)),
             _ => {}
         }
@@ -251,95 +1128,506 @@ pub fn parse_workbook<R: BufRead>(
     Ok((sheets, is_1904))
 }
 
-/// Read the shared strings from the excel file
-/// Returns a vector of strings
-pub fn read_shared_strings<R: BufRead>(reader: R) -> Result<Vec<String>> {
+/// Read the workbook rels and the workbook part from the archive
+/// Returns a vector of SheetInfo and a boolean indicating if the 1904 date system is used
+/// Sheets whose relationship is missing, broken or points at no part are matched with the
+/// worksheet parts no other sheet uses, in sheetId order (see assign_orphan_sheets). When the
+/// rels or the workbook part are missing altogether, worksheet parts left over become sheets
+/// named after the part, e.g. Sheet3 for xl/worksheets/sheet3.xml.
+pub fn read_workbook<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    parts: &PackageParts,
+) -> Result<(Vec<SheetInfo>, bool)> {
+    let rels_map = read_workbook_rels_lenient(zip, parts);
+    let mut lenient = rels_map.is_none();
+    let (mut sheets, is_1904) = match zip.by_name(&parts.workbook) {
+        Ok(f) => parse_workbook_sheets(BufReader::new(f), &rels_map.unwrap_or_default())?,
+        Err(_) => {
+            lenient = true;
+            (Vec::new(), false)
+        }
+    };
+    let names: Vec<&str> = zip.file_names().collect();
+    for i in assign_orphan_sheets(&mut sheets, &names, &parts.workbook) {
+        tracing::warn!(
+            "sheet '{}' has no valid relationship, reading it from {} (run `xcsv repair` to fix the workbook)",
+            sheets[i].info.name,
+            sheets[i].info.path_in_zip
+        );
+    }
+    for sheet in sheets.iter().filter(|s| s.info.path_in_zip.is_empty()) {
+        tracing::warn!(
+            "sheet '{}' has no valid relationship and no worksheet part is left for it, skipping it",
+            sheet.info.name
+        );
+    }
+    // Without rels or workbook part, the worksheets are all there is to go by
+    if lenient {
+        for i in add_unclaimed_worksheets(&mut sheets, &names, &parts.workbook) {
+            tracing::warn!(
+                "{} is not listed in {}, reading it as sheet '{}'",
+                sheets[i].info.path_in_zip,
+                parts.workbook,
+                sheets[i].info.name
+            );
+        }
+        if sheets.is_empty() && zip.by_name(&parts.workbook).is_err() {
+            anyhow::bail!("missing {}", parts.workbook);
+        }
+    }
+    Ok((
+        sheets
+            .into_iter()
+            .map(|sheet| sheet.info)
+            .filter(|info| !info.path_in_zip.is_empty())
+            .collect(),
+        is_1904,
+    ))
+}
+
+/// Parse a docProps part (core.xml or app.xml) into the given DocProperties
+/// Elements are matched by local name, so both parts can be fed into the same struct.
+pub fn parse_doc_properties<R: BufRead>(reader: R, props: &mut DocProperties) -> Result<()> {
     let mut xml = Reader::from_reader(reader);
-    // xml.config_mut().trim_text(true);
     let mut buf = Vec::new();
-    let mut strings = Vec::new();
-    let mut in_si = false;
-    let mut current = String::new();
+    let mut current: Option<Vec<u8>> = None;
+    let mut text = String::new();
     loop {
         match xml.read_event_into(&mut buf) {
             Ok(Event::Start(e)) => {
-                if tag_eq_ignore_case(e.name().as_ref(), "si") {
-                    in_si = true;
-                    current.clear();
-                }
+                current = Some(e.local_name().as_ref().to_vec());
+                text.clear();
             }
-            Ok(Event::End(e)) => {
-                if tag_eq_ignore_case(e.name().as_ref(), "si") {
-                    strings.push(current.clone());
-                    in_si = false;
-                }
+            Ok(Event::Text(t)) if current.is_some() => {
+                text.push_str(&t.unescape()?);
             }
-            Ok(Event::Text(t)) => {
-                if in_si {
-                    // Due to quick-xml 0.38.3 (i assume 0.37+)
-                    // The config is unescaping everything way too early.
-                    // So we have reverted to 0.31.0 to have a functioning parser
-                    // to show correct characters like angle brackets.
-                    current.push_str(&t.unescape()?);
+            Ok(Event::End(_)) => {
+                if let Some(name) = current.take() {
+                    let value = Some(text.trim().to_string()).filter(|v| !v.is_empty());
+                    let field = match name.as_slice() {
+                        b"title" => Some(&mut props.title),
+                        b"subject" => Some(&mut props.subject),
+                        b"creator" => Some(&mut props.creator),
+                        b"lastModifiedBy" => Some(&mut props.last_modified_by),
+                        b"created" => Some(&mut props.created),
+                        b"modified" => Some(&mut props.modified),
+                        b"Application" => Some(&mut props.application),
+                        b"AppVersion" => Some(&mut props.app_version),
+                        b"Company" => Some(&mut props.company),
+                        _ => None,
+                    };
+                    if let (Some(field), Some(value)) = (field, value) {
+                        *field = Some(value);
+                    }
                 }
             }
             Ok(Event::Eof) => break,
-            Err(e) => return Err(anyhow::anyhow!("XML error in sharedStrings: {}", e)),
+            Err(e) => return Err(anyhow::anyhow!("XML error in docProps: {}", e)),
             _ => {}
         }
         buf.clear();
     }
-    Ok(strings)
+    Ok(())
 }
 
-/// A cell reference in the form of column and row index
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub struct CellRef {
-    pub col: u32,
-    pub row: u32,
+/// Read docProps/core.xml and docProps/app.xml from the archive, if present
+pub fn read_doc_properties<R: Read + Seek>(zip: &mut ZipArchive<R>) -> Result<DocProperties> {
+    let mut props = DocProperties::default();
+    for part in ["docProps/core.xml", "docProps/app.xml"] {
+        if let Ok(f) = zip.by_name(part) {
+            parse_doc_properties(BufReader::new(f), &mut props)?;
+        }
+    }
+    Ok(props)
 }
 
-/// Convert a column string (e.g., "A", "AB") to a 1-based index
-/// Examples:
-///   "A" -> 1
-///   "Z" -> 26
-///   "AA" -> 27
-///   "AB" -> 28
-pub fn col_to_index(col: &str) -> u32 {
-    let mut n: u32 = 0;
-
-    col.bytes().into_iter().for_each(|b| {
-        if !(b'A'..=b'Z').contains(&b) {
-            return;
+/// The names of the `<definedName>`s of a workbook part: named ranges and constants, and
+/// built-in names like _xlnm.Print_Area
+/// Parsing stops at `</definedNames>`.
+pub fn parse_defined_names<R: BufRead>(reader: R) -> Result<Vec<String>> {
+    let mut xml = Reader::from_reader(reader);
+    let mut buf = Vec::new();
+    let mut names = Vec::new();
+    loop {
+        match xml.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e))
+                if e.local_name().as_ref() == b"definedName" =>
+            {
+                if let Some(a) = e
+                    .attributes()
+                    .flatten()
+                    .find(|a| a.key.local_name().as_ref() == b"name")
+                {
+                    names.push(String::from_utf8_lossy(&a.value).into_owned());
+                }
+            }
+            Ok(Event::End(e)) if e.local_name().as_ref() == b"definedNames" => break,
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(anyhow::anyhow!("XML error in workbook: {}", e)),
+            _ => {}
         }
-        n = n * 26 + ((b - b'A' + 1) as u32);
-    });
-
-    n
+        buf.clear();
+    }
+    Ok(names)
 }
 
-/// Parse a cell reference string (e.g., "A1", "BC23") into a CellRef struct
-/// Returns None if the input is invalid
-pub fn parse_cell_ref(s: &str) -> Option<CellRef> {
-    let mut col = String::new();
-    let mut row = String::new();
-
-    s.chars().into_iter().for_each(|c| {
-        if c.is_ascii_alphabetic() {
-            col.push(c.to_ascii_uppercase());
-        } else {
-            row.push(c);
+/// Read the metadata at the head of a worksheet part
+/// Parsing stops at `<sheetData>`, so this is cheap even for huge sheets.
+pub fn read_sheet_meta<R: BufRead>(reader: R) -> Result<SheetMeta> {
+    let mut xml = NsReader::from_reader(reader);
+    let mut buf = Vec::new();
+    let mut meta = SheetMeta::default();
+    loop {
+        match read_event(&mut xml, &mut buf, SPREADSHEETML) {
+            Ok((true, Event::Empty(e))) | Ok((true, Event::Start(e))) => {
+                let name = e.local_name();
+                if name.as_ref() == b"sheetData" {
+                    break;
+                } else if name.as_ref() == b"dimension" {
+                    meta.dimension = e
+                        .attributes()
+                        .flatten()
+                        .find(|a| a.key.as_ref() == b"ref")
+                        .map(|a| String::from_utf8_lossy(&a.value).into_owned());
+                } else if name.as_ref() == b"tabColor" {
+                    e.attributes().flatten().for_each(|a| {
+                        let value = String::from_utf8_lossy(&a.value);
+                        match a.key.as_ref() {
+                            b"rgb" => {
+                                // ARGB; drop the alpha channel
+                                let rgb = value.get(value.len().saturating_sub(6)..).unwrap_or("");
+                                meta.tab_color = Some(format!("#{}", rgb.to_ascii_uppercase()));
+                            }
+                            b"theme" if meta.tab_color.is_none() => {
+                                meta.tab_color = Some(format!("theme:{}", value));
+                            }
+                            b"indexed" if meta.tab_color.is_none() => {
+                                meta.tab_color = Some(format!("indexed:{}", value));
+                            }
+                            _ => {}
+                        }
+                    });
+                }
+            }
+            Ok((_, Event::Eof)) => break,
+            Err(e) => return Err(anyhow::anyhow!("XML error in worksheet: {}", e)),
+            _ => {}
         }
-    });
+        buf.clear();
+    }
+    Ok(meta)
+}
 
-    if col.is_empty() || row.is_empty() {
-        return None;
+/// Scan a whole worksheet for the number of columns it spans (the last column holding a cell)
+/// Unlike SheetMeta::last_column this doesn't trust `<dimension>`, at the cost of a full pass.
+pub fn scan_sheet_width<R: BufRead>(reader: R) -> Result<u32> {
+    let mut xml = NsReader::from_reader(reader);
+    let mut buf = Vec::new();
+    let mut width = 0;
+    let mut col = 0;
+    loop {
+        match read_event(&mut xml, &mut buf, SPREADSHEETML) {
+            Ok((true, Event::Start(e))) if e.local_name().as_ref() == b"row" => col = 0,
+            Ok((true, Event::Empty(e))) | Ok((true, Event::Start(e)))
+                if e.local_name().as_ref() == b"c" =>
+            {
+                // Cells without an r attribute follow the previous cell
+                col = e
+                    .attributes()
+                    .flatten()
+                    .find(|a| a.key.as_ref() == b"r")
+                    .and_then(|a| parse_cell_ref(&String::from_utf8_lossy(&a.value)))
+                    .map_or(col + 1, |cr| cr.col);
+                width = width.max(col);
+            }
+            Ok((_, Event::Eof)) => break,
+            Err(e) => return Err(anyhow::anyhow!("XML error in worksheet: {}", e)),
+            _ => {}
+        }
+        buf.clear();
     }
+    Ok(width)
+}
 
-    Some(CellRef {
-        col: col_to_index(&col),
-        row: row.parse().ok()?,
-    })
+/// Raw contents of a cell as stored in the worksheet, for inspecting cells
+/// cell_type: the `t` attribute (s, str, inlineStr, b, e, d); None for plain numbers
+/// style: the `s` attribute, an index into the cell formats (see parse_styles)
+/// formula: the `<f>` text, without a leading '='; cells sharing a formula get the formula of
+///   the first cell of the range, translated to their position
+/// value: the `<v>` text, i.e. the shared string index for t="s", or the inline string
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CellDetails {
+    pub cell: CellRef,
+    pub cell_type: Option<String>,
+    pub style: Option<u32>,
+    pub formula: Option<String>,
+    pub value: String,
+}
+
+/// Read the raw CellDetails of every cell in the first max_rows rows of a worksheet
+pub fn read_cell_details<R: BufRead>(reader: R, max_rows: Option<u32>) -> Result<Vec<CellDetails>> {
+    let mut cells = Vec::new();
+    for_each_cell_details(reader, max_rows, |details| {
+        cells.push(details);
+        Ok(())
+    })?;
+    Ok(cells)
+}
+
+/// Pass the raw CellDetails of every cell in the first max_rows rows of a worksheet to f, one
+/// at a time
+/// Cells sharing a formula get the formula of the first cell, translated to their position.
+pub fn for_each_cell_details<R: BufRead>(
+    reader: R,
+    max_rows: Option<u32>,
+    mut f: impl FnMut(CellDetails) -> Result<()>,
+) -> Result<()> {
+    let mut xml = NsReader::from_reader(reader);
+    let mut buf = Vec::new();
+    let mut row = 0;
+    let mut previous: Option<CellRef> = None;
+    // The cell between <c> and </c>, if any
+    let mut current: Option<CellDetails> = None;
+    let mut in_formula = false;
+    // The si of the shared formula of the current cell, and the first cell and formula of
+    // every shared formula by si
+    let mut shared_index: Option<u32> = None;
+    let mut shared: HashMap<u32, (CellRef, String)> = HashMap::new();
+    loop {
+        match read_event(&mut xml, &mut buf, SPREADSHEETML) {
+            Ok((true, Event::Start(e))) if e.local_name().as_ref() == b"row" => {
+                row = e
+                    .attributes()
+                    .flatten()
+                    .find(|a| a.key.as_ref() == b"r")
+                    .and_then(|a| String::from_utf8_lossy(&a.value).parse().ok())
+                    .unwrap_or(row + 1);
+                if max_rows.is_some_and(|max| row > max) {
+                    break;
+                }
+            }
+            Ok((true, Event::Empty(e))) if e.local_name().as_ref() == b"c" => {
+                let details = cell_details(&e, row, previous);
+                previous = Some(details.cell);
+                f(details)?;
+            }
+            Ok((true, Event::Start(e))) if e.local_name().as_ref() == b"c" => {
+                let details = cell_details(&e, row, previous);
+                previous = Some(details.cell);
+                current = Some(details);
+            }
+            Ok((true, Event::Start(e))) if e.local_name().as_ref() == b"f" => {
+                in_formula = true;
+                shared_index = shared_formula_index(&e);
+            }
+            Ok((true, Event::Empty(e))) if e.local_name().as_ref() == b"f" => {
+                shared_index = shared_formula_index(&e);
+                if let Some(details) = current.as_mut() {
+                    expand_shared_formula(
+                        details.cell,
+                        &mut details.formula,
+                        shared_index.take(),
+                        &mut shared,
+                    );
+                }
+            }
+            Ok((true, Event::End(e))) => match e.local_name().as_ref() {
+                b"f" => {
+                    in_formula = false;
+                    if let Some(details) = current.as_mut() {
+                        expand_shared_formula(
+                            details.cell,
+                            &mut details.formula,
+                            shared_index.take(),
+                            &mut shared,
+                        );
+                    }
+                }
+                b"c" => {
+                    if let Some(details) = current.take() {
+                        f(details)?;
+                    }
+                }
+                _ => {}
+            },
+            Ok((_, Event::Text(t))) => {
+                if let Some(details) = current.as_mut() {
+                    let text = t.unescape()?;
+                    if in_formula {
+                        details
+                            .formula
+                            .get_or_insert_with(String::new)
+                            .push_str(&text);
+                    } else {
+                        details.value.push_str(&text);
+                    }
+                }
+            }
+            Ok((_, Event::Eof)) => break,
+            Err(e) => return Err(anyhow::anyhow!("XML error in worksheet: {}", e)),
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(())
+}
+
+/// The si of an `<f t="shared">` element, None for other formulas
+fn shared_formula_index(e: &BytesStart) -> Option<u32> {
+    let mut shared = false;
+    let mut index = None;
+    e.attributes().flatten().for_each(|a| match a.key.as_ref() {
+        b"t" => shared = a.value.as_ref() == b"shared",
+        b"si" => index = String::from_utf8_lossy(&a.value).parse().ok(),
+        _ => {}
+    });
+    index.filter(|_| shared)
+}
+
+/// Remember the formula of the first cell of a shared formula, or give a later cell of it the
+/// formula translated to its position
+fn expand_shared_formula(
+    cell: CellRef,
+    formula: &mut Option<String>,
+    index: Option<u32>,
+    shared: &mut HashMap<u32, (CellRef, String)>,
+) {
+    let Some(index) = index else {
+        return;
+    };
+    match formula {
+        Some(formula) => {
+            shared.insert(index, (cell, formula.clone()));
+        }
+        None => {
+            if let Some((first, first_formula)) = shared.get(&index) {
+                *formula = Some(translate_formula(
+                    first_formula,
+                    i64::from(cell.row) - i64::from(first.row),
+                    i64::from(cell.col) - i64::from(first.col),
+                ));
+            }
+        }
+    }
+}
+
+/// The attributes of a `<c>` element in row; cells without an r attribute follow previous
+fn cell_details(e: &BytesStart, row: u32, previous: Option<CellRef>) -> CellDetails {
+    let mut details = CellDetails::default();
+    let mut cell = None;
+    e.attributes().flatten().for_each(|a| match a.key.as_ref() {
+        b"r" => cell = parse_cell_ref(&String::from_utf8_lossy(&a.value)),
+        b"t" => details.cell_type = Some(String::from_utf8_lossy(&a.value).into_owned()),
+        b"s" => details.style = String::from_utf8_lossy(&a.value).parse().ok(),
+        _ => {}
+    });
+    details.cell = cell.unwrap_or(CellRef {
+        col: previous.filter(|p| p.row == row).map_or(1, |p| p.col + 1),
+        row,
+    });
+    details
+}
+
+/// The number of columns of the sheet stored at part
+/// Trusts `<dimension>` unless it is missing or just "A1", which some writers emit regardless of
+/// the data; then the whole sheet is scanned with scan_sheet_width.
+pub fn read_sheet_width<R: Read + Seek>(zip: &mut ZipArchive<R>, part: &str) -> Result<u32> {
+    let f = zip
+        .by_name(part)
+        .with_context(|| format!("missing {}", part))?;
+    let meta = read_sheet_meta(BufReader::new(f))?;
+    match meta.last_column() {
+        Some(width) if meta.dimension.as_deref() != Some("A1") => Ok(width),
+        _ => scan_sheet_width(BufReader::new(zip.by_name(part)?)),
+    }
+}
+
+/// Read the SheetMeta of every sheet from the archive, in the same order as sheets
+/// Sheets other than worksheets whose part is missing get an empty SheetMeta.
+pub fn read_sheet_metas<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    sheets: &[SheetInfo],
+) -> Result<Vec<SheetMeta>> {
+    sheets
+        .iter()
+        .map(|sheet| match zip.by_name(&sheet.path_in_zip) {
+            Ok(f) => read_sheet_meta(BufReader::new(f)),
+            Err(_) if sheet.kind != SheetKind::Worksheet => Ok(SheetMeta::default()),
+            Err(_) => anyhow::bail!("missing {}", sheet.path_in_zip),
+        })
+        .collect()
+}
+
+/// A cell reference in the form of column and row index
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CellRef {
+    pub col: u32,
+    pub row: u32,
+}
+
+/// Convert a column string (e.g., "A", "AB") to a 1-based index
+/// Returns None if the string is empty, holds anything but the letters A to Z, or names a
+/// column beyond XFD (MAX_COLUMNS)
+/// Examples:
+///   "A" -> Some(1)
+///   "Z" -> Some(26)
+///   "AA" -> Some(27)
+///   "XFE" -> None
+pub fn col_to_index(col: &str) -> Option<u32> {
+    if col.is_empty() {
+        return None;
+    }
+    col.bytes().try_fold(0u32, |n, b| {
+        if !b.is_ascii_uppercase() {
+            return None;
+        }
+        Some(n * 26 + u32::from(b - b'A' + 1)).filter(|&n| n <= MAX_COLUMNS)
+    })
+}
+
+/// Convert a 1-based column index to its column letters (the inverse of col_to_index)
+/// Examples:
+///   1 -> "A"
+///   26 -> "Z"
+///   27 -> "AA"
+///   0 -> ""
+pub fn index_to_col(mut n: u32) -> String {
+    let mut letters = Vec::new();
+    while n > 0 {
+        let rem = ((n - 1) % 26) as u8;
+        letters.push(b'A' + rem);
+        n = (n - 1) / 26;
+    }
+    letters.reverse();
+    String::from_utf8(letters).unwrap_or_default()
+}
+
+/// Parse a cell reference string (e.g., "A1", "BC23") into a CellRef struct
+/// Returns None if the input is invalid, including columns beyond XFD
+pub fn parse_cell_ref(s: &str) -> Option<CellRef> {
+    let letters = s.bytes().take_while(u8::is_ascii_alphabetic).count();
+    let (col, row) = s.split_at(letters);
+    if row.is_empty() || !row.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    Some(CellRef {
+        col: col_to_index(&col.to_ascii_uppercase())?,
+        row: row.parse().ok()?,
+    })
+}
+
+/// Parse a range reference string (e.g., "A1:Q50000") into its first and last CellRef
+/// A single cell reference (e.g., "A1") yields the same CellRef twice.
+/// Returns None if the input is invalid
+pub fn parse_range_ref(s: &str) -> Option<(CellRef, CellRef)> {
+    match s.split_once(':') {
+        Some((start, end)) => Some((parse_cell_ref(start)?, parse_cell_ref(end)?)),
+        None => {
+            let cell = parse_cell_ref(s)?;
+            Some((cell, cell))
+        }
+    }
 }
 
 /// Convert a sheet name to a lowercase filename-safe string
@@ -367,96 +1655,457 @@ pub fn to_lowercase_filename(name: &str) -> String {
 
 // Excel date/time utilities
 // Excel stores dates as serial numbers: days since 1900-01-01 (with 1900 incorrectly treated as leap year)
-static SECONDS_PER_DAY: f64 = 86400.0;
+// The date systems themselves live in dates.rs behind the DateSystem trait.
 
 /// Convert an Excel serial date to an ISO 8601 date string (UTC)
 /// If is_1904 is true, use the 1904 date system; otherwise, use the 1900 date system.
 /// Returns None if the serial number is invalid.
 /// Examples:
 ///   excel_serial_to_iso_date(44197.0, false) -> Some("2021-01-01T00:00:00.000Z")
-///   excel_serial_to_iso_date(0.0, false) -> Some("1899-12-30T00:00:00.000Z")
-///   excel_serial_to_iso_date(1.0, false) -> Some("1899-12-31T00:00:00.000Z")
-///   excel_serial_to_iso_date(60.0, false) -> Some("1900-02-29T00:00:00.000Z") // Excel bug
+///   excel_serial_to_iso_date(0.0, false) -> Some("1899-12-31T00:00:00.000Z") // Excel's 1900-01-00
+///   excel_serial_to_iso_date(1.0, false) -> Some("1900-01-01T00:00:00.000Z")
+///   excel_serial_to_iso_date(60.0, false) -> Some("1900-02-28T00:00:00.000Z") // Excel's 1900-02-29, see LeapBug
 pub fn excel_serial_to_iso_date(serial: f64, is_1904: bool) -> Option<String> {
-    let excel_epoch_days = if is_1904 {
-        24107 // Days from 1970-01-01 to 1904-01-01
+    let datetime = if is_1904 {
+        Excel1904.to_datetime(serial)
     } else {
-        25569 // Days from 1970-01-01 to 1900-01-01
+        Excel1900.to_datetime(serial)
     };
-
-    let days = serial.floor() as i32;
-    let time_fraction = serial - days as f64;
-
-    // In the 1900 system, Excel incorrectly treats 1900 as a leap year.
-    // This means any date after Feb 28, 1900, is off by one.
-    // Serial numbers 1-59 are unaffected. 60 is "Feb 29, 1900". 61 is Mar 1, 1900.
-    // Our epoch calculation for 1900 already accounts for this by starting from 1899-12-30,
-    // so we don't need a special adjustment here if using a correct epoch day count.
-    // The constant 25569 = days between 1970-01-01 and 1899-12-30.
-
-    let unix_days = days - excel_epoch_days;
-    let unix_seconds =
-        (unix_days as f64 * SECONDS_PER_DAY) + (time_fraction * SECONDS_PER_DAY).round();
-
-    let datetime = chrono::DateTime::from_timestamp(unix_seconds as i64, 0)?;
-    Some(datetime.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string())
+    datetime.map(|d| format_iso_datetime(&d))
 }
 
 /// Export a sheet XML to CSV file
 /// reader: BufRead of the sheet XML
 /// shared_strings: slice of shared strings
 /// styles: slice of StyleInfo
-/// is_1904: whether the workbook uses the 1904 date system
+/// is_1904: whether the workbook uses the 1904 date system (ignored if options.date_system is set)
 /// out_path: path to output CSV file
-/// delimiter: CSV delimiter character (e.g., b',' or b';')
-/// Returns Result<()>
-pub fn export_sheet_xml_to_csv<R: BufRead>(
+/// options: ExportOptions controlling the CSV layout
+/// Returns an ExportSummary with the number of rows written and warnings raised
+pub fn export_sheet_xml_to_csv<R: BufRead, S: StringTable + ?Sized>(
     reader: R,
-    shared_strings: &[String],
+    shared_strings: &S,
     styles: &[StyleInfo],
     is_1904: bool,
     out_path: &Path,
-    delimiter: u8,
-) -> Result<()> {
-    let mut xml = Reader::from_reader(reader);
+    options: &ExportOptions,
+) -> Result<ExportSummary> {
+    let (summary, _) = export_sheet(
+        reader,
+        shared_strings,
+        styles,
+        is_1904,
+        out_path,
+        options,
+        false,
+    )?;
+    Ok(summary)
+}
+
+/// export_sheet_xml_to_csv, keeping the files in memory instead of writing them
+/// Returns the summary, whose files are named after out_path like those written, and the
+/// contents of those files in the same order. Compression and pgcopy scripts are not supported.
+pub fn export_sheet_xml_to_memory<R: BufRead, S: StringTable + ?Sized>(
+    reader: R,
+    shared_strings: &S,
+    styles: &[StyleInfo],
+    is_1904: bool,
+    out_path: &Path,
+    options: &ExportOptions,
+) -> Result<(ExportSummary, Vec<Vec<u8>>)> {
+    export_sheet(
+        reader,
+        shared_strings,
+        styles,
+        is_1904,
+        out_path,
+        options,
+        true,
+    )
+}
+
+/// export_sheet_xml_to_csv, keeping the files in memory instead of writing them with in_memory
+/// Returns the summary and with in_memory the contents of its files, in the same order.
+pub(crate) fn export_sheet<R: BufRead, S: StringTable + ?Sized>(
+    reader: R,
+    shared_strings: &S,
+    styles: &[StyleInfo],
+    is_1904: bool,
+    out_path: &Path,
+    options: &ExportOptions,
+    in_memory: bool,
+) -> Result<(ExportSummary, Vec<Vec<u8>>)> {
+    if options.format == OutputFormat::CellsJson {
+        return cells::export_cells(
+            reader,
+            shared_strings,
+            styles,
+            is_1904,
+            out_path,
+            options,
+            in_memory,
+        );
+    }
+    let mut wtr = RowWriter::new(out_path, options, in_memory)?;
+    let mut summary = ExportSummary::default();
+    let read = if options.pipeline {
+        read_sheet_pipelined(
+            reader,
+            shared_strings,
+            styles,
+            is_1904,
+            options,
+            &mut wtr,
+            &mut summary,
+        )
+    } else {
+        read_sheet(
+            reader,
+            shared_strings,
+            styles,
+            is_1904,
+            options,
+            &mut wtr,
+            &mut summary,
+        )
+    };
+    let mut progress = match read {
+        Ok(progress) => progress,
+        Err(e) => {
+            // Leave no partial output behind, e.g. rows a failing cell_transform never redacted
+            wtr.remove_files();
+            return Err(e);
+        }
+    };
+    let (written, contents) = wtr.finish(&mut summary)?;
+    progress.rows_written += written;
+    if let Some(cb) = &options.progress {
+        cb(&progress);
+    }
+    summary.rows_written = progress.rows_written;
+    Ok((summary, contents))
+}
+
+/// Where read_sheet writes the rows of a sheet: a RowWriter, or a RowSink through SinkWriter
+pub(crate) trait RowTarget {
+    /// Whether write needs the typed values of the cells besides their fields
+    fn typed(&self) -> bool {
+        false
+    }
+
+    /// Width of the sheet from its `<dimension>`
+    fn set_sheet_width(&mut self, width: usize);
+
+    /// Write a row: its fields, which of them hold a value, with typed its values, and its
+    /// 1-based row number
+    /// Returns the number of rows written, like RowWriter::write.
+    fn write(
+        &mut self,
+        row: &mut Vec<String>,
+        present: &[bool],
+        values: &mut Vec<CellValue>,
+        row_number: u32,
+    ) -> Result<u64>;
+
+    /// Whether later rows are dropped anyway, so reading can stop
+    fn limit_reached(&self) -> bool;
+
+    /// Whether cell wants every cell holding a value or a formula
+    fn wants_cells(&self) -> bool {
+        false
+    }
+
+    /// A cell as read, passed before write gets its row; the rows of some cells never get
+    /// there, e.g. those dropped by cell_transform or above header_row
+    fn cell(&mut self, _cell: &ReadCell) -> Result<()> {
+        Ok(())
+    }
+
+    /// With UnitOutput::Column: the 0-based columns followed by a unit column, and their unit;
+    /// passed once, before the first row
+    fn set_unit_columns(&mut self, _columns: &[(usize, Unit)]) {}
+
+    /// With UnitOutput::Column: which fields of the row passed to the next write hold a value
+    /// in the unit of their unit column
+    fn set_row_units(&mut self, _in_unit: &[bool]) {}
+
+    /// Called by the thread that wrote the rows once it is done, before another thread may
+    /// finish the target
+    fn release(&mut self) {}
+}
+
+/// A cell read by read_sheet, for RowTarget::cell
+/// raw: the value as stored, i.e. the `<v>` text or the inline string
+/// field: the value as written to the row; is_text: whether it is text, even if it looks like a
+///   number
+/// style: the cell's own style, else that of its row or column
+/// formula: the `<f>` text without '=', shared formulas translated to the cell
+pub(crate) struct ReadCell<'a> {
+    pub row: u32,
+    pub column: u32,
+    pub raw: &'a str,
+    pub field: &'a str,
+    pub is_text: bool,
+    pub kind: CellKind,
+    pub style: Option<u32>,
+    pub formula: Option<&'a str>,
+}
+
+/// The last row and column that cell and row references of a sheet may point at
+/// dimension: the `<dimension>` they come from, else they are the worksheet limits
+struct SheetBounds {
+    rows: u32,
+    columns: u32,
+    dimension: Option<String>,
+}
+
+impl Default for SheetBounds {
+    fn default() -> Self {
+        Self {
+            rows: MAX_ROWS,
+            columns: MAX_COLUMNS,
+            dimension: None,
+        }
+    }
+}
+
+impl SheetBounds {
+    /// The last row, described for a warning
+    fn last_row(&self) -> String {
+        match &self.dimension {
+            Some(dimension) => format!("the last row {} of the dimension {}", self.rows, dimension),
+            None => format!("the last worksheet row {}", self.rows),
+        }
+    }
+
+    /// The last column, described for a warning
+    fn last_column(&self) -> String {
+        match &self.dimension {
+            Some(dimension) => format!(
+                "the last column {} of the dimension {}",
+                index_to_col(self.columns),
+                dimension
+            ),
+            None => format!("the last worksheet column {}", index_to_col(self.columns)),
+        }
+    }
+}
+
+/// Parse the rows of a worksheet into target, counting cells and warnings in summary
+/// Returns the progress at the end of the sheet; rows the target writes on finishing are not
+/// counted yet.
+pub(crate) fn read_sheet<R: BufRead, S: StringTable + ?Sized, T: RowTarget>(
+    reader: R,
+    shared_strings: &S,
+    styles: &[StyleInfo],
+    is_1904: bool,
+    options: &ExportOptions,
+    wtr: &mut T,
+    summary: &mut ExportSummary,
+) -> Result<ExportProgress> {
+    let mut xml = NsReader::from_reader(reader);
     let mut buf = Vec::new();
-    let mut wtr = csv::WriterBuilder::new()
-        .flexible(true)
-        .delimiter(delimiter)
-        .from_path(out_path)?;
+    let typed = wtr.typed();
+    let cells = wtr.wants_cells();
+    // For a target that wants cells: the raw value and the formula of the current cell, and
+    // the first cell and formula of every shared formula by si
+    let mut raw = String::new();
+    let mut in_formula = false;
+    let mut cell_formula: Option<String> = None;
+    let mut shared_index: Option<u32> = None;
+    let mut shared_formulas: HashMap<u32, (CellRef, String)> = HashMap::new();
+    // With cell_transform: the fields of the first non-blank row, naming the columns
+    let mut headers: Option<Vec<String>> = None;
+    // Set by cell_transform to leave out the current row
+    let drop_row = std::cell::Cell::new(false);
+    // Whether a row lies above the header row and is left out
+    let above_header = |row: u32| options.header_row.is_some_and(|header| row < header);
 
     let mut num_columns: Option<usize> = None;
     let mut current_row_idx: u32 = 0;
     let mut row_vals: Vec<String> = Vec::new();
+    // Which fields of row_vals hold a value (an empty string counts, a missing cell doesn't)
+    let mut row_present: Vec<bool> = Vec::new();
+    // The typed values of row_vals, only kept for targets that need them
+    let mut row_values: Vec<CellValue> = Vec::new();
+    // With UnitOutput::Column: the units of row_vals, and the columns followed by a unit
+    // column once the first row written decides them from the `<col>` styles
+    let units_in_columns = options.units == UnitOutput::Column;
+    let mut row_units: Vec<Option<Unit>> = Vec::new();
+    let mut column_units: Option<Vec<(usize, Unit)>> = None;
+    let mut dimension_width: usize = 0;
+    // The unit of every style's number format, if units are written
+    let style_units: Vec<Option<Unit>> = match options.units {
+        UnitOutput::Off => Vec::new(),
+        _ => styles
+            .iter()
+            .map(|s| s.format_code.as_deref().and_then(format_unit))
+            .collect(),
+    };
     let mut cell_col: Option<u32> = None;
+    // Inside a <v> or an inline string's <t>, whose text (whitespace included) is the value;
+    // the whitespace around them and <f> and phonetic <rPh> text are left out
+    let mut in_value = false;
+    let mut in_phonetic = false;
+    // The r attribute of the current cell if it is not a valid reference
+    let mut invalid_ref: Option<String> = None;
     let mut cell_type: Option<String> = None;
     let mut cell_style_idx: Option<u32> = None;
     let mut cell_val: String = String::new();
+    // Default styles for cells without an `s` attribute: <col style> ranges and <row s customFormat>
+    let mut col_styles: Vec<(u32, u32, u32)> = Vec::new();
+    let mut row_style: Option<u32> = None;
+    // The tokens of every date format, if text in date cells is read as dates
+    let style_dates: Vec<Option<Vec<DateToken>>> = match options.year_window {
+        Some(_) => styles
+            .iter()
+            .map(|s| {
+                s.format_code
+                    .as_deref()
+                    .filter(|_| s.is_date)
+                    .and_then(parse_date_format)
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    let mut bounds = SheetBounds::default();
+    // Symbols for Formatted numbers whose format carries no locale
+    let number_locale = if options.decimal_comma {
+        NumberLocale {
+            decimal_separator: ',',
+            group_separator: '.',
+        }
+    } else {
+        NumberLocale::default()
+    };
+    let mut progress = ExportProgress::default();
+    let date_system = WithLeapBug {
+        system: match &options.date_system {
+            Some(system) => system.as_ref(),
+            None if is_1904 => &Excel1904,
+            None => &Excel1900,
+        },
+        policy: options.leap_bug,
+    };
+    let date_system: &dyn DateSystem = &date_system;
 
     loop {
-        match xml.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) => {
-                if tag_eq_ignore_case(e.name().as_ref(), "row") {
+        match read_event(&mut xml, &mut buf, SPREADSHEETML) {
+            Ok((true, Event::Empty(e))) | Ok((true, Event::Start(e)))
+                if e.local_name().as_ref() == b"col" =>
+            {
+                let (mut min, mut max, mut style) = (None, None, None);
+                e.attributes().flatten().for_each(|a| {
+                    let value = String::from_utf8_lossy(&a.value).parse::<u32>().ok();
+                    match a.key.as_ref() {
+                        b"min" => min = value,
+                        b"max" => max = value,
+                        b"style" => style = value,
+                        _ => {}
+                    }
+                });
+                if let (Some(min), Some(style)) = (min, style) {
+                    col_styles.push((min, max.unwrap_or(min), style));
+                }
+            }
+            Ok((true, Event::Empty(e))) if e.local_name().as_ref() == b"dimension" => {
+                e.attributes().flatten().for_each(|a| {
+                    if a.key.as_ref() == b"ref"
+                        && let Some((_, end)) = parse_range_ref(&String::from_utf8_lossy(&a.value))
+                    {
+                        progress.estimated_rows = Some(end.row.min(MAX_ROWS));
+                        // "A1" is what some writers emit whatever the sheet holds
+                        if options.on_out_of_bounds != OutOfBounds::Keep
+                            && (end.col, end.row) != (1, 1)
+                        {
+                            bounds = SheetBounds {
+                                rows: end.row.min(MAX_ROWS),
+                                columns: end.col.min(MAX_COLUMNS),
+                                dimension: Some(String::from_utf8_lossy(&a.value).into_owned()),
+                            };
+                        }
+                        // Size the row buffers once instead of growing them cell by cell
+                        let width = end.col.min(MAX_COLUMNS) as usize;
+                        dimension_width = width;
+                        row_vals.reserve(width);
+                        row_present.reserve(width);
+                        wtr.set_sheet_width(width);
+                    }
+                });
+            }
+            // A cell without content, e.g. one that only carries formatting
+            Ok((true, Event::Empty(e))) if e.local_name().as_ref() == b"c" => {
+                *summary.cells.entry(CellKind::Empty).or_default() += 1;
+            }
+            Ok((true, Event::Start(e))) => {
+                if e.local_name().as_ref() == b"row" {
                     let mut r_attr = None;
+                    let mut style = None;
+                    let mut custom_format = false;
 
-                    e.attributes().flatten().into_iter().for_each(|a| {
-                        if a.key.as_ref() == b"r" {
-                            r_attr = String::from_utf8_lossy(&a.value).parse::<u32>().ok();
+                    e.attributes().flatten().for_each(|a| match a.key.as_ref() {
+                        b"r" => r_attr = String::from_utf8_lossy(&a.value).parse::<u32>().ok(),
+                        b"s" => style = String::from_utf8_lossy(&a.value).parse::<u32>().ok(),
+                        b"customFormat" => {
+                            custom_format = matches!(a.value.as_ref(), b"1" | b"true")
                         }
+                        _ => {}
                     });
+                    // The row style only applies to its cells when customFormat is set
+                    row_style = style.filter(|_| custom_format);
 
-                    let next = r_attr.unwrap_or(current_row_idx + 1);
+                    let next = match r_attr {
+                        Some(r) if r > bounds.rows => {
+                            // A corrupt row number must not produce a million blank rows
+                            let message = format!(
+                                "row {} is beyond {}; treated as row {}",
+                                r,
+                                bounds.last_row(),
+                                current_row_idx + 1
+                            );
+                            if options.on_out_of_bounds == OutOfBounds::Error {
+                                anyhow::bail!(message);
+                            }
+                            report_warning(
+                                summary,
+                                options,
+                                ExportWarning {
+                                    kind: WarningKind::InvalidReference,
+                                    cell: None,
+                                    message,
+                                },
+                            );
+                            current_row_idx + 1
+                        }
+                        Some(r) => r,
+                        None => current_row_idx + 1,
+                    };
                     while current_row_idx + 1 < next {
-                        wtr.write_record(std::iter::empty::<String>())?;
+                        if !above_header(current_row_idx + 1) {
+                            progress.rows_written += wtr.write(
+                                &mut Vec::new(),
+                                &[],
+                                &mut Vec::new(),
+                                current_row_idx + 1,
+                            )?;
+                            // Rows left out of the XML are written too, so they count as well
+                            if let Some(cb) = &options.progress {
+                                cb(&progress);
+                            }
+                        }
                         current_row_idx += 1;
                     }
                     current_row_idx = next;
                     row_vals.clear();
-                } else if tag_eq_ignore_case(e.name().as_ref(), "c") {
+                    row_present.clear();
+                    row_values.clear();
+                } else if e.local_name().as_ref() == b"c" {
                     cell_col = None;
                     cell_type = None;
                     cell_val.clear();
                     cell_style_idx = None;
+                    invalid_ref = None;
+                    cell_formula = None;
                     let mut r_attr: Option<CellRef> = None;
 
                     e.attributes()
@@ -464,7 +2113,11 @@ pub fn export_sheet_xml_to_csv<R: BufRead>(
                         .into_iter()
                         .for_each(|a| match a.key.as_ref() {
                             b"r" => {
-                                r_attr = parse_cell_ref(&String::from_utf8_lossy(&a.value));
+                                let reference = String::from_utf8_lossy(&a.value);
+                                r_attr = parse_cell_ref(&reference);
+                                if r_attr.is_none() {
+                                    invalid_ref = Some(reference.into_owned());
+                                }
                             }
                             b"t" => {
                                 cell_type = Some(String::from_utf8_lossy(&a.value).into_owned())
@@ -479,131 +2132,3024 @@ pub fn export_sheet_xml_to_csv<R: BufRead>(
                     if let Some(cr) = r_attr {
                         cell_col = Some(cr.col);
                     }
-                } else if tag_eq_ignore_case(e.name().as_ref(), "is") {
+                } else if e.local_name().as_ref() == b"is" {
                     cell_val.clear();
-                } else if tag_eq_ignore_case(e.name().as_ref(), "t") {
-                    // text will come in Text event
+                } else if e.local_name().as_ref() == b"v"
+                    || (e.local_name().as_ref() == b"t" && !in_phonetic)
+                {
+                    in_value = true;
+                } else if e.local_name().as_ref() == b"rPh" {
+                    in_phonetic = true;
+                } else if e.local_name().as_ref() == b"f" && cells {
+                    in_formula = true;
+                    shared_index = shared_formula_index(&e);
                 }
             }
-            Ok(Event::End(e)) => {
-                if tag_eq_ignore_case(e.name().as_ref(), "c") {
+            Ok((true, Event::Empty(e))) if cells && e.local_name().as_ref() == b"f" => {
+                let cell = CellRef {
+                    col: cell_col.unwrap_or((row_vals.len() as u32) + 1),
+                    row: current_row_idx,
+                };
+                expand_shared_formula(
+                    cell,
+                    &mut cell_formula,
+                    shared_formula_index(&e),
+                    &mut shared_formulas,
+                );
+            }
+            Ok((true, Event::End(e)))
+                if matches!(e.local_name().as_ref(), b"v" | b"t" | b"rPh") =>
+            {
+                in_value = false;
+                in_phonetic &= e.local_name().as_ref() != b"rPh";
+            }
+            Ok((true, Event::End(e))) if in_formula && e.local_name().as_ref() == b"f" => {
+                in_formula = false;
+                let cell = CellRef {
+                    col: cell_col.unwrap_or((row_vals.len() as u32) + 1),
+                    row: current_row_idx,
+                };
+                expand_shared_formula(
+                    cell,
+                    &mut cell_formula,
+                    shared_index.take(),
+                    &mut shared_formulas,
+                );
+            }
+            Ok((true, Event::End(e))) => {
+                if e.local_name().as_ref() == b"c" {
                     let col = cell_col.unwrap_or((row_vals.len() as u32) + 1);
+                    // A corrupt reference like ZZZZ1 must neither allocate a huge row nor be
+                    // placed after the previous cell
+                    let skipped = match invalid_ref.take() {
+                        Some(reference) => Some((
+                            reference,
+                            "not a cell reference within A1:XFD1048576; cell skipped".to_string(),
+                        )),
+                        None if col > bounds.columns => Some((
+                            format!("{}{}", index_to_col(col), current_row_idx),
+                            format!(
+                                "column {} is beyond {}; cell skipped",
+                                col,
+                                bounds.last_column()
+                            ),
+                        )),
+                        None => None,
+                    };
+                    if let Some((cell, message)) = skipped {
+                        if options.on_out_of_bounds == OutOfBounds::Error {
+                            anyhow::bail!("{}: {}", cell, message);
+                        }
+                        report_warning(
+                            summary,
+                            options,
+                            ExportWarning {
+                                kind: WarningKind::InvalidReference,
+                                cell: Some(cell),
+                                message,
+                            },
+                        );
+                        cell_col = None;
+                        cell_type = None;
+                        cell_val.clear();
+                        cell_style_idx = None;
+                        buf.clear();
+                        continue;
+                    }
                     let needed = col as usize;
                     if row_vals.len() < needed {
                         row_vals.resize(needed, String::new());
+                        row_present.resize(needed, false);
+                    }
+                    if typed && row_values.len() < needed {
+                        row_values.resize(needed, CellValue::Empty);
+                    }
+                    if units_in_columns && row_units.len() < needed {
+                        row_units.resize(needed, None);
                     }
 
-                    let v = match cell_type.as_deref() {
+                    // Cells without their own style inherit the row or column style
+                    let style_idx = cell_style_idx.or(row_style).or_else(|| {
+                        col_styles
+                            .iter()
+                            .find(|(min, max, _)| (*min..=*max).contains(&col))
+                            .map(|(_, _, style)| *style)
+                    });
+                    if cells {
+                        raw.clone_from(&cell_val);
+                    }
+                    let mut is_text = false;
+                    let mut kind = CellKind::Number;
+                    let mut v = match cell_type.as_deref() {
                         Some("s") => {
-                            if let Ok(idx) = cell_val.trim().parse::<usize>() {
-                                shared_strings.get(idx).cloned().unwrap_or_default()
+                            let raw = cell_val.trim();
+                            let string = match raw.parse::<usize>() {
+                                Ok(idx) => shared_strings.string(idx)?,
+                                Err(_) => None,
+                            };
+                            match string {
+                                Some(s) => {
+                                    is_text = true;
+                                    kind = CellKind::Text;
+                                    s.into_owned()
+                                }
+                                None => {
+                                    kind = CellKind::Empty;
+                                    let cell = format!("{}{}", index_to_col(col), current_row_idx);
+                                    let message = format!(
+                                        "shared string index '{}' out of range ({} strings)",
+                                        raw,
+                                        shared_strings.string_count()
+                                    );
+                                    // Reported with Error too, so on_warning sees it before the export fails
+                                    report_warning(
+                                        summary,
+                                        options,
+                                        ExportWarning {
+                                            kind: WarningKind::MissingSharedString,
+                                            cell: Some(cell.clone()),
+                                            message: message.clone(),
+                                        },
+                                    );
+                                    if options.on_missing_string == MissingString::Error {
+                                        anyhow::bail!("{}: {}", cell, message);
+                                    }
+                                    match options.on_missing_string {
+                                        MissingString::Index => {
+                                            kind = CellKind::Number;
+                                            raw.to_string()
+                                        }
+                                        _ if options.debug_cells => format!("⟨missing-ss:{}⟩", raw),
+                                        _ => String::new(),
+                                    }
+                                }
+                            }
+                        }
+                        Some("b") => {
+                            kind = CellKind::Bool;
+                            if cell_val.trim() == "1" {
+                                "TRUE"
                             } else {
-                                String::new()
+                                "FALSE"
                             }
+                            .to_string()
                         }
-                        Some("b") => if cell_val.trim() == "1" {
-                            "TRUE"
-                        } else {
-                            "FALSE"
+                        Some("inlineStr") | Some("str") => {
+                            is_text = true;
+                            kind = CellKind::Text;
+                            cell_val.clone()
                         }
-                        .to_string(),
-                        Some("inlineStr") | Some("str") => cell_val.clone(),
                         Some("e") => {
+                            kind = CellKind::Error;
                             format!("#ERROR:{}", cell_val)
                         }
+                        // ISO 8601 dates, written by strict OOXML files instead of serials
+                        Some("d") => {
+                            kind = CellKind::Date;
+                            parse_iso_datetime(cell_val.trim())
+                                .map(|d| options.date_output.format(&d, &options.timezone))
+                                .unwrap_or_else(|| cell_val.clone())
+                        }
                         _ => {
                             // Numeric value
+                            let style = style_idx.and_then(|idx| styles.get(idx as usize));
+                            let unit = style_idx
+                                .and_then(|idx| style_units.get(idx as usize))
+                                .and_then(Option::as_ref);
                             match cell_val.trim().parse::<f64>() {
+                                // Text (@) formatted cells keep the stored string, e.g. "00420"
+                                _ if style.is_some_and(|style_info| style_info.is_text) => {
+                                    kind = CellKind::Text;
+                                    cell_val.clone()
+                                }
                                 Ok(num) => {
-                                    let is_date_style = cell_style_idx
-                                        .and_then(|idx| styles.get(idx as usize))
-                                        .is_some_and(|style_info| style_info.is_date);
-
-                                    if is_date_style {
-                                        excel_serial_to_iso_date(num, is_1904)
+                                    if style.is_some_and(|style_info| style_info.is_date) {
+                                        kind = CellKind::Date;
+                                        if options.leap_bug == LeapBug::Error
+                                            && date_system.is_leap_bug_day(num)
+                                        {
+                                            anyhow::bail!(
+                                                "{}{}: serial {} is 1900-02-29 in the 1900 date system, a day that doesn't exist",
+                                                index_to_col(col),
+                                                current_row_idx,
+                                                cell_val.trim()
+                                            );
+                                        }
+                                        date_system
+                                            .to_datetime(num)
+                                            .map(|d| {
+                                                options.date_output.format(&d, &options.timezone)
+                                            })
                                             .unwrap_or_else(|| cell_val.clone())
+                                    } else if let Some(unit) = unit {
+                                        let value = match unit {
+                                            Unit::Percent => format_general(num * 100.0),
+                                            Unit::Currency(_) => match options.general_precision {
+                                                GeneralPrecision::Exact => cell_val.clone(),
+                                                GeneralPrecision::Excel => format_general(num),
+                                            },
+                                        };
+                                        if units_in_columns {
+                                            row_units[(col as usize) - 1] = Some(unit.clone());
+                                            value
+                                        } else {
+                                            format!("{} {}", value, unit.symbol())
+                                        }
+                                    } else if options.numbers == NumberOutput::Formatted {
+                                        let code = style
+                                            .and_then(|s| s.format_code.as_deref())
+                                            .unwrap_or("General");
+                                        format_number(num, code, number_locale)
                                     } else {
-                                        cell_val.clone()
+                                        match options.general_precision {
+                                            GeneralPrecision::Exact => cell_val.clone(),
+                                            GeneralPrecision::Excel => format_general(num),
+                                        }
                                     }
                                 }
-                                Err(_) => cell_val.clone(),
+                                Err(_) => {
+                                    kind = CellKind::Text;
+                                    cell_val.clone()
+                                }
                             }
                         }
                     };
+                    // Text typed into a date formatted cell, read the way Excel does
+                    if let Some(window_start) = options.year_window
+                        && kind == CellKind::Text
+                        && let Some(date) = style_idx
+                            .and_then(|idx| style_dates.get(idx as usize))
+                            .and_then(Option::as_ref)
+                            .and_then(|tokens| parse_date_text(&v, tokens, window_start))
+                    {
+                        kind = CellKind::Date;
+                        v = options.date_output.format(&date, &options.timezone);
+                        // The typed value is read like the ISO date of a t="d" cell
+                        cell_val = format_iso_datetime(&date);
+                        cell_type = Some("d".to_string());
+                    }
+                    if !is_text && v.is_empty() {
+                        kind = CellKind::Empty;
+                    }
+                    *summary.cells.entry(kind).or_default() += 1;
+                    if typed || options.cell_transform.is_some() {
+                        let mut value = CellValue::parse(
+                            kind,
+                            &v,
+                            &cell_val,
+                            cell_type.as_deref(),
+                            date_system,
+                        );
+                        if let Some(transform) = &options.cell_transform {
+                            let context = CellContext {
+                                row: current_row_idx,
+                                column: col,
+                                header: headers
+                                    .as_ref()
+                                    .and_then(|h| h.get(col as usize - 1))
+                                    .map(String::as_str),
+                                dropped: &drop_row,
+                            };
+                            let transformed =
+                                transform(&context, value.clone()).with_context(|| {
+                                    format!("cell {}{}", index_to_col(col), current_row_idx)
+                                })?;
+                            // Unchanged values keep their field, e.g. a number as stored
+                            if transformed != value {
+                                v = match &transformed {
+                                    CellValue::Date(date) => {
+                                        options.date_output.format(date, &options.timezone)
+                                    }
+                                    _ => transformed.to_field(options.general_precision),
+                                };
+                                is_text = matches!(transformed, CellValue::Text(_));
+                                value = transformed;
+                            }
+                        }
+                        if typed {
+                            row_values[(col as usize) - 1] = value;
+                        }
+                    }
+                    if cells && (!raw.is_empty() || cell_formula.is_some()) {
+                        wtr.cell(&ReadCell {
+                            row: current_row_idx,
+                            column: col,
+                            raw: &raw,
+                            field: &v,
+                            is_text,
+                            kind,
+                            style: style_idx,
+                            formula: cell_formula.as_deref(),
+                        })?;
+                    }
+                    row_present[(col as usize) - 1] = is_text || !v.is_empty();
                     row_vals[(col as usize) - 1] = v;
 
                     cell_col = None;
                     cell_type = None;
                     cell_val.clear();
                     cell_style_idx = None;
-                } else if tag_eq_ignore_case(e.name().as_ref(), "row") {
+                } else if e.local_name().as_ref() == b"row" {
+                    if drop_row.take() || above_header(current_row_idx) {
+                        // Dropped by cell_transform or above header_row: not written, not even
+                        // as a blank row
+                        row_vals.clear();
+                        row_present.clear();
+                        row_values.clear();
+                        row_units.clear();
+                        buf.clear();
+                        continue;
+                    }
                     if num_columns.is_none() {
                         let last_non_empty = row_vals.iter().rposition(|c| !c.is_empty());
                         num_columns = Some(last_non_empty.map_or(0, |i| i + 1));
                     }
+                    if options.cell_transform.is_some()
+                        && headers.is_none()
+                        && row_vals.iter().any(|v| !v.is_empty())
+                    {
+                        headers = Some(row_vals.clone());
+                    }
                     if let Some(n) = num_columns {
                         if row_vals.len() < n {
                             row_vals.resize(n, String::new());
                         }
+                        if typed && row_values.len() < n {
+                            row_values.resize(n, CellValue::Empty);
+                        }
                     }
-                    wtr.write_record(row_vals.iter())?;
+                    if units_in_columns {
+                        let width = dimension_width.max(row_vals.len());
+                        set_row_units(
+                            wtr,
+                            &mut column_units,
+                            || unit_columns(&col_styles, &style_units, width),
+                            &mut row_vals,
+                            &row_units,
+                        );
+                        row_units.clear();
+                    }
+                    progress.rows_written += wtr.write(
+                        &mut row_vals,
+                        &row_present,
+                        &mut row_values,
+                        current_row_idx,
+                    )?;
                     row_vals.clear();
+                    row_present.clear();
+                    row_values.clear();
+                    progress.bytes_read = xml.buffer_position() as u64;
+                    if let Some(cb) = &options.progress {
+                        cb(&progress);
+                    }
+                    if wtr.limit_reached() {
+                        break;
+                    }
                 }
             }
-            Ok(Event::Text(t)) => {
-                let txt = t.unescape()?;
-                if !txt.is_empty() {
-                    cell_val.push_str(&txt);
-                }
+            Ok((_, Event::Text(t))) if in_value => {
+                cell_val.push_str(&t.unescape()?);
             }
-            Ok(Event::Eof) => break,
+            Ok((_, Event::Text(t))) if in_formula => {
+                cell_formula
+                    .get_or_insert_with(String::new)
+                    .push_str(&t.unescape()?);
+            }
+            Ok((_, Event::Eof)) => break,
             Err(e) => return Err(anyhow::anyhow!("XML error in worksheet: {}", e)),
             _ => {}
         }
         buf.clear();
     }
-    if !row_vals.is_empty() {
-        wtr.write_record(row_vals.iter())?;
+    if !row_vals.is_empty() && !drop_row.get() && !above_header(current_row_idx) {
+        if units_in_columns {
+            let width = dimension_width.max(row_vals.len());
+            set_row_units(
+                wtr,
+                &mut column_units,
+                || unit_columns(&col_styles, &style_units, width),
+                &mut row_vals,
+                &row_units,
+            );
+        }
+        progress.rows_written += wtr.write(
+            &mut row_vals,
+            &row_present,
+            &mut row_values,
+            current_row_idx,
+        )?;
     }
-    wtr.flush()?;
-    Ok(())
+    progress.bytes_read = xml.buffer_position() as u64;
+    Ok(progress)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-    use std::io::BufReader;
-    use tempfile::NamedTempFile;
+/// With UnitOutput::Column: tell wtr which fields of a row go with a unit column, first
+/// deciding the unit columns with decide if no row did yet
+fn set_row_units<T: RowTarget + ?Sized>(
+    wtr: &mut T,
+    columns: &mut Option<Vec<(usize, Unit)>>,
+    decide: impl FnOnce() -> Vec<(usize, Unit)>,
+    row: &mut [String],
+    units: &[Option<Unit>],
+) {
+    let columns = columns.get_or_insert_with(|| {
+        let columns = decide();
+        wtr.set_unit_columns(&columns);
+        columns
+    });
+    wtr.set_row_units(&place_units(columns, row, units));
+}
 
-    #[test]
-    fn test_geo_coordinate_parsing_from_xml() {
-        let xml_data = r#"
-        <worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">
-            <sheetData>
-                <row r="1">
-                    <c r="A1" t="s"><v>0</v></c>
-                    <c r="B1" t="s"><v>1</v></c>
-                </row>
-                <row r="2">
-                    <c r="A2"><v>10.123</v></c>
-                    <c r="B2"><v>-20.456</v></c>
-                </row>
-            </sheetData>
-        </worksheet>
-        "#;
-        let shared_strings = vec![
-            "origin_latitude".to_string(),
-            "origin_longitude".to_string(),
-        ];
-        let reader = BufReader::new(xml_data.as_bytes());
-        let temp_file = NamedTempFile::new().unwrap();
-        let out_path = temp_file.path();
+/// Count a warning in the summary and hand it to the warning callback, if any
+fn report_warning(summary: &mut ExportSummary, options: &ExportOptions, warning: ExportWarning) {
+    *summary.warnings.entry(warning.kind).or_default() += 1;
+    if let Some(cb) = &options.on_warning {
+        cb(&warning);
+    }
+}
 
-        export_sheet_xml_to_csv(reader, &shared_strings, &[], false, out_path, b',').unwrap();
+/// A row kept by sample: its row number, fields and present flags
+type SampledRow = (u32, Vec<String>, Vec<bool>, Vec<bool>);
 
-        let csv_content = fs::read_to_string(out_path).unwrap();
-        let expected_content = "origin_latitude,origin_longitude\n10.123,-20.456\n";
-        assert_eq!(csv_content, expected_content);
+/// Header of the column added by row_numbers
+const ROW_NUMBER_COLUMN: &str = "row";
+
+/// Holds the bytes of the record just encoded by RowWriter; a Cell so they can be taken out
+/// through csv::Writer::get_ref
+#[derive(Default)]
+struct RecordBuffer(std::cell::Cell<Vec<u8>>);
+
+impl Write for RecordBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.get_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An output file of RowWriter, counting the bytes written to it
+struct Counted {
+    out: OutputWriter,
+    bytes: u64,
+}
+
+impl Write for Counted {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.out.write(buf)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// The current output file of RowWriter
+/// Csv: CSV records are encoded straight into it
+/// Encoded: records are encoded first and written as bytes: Markdown, HTML and pgcopy, and
+///   CSV with split_size, which needs the size of a record before deciding on its part
+enum RowOutput {
+    Csv(Box<csv::Writer<Counted>>),
+    Encoded(Counted),
+}
+
+impl RowOutput {
+    /// The output for the rows of options, starting with the bytes of first
+    fn new(out: OutputWriter, options: &ExportOptions, first: &[u8]) -> Result<Self> {
+        let mut out = Counted { out, bytes: 0 };
+        out.write_all(first)?;
+        Ok(
+            if options.format == OutputFormat::Csv && options.split_size.is_none() {
+                RowOutput::Csv(Box::new(csv_writer(options).from_writer(out)))
+            } else {
+                RowOutput::Encoded(out)
+            },
+        )
+    }
+
+    /// Flush the CSV encoder and return the file
+    fn into_inner(self) -> Result<Counted> {
+        match self {
+            RowOutput::Csv(encoder) => encoder.into_inner().map_err(|e| e.into_error().into()),
+            RowOutput::Encoded(out) => Ok(out),
+        }
+    }
+}
+
+/// The CSV encoder settings of options
+fn csv_writer(options: &ExportOptions) -> csv::WriterBuilder {
+    let mut builder = csv::WriterBuilder::new();
+    builder.flexible(true).delimiter(options.delimiter);
+    builder
+}
+
+/// Writes sheet rows to CSV, applying the row level ExportOptions
+/// With trim, blank rows are held back until a non-blank row follows, so trailing blank rows at
+/// the end of the sheet are never written.
+/// The header row (if any) is written just before the first record.
+/// CSV goes straight into the file, unless split_size needs the exact size of every record
+/// before deciding whether it still fits into the current part (see RowOutput).
+struct RowWriter<'a> {
+    /// Encodes the CSV records that are not written straight into out
+    encoder: csv::Writer<RecordBuffer>,
+    out: RowOutput,
+    out_path: PathBuf,
+    options: &'a ExportOptions,
+    /// Row numbers of the blank rows held back by trim
+    pending_blank_rows: Vec<u32>,
+    /// Width of the sheet from its `<dimension>`, used for the header row
+    sheet_width: Option<usize>,
+    header_written: bool,
+    /// Records written, not counting the first record repeated in every part
+    records: u64,
+    /// The files written so far, the last one is out
+    files: Vec<PathBuf>,
+    /// The encoded first record, repeated at the top of every part when splitting
+    first_record: Option<Vec<u8>>,
+    /// Records in the current part, not counting the repeated first record
+    part_rows: u64,
+    /// Whether the columns of row_filter, column_types and column_mapping are known: from the
+    /// start with a synthetic header, else once the first non-blank row is seen
+    columns_bound: bool,
+    filter: Option<BoundFilter<'a>>,
+    types: Option<BoundColumnTypes>,
+    mapping: Option<BoundMapping>,
+    /// With UnitOutput::Column: the columns followed by a unit column, and the fields of the
+    /// row being written that hold a value in its unit (see RowTarget::set_row_units)
+    unit_columns: Vec<(usize, Unit)>,
+    row_units: Vec<bool>,
+    /// The dedupe columns (empty for whole rows), the keys of the rows seen and how many
+    /// duplicates were skipped
+    dedupe_on: Option<Vec<usize>>,
+    seen: HashSet<Vec<String>>,
+    duplicates: u64,
+    /// With sample: the rows kept so far, with their row number and present flags
+    sample: Option<Reservoir<SampledRow>>,
+    /// Warnings about rows, merged into the summary by finish
+    warnings: BTreeMap<WarningKind, u64>,
+    /// With pgcopy: the schema of the rows so far, started by the first record; with pgcopy and
+    /// markdown: the number of columns every row is padded to
+    schema: Option<SchemaBuilder>,
+    columns: usize,
+    /// Number of fields of the widest record written
+    widest: usize,
+    /// Whether the files are kept in memory, and the contents of those finished so far
+    in_memory: bool,
+    contents: Vec<Vec<u8>>,
+    /// Debug span of the current file, entered by its first record until it is closed or the
+    /// writing thread releases it; records its size when closed
+    /// The spans of all parts are children of the span current when the writer was created.
+    part_span: tracing::Span,
+    parent_span: tracing::Span,
+    part_entered: bool,
+}
+
+/// Create an output file of RowWriter, a writer discarding everything on a dry run, or one
+/// keeping it in memory
+fn open_output(path: &Path, options: &ExportOptions, in_memory: bool) -> Result<OutputWriter> {
+    if options.dry_run {
+        return Ok(OutputWriter::discard());
+    }
+    if in_memory {
+        return Ok(OutputWriter::Memory(Vec::new()));
+    }
+    OutputWriter::create(path, options.compression)
+}
+
+/// The debug span of an output file of RowWriter
+fn part_span(parent: &tracing::Span, path: &Path) -> tracing::Span {
+    tracing::debug_span!(parent: parent, "part", file = %path.display(), bytes = tracing::field::Empty)
+}
+
+impl<'a> RowWriter<'a> {
+    fn new(out_path: &Path, options: &'a ExportOptions, in_memory: bool) -> Result<Self> {
+        let first_file = if options.split_rows.is_some() || options.split_size.is_some() {
+            part_path(out_path, 1)
+        } else {
+            out_path.to_path_buf()
+        };
+        let parent_span = tracing::Span::current();
+        let mut writer = Self {
+            part_span: part_span(&parent_span, &first_file),
+            parent_span,
+            part_entered: false,
+            encoder: csv_writer(options).from_writer(RecordBuffer::default()),
+            out: RowOutput::new(open_output(&first_file, options, in_memory)?, options, &[])?,
+            out_path: out_path.to_path_buf(),
+            options,
+            pending_blank_rows: Vec::new(),
+            sheet_width: None,
+            header_written: !options.header.is_synthetic(),
+            records: 0,
+            files: vec![first_file],
+            first_record: None,
+            part_rows: 0,
+            columns_bound: false,
+            filter: None,
+            types: None,
+            mapping: None,
+            unit_columns: Vec::new(),
+            row_units: Vec::new(),
+            dedupe_on: None,
+            seen: HashSet::new(),
+            duplicates: 0,
+            sample: options
+                .sample
+                .map(|n| Reservoir::new(n, options.sample_seed)),
+            warnings: BTreeMap::new(),
+            schema: None,
+            columns: 0,
+            widest: 0,
+            in_memory,
+            contents: Vec::new(),
+        };
+        if options.header.is_synthetic() {
+            writer.bind_columns(None)?;
+        }
+        Ok(writer)
+    }
+
+    /// Resolve the columns of row_filter, column_types and column_mapping by the header row, or
+    /// by position with a synthetic header
+    fn bind_columns(&mut self, header: Option<&[String]>) -> Result<()> {
+        let letters = self.options.header == HeaderRow::Letters;
+        if let Some(filter) = &self.options.row_filter {
+            self.filter = Some(match header {
+                Some(header) => filter.bind(header)?,
+                None => filter.bind_positions(letters)?,
+            });
+        }
+        if let Some(types) = &self.options.column_types {
+            if types.has_dates() && self.options.date_output != DateOutput::Iso {
+                anyhow::bail!("date and datetime column types need dates written as iso");
+            }
+            self.types = Some(match header {
+                Some(header) => types.bind(header)?,
+                None => types.bind_positions(letters)?,
+            });
+        }
+        if let Some(mapping) = &self.options.column_mapping {
+            self.mapping = Some(match header {
+                Some(header) => mapping.bind(header)?,
+                None => mapping.bind_positions(letters)?,
+            });
+        }
+        if let Some(columns) = &self.options.dedupe {
+            let positions = columns
+                .iter()
+                .map(|name| match header {
+                    Some(header) => find_column(header, name).with_context(|| {
+                        format!(
+                            "unknown column '{}' in dedupe (columns: {})",
+                            name,
+                            header.join(", ")
+                        )
+                    }),
+                    None => column_position(name, letters)
+                        .with_context(|| format!("unknown column '{}' in dedupe", name)),
+                })
+                .collect::<Result<_>>()?;
+            self.dedupe_on = Some(positions);
+        }
+        self.columns_bound = true;
+        Ok(())
+    }
+
+    /// Write a single row, applying the trim, fixed field count, empty marker and trailing
+    /// delimiter options
+    /// present flags the fields that hold a value; fields beyond it count as missing.
+    /// values are the typed values of the fields, only passed with decimal_comma (see typed).
+    /// row_number is the 1-based row in the sheet, written in front with row_numbers.
+    /// Returns the number of rows written: 0 for a skipped or held back blank row, more than 1
+    /// when held back blank rows are written first.
+    fn write(
+        &mut self,
+        row: &mut Vec<String>,
+        present: &[bool],
+        values: &[CellValue],
+        row_number: u32,
+    ) -> Result<u64> {
+        let in_unit = std::mem::take(&mut self.row_units);
+        if self.options.trim {
+            let len = row
+                .iter()
+                .rposition(|field| !field.is_empty())
+                .map_or(0, |i| i + 1);
+            row.truncate(len);
+        }
+        let blank = row.iter().all(|field| field.is_empty());
+        // The first non-blank row names the columns (without a synthetic header)
+        let mut header_row = false;
+        if !self.columns_bound {
+            if !blank {
+                self.bind_columns(Some(row))?;
+                header_row = true;
+            }
+        } else {
+            if let Some(filter) = &self.filter
+                && !filter.matches(row)
+            {
+                return Ok(0);
+            }
+            if let Some(types) = &self.types {
+                let errors = types.apply(row);
+                for error in &errors {
+                    self.warn(ExportWarning {
+                        kind: WarningKind::TypeMismatch,
+                        cell: Some(format!(
+                            "{}{}",
+                            index_to_col(error.index as u32 + 1),
+                            row_number
+                        )),
+                        message: format!("row {} left out: {}", row_number, error.message),
+                    });
+                }
+                if !errors.is_empty() {
+                    return Ok(0);
+                }
+            }
+            // Blank rows are left to skip_blank_rows and trim
+            if let Some(columns) = &self.dedupe_on
+                && !blank
+            {
+                let key: Vec<String> = if columns.is_empty() {
+                    // Trailing empty fields do not make a row different
+                    let len = row.iter().rposition(|f| !f.is_empty()).map_or(0, |i| i + 1);
+                    row[..len].to_vec()
+                } else {
+                    columns
+                        .iter()
+                        .map(|&i| row.get(i).cloned().unwrap_or_default())
+                        .collect()
+                };
+                if !self.seen.insert(key) {
+                    self.duplicates += 1;
+                    return Ok(0);
+                }
+            }
+        }
+        // After row_filter and column_types, which read numbers with a decimal point;
+        // Formatted numbers have their decimal comma already
+        if self.typed() {
+            for (field, value) in row.iter_mut().zip(values) {
+                if matches!(value, CellValue::Number(_)) && field.contains('.') {
+                    *field = field.replace('.', ",");
+                }
+            }
+        }
+        if let Some(sample) = &mut self.sample
+            && !header_row
+        {
+            if !blank {
+                sample.offer((row_number, std::mem::take(row), present.to_vec(), in_unit));
+            }
+            return Ok(0);
+        }
+        self.emit(row, present, &in_unit, row_number, header_row)
+    }
+
+    /// Write a row that made it past the filters: hold back or skip blank rows, write the
+    /// header row first, then the held back blank rows and the row itself
+    fn emit(
+        &mut self,
+        row: &mut Vec<String>,
+        present: &[bool],
+        in_unit: &[bool],
+        row_number: u32,
+        header_row: bool,
+    ) -> Result<u64> {
+        let blank = row.iter().all(|field| field.is_empty());
+        if blank && self.options.skip_blank_rows {
+            return Ok(0);
+        }
+        if blank && self.options.trim {
+            self.pending_blank_rows.push(row_number);
+            return Ok(0);
+        }
+        let mut written = 0;
+        if !self.header_written {
+            let width = self
+                .options
+                .pad_to_columns
+                .or(self.sheet_width)
+                .unwrap_or(row.len());
+            let mut header = self.options.header.fields(width);
+            let present = self.map_columns(&mut header, &vec![true; width], &[], true)?;
+            written += self.write_record(&mut header, &present, ROW_NUMBER_COLUMN.to_string())?;
+            self.header_written = true;
+        }
+        for pending in std::mem::take(&mut self.pending_blank_rows) {
+            let mut blank_row = Vec::new();
+            let present = self.map_columns(&mut blank_row, &[], &[], false)?;
+            written += self.write_record(&mut blank_row, &present, pending.to_string())?;
+        }
+        let present = self.map_columns(row, present, in_unit, header_row)?;
+        written += self.write_record(row, &present, row_number.to_string())?;
+        Ok(written)
+    }
+
+    /// Apply column_mapping to a row (renaming the columns of a header row), returning present
+    /// reordered like the row
+    /// The unit columns go after the output columns they belong to: named "<name> unit" in a
+    /// header row, else holding the unit for the fields flagged by in_unit.
+    fn map_columns(
+        &self,
+        row: &mut Vec<String>,
+        present: &[bool],
+        in_unit: &[bool],
+        header: bool,
+    ) -> Result<Vec<bool>> {
+        if let Some(&(last, _)) = self.unit_columns.last()
+            && row.len() <= last
+        {
+            row.resize(last + 1, String::new());
+        }
+        let sources: Vec<Option<usize>> = match &self.mapping {
+            Some(mapping) => mapping.order(row.len()),
+            None => (0..row.len()).map(Some).collect(),
+        };
+        let mut present = match &self.mapping {
+            Some(mapping) if header => {
+                mapping.apply_header(row)?;
+                vec![true; row.len()]
+            }
+            Some(mapping) => mapping.apply(row, present),
+            None => present.to_vec(),
+        };
+        if self.unit_columns.is_empty() {
+            return Ok(present);
+        }
+        present.resize(row.len(), false);
+        let snake_case = self.mapping.as_ref().is_some_and(BoundMapping::snake_case);
+        for (position, source) in sources.iter().enumerate().rev() {
+            let Some((column, unit)) = source.and_then(|source| {
+                self.unit_columns
+                    .iter()
+                    .find(|(column, _)| *column == source)
+            }) else {
+                continue;
+            };
+            let field = match &row[position] {
+                _ if !header => match in_unit.get(*column).copied().unwrap_or(false) {
+                    true => unit.symbol().to_string(),
+                    false => String::new(),
+                },
+                name if name.is_empty() => "unit".to_string(),
+                name if snake_case => format!("{}_unit", name),
+                name => format!("{} unit", name),
+            };
+            present.insert(position + 1, !field.is_empty());
+            row.insert(position + 1, field);
+        }
+        Ok(present)
+    }
+
+    /// label is the row number field, only written with row_numbers
+    /// Returns the number of records written: 2 when a new part was started and the first
+    /// record repeated, 1 otherwise
+    fn write_record(
+        &mut self,
+        row: &mut Vec<String>,
+        present: &[bool],
+        label: String,
+    ) -> Result<u64> {
+        self.enter_part();
+        if let Some(n) = self.options.pad_to_columns {
+            row.resize(n, String::new());
+        }
+        // pgcopy always writes fields without a value as \N
+        let pgcopy = self.options.format == OutputFormat::PgCopy;
+        let mut nulls: Vec<bool> = if pgcopy {
+            (0..row.len())
+                .map(|i| !present.get(i).copied().unwrap_or(false))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        if self.options.newlines != Newlines::Keep {
+            row.iter_mut()
+                .for_each(|field| self.options.newlines.apply(field));
+        }
+        if let Some(marker) = &self.options.empty_as
+            && !pgcopy
+        {
+            for (i, field) in row.iter_mut().enumerate() {
+                if !present.get(i).copied().unwrap_or(false) {
+                    *field = marker.clone();
+                }
+            }
+        }
+        if self.options.row_numbers {
+            row.insert(0, label);
+            nulls.insert(0, false);
+        }
+        if self.options.trailing_delimiter {
+            row.push(String::new());
+        }
+        if self.limit_reached() {
+            return Ok(0);
+        }
+        let first = self.records == 0;
+        self.records += 1;
+        self.widest = self.widest.max(row.len());
+        let splitting = self.options.split_rows.is_some() || self.options.split_size.is_some();
+        let direct = matches!(self.out, RowOutput::Csv(_));
+        let record = match self.options.format {
+            // Written straight into the file below; the first record is still encoded here to
+            // be repeated in every part
+            OutputFormat::Csv if direct && !(first && splitting) => Vec::new(),
+            OutputFormat::Csv => {
+                self.encoder.write_record(row.iter())?;
+                self.encoder.flush()?;
+                self.encoder.get_ref().0.take()
+            }
+            OutputFormat::Markdown => {
+                // Renderers drop cells beyond the header and may not draw short rows, so every
+                // row gets the columns of the header
+                if first {
+                    self.columns = self.first_record_width(row);
+                }
+                markdown_row(row, self.columns, first).into_bytes()
+            }
+            OutputFormat::Html => html_row(row, first).into_bytes(),
+            OutputFormat::PgCopy => self.pgcopy_record(row, &mut nulls, first),
+            // Its records are cells, written by cells::CellsWriter instead
+            OutputFormat::CellsJson => anyhow::bail!("cells-json has no rows to write"),
+        };
+
+        let mut written = u64::from(!(pgcopy && first));
+        if first {
+            if splitting {
+                self.first_record = Some(record.clone());
+            }
+        } else if let Some(first) = &self.first_record {
+            let part_bytes = match &self.out {
+                RowOutput::Encoded(out) => out.bytes,
+                RowOutput::Csv(_) => 0,
+            };
+            let full = self.options.split_rows.is_some_and(|n| self.part_rows >= n)
+                || self
+                    .options
+                    .split_size
+                    .is_some_and(|n| self.part_rows > 0 && part_bytes + record.len() as u64 > n);
+            if full {
+                let first = first.clone();
+                self.next_part(&first)?;
+                written += 1;
+            }
+            self.part_rows += 1;
+        }
+        match &mut self.out {
+            RowOutput::Csv(encoder) => encoder.write_record(row.iter())?,
+            RowOutput::Encoded(out) => out.write_all(&record)?,
+        }
+        Ok(written)
+    }
+
+    /// The number of columns of a table started by the first record
+    /// A column mapping decides the columns itself; else the sheet may be wider than its header
+    /// row.
+    fn first_record_width(&self, row: &[String]) -> usize {
+        let sheet_width = match self.mapping {
+            Some(_) => 0,
+            None => self.sheet_width.unwrap_or(0) + usize::from(self.options.row_numbers),
+        };
+        row.len().max(sheet_width)
+    }
+
+    /// Encode a pgcopy line, padding it with nulls to the width of the first record
+    /// The first record starts the schema and is not written itself.
+    fn pgcopy_record(
+        &mut self,
+        row: &mut Vec<String>,
+        nulls: &mut Vec<bool>,
+        first: bool,
+    ) -> Vec<u8> {
+        if first {
+            self.columns = self.first_record_width(row);
+            let mut header = row.clone();
+            header.resize(self.columns, String::new());
+            self.schema = Some(SchemaBuilder::new(header));
+            return Vec::new();
+        }
+        if row.len() < self.columns {
+            row.resize(self.columns, String::new());
+            nulls.resize(self.columns, true);
+        }
+        if let Some(schema) = &mut self.schema {
+            schema.add_row(
+                row.iter()
+                    .zip(nulls.iter())
+                    .map(|(field, &null)| if null { "" } else { field.as_str() }),
+            );
+        }
+        pgcopy_row(row, nulls).into_bytes()
+    }
+
+    /// Finish the current part and continue in the next one, starting with the first record
+    fn next_part(&mut self, first: &[u8]) -> Result<()> {
+        let path = part_path(&self.out_path, self.files.len() as u32 + 1);
+        let out = open_output(&path, self.options, self.in_memory)?;
+        self.end_table()?;
+        let finished = std::mem::replace(&mut self.out, RowOutput::new(out, self.options, first)?);
+        self.close(finished)?;
+        self.part_span = part_span(&self.parent_span, &path);
+        self.files.push(path);
+        self.part_rows = 0;
+        Ok(())
+    }
+
+    /// Report a warning about a row, like report_warning
+    fn warn(&mut self, warning: ExportWarning) {
+        *self.warnings.entry(warning.kind).or_default() += 1;
+        if let Some(cb) = &self.options.on_warning {
+            cb(&warning);
+        }
+    }
+
+    /// Enter the span of the current part, unless it is entered already
+    fn enter_part(&mut self) {
+        if !self.part_entered {
+            self.part_span
+                .with_subscriber(|(id, dispatch)| dispatch.enter(id));
+            self.part_entered = true;
+        }
+    }
+
+    /// Exit the span of the current part if it is entered; on the thread that entered it
+    fn exit_part(&mut self) {
+        if self.part_entered {
+            self.part_span
+                .with_subscriber(|(id, dispatch)| dispatch.exit(id));
+            self.part_entered = false;
+        }
+    }
+
+    /// Finish an output file, recording its size in its span and keeping its contents when in
+    /// memory
+    fn close(&mut self, out: RowOutput) -> Result<()> {
+        self.exit_part();
+        let out = out.into_inner()?;
+        self.part_span.record("bytes", out.bytes);
+        match out.out {
+            OutputWriter::Memory(bytes) => {
+                self.contents.push(bytes);
+                Ok(())
+            }
+            out => out.finish(),
+        }
+    }
+
+    /// Close the output file and remove every file written, after the sheet failed to export
+    fn remove_files(mut self) {
+        self.exit_part();
+        drop(self.out);
+        if self.options.dry_run || self.in_memory {
+            return;
+        }
+        for file in &self.files {
+            if let Err(e) = std::fs::remove_file(file) {
+                tracing::warn!("could not remove {}: {}", file.display(), e);
+            }
+        }
+    }
+
+    /// Write the sampled rows and finish the output file, recording the files written, the
+    /// pgcopy schema and the row warnings in summary
+    /// Returns the number of rows written, like write, and the contents of the files when they
+    /// are kept in memory.
+    /// On failure the files are removed like with remove_files.
+    fn finish(mut self, summary: &mut ExportSummary) -> Result<(u64, Vec<Vec<u8>>)> {
+        let written = match self.write_sample() {
+            Ok(written) => written,
+            Err(e) => {
+                self.remove_files();
+                return Err(e);
+            }
+        };
+        let out = std::mem::replace(
+            &mut self.out,
+            RowOutput::Encoded(Counted {
+                out: OutputWriter::discard(),
+                bytes: 0,
+            }),
+        );
+        if let Err(e) = self.close(out) {
+            self.remove_files();
+            return Err(e);
+        }
+        summary.files = self.files;
+        summary.schema = self.schema.map(SchemaBuilder::finish);
+        summary.columns = self.widest;
+        summary.duplicates = self.duplicates;
+        for (kind, n) in self.warnings {
+            *summary.warnings.entry(kind).or_default() += n;
+        }
+        Ok((written, self.contents))
+    }
+
+    /// Write the sampled rows and close the HTML table, returning the number of rows written
+    fn write_sample(&mut self) -> Result<u64> {
+        let mut written = 0;
+        if let Some(sample) = self.sample.take() {
+            let mut rows = sample.into_items();
+            rows.sort_by_key(|(row_number, _, _, _)| *row_number);
+            for (row_number, mut row, present, in_unit) in rows {
+                written += self.emit(&mut row, &present, &in_unit, row_number, false)?;
+            }
+        }
+        self.end_table()?;
+        Ok(written)
+    }
+
+    /// Whether max_rows records have been written; later rows are dropped
+    fn limit_reached(&self) -> bool {
+        self.options.max_rows.is_some_and(|n| self.records >= n)
+    }
+
+    /// Close the HTML table of the current file, if one was opened
+    fn end_table(&mut self) -> Result<()> {
+        if self.options.format == OutputFormat::Html
+            && self.records > 0
+            && let RowOutput::Encoded(out) = &mut self.out
+        {
+            out.write_all(HTML_END.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl RowTarget for RowWriter<'_> {
+    fn typed(&self) -> bool {
+        self.options.decimal_comma && self.options.numbers == NumberOutput::Raw
+    }
+
+    fn set_sheet_width(&mut self, width: usize) {
+        self.sheet_width = Some(width);
+    }
+
+    fn set_unit_columns(&mut self, columns: &[(usize, Unit)]) {
+        self.unit_columns = columns.to_vec();
+    }
+
+    fn set_row_units(&mut self, in_unit: &[bool]) {
+        self.row_units = in_unit.to_vec();
+    }
+
+    fn write(
+        &mut self,
+        row: &mut Vec<String>,
+        present: &[bool],
+        values: &mut Vec<CellValue>,
+        row_number: u32,
+    ) -> Result<u64> {
+        RowWriter::write(self, row, present, values, row_number)
+    }
+
+    fn limit_reached(&self) -> bool {
+        RowWriter::limit_reached(self)
+    }
+
+    fn release(&mut self) {
+        self.exit_part();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::BufReader;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_geo_coordinate_parsing_from_xml() {
+        let xml_data = r#"
+        <worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">
+            <sheetData>
+                <row r="1">
+                    <c r="A1" t="s"><v>0</v></c>
+                    <c r="B1" t="s"><v>1</v></c>
+                </row>
+                <row r="2">
+                    <c r="A2"><v>10.123</v></c>
+                    <c r="B2"><v>-20.456</v></c>
+                </row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let shared_strings = vec![
+            "origin_latitude".to_string(),
+            "origin_longitude".to_string(),
+        ];
+        let reader = BufReader::new(xml_data.as_bytes());
+        let temp_file = NamedTempFile::new().unwrap();
+        let out_path = temp_file.path();
+
+        export_sheet_xml_to_csv(
+            reader,
+            &shared_strings,
+            &[],
+            false,
+            out_path,
+            &ExportOptions::default(),
+        )
+        .unwrap();
+
+        let csv_content = fs::read_to_string(out_path).unwrap();
+        let expected_content = "origin_latitude,origin_longitude\n10.123,-20.456\n";
+        assert_eq!(csv_content, expected_content);
+    }
+
+    #[test]
+    fn test_pad_to_columns_with_trailing_delimiter() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1"><c r="A1" t="inlineStr"><is><t>a</t></is></c></row>
+                <row r="3">
+                    <c r="A3"><v>1</v></c>
+                    <c r="B3"><v>2</v></c>
+                    <c r="C3"><v>3</v></c>
+                    <c r="D3"><v>4</v></c>
+                </row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let reader = BufReader::new(xml_data.as_bytes());
+        let temp_file = NamedTempFile::new().unwrap();
+        let out_path = temp_file.path();
+        let options = ExportOptions {
+            pad_to_columns: Some(3),
+            trailing_delimiter: true,
+            ..ExportOptions::default()
+        };
+
+        export_sheet_xml_to_csv(reader, &[], &[], false, out_path, &options).unwrap();
+
+        let csv_content = fs::read_to_string(out_path).unwrap();
+        assert_eq!(csv_content, "a,,,\n,,,\n1,2,3,\n");
+    }
+
+    #[test]
+    fn test_empty_as_marks_missing_cells_only() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1">
+                    <c r="A1"><v>1</v></c>
+                    <c r="C1" t="inlineStr"><is><t></t></is></c>
+                    <c r="D1"/>
+                </row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let reader = BufReader::new(xml_data.as_bytes());
+        let temp_file = NamedTempFile::new().unwrap();
+        let out_path = temp_file.path();
+        let options = ExportOptions {
+            pad_to_columns: Some(5),
+            empty_as: Some("\\N".to_string()),
+            ..ExportOptions::default()
+        };
+
+        export_sheet_xml_to_csv(reader, &[], &[], false, out_path, &options).unwrap();
+
+        let csv_content = fs::read_to_string(out_path).unwrap();
+        assert_eq!(csv_content, "1,\\N,,\\N,\\N\n");
+    }
+
+    #[test]
+    fn test_text_format_keeps_stored_value() {
+        let styles_xml = r#"
+        <styleSheet>
+            <numFmts><numFmt numFmtId="164" formatCode="@"/></numFmts>
+            <cellXfs>
+                <xf numFmtId="0"/>
+                <xf numFmtId="49" applyNumberFormat="1"/>
+                <xf numFmtId="164" applyNumberFormat="1"/>
+            </cellXfs>
+        </styleSheet>
+        "#;
+        let styles = parse_styles(BufReader::new(styles_xml.as_bytes())).unwrap();
+        assert!(!styles[0].is_text);
+        assert!(styles[1].is_text && styles[2].is_text);
+        assert_eq!(styles[0].format_code.as_deref(), Some("General"));
+        assert_eq!(styles[2].num_fmt_id, 164);
+        assert_eq!(styles[2].format_code.as_deref(), Some("@"));
+
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1">
+                    <c r="A1" s="1"><v>00420</v></c>
+                    <c r="B1" s="2"><v>0.30000000000000004</v></c>
+                    <c r="C1" s="0"><v>0.30000000000000004</v></c>
+                </row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let temp_file = NamedTempFile::new().unwrap();
+        let options = ExportOptions {
+            general_precision: GeneralPrecision::Excel,
+            ..ExportOptions::default()
+        };
+        export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &styles,
+            false,
+            temp_file.path(),
+            &options,
+        )
+        .unwrap();
+
+        let csv_content = fs::read_to_string(temp_file.path()).unwrap();
+        assert_eq!(csv_content, "00420,0.30000000000000004,0.3\n");
+    }
+
+    #[test]
+    fn test_row_and_column_default_styles() {
+        let styles = vec![
+            StyleInfo::default(),
+            StyleInfo {
+                is_date: true,
+                ..StyleInfo::default()
+            },
+        ];
+        let xml_data = r#"
+        <worksheet>
+            <cols><col min="2" max="3" width="12" style="1" customWidth="1"/></cols>
+            <sheetData>
+                <row r="1"><c r="A1"><v>44927</v></c><c r="B1"><v>44927</v></c><c r="C1" s="0"><v>44927</v></c></row>
+                <row r="2" s="1" customFormat="1"><c r="A2"><v>44927</v></c></row>
+                <row r="3" s="1"><c r="A3"><v>44927</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let temp_file = NamedTempFile::new().unwrap();
+        export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &styles,
+            false,
+            temp_file.path(),
+            &ExportOptions::default(),
+        )
+        .unwrap();
+
+        let csv_content = fs::read_to_string(temp_file.path()).unwrap();
+        assert_eq!(
+            csv_content,
+            "44927,2023-01-01T00:00:00.000Z,44927\n2023-01-01T00:00:00.000Z,,\n44927,,\n"
+        );
+    }
+
+    #[test]
+    fn test_prefixed_elements_are_resolved_by_namespace() {
+        // SpreadsheetML under an "x" prefix, with an extension whose elements end in "c"/"v"
+        let xml_data = r#"
+        <x:worksheet xmlns:x="http://schemas.openxmlformats.org/spreadsheetml/2006/main"
+                     xmlns:x14="http://schemas.microsoft.com/office/spreadsheetml/2009/9/main">
+            <x:sheetData>
+                <x:row r="1"><x:c r="A1" t="inlineStr"><x:is><x:t>a</x:t></x:is></x:c><x:c r="B1"><x:v>2</x:v></x:c></x:row>
+            </x:sheetData>
+            <x:extLst><x:ext uri="{78C0D931-6437-407d-A8EE-F0AAD7539E65}">
+                <x14:c r="D1"><x14:v>9</x14:v></x14:c>
+            </x:ext></x:extLst>
+        </x:worksheet>
+        "#;
+        let temp_file = NamedTempFile::new().unwrap();
+        let summary = export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &[],
+            false,
+            temp_file.path(),
+            &ExportOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(summary.rows_written, 1);
+        assert_eq!(fs::read_to_string(temp_file.path()).unwrap(), "a,2\n");
+    }
+
+    #[test]
+    fn test_strict_ooxml_namespaces() {
+        let rels_xml = r#"
+        <Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+            <Relationship Id="rId1" Type="http://purl.oclc.org/ooxml/officeDocument/relationships/worksheet" Target="worksheets/sheet1.xml"/>
+        </Relationships>
+        "#;
+        let workbook_xml = r#"
+        <workbook xmlns="http://purl.oclc.org/ooxml/spreadsheetml/main"
+                  xmlns:r="http://purl.oclc.org/ooxml/officeDocument/relationships">
+            <workbookPr date1904="1"/>
+            <sheets><sheet name="Data" sheetId="1" r:id="rId1"/></sheets>
+        </workbook>
+        "#;
+        let rels =
+            parse_workbook_rels(BufReader::new(rels_xml.as_bytes()), "xl/workbook.xml").unwrap();
+        let (sheets, is_1904) =
+            parse_workbook(BufReader::new(workbook_xml.as_bytes()), &rels).unwrap();
+        assert_eq!(sheets.len(), 1);
+        assert_eq!(sheets[0].path_in_zip, "xl/worksheets/sheet1.xml");
+        assert!(is_1904);
+
+        let sst_xml = r#"<sst xmlns="http://purl.oclc.org/ooxml/spreadsheetml/main"><si><t>name</t></si></sst>"#;
+        let shared_strings = read_shared_strings(BufReader::new(sst_xml.as_bytes())).unwrap();
+        let sheet_xml = r#"
+        <worksheet xmlns="http://purl.oclc.org/ooxml/spreadsheetml/main">
+            <sheetData>
+                <row r="1"><c r="A1" t="s"><v>0</v></c><c r="B1" t="d"><v>2023-01-01T00:00:00</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let temp_file = NamedTempFile::new().unwrap();
+        export_sheet_xml_to_csv(
+            BufReader::new(sheet_xml.as_bytes()),
+            &shared_strings,
+            &[],
+            false,
+            temp_file.path(),
+            &ExportOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(temp_file.path()).unwrap(),
+            "name,2023-01-01T00:00:00.000Z\n"
+        );
+    }
+
+    #[test]
+    fn test_scan_sheet_width() {
+        let xml_data = r#"
+        <worksheet>
+            <dimension ref="A1"/>
+            <sheetData>
+                <row r="1"><c r="A1"><v>1</v></c></row>
+                <row r="2"><c r="B2"><v>1</v></c><c><v>2</v></c><c r="E2"/></row>
+                <row r="3"><c><v>3</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        assert_eq!(
+            scan_sheet_width(BufReader::new(xml_data.as_bytes())).unwrap(),
+            5
+        );
+        let meta = read_sheet_meta(BufReader::new(xml_data.as_bytes())).unwrap();
+        assert_eq!(meta.last_column(), Some(1));
+    }
+
+    #[test]
+    fn test_out_of_range_references_are_skipped() {
+        let xml_data = r#"
+        <worksheet>
+            <dimension ref="A1:B3"/>
+            <sheetData>
+                <row r="1"><c r="A1"><v>1</v></c><c r="ZZZZZZZZ1"><v>2</v></c><c r="B1A"><v>4</v></c></row>
+                <row r="99999999"><c r="A2"><v>3</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let temp_file = NamedTempFile::new().unwrap();
+        let summary = export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &[],
+            false,
+            temp_file.path(),
+            &ExportOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(fs::read_to_string(temp_file.path()).unwrap(), "1\n3\n");
+        assert_eq!(
+            summary.warnings.get(&WarningKind::InvalidReference),
+            Some(&3)
+        );
+    }
+
+    #[test]
+    fn test_inline_strings_keep_significant_whitespace() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1">
+                    <c r="A1" t="inlineStr">
+                        <is>
+                            <t xml:space="preserve"> inline </t>
+                        </is>
+                    </c>
+                    <c r="B1" t="inlineStr"><is><t xml:space="preserve"> </t></is></c>
+                    <c r="C1" t="str"><f>A1&amp;B1</f><v>xy</v></c>
+                    <c r="D1">
+                        <v>2</v>
+                    </c>
+                </row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let temp_file = NamedTempFile::new().unwrap();
+        export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &[],
+            false,
+            temp_file.path(),
+            &ExportOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(temp_file.path()).unwrap(),
+            " inline , ,xy,2\n"
+        );
+    }
+
+    #[test]
+    fn test_newlines_in_cell_values() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1"><c r="A1" t="inlineStr"><is><t>a
+b</t></is></c><c r="B1" t="inlineStr"><is><t>c&#13;&#10;d&#13;e</t></is></c><c r="C1" t="inlineStr"><is><t>C:\new</t></is></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let export = |newlines| {
+            let temp_file = NamedTempFile::new().unwrap();
+            let options = ExportOptions {
+                newlines,
+                ..ExportOptions::default()
+            };
+            export_sheet_xml_to_csv(
+                BufReader::new(xml_data.as_bytes()),
+                &[],
+                &[],
+                false,
+                temp_file.path(),
+                &options,
+            )
+            .unwrap();
+            fs::read_to_string(temp_file.path()).unwrap()
+        };
+
+        assert_eq!(export(Newlines::Keep), "\"a\nb\",\"c\r\nd\re\",C:\\new\n");
+        assert_eq!(export(Newlines::Space), "a b,c d e,C:\\new\n");
+        assert_eq!(export(Newlines::Escape), "a\\nb,c\\r\\nd\\re,C:\\\\new\n");
+    }
+
+    #[test]
+    fn test_decimal_comma_only_changes_numbers() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1"><c r="A1" t="inlineStr"><is><t>amount</t></is></c><c r="B1" t="inlineStr"><is><t>note</t></is></c><c r="C1" t="inlineStr"><is><t>when</t></is></c></row>
+                <row r="2"><c r="A2"><v>3.14</v></c><c r="B2" t="inlineStr"><is><t>v1.2</t></is></c><c r="C2" s="0"><v>44927.5</v></c></row>
+                <row r="3"><c r="A3"><v>4.5</v></c><c r="B3" t="inlineStr"><is><t>1.5</t></is></c><c r="C3"><v>-2.25</v></c></row>
+                <row r="4"><c r="A4"><v>1.5</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let styles = [StyleInfo {
+            num_fmt_id: 22,
+            format_code: None,
+            is_date: true,
+            is_text: false,
+            ..StyleInfo::default()
+        }];
+        let temp_file = NamedTempFile::new().unwrap();
+        let options = ExportOptions {
+            delimiter: b';',
+            decimal_comma: true,
+            // Filters still read numbers with a decimal point
+            row_filter: Some("amount > 2.5".parse().unwrap()),
+            ..ExportOptions::default()
+        };
+        export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &styles,
+            false,
+            temp_file.path(),
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(temp_file.path()).unwrap(),
+            "amount;note;when\n3,14;v1.2;2023-01-01T12:00:00.000Z\n4,5;1.5;-2,25\n"
+        );
+    }
+
+    #[test]
+    fn test_formatted_numbers() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1"><c r="A1" s="0"><v>1234.5</v></c><c r="B1" s="1"><v>0.125</v></c><c r="C1"><v>0.1</v></c><c r="D1" s="2"><v>44927</v></c></row>
+                <row r="2"><c r="A2" s="0"><v>-0.001</v></c><c r="B2" s="1"><v>1</v></c><c r="C2" t="inlineStr"><is><t>1.5</t></is></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let style = |num_fmt_id, format_code: &str, is_date| StyleInfo {
+            num_fmt_id,
+            format_code: Some(format_code.to_string()),
+            is_date,
+            is_text: false,
+            ..StyleInfo::default()
+        };
+        let styles = [
+            style(164, "#,##0.00 \"€\";-#,##0.00 \"€\"", false),
+            style(10, "0.00%", false),
+            style(14, "mm-dd-yy", true),
+        ];
+        let export = |options: &ExportOptions| {
+            let temp_file = NamedTempFile::new().unwrap();
+            export_sheet_xml_to_csv(
+                BufReader::new(xml_data.as_bytes()),
+                &[],
+                &styles,
+                false,
+                temp_file.path(),
+                options,
+            )
+            .unwrap();
+            fs::read_to_string(temp_file.path()).unwrap()
+        };
+
+        let options = ExportOptions {
+            numbers: NumberOutput::Formatted,
+            ..ExportOptions::default()
+        };
+        assert_eq!(
+            export(&options),
+            "\"1,234.50 €\",12.50%,0.1,2023-01-01T00:00:00.000Z\n-0.00 €,100.00%,1.5,\n"
+        );
+
+        let options = ExportOptions {
+            numbers: NumberOutput::Formatted,
+            decimal_comma: true,
+            delimiter: b';',
+            ..ExportOptions::default()
+        };
+        assert_eq!(
+            export(&options),
+            "1.234,50 €;12,50%;0,1;2023-01-01T00:00:00.000Z\n-0,00 €;100,00%;1.5;\n"
+        );
+    }
+
+    /// A € currency style and a percent style
+    fn unit_styles() -> [StyleInfo; 2] {
+        let style = |num_fmt_id, format_code: &str| StyleInfo {
+            num_fmt_id,
+            format_code: Some(format_code.to_string()),
+            ..StyleInfo::default()
+        };
+        [style(164, "#,##0.00 [$€-407]"), style(10, "0.00%")]
+    }
+
+    #[test]
+    fn test_units_of_currency_and_percent_numbers() {
+        let xml_data = r#"
+        <worksheet>
+            <cols><col min="2" max="2" style="0"/><col min="3" max="3" style="1"/></cols>
+            <sheetData>
+                <row r="1"><c r="A1" t="inlineStr"><is><t>item</t></is></c><c r="B1" t="inlineStr"><is><t>price</t></is></c><c r="C1" t="inlineStr"><is><t>rate</t></is></c></row>
+                <row r="2"><c r="A2" t="inlineStr"><is><t>a</t></is></c><c r="B2" s="0"><v>1234.5</v></c><c r="C2" s="1"><v>0.125</v></c></row>
+                <row r="3"><c r="A3" s="1"><v>0.07</v></c><c r="B3"><v>3</v></c><c r="C3" s="0"><v>2</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let styles = unit_styles();
+        let export = |units| {
+            let temp_file = NamedTempFile::new().unwrap();
+            let options = ExportOptions {
+                units,
+                ..ExportOptions::default()
+            };
+            export_sheet_xml_to_csv(
+                BufReader::new(xml_data.as_bytes()),
+                &[],
+                &styles,
+                false,
+                temp_file.path(),
+                &options,
+            )
+            .unwrap();
+            fs::read_to_string(temp_file.path()).unwrap()
+        };
+
+        assert_eq!(
+            export(UnitOutput::Off),
+            "item,price,rate\na,1234.5,0.125\n0.07,3,2\n"
+        );
+        assert_eq!(
+            export(UnitOutput::Suffix),
+            "item,price,rate\na,1234.5 €,12.5 %\n7 %,3 €,2 €\n"
+        );
+        // The <col> styles decide the unit columns; a unit elsewhere is a suffix
+        assert_eq!(
+            export(UnitOutput::Column),
+            "item,price,price unit,rate,rate unit\na,1234.5,€,12.5,%\n7 %,3,€,2 €,\n"
+        );
+    }
+
+    #[test]
+    fn test_unit_columns_follow_the_column_mapping() {
+        let xml_data = r#"
+        <worksheet>
+            <cols><col min="2" max="2" style="0"/><col min="3" max="3" style="1"/></cols>
+            <sheetData>
+                <row r="1"><c r="A1"><v>1</v></c><c r="B1" s="0"><v>1234.5</v></c><c r="C1" s="1"><v>0.125</v></c></row>
+                <row r="2"><c r="A2"><v>2</v></c><c r="B2"><v>3</v></c><c r="C2" s="1"><v>0.5</v></c></row>
+                <row r="3"><c r="A3"><v>3</v></c><c r="B3" s="0"><v>3</v></c><c r="C3" s="1"><v>0.5</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let styles = unit_styles();
+        let temp_file = NamedTempFile::new().unwrap();
+        let options = ExportOptions {
+            units: UnitOutput::Column,
+            header: HeaderRow::Letters,
+            column_types: Some("B:decimal(2)".parse().unwrap()),
+            dedupe: Some(vec!["B".to_string()]),
+            column_mapping: Some(ColumnMapping {
+                renames: vec!["C".parse().unwrap(), "A".parse().unwrap()],
+                ..ColumnMapping::default()
+            }),
+            ..ExportOptions::default()
+        };
+        export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &styles,
+            false,
+            temp_file.path(),
+            &options,
+        )
+        .unwrap();
+
+        // Types and dedupe see the sheet's columns; the unit columns follow theirs
+        assert_eq!(
+            fs::read_to_string(temp_file.path()).unwrap(),
+            "C,C unit,A,B,B unit\n12.5,%,1,1234.50,€\n50,%,2,3.00,€\n"
+        );
+    }
+
+    #[test]
+    fn test_text_in_date_cells_with_a_year_window() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1"><c r="A1" t="s" s="0"><v>0</v></c><c r="B1" t="inlineStr" s="0"><is><t>29/02/24</t></is></c><c r="C1" t="s" s="0"><v>1</v></c><c r="D1" t="s"><v>0</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let styles = [StyleInfo {
+            num_fmt_id: 164,
+            format_code: Some("dd/mm/yy".to_string()),
+            is_date: true,
+            is_text: false,
+            ..StyleInfo::default()
+        }];
+        let export = |year_window| {
+            let temp_file = NamedTempFile::new().unwrap();
+            let options = ExportOptions {
+                year_window,
+                ..ExportOptions::default()
+            };
+            export_sheet_xml_to_csv(
+                BufReader::new(xml_data.as_bytes()),
+                &["05/03/34".to_string(), "n/a".to_string()],
+                &styles,
+                false,
+                temp_file.path(),
+                &options,
+            )
+            .unwrap();
+            fs::read_to_string(temp_file.path()).unwrap()
+        };
+
+        assert_eq!(export(None), "05/03/34,29/02/24,n/a,05/03/34\n");
+        assert_eq!(
+            export(Some(1930)),
+            "1934-03-05T00:00:00.000Z,2024-02-29T00:00:00.000Z,n/a,05/03/34\n"
+        );
+        assert_eq!(
+            export(Some(1950)),
+            "2034-03-05T00:00:00.000Z,2024-02-29T00:00:00.000Z,n/a,05/03/34\n"
+        );
+    }
+
+    #[test]
+    fn test_leap_bug_serial_in_a_sheet() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1"><c r="A1" s="0"><v>59</v></c><c r="B1" s="0"><v>60</v></c><c r="C1" s="0"><v>61</v></c><c r="D1"><v>60</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let styles = [StyleInfo {
+            num_fmt_id: 14,
+            format_code: Some("mm-dd-yy".to_string()),
+            is_date: true,
+            is_text: false,
+            ..StyleInfo::default()
+        }];
+        let export = |leap_bug| {
+            let temp_file = NamedTempFile::new().unwrap();
+            let options = ExportOptions {
+                leap_bug,
+                ..ExportOptions::default()
+            };
+            export_sheet_xml_to_csv(
+                BufReader::new(xml_data.as_bytes()),
+                &[],
+                &styles,
+                false,
+                temp_file.path(),
+                &options,
+            )
+            .map(|_| fs::read_to_string(temp_file.path()).unwrap())
+        };
+
+        assert_eq!(
+            export(LeapBug::Feb28).unwrap(),
+            "1900-02-28T00:00:00.000Z,1900-02-28T00:00:00.000Z,1900-03-01T00:00:00.000Z,60\n"
+        );
+        assert_eq!(
+            export(LeapBug::Mar1).unwrap(),
+            "1900-02-28T00:00:00.000Z,1900-03-01T00:00:00.000Z,1900-03-01T00:00:00.000Z,60\n"
+        );
+        let error = export(LeapBug::Error).unwrap_err().to_string();
+        assert!(
+            error.starts_with("B1: serial 60 is 1900-02-29"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_dates_in_a_timezone() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1"><c r="A1" t="inlineStr"><is><t>when</t></is></c></row>
+                <row r="2"><c r="A2" s="0"><v>45108.5</v></c></row>
+                <row r="3"><c r="A3" t="d"><v>2023-01-01T08:00:00</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let styles = [StyleInfo {
+            num_fmt_id: 22,
+            format_code: None,
+            is_date: true,
+            is_text: false,
+            ..StyleInfo::default()
+        }];
+        let export = |timezone: &str| {
+            let temp_file = NamedTempFile::new().unwrap();
+            let options = ExportOptions {
+                timezone: timezone.parse().unwrap(),
+                column_types: Some("when:datetime".parse().unwrap()),
+                ..ExportOptions::default()
+            };
+            export_sheet_xml_to_csv(
+                BufReader::new(xml_data.as_bytes()),
+                &[],
+                &styles,
+                false,
+                temp_file.path(),
+                &options,
+            )
+            .unwrap();
+            fs::read_to_string(temp_file.path()).unwrap()
+        };
+
+        assert_eq!(
+            export("utc"),
+            "when\n2023-07-01T12:00:00.000Z\n2023-01-01T08:00:00.000Z\n"
+        );
+        assert_eq!(
+            export("naive"),
+            "when\n2023-07-01T12:00:00.000\n2023-01-01T08:00:00.000\n"
+        );
+        assert_eq!(
+            export("+02:00"),
+            "when\n2023-07-01T12:00:00.000+02:00\n2023-01-01T08:00:00.000+02:00\n"
+        );
+        #[cfg(feature = "tz")]
+        assert_eq!(
+            export("Europe/Amsterdam"),
+            "when\n2023-07-01T12:00:00.000+02:00\n2023-01-01T08:00:00.000+01:00\n"
+        );
+
+        // Epochs and serials are not the ISO dates a datetime column reads
+        let temp_file = NamedTempFile::new().unwrap();
+        let options = ExportOptions {
+            date_output: DateOutput::UnixSeconds,
+            column_types: Some("when:datetime".parse().unwrap()),
+            ..ExportOptions::default()
+        };
+        let error = export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &styles,
+            false,
+            temp_file.path(),
+            &options,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("need dates written as iso"));
+    }
+
+    #[test]
+    fn test_col_to_index_rejects_invalid_columns() {
+        assert_eq!(col_to_index("A"), Some(1));
+        assert_eq!(col_to_index("AB"), Some(28));
+        assert_eq!(col_to_index("XFD"), Some(MAX_COLUMNS));
+        assert_eq!(col_to_index("XFE"), None);
+        assert_eq!(col_to_index("ZZZZZZZZZZZZZZ"), None);
+        assert_eq!(col_to_index("A1"), None);
+        assert_eq!(col_to_index("a"), None);
+        assert_eq!(col_to_index(""), None);
+
+        assert_eq!(parse_cell_ref("b7"), Some(CellRef { col: 2, row: 7 }));
+        assert_eq!(parse_cell_ref("XFE1"), None);
+        assert_eq!(parse_cell_ref("A1B"), None);
+        assert_eq!(parse_cell_ref("1A"), None);
+    }
+
+    #[test]
+    fn test_references_beyond_the_dimension() {
+        let xml_data = r#"
+        <worksheet>
+            <dimension ref="A1:B2"/>
+            <sheetData>
+                <row r="1"><c r="A1"><v>1</v></c><c r="B1"><v>2</v></c></row>
+                <row r="1048576"><c r="A1048576"><v>3</v></c><c r="XFD1048576"><v>4</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let export = |on_out_of_bounds| {
+            let temp_file = NamedTempFile::new().unwrap();
+            let options = ExportOptions {
+                on_out_of_bounds,
+                ..ExportOptions::default()
+            };
+            export_sheet_xml_to_csv(
+                BufReader::new(xml_data.as_bytes()),
+                &[],
+                &[],
+                false,
+                temp_file.path(),
+                &options,
+            )
+            .map(|summary| (fs::read_to_string(temp_file.path()).unwrap(), summary))
+        };
+
+        // By default the row is read as row 2 and the cell past column B is skipped
+        let (csv, summary) = export(OutOfBounds::default()).unwrap();
+        assert_eq!(csv, "1,2\n3,\n");
+        assert_eq!(summary.warnings[&WarningKind::InvalidReference], 2);
+
+        let (csv, summary) = export(OutOfBounds::Keep).unwrap();
+        assert_eq!(csv.lines().count(), 1_048_576);
+        assert_eq!(csv.lines().last().unwrap().split(',').count(), 16_384);
+        assert!(summary.warnings.is_empty());
+
+        let err = export(OutOfBounds::Error).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "row 1048576 is beyond the last row 2 of the dimension A1:B2; treated as row 2"
+        );
+    }
+
+    #[test]
+    fn test_skip_blank_rows() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1"><c r="A1"><v>1</v></c></row>
+                <row r="2"><c r="A2" t="inlineStr"><is><t></t></is></c></row>
+                <row r="5"><c r="B5"><v>5</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let temp_file = NamedTempFile::new().unwrap();
+        let summary = export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &[],
+            false,
+            temp_file.path(),
+            &ExportOptions {
+                skip_blank_rows: true,
+                ..ExportOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(fs::read_to_string(temp_file.path()).unwrap(), "1\n,5\n");
+        assert_eq!(summary.rows_written, 2);
+    }
+
+    #[test]
+    fn test_trim_trailing_columns_and_rows() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1"><c r="A1"><v>1</v></c><c r="B1"><v>2</v></c><c r="C1" s="0"></c></row>
+                <row r="2"><c r="D2" s="0"></c></row>
+                <row r="3"><c r="A3"><v>3</v></c><c r="E3" s="0"></c></row>
+                <row r="4"><c r="B4" s="0"></c></row>
+                <row r="900"><c r="A900" s="0"></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let temp_file = NamedTempFile::new().unwrap();
+        let summary = export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &[],
+            false,
+            temp_file.path(),
+            &ExportOptions {
+                trim: true,
+                ..ExportOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(temp_file.path()).unwrap(),
+            "1,2\n\"\"\n3\n"
+        );
+        assert_eq!(summary.rows_written, 3);
+    }
+
+    #[test]
+    fn test_header_row() {
+        let xml_data = r#"
+        <worksheet>
+            <dimension ref="A1:C2"/>
+            <sheetData>
+                <row r="1"><c r="A1"><v>1</v></c></row>
+                <row r="2"><c r="A2"><v>2</v></c><c r="C2"><v>3</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let temp_file = NamedTempFile::new().unwrap();
+        let summary = export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &[],
+            false,
+            temp_file.path(),
+            &ExportOptions {
+                header: HeaderRow::Letters,
+                ..ExportOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(temp_file.path()).unwrap(),
+            "A,B,C\n1\n2,,3\n"
+        );
+        assert_eq!(summary.rows_written, 3);
+        assert_eq!(HeaderRow::Index.fields(3), ["1", "2", "3"]);
+        assert!(HeaderRow::None.fields(3).is_empty());
+    }
+
+    #[test]
+    fn test_row_numbers() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="2"><c r="A2"><v>1</v></c></row>
+                <row r="4"><c r="B4"><v>2</v></c></row>
+                <row r="5"><c r="B5"></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let temp_file = NamedTempFile::new().unwrap();
+        export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &[],
+            false,
+            temp_file.path(),
+            &ExportOptions {
+                header: HeaderRow::Letters,
+                row_numbers: true,
+                skip_blank_rows: true,
+                ..ExportOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(temp_file.path()).unwrap(),
+            "row,A\n2,1\n4,,2\n"
+        );
+    }
+
+    #[test]
+    fn test_split_rows_repeats_header() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1"><c r="A1" t="inlineStr"><is><t>id</t></is></c></row>
+                <row r="2"><c r="A2"><v>1</v></c></row>
+                <row r="3"><c r="A3"><v>2</v></c></row>
+                <row r="4"><c r="A4"><v>3</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let dir = tempfile::tempdir().unwrap();
+        let out_path = dir.path().join("data.csv");
+        let summary = export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &[],
+            false,
+            &out_path,
+            &ExportOptions {
+                split_rows: Some(2),
+                ..ExportOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            summary.files,
+            vec![
+                dir.path().join("data.part-0001.csv"),
+                dir.path().join("data.part-0002.csv")
+            ]
+        );
+        assert_eq!(fs::read_to_string(&summary.files[0]).unwrap(), "id\n1\n2\n");
+        assert_eq!(fs::read_to_string(&summary.files[1]).unwrap(), "id\n3\n");
+        assert!(!out_path.exists());
+    }
+
+    #[test]
+    fn test_pipeline_writes_like_a_single_thread() {
+        let rows: String = (2..=5000)
+            .map(|i| {
+                format!(
+                    r#"<row r="{}"><c r="A{}"><v>{}</v></c></row>"#,
+                    i * 2,
+                    i * 2,
+                    i
+                )
+            })
+            .collect();
+        let xml_data = format!(
+            r#"<worksheet><dimension ref="A1:A10000"/><sheetData><row r="1"><c r="A1" t="inlineStr"><is><t>id</t></is></c></row>{}</sheetData></worksheet>"#,
+            rows
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let export = |name: &str, pipeline: bool, row_filter: &str| {
+            export_sheet_xml_to_csv(
+                BufReader::new(xml_data.as_bytes()),
+                &[],
+                &[],
+                false,
+                &dir.path().join(name),
+                &ExportOptions {
+                    split_rows: Some(700),
+                    max_rows: Some(3000),
+                    row_filter: Some(row_filter.parse().unwrap()),
+                    pipeline,
+                    ..ExportOptions::default()
+                },
+            )
+        };
+        let single = export("single.csv", false, "id > 10").unwrap();
+        let piped = export("piped.csv", true, "id > 10").unwrap();
+        assert_eq!(piped.rows_written, single.rows_written);
+        assert_eq!(piped.files.len(), single.files.len());
+        for (a, b) in single.files.iter().zip(&piped.files) {
+            assert_eq!(fs::read(a).unwrap(), fs::read(b).unwrap());
+        }
+
+        // An error of the writing thread stops the sheet
+        let error = export("bad.csv", true, "missing > 10").unwrap_err();
+        assert!(format!("{:#}", error).contains("missing"), "{:#}", error);
+    }
+
+    #[test]
+    fn test_dry_run_writes_nothing() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1"><c r="A1" t="inlineStr"><is><t>id</t></is></c></row>
+                <row r="2"><c r="A2"><v>1</v></c><c r="C2"><v>2</v></c></row>
+                <row r="3"><c r="A3"><v>3</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let dir = tempfile::tempdir().unwrap();
+        let out_path = dir.path().join("data.csv");
+        let summary = export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &[],
+            false,
+            &out_path,
+            &ExportOptions {
+                split_rows: Some(1),
+                dry_run: true,
+                ..ExportOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(summary.rows_written, 4);
+        assert_eq!(summary.columns, 3);
+        assert_eq!(summary.files.len(), 2);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_markdown_and_html_formats() {
+        let xml_data = r#"
+        <worksheet>
+            <dimension ref="A1:C3"/>
+            <sheetData>
+                <row r="1">
+                    <c r="A1" t="inlineStr"><is><t>name</t></is></c>
+                    <c r="B1" t="inlineStr"><is><t>a|b</t></is></c>
+                </row>
+                <row r="2">
+                    <c r="A2" t="inlineStr"><is><t>&lt;x&gt; &amp; y</t></is></c>
+                    <c r="B2"><v>2</v></c>
+                </row>
+                <row r="3">
+                    <c r="A3"><v>3</v></c>
+                    <c r="C3"><v>4</v></c>
+                </row>
+            </sheetData>
+        </worksheet>
+        "#;
+        for (format, expected) in [
+            // The header gets the width of the sheet, and rows are padded to it
+            (
+                OutputFormat::Markdown,
+                "| name | a\\|b |  |\n| --- | --- | --- |\n| <x> & y | 2 |  |\n| 3 |  | 4 |\n",
+            ),
+            (
+                OutputFormat::Html,
+                "<table>\n<thead>\n<tr><th>name</th><th>a|b</th></tr>\n</thead>\n<tbody>\n\
+                 <tr><td>&lt;x&gt; &amp; y</td><td>2</td></tr>\n<tr><td>3</td><td></td><td>4</td></tr>\n</tbody>\n</table>\n",
+            ),
+        ] {
+            let temp_file = NamedTempFile::new().unwrap();
+            export_sheet_xml_to_csv(
+                BufReader::new(xml_data.as_bytes()),
+                &[],
+                &[],
+                false,
+                temp_file.path(),
+                &ExportOptions {
+                    format,
+                    ..ExportOptions::default()
+                },
+            )
+            .unwrap();
+            assert_eq!(
+                fs::read_to_string(temp_file.path()).unwrap(),
+                expected,
+                "{:?}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn test_column_types_leave_out_bad_rows() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1"><c r="A1"><v>1</v></c><c r="B1"><v>2.5</v></c></row>
+                <row r="2"><c r="A2"><v>2</v></c><c r="B2" t="inlineStr"><is><t>n/a</t></is></c></row>
+                <row r="3"><c r="A3"><v>3.0</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let temp_file = NamedTempFile::new().unwrap();
+        let summary = export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &[],
+            false,
+            temp_file.path(),
+            &ExportOptions {
+                header: HeaderRow::Letters,
+                column_types: Some("A:int,B:decimal(2)".parse().unwrap()),
+                ..ExportOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(temp_file.path()).unwrap(),
+            "A,B\n1,2.50\n3,\n"
+        );
+        assert_eq!(summary.warnings[&WarningKind::TypeMismatch], 1);
+    }
+
+    #[test]
+    fn test_column_mapping_renames_header_row() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1">
+                    <c r="A1" t="inlineStr"><is><t>Order ID</t></is></c>
+                    <c r="B1" t="inlineStr"><is><t>Notes</t></is></c>
+                    <c r="C1" t="inlineStr"><is><t>Total</t></is></c>
+                </row>
+                <row r="2"><c r="A2"><v>1</v></c><c r="B2"><v>2</v></c><c r="C2"><v>3</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let temp_file = NamedTempFile::new().unwrap();
+        export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &[],
+            false,
+            temp_file.path(),
+            &ExportOptions {
+                column_mapping: Some(ColumnMapping {
+                    renames: vec!["total=amount".parse().unwrap(), "Notes=".parse().unwrap()],
+                    drop_unmapped: false,
+                    snake_case: true,
+                }),
+                ..ExportOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(temp_file.path()).unwrap(),
+            "amount,order_id\n3,1\n"
+        );
+    }
+
+    #[test]
+    fn test_dedupe_skips_repeated_rows() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1">
+                    <c r="A1" t="inlineStr"><is><t>id</t></is></c>
+                    <c r="B1" t="inlineStr"><is><t>name</t></is></c>
+                </row>
+                <row r="2"><c r="A2"><v>1</v></c><c r="B2" t="inlineStr"><is><t>a</t></is></c></row>
+                <row r="3"><c r="A3"><v>1.0</v></c><c r="B3" t="inlineStr"><is><t>a</t></is></c></row>
+                <row r="4"><c r="A4"><v>1</v></c><c r="B4" t="inlineStr"><is><t>b</t></is></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let export = |dedupe: &[&str]| {
+            let temp_file = NamedTempFile::new().unwrap();
+            let summary = export_sheet_xml_to_csv(
+                BufReader::new(xml_data.as_bytes()),
+                &[],
+                &[],
+                false,
+                temp_file.path(),
+                &ExportOptions {
+                    column_types: Some("id:int".parse().unwrap()),
+                    dedupe: Some(dedupe.iter().map(|c| c.to_string()).collect()),
+                    ..ExportOptions::default()
+                },
+            )
+            .unwrap();
+            (
+                fs::read_to_string(temp_file.path()).unwrap(),
+                summary.duplicates,
+            )
+        };
+
+        assert_eq!(export(&[]), ("id,name\n1,a\n1,b\n".to_string(), 1));
+        assert_eq!(export(&["ID"]), ("id,name\n1,a\n".to_string(), 2));
+    }
+
+    #[test]
+    fn test_cell_transform_rewrites_values() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1">
+                    <c r="A1" t="inlineStr"><is><t>email</t></is></c>
+                    <c r="B1" t="inlineStr"><is><t>status</t></is></c>
+                    <c r="C1" t="inlineStr"><is><t>amount</t></is></c>
+                    <c r="D1" t="inlineStr"><is><t>name</t></is></c>
+                </row>
+                <row r="2">
+                    <c r="A2" t="inlineStr"><is><t> ann@example.com </t></is></c>
+                    <c r="B2"><v>2</v></c>
+                    <c r="C2"><v>1E-3</v></c>
+                    <c r="D2" t="inlineStr"><is><t> Ann </t></is></c>
+                </row>
+                <row r="3"><c r="A3" t="inlineStr"><is><t>x</t></is></c><c r="B3"><v>9</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let temp_file = NamedTempFile::new().unwrap();
+        let options = ExportOptions {
+            cell_transform: Some(Arc::new(|cell: &CellContext, value| {
+                Ok(match (cell.header, value) {
+                    (Some("email"), CellValue::Text(_)) => CellValue::Text("<redacted>".into()),
+                    (Some("status"), CellValue::Number(2.0)) => CellValue::Text("closed".into()),
+                    (Some("status"), value @ CellValue::Number(9.0)) => {
+                        cell.drop_row();
+                        value
+                    }
+                    (_, CellValue::Text(text)) => CellValue::Text(text.trim().to_string()),
+                    (_, value) => value,
+                })
+            })),
+            ..ExportOptions::default()
+        };
+        export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &[],
+            false,
+            temp_file.path(),
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(temp_file.path()).unwrap(),
+            "email,status,amount,name\n<redacted>,closed,1E-3,Ann\n"
+        );
+    }
+
+    #[test]
+    fn test_sample_keeps_header_and_sheet_order() {
+        let rows: String = (2..=101)
+            .map(|r| format!(r#"<row r="{r}"><c r="A{r}"><v>{r}</v></c></row>"#))
+            .collect();
+        let xml_data = format!(
+            r#"<worksheet><sheetData><row r="1"><c r="A1" t="inlineStr"><is><t>n</t></is></c></row>{}</sheetData></worksheet>"#,
+            rows
+        );
+        let export = |seed| {
+            let temp_file = NamedTempFile::new().unwrap();
+            let summary = export_sheet_xml_to_csv(
+                BufReader::new(xml_data.as_bytes()),
+                &[],
+                &[],
+                false,
+                temp_file.path(),
+                &ExportOptions {
+                    sample: Some(5),
+                    sample_seed: seed,
+                    ..ExportOptions::default()
+                },
+            )
+            .unwrap();
+            assert_eq!(summary.rows_written, 6);
+            fs::read_to_string(temp_file.path()).unwrap()
+        };
+
+        let csv = export(42);
+        assert_eq!(csv, export(42));
+        assert_ne!(csv, export(43));
+        let numbers: Vec<u32> = csv.lines().skip(1).map(|l| l.parse().unwrap()).collect();
+        assert!(csv.starts_with("n\n"));
+        assert!(numbers.windows(2).all(|w| w[0] < w[1]), "{}", csv);
+    }
+
+    #[test]
+    fn test_summary_counts_cells_by_kind() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1">
+                    <c r="A1"><v>1.5</v></c>
+                    <c r="B1" s="1"><v>45000</v></c>
+                    <c r="C1" t="inlineStr"><is><t>x</t></is></c>
+                    <c r="D1" t="b"><v>1</v></c>
+                    <c r="E1" t="e"><v>#N/A</v></c>
+                    <c r="F1" s="1"/>
+                </row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let styles = vec![
+            StyleInfo::default(),
+            StyleInfo {
+                is_date: true,
+                ..StyleInfo::default()
+            },
+        ];
+        let temp_file = NamedTempFile::new().unwrap();
+        let summary = export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &styles,
+            false,
+            temp_file.path(),
+            &ExportOptions::default(),
+        )
+        .unwrap();
+        let kinds = [
+            CellKind::Number,
+            CellKind::Date,
+            CellKind::Text,
+            CellKind::Bool,
+            CellKind::Error,
+            CellKind::Empty,
+        ];
+        assert_eq!(summary.cells, kinds.into_iter().map(|k| (k, 1)).collect());
+    }
+
+    #[test]
+    fn test_pgcopy_format() {
+        let xml_data = r#"
+        <worksheet>
+            <dimension ref="A1:C3"/>
+            <sheetData>
+                <row r="1">
+                    <c r="A1" t="inlineStr"><is><t>id</t></is></c>
+                    <c r="B1" t="inlineStr"><is><t>note</t></is></c>
+                </row>
+                <row r="2">
+                    <c r="A2"><v>1</v></c>
+                    <c r="B2" t="inlineStr"><is><t>a&#9;b\c</t></is></c>
+                </row>
+                <row r="3"><c r="A3"><v>2</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let temp_file = NamedTempFile::new().unwrap();
+        let summary = export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &[],
+            false,
+            temp_file.path(),
+            &ExportOptions {
+                format: OutputFormat::PgCopy,
+                empty_as: Some("NULL".to_string()),
+                ..ExportOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(summary.rows_written, 2);
+        assert_eq!(
+            fs::read_to_string(temp_file.path()).unwrap(),
+            "1\ta\\tb\\\\c\t\\N\n2\t\\N\t\\N\n"
+        );
+        let schema = summary.schema.unwrap();
+        let columns: Vec<(&str, ColumnType, u64)> = schema
+            .columns
+            .iter()
+            .map(|c| (c.name.as_str(), c.column_type, c.nulls))
+            .collect();
+        assert_eq!(
+            columns,
+            vec![
+                ("id", ColumnType::Integer, 0),
+                ("note", ColumnType::Text, 1),
+                ("column_3", ColumnType::Text, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_max_rows() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1"><c r="A1"><v>1</v></c></row>
+                <row r="3"><c r="A3"><v>3</v></c></row>
+                <row r="4"><c r="A4"><v>4</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let temp_file = NamedTempFile::new().unwrap();
+        let summary = export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &[],
+            false,
+            temp_file.path(),
+            &ExportOptions {
+                header: HeaderRow::Index,
+                max_rows: Some(3),
+                ..ExportOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(summary.rows_written, 3);
+        assert_eq!(
+            fs::read_to_string(temp_file.path()).unwrap(),
+            "1\n1\n\"\"\n"
+        );
+    }
+
+    #[test]
+    fn test_header_row_skips_title_rows() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1"><c r="A1" t="inlineStr"><is><t>Quarterly report</t></is></c></row>
+                <row r="3"><c r="A3" t="inlineStr"><is><t>id</t></is></c><c r="B3" t="inlineStr"><is><t>amount</t></is></c></row>
+                <row r="4"><c r="A4"><v>1</v></c><c r="B4"><v>150</v></c></row>
+                <row r="6"><c r="A6"><v>2</v></c></row>
+            </sheetData>
+            <autoFilter ref="A3:B6"/>
+        </worksheet>
+        "#;
+        let temp_file = NamedTempFile::new().unwrap();
+        let summary = export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &[],
+            false,
+            temp_file.path(),
+            &ExportOptions {
+                header: HeaderRow::Auto,
+                header_row: Some(3),
+                row_numbers: true,
+                ..ExportOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(summary.rows_written, 4);
+        assert_eq!(
+            fs::read_to_string(temp_file.path()).unwrap(),
+            "3,id,amount\n4,1,150\n5\n6,2,\n"
+        );
+    }
+
+    #[test]
+    fn test_row_filter_keeps_header_and_matching_rows() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1"><c r="A1" t="inlineStr"><is><t>amount</t></is></c><c r="B1" t="inlineStr"><is><t>status</t></is></c></row>
+                <row r="2"><c r="A2"><v>150</v></c><c r="B2" t="inlineStr"><is><t>open</t></is></c></row>
+                <row r="3"><c r="A3"><v>20</v></c><c r="B3" t="inlineStr"><is><t>open</t></is></c></row>
+                <row r="4"><c r="A4"><v>300</v></c><c r="B4" t="inlineStr"><is><t>closed</t></is></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let temp_file = NamedTempFile::new().unwrap();
+        let summary = export_sheet_xml_to_csv(
+            BufReader::new(xml_data.as_bytes()),
+            &[],
+            &[],
+            false,
+            temp_file.path(),
+            &ExportOptions {
+                row_filter: Some("amount > 100 && status == 'open'".parse().unwrap()),
+                ..ExportOptions::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(summary.rows_written, 2);
+        assert_eq!(
+            fs::read_to_string(temp_file.path()).unwrap(),
+            "amount,status\n150,open\n"
+        );
+    }
+
+    #[test]
+    fn test_read_cell_details() {
+        let xml_data = r#"
+        <worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">
+            <sheetData>
+                <row r="1">
+                    <c r="A1" t="s" s="2"><v>0</v></c>
+                    <c t="str"><f>CONCAT(A1,"!")</f><v>hi!</v></c>
+                </row>
+                <row r="3"><c s="1"/></row>
+                <row r="4"><c r="A4"><v>4</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let cells = read_cell_details(BufReader::new(xml_data.as_bytes()), Some(3)).unwrap();
+        assert_eq!(
+            cells,
+            vec![
+                CellDetails {
+                    cell: CellRef { col: 1, row: 1 },
+                    cell_type: Some("s".to_string()),
+                    style: Some(2),
+                    formula: None,
+                    value: "0".to_string(),
+                },
+                CellDetails {
+                    cell: CellRef { col: 2, row: 1 },
+                    cell_type: Some("str".to_string()),
+                    style: None,
+                    formula: Some("CONCAT(A1,\"!\")".to_string()),
+                    value: "hi!".to_string(),
+                },
+                CellDetails {
+                    cell: CellRef { col: 1, row: 3 },
+                    style: Some(1),
+                    ..CellDetails::default()
+                },
+            ]
+        );
+
+        let xml_data = r#"<worksheet><sheetData><row r="2">
+            <c r="B2"><f t="shared" ref="B2:C3" si="0">A2*$A$1</f><v>1</v></c>
+            <c r="C2"><f t="shared" si="0"/><v>2</v></c>
+        </row></sheetData></worksheet>"#;
+        let cells = read_cell_details(BufReader::new(xml_data.as_bytes()), None).unwrap();
+        assert_eq!(cells[1].formula.as_deref(), Some("B2*$A$1"));
+    }
+
+    #[test]
+    fn test_parse_workbook_sheet_ids_and_state() {
+        let rels_xml = r#"
+        <Relationships>
+            <Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="/xl/worksheets/sheet1.xml"/>
+            <Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/../worksheets/sheet2.xml"/>
+            <Relationship Id="rId3" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/>
+            <Relationship Id="rId5" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/chartsheet" Target="chartsheets/sheet1.xml"/>
+            <Relationship Id="rId4" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="https://example.com/sheet.xml" TargetMode="External"/>
+        </Relationships>
+        "#;
+        let workbook_xml = r#"
+        <workbook xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">
+            <sheets>
+                <sheet name="Visible" sheetId="1" r:id="rId1"/>
+                <sheet name="Secret" sheetId="7" state="veryHidden" r:id="rId2"/>
+                <sheet name="Chart1" sheetId="8" r:id="rId5"/>
+            </sheets>
+        </workbook>
+        "#;
+        let rels =
+            parse_workbook_rels(BufReader::new(rels_xml.as_bytes()), "xl/workbook.xml").unwrap();
+        assert_eq!(rels.len(), 3);
+        let (sheets, _) = parse_workbook(BufReader::new(workbook_xml.as_bytes()), &rels).unwrap();
+
+        assert_eq!(sheets.len(), 3);
+        assert_eq!(sheets[0].path_in_zip, "xl/worksheets/sheet1.xml");
+        assert_eq!(sheets[0].sheet_id, Some(1));
+        assert_eq!(sheets[0].state, SheetState::Visible);
+        assert_eq!(sheets[1].sheet_id, Some(7));
+        assert_eq!(sheets[1].state, SheetState::VeryHidden);
+        assert_eq!(sheets[1].path_in_zip, "xl/worksheets/sheet2.xml");
+        assert_eq!(sheets[2].kind, SheetKind::Chartsheet);
+
+        let sheet_xml =
+            r#"<worksheet><dimension ref="A1:C9"/><sheetData><row r="1"/></sheetData></worksheet>"#;
+        let meta = read_sheet_meta(BufReader::new(sheet_xml.as_bytes())).unwrap();
+        assert_eq!(meta.dimension.as_deref(), Some("A1:C9"));
+    }
+
+    #[test]
+    fn test_parse_doc_properties() {
+        let core_xml = r#"
+        <cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties"
+            xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/">
+            <dc:creator>Jane Doe</dc:creator>
+            <cp:lastModifiedBy>John Roe</cp:lastModifiedBy>
+            <dcterms:created xsi:type="dcterms:W3CDTF">2024-01-02T03:04:05Z</dcterms:created>
+        </cp:coreProperties>
+        "#;
+        let app_xml = r#"<Properties><Application>Microsoft Excel</Application><AppVersion>16.0300</AppVersion></Properties>"#;
+        let mut props = DocProperties::default();
+        parse_doc_properties(BufReader::new(core_xml.as_bytes()), &mut props).unwrap();
+        parse_doc_properties(BufReader::new(app_xml.as_bytes()), &mut props).unwrap();
+
+        assert_eq!(props.creator.as_deref(), Some("Jane Doe"));
+        assert_eq!(props.last_modified_by.as_deref(), Some("John Roe"));
+        assert_eq!(props.created.as_deref(), Some("2024-01-02T03:04:05Z"));
+        assert_eq!(props.modified, None);
+        assert_eq!(props.application.as_deref(), Some("Microsoft Excel"));
+    }
+
+    #[test]
+    fn test_parse_defined_names() {
+        let workbook_xml = r#"<workbook><sheets><sheet name="Data" sheetId="1" r:id="rId1"/></sheets>
+            <definedNames><definedName name="Rates">Data!$A$1:$B$4</definedName>
+            <definedName name="_xlnm.Print_Area" localSheetId="0">Data!$A$1:$F$40</definedName>
+            </definedNames></workbook>"#;
+        let names = parse_defined_names(BufReader::new(workbook_xml.as_bytes())).unwrap();
+        assert_eq!(names, vec!["Rates", "_xlnm.Print_Area"]);
+        let names = parse_defined_names(BufReader::new("<workbook/>".as_bytes())).unwrap();
+        assert!(names.is_empty());
+    }
+
+    #[test]
+    fn test_missing_shared_string_is_counted_and_marked() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1">
+                    <c r="A1" t="s"><v>0</v></c>
+                    <c r="B1" t="s"><v>1234</v></c>
+                </row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let reader = BufReader::new(xml_data.as_bytes());
+        let temp_file = NamedTempFile::new().unwrap();
+        let options = ExportOptions {
+            debug_cells: true,
+            ..ExportOptions::default()
+        };
+
+        let summary = export_sheet_xml_to_csv(
+            reader,
+            &["ok".to_string()],
+            &[],
+            false,
+            temp_file.path(),
+            &options,
+        )
+        .unwrap();
+
+        let csv_content = fs::read_to_string(temp_file.path()).unwrap();
+        assert_eq!(csv_content, "ok,⟨missing-ss:1234⟩\n");
+        assert_eq!(summary.warnings[&WarningKind::MissingSharedString], 1);
+        assert_eq!(index_to_col(28), "AB");
+
+        let warned = Arc::new(AtomicUsize::new(0));
+        let export = |on_missing_string| {
+            let warned = Arc::clone(&warned);
+            let options = ExportOptions {
+                on_missing_string,
+                on_warning: Some(Arc::new(move |_: &ExportWarning| {
+                    warned.fetch_add(1, Ordering::Relaxed);
+                })),
+                ..ExportOptions::default()
+            };
+            export_sheet_xml_to_csv(
+                BufReader::new(xml_data.as_bytes()),
+                &["ok".to_string()],
+                &[],
+                false,
+                temp_file.path(),
+                &options,
+            )
+        };
+        let summary = export(MissingString::Index).unwrap();
+        assert_eq!(fs::read_to_string(temp_file.path()).unwrap(), "ok,1234\n");
+        assert_eq!(summary.warnings[&WarningKind::MissingSharedString], 1);
+        let err = export(MissingString::Error).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("B1: shared string index '1234'")
+        );
+        // The failing cell is reported as a warning too
+        assert_eq!(warned.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_progress_callback_reports_rows_and_dimension() {
+        use std::sync::{Arc, Mutex};
+
+        let xml_data = r#"
+        <worksheet>
+            <dimension ref="A1:B4"/>
+            <sheetData>
+                <row r="1"><c r="A1"><v>1</v></c></row>
+                <row r="2"><c r="A2"><v>2</v></c></row>
+                <row r="4"><c r="B4"><v>4</v></c></row>
+            </sheetData>
+        </worksheet>
+        "#;
+        let reader = BufReader::new(xml_data.as_bytes());
+        let temp_file = NamedTempFile::new().unwrap();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        let options = ExportOptions {
+            progress: Some(Arc::new(move |p: &ExportProgress| {
+                sink.lock().unwrap().push(*p)
+            })),
+            ..ExportOptions::default()
+        };
+
+        export_sheet_xml_to_csv(reader, &[], &[], false, temp_file.path(), &options).unwrap();
+
+        let seen = seen.lock().unwrap();
+        // The blank row 3, missing from the XML, is reported like the others; the export
+        // reports its final progress once more
+        let rows: Vec<u64> = seen.iter().map(|p| p.rows_written).collect();
+        assert_eq!(rows, [1, 2, 3, 4, 4]);
+        let last = seen.last().unwrap();
+        assert_eq!(last.rows_written, 4);
+        assert_eq!(last.estimated_rows, Some(4));
+        assert_eq!(last.bytes_read, xml_data.len() as u64);
+    }
+
+    /// data, a zip archive without a comment, with its end of central directory moved into the
+    /// zip64 records that an archive of more than 65,535 entries or 4 GB needs
+    fn with_zip64_end(mut data: Vec<u8>) -> Vec<u8> {
+        let end_offset = data.len() - 22;
+        let mut end = data.split_off(end_offset);
+        let field = |at: usize, len: usize| {
+            let mut bytes = [0; 8];
+            bytes[..len].copy_from_slice(&end[at..at + len]);
+            u64::from_le_bytes(bytes)
+        };
+        let (entries, size, offset) = (field(10, 2), field(12, 4), field(16, 4));
+        // Zip64 end of central directory record: the size of the rest of it, versions made by
+        // and needed, disk numbers, entries on this disk and in all, the directory's size and
+        // offset
+        data.extend(0x0606_4b50u32.to_le_bytes());
+        data.extend(44u64.to_le_bytes());
+        data.extend([45, 0, 45, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        for value in [entries, entries, size, offset] {
+            data.extend(value.to_le_bytes());
+        }
+        // Its locator: the disk and offset of the record and the number of disks
+        data.extend(0x0706_4b50u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend((end_offset as u64).to_le_bytes());
+        data.extend(1u32.to_le_bytes());
+        // The counts, size and offset of the end record then point at the zip64 record
+        end[8..20].fill(0xFF);
+        data.extend(end);
+        data
+    }
+
+    #[test]
+    fn test_zip64_archive_opens_and_streams() {
+        use zip::write::FileOptions;
+        use zip::{CompressionMethod, ZipWriter};
+
+        let mut book = WorkbookWriter::new(std::io::Cursor::new(Vec::new()));
+        book.start_sheet("Data").unwrap();
+        book.write_row(&[CellValue::Text("id".into())]).unwrap();
+        book.write_row(&[CellValue::Number(42.0)]).unwrap();
+        let mut book = ZipArchive::new(book.finish().unwrap()).unwrap();
+
+        // Zip64 extra fields on every entry and a zip64 end of central directory: what a
+        // workbook over 4 GB or 65,535 entries gets. The zip writer only writes the latter from
+        // 65,536 entries on, so with_zip64_end puts it in and a few padding entries stand in for
+        // the many.
+        let mut zip64_archive = |padding: usize| {
+            let mut zip = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+            let options = FileOptions::default().large_file(true);
+            for i in 0..book.len() {
+                let mut entry = book.by_index(i).unwrap();
+                zip.start_file(entry.name(), options).unwrap();
+                std::io::copy(&mut entry, &mut zip).unwrap();
+            }
+            let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+            for i in 0..padding {
+                zip.start_file(format!("xl/media/pad{}.bin", i), stored)
+                    .unwrap();
+            }
+            with_zip64_end(zip.finish().unwrap().into_inner())
+        };
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), zip64_archive(3)).unwrap();
+
+        let mut zip = open_zip(file.path()).unwrap();
+        assert_eq!(zip.len(), book.len() + 3);
+        let parts = read_package_parts(&mut zip).unwrap();
+        let (sheets, _) = read_workbook(&mut zip, &parts).unwrap();
+        let strings = read_shared_strings(BufReader::new(
+            zip.by_name(parts.shared_strings.as_ref().unwrap()).unwrap(),
+        ))
+        .unwrap();
+        let out = NamedTempFile::new().unwrap();
+        export_sheet_xml_to_csv(
+            BufReader::new(zip.by_name(&sheets[0].path_in_zip).unwrap()),
+            &strings,
+            &[],
+            false,
+            out.path(),
+            &ExportOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(out.path()).unwrap(), "id\n42\n");
+
+        // Cut off before the end of central directory
+        let data = fs::read(file.path()).unwrap();
+        fs::write(file.path(), &data[..data.len() - 100]).unwrap();
+        let err = open_zip(file.path()).unwrap_err();
+        assert!(err.to_string().contains("damaged or incomplete"), "{}", err);
     }
 }
@@ -0,0 +1,252 @@
+use anyhow::Result;
+use std::str::FromStr;
+
+use super::filter::{column_position, find_column};
+
+/// One entry of a ColumnMapping: `Old Header=new_name` renames a column, `Old Header=` drops it
+/// and a bare `Old Header` keeps it under its own name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnRename {
+    pub column: String,
+    pub to: RenameTo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenameTo {
+    Keep,
+    Name(String),
+    Drop,
+}
+
+impl FromStr for ColumnRename {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (column, to) = match s.rsplit_once('=') {
+            Some((column, "")) => (column, RenameTo::Drop),
+            Some((column, name)) => (column, RenameTo::Name(name.trim().to_string())),
+            None => (s, RenameTo::Keep),
+        };
+        let column = column.trim();
+        if column.is_empty() {
+            return Err(format!("invalid rename '{}', expected OLD=NEW", s));
+        }
+        Ok(Self {
+            column: column.to_string(),
+            to,
+        })
+    }
+}
+
+/// Parse a mapping file: one ColumnRename per line; blank lines and lines starting with #
+/// are ignored
+pub fn parse_column_mapping(text: &str) -> Result<Vec<ColumnRename>, String> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| line.parse().map_err(|e| format!("line {}: {}", i + 1, e)))
+        .collect()
+}
+
+/// How the columns of a sheet are renamed, reordered and dropped
+/// The listed columns come first, in the order of renames, followed by the other columns in
+/// sheet order unless drop_unmapped is set. With snake_case the columns without an explicit
+/// new name get snake_case names ("Order Date (UTC)" -> order_date_utc).
+/// Columns are named like for a RowFilter: by the header row, or by letter or number with a
+/// synthetic header.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColumnMapping {
+    pub renames: Vec<ColumnRename>,
+    pub drop_unmapped: bool,
+    pub snake_case: bool,
+}
+
+impl ColumnMapping {
+    /// Resolve the listed columns against a header row
+    pub fn bind(&self, header: &[String]) -> Result<BoundMapping> {
+        self.bind_with(|name| find_column(header, name), || header.join(", "))
+    }
+
+    /// Resolve the listed columns as letters (A, B, ...) or 1-based numbers
+    pub fn bind_positions(&self, letters: bool) -> Result<BoundMapping> {
+        let expected = if letters { "A, B, ..." } else { "1, 2, ..." };
+        self.bind_with(
+            |name| column_position(name, letters),
+            || expected.to_string(),
+        )
+    }
+
+    fn bind_with(
+        &self,
+        find: impl Fn(&str) -> Option<usize>,
+        columns: impl Fn() -> String,
+    ) -> Result<BoundMapping> {
+        let mut listed: Vec<(usize, RenameTo)> = Vec::new();
+        for rename in &self.renames {
+            let index = find(&rename.column).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "unknown column '{}' in rename (columns: {})",
+                    rename.column,
+                    columns()
+                )
+            })?;
+            if listed.iter().any(|(i, _)| *i == index) {
+                anyhow::bail!("column '{}' is renamed more than once", rename.column);
+            }
+            listed.push((index, rename.to.clone()));
+        }
+        Ok(BoundMapping {
+            listed,
+            drop_unmapped: self.drop_unmapped,
+            snake_case: self.snake_case,
+        })
+    }
+}
+
+/// A ColumnMapping with the listed columns resolved to field positions
+#[derive(Debug, Clone)]
+pub struct BoundMapping {
+    listed: Vec<(usize, RenameTo)>,
+    drop_unmapped: bool,
+    snake_case: bool,
+}
+
+impl BoundMapping {
+    /// The source position of every output column for a row of len fields; None for a listed
+    /// column the row is too short for
+    pub(crate) fn order(&self, len: usize) -> Vec<Option<usize>> {
+        let mut order: Vec<Option<usize>> = self
+            .listed
+            .iter()
+            .filter(|(_, to)| *to != RenameTo::Drop)
+            .map(|(i, _)| (*i < len).then_some(*i))
+            .collect();
+        if !self.drop_unmapped {
+            order.extend(
+                (0..len)
+                    .filter(|i| !self.listed.iter().any(|(l, _)| l == i))
+                    .map(Some),
+            );
+        }
+        order
+    }
+
+    /// Reorder the fields of row into the output columns, returning present reordered alike
+    pub fn apply(&self, row: &mut Vec<String>, present: &[bool]) -> Vec<bool> {
+        let order = self.order(row.len());
+        let mut fields = std::mem::take(row);
+        let mapped_present = order
+            .iter()
+            .map(|i| i.is_some_and(|i| present.get(i).copied().unwrap_or(false)))
+            .collect();
+        *row = order
+            .iter()
+            .map(|i| i.map_or_else(String::new, |i| std::mem::take(&mut fields[i])))
+            .collect();
+        mapped_present
+    }
+
+    /// Whether columns without an explicit new name get snake_case names
+    pub(crate) fn snake_case(&self) -> bool {
+        self.snake_case
+    }
+
+    /// Reorder and rename a header row; with snake_case empty names become column_N
+    /// Fails when two output columns would end up with the same name.
+    pub fn apply_header(&self, header: &mut Vec<String>) -> Result<()> {
+        let order = self.order(header.len());
+        let mut names: Vec<String> = Vec::with_capacity(order.len());
+        for (position, source) in order.iter().enumerate() {
+            let original = source.map_or("", |i| header[i].as_str());
+            let renamed = source
+                .and_then(|i| self.listed.iter().find(|(l, _)| *l == i))
+                .and_then(|(_, to)| match to {
+                    RenameTo::Name(name) => Some(name.clone()),
+                    _ => None,
+                });
+            let name = match renamed {
+                Some(name) => name,
+                // Nothing is left of names like "€" or ""
+                None if self.snake_case => match snake_case(original) {
+                    name if name.is_empty() => format!("column_{}", position + 1),
+                    name => name,
+                },
+                None => original.to_string(),
+            };
+            if !name.is_empty() && names.contains(&name) {
+                anyhow::bail!("two columns would both be named '{}'", name);
+            }
+            names.push(name);
+        }
+        *header = names;
+        Ok(())
+    }
+}
+
+/// A database friendly column name: lowercase words joined by underscores
+/// Examples:
+///   "Order Date (UTC)" -> "order_date_utc"
+///   "customerID" -> "customer_id"
+///   "  Total  €" -> "total"
+pub fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    let mut previous: Option<char> = None;
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            let boundary = c.is_uppercase()
+                && previous.is_some_and(|p| p.is_lowercase() || p.is_ascii_digit());
+            if boundary || (previous.is_none() && !out.is_empty()) {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+            previous = Some(c);
+        } else {
+            previous = None;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_mapping() {
+        let mapping = ColumnMapping {
+            renames: vec![
+                "Total €=total".parse().unwrap(),
+                "Internal Notes=".parse().unwrap(),
+            ],
+            drop_unmapped: false,
+            snake_case: true,
+        };
+        let mut header: Vec<String> = ["Order ID", "Internal Notes", "Total €", "customerName"]
+            .map(String::from)
+            .to_vec();
+        let bound = mapping.bind(&header).unwrap();
+        bound.apply_header(&mut header).unwrap();
+        assert_eq!(header, vec!["total", "order_id", "customer_name"]);
+
+        let mut row: Vec<String> = ["1", "secret", "9.99"].map(String::from).to_vec();
+        let present = bound.apply(&mut row, &[true, true, true]);
+        assert_eq!(row, vec!["9.99", "1"]);
+        assert_eq!(present, vec![true, true]);
+
+        let only = ColumnMapping {
+            renames: parse_column_mapping("# keep two\nOrder ID = id\n\ncustomerName\n").unwrap(),
+            drop_unmapped: true,
+            snake_case: false,
+        };
+        let mut header: Vec<String> = ["Order ID", "customerName"].map(String::from).to_vec();
+        only.bind(&header)
+            .unwrap()
+            .apply_header(&mut header)
+            .unwrap();
+        assert_eq!(header, vec!["id", "customerName"]);
+
+        assert!(mapping.bind(&header).is_err());
+        assert!("=x".parse::<ColumnRename>().is_err());
+    }
+}
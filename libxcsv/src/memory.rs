@@ -0,0 +1,107 @@
+use anyhow::{Context, Result};
+use std::io::{BufReader, Cursor};
+use std::path::Path;
+use zip::ZipArchive;
+
+use crate::{
+    ExportOptions, NameTemplate, SheetKind, export_sheet, read_package_parts,
+    read_strings_and_styles, read_workbook,
+};
+
+/// Convert every worksheet of an .xlsx file held in memory, without touching the filesystem
+/// Returns the name and contents of every file `export` would write, in sheet order: the
+/// `{slug}.<format extension>` of each sheet, or its parts with split_rows/split_size.
+/// compression, progress and pgcopy scripts are not supported. This is the entry point for
+/// targets without a filesystem, like wasm32-unknown-unknown.
+///
+/// Examples
+/// let files = convert_xlsx_bytes_to_csv(&upload, &ExportOptions::default())?;
+pub fn convert_xlsx_bytes_to_csv(
+    bytes: &[u8],
+    options: &ExportOptions,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut zip =
+        ZipArchive::new(Cursor::new(bytes)).context("Failed to read XLSX (zip) archive")?;
+    let parts = read_package_parts(&mut zip)?;
+    let (shared_strings, styles) = read_strings_and_styles(&mut zip, &parts)?;
+    let (sheets, is_1904) = read_workbook(&mut zip, &parts)?;
+    let template: NameTemplate = format!("{{slug}}.{}", options.format.extension())
+        .parse()
+        .map_err(anyhow::Error::msg)?;
+
+    let mut files: Vec<(String, Vec<u8>)> = Vec::new();
+    for (position, sheet) in sheets.iter().enumerate() {
+        if sheet.kind != SheetKind::Worksheet {
+            continue;
+        }
+        let name = template.render(&sheet.name, position + 1, "workbook");
+        if files
+            .iter()
+            .any(|(other, _)| other.eq_ignore_ascii_case(&name))
+        {
+            anyhow::bail!(
+                "sheet '{}' would be written to {} like an earlier sheet",
+                sheet.name,
+                name
+            );
+        }
+        let f = zip
+            .by_name(&sheet.path_in_zip)
+            .with_context(|| format!("missing {}", sheet.path_in_zip))?;
+        let (summary, contents) = export_sheet(
+            BufReader::new(f),
+            &shared_strings,
+            &styles,
+            is_1904,
+            Path::new(&name),
+            options,
+            true,
+        )
+        .with_context(|| format!("export {}", sheet.name))?;
+        files.extend(
+            summary
+                .files
+                .iter()
+                .map(|path| path.to_string_lossy().into_owned())
+                .zip(contents),
+        );
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::{FileOptions, ZipWriter};
+
+    #[test]
+    fn test_convert_xlsx_bytes_to_csv() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let parts = [
+            (
+                "xl/workbook.xml",
+                r#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="Q1 Sales" sheetId="1" r:id="rId1"/></sheets></workbook>"#,
+            ),
+            (
+                "xl/_rels/workbook.xml.rels",
+                r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/></Relationships>"#,
+            ),
+            (
+                "xl/worksheets/sheet1.xml",
+                r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData><row r="1"><c r="A1" t="inlineStr"><is><t>id</t></is></c></row><row r="2"><c r="A2"><v>7</v></c></row></sheetData></worksheet>"#,
+            ),
+        ];
+        for (name, xml) in parts {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(xml.as_bytes()).unwrap();
+        }
+        let bytes = zip.finish().unwrap().into_inner();
+
+        let files = convert_xlsx_bytes_to_csv(&bytes, &ExportOptions::default()).unwrap();
+        assert_eq!(
+            files,
+            vec![("q1_sales.csv".to_string(), b"id\n7\n".to_vec())]
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use std::str::FromStr;
+
+use super::to_lowercase_filename;
+
+/// The default file name for a sheet: the lowercased sheet name plus ".csv"
+pub const DEFAULT_NAME_TEMPLATE: &str = "{slug}.csv";
+
+/// A file name template for exported sheets
+/// Placeholders:
+///   {sheet}: The sheet name with characters not allowed in file names replaced by '_'
+///   {slug}: The sheet name as returned by to_lowercase_filename
+///   {index}: The 1-based position of the sheet in the workbook; {index:03} pads with zeros
+///   {workbook}: The file stem of the workbook
+/// Use {{ and }} for literal braces. Path separators are not allowed.
+///
+/// Examples:
+///   "{index:02}_{sheet}.csv" -> "01_Sales Q1.csv"
+///   "{workbook}-{slug}.tsv" -> "report-sales_q1.tsv"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplatePart {
+    Literal(String),
+    Sheet,
+    Slug,
+    Index { width: usize },
+    Workbook,
+}
+
+impl Default for NameTemplate {
+    fn default() -> Self {
+        DEFAULT_NAME_TEMPLATE
+            .parse()
+            .expect("default template is valid")
+    }
+}
+
+impl FromStr for NameTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains(['/', '\\']) {
+            return Err(format!(
+                "name template '{}' must not contain path separators",
+                s
+            ));
+        }
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| format!("unclosed '{{' in name template '{}'", s))?;
+                    let (name, spec) = match rest[..end].split_once(':') {
+                        Some((name, spec)) => (name, Some(spec)),
+                        None => (&rest[..end], None),
+                    };
+                    let part = match (name, spec) {
+                        ("sheet", None) => TemplatePart::Sheet,
+                        ("slug", None) => TemplatePart::Slug,
+                        ("workbook", None) => TemplatePart::Workbook,
+                        ("index", None) => TemplatePart::Index { width: 0 },
+                        ("index", Some(spec)) => TemplatePart::Index {
+                            width: spec
+                                .strip_prefix('0')
+                                .and_then(|w| w.parse().ok())
+                                .ok_or_else(|| {
+                                    format!("invalid index format '{}', expected e.g. 02", spec)
+                                })?,
+                        },
+                        _ => {
+                            return Err(format!(
+                                "unknown placeholder '{{{}}}' (expected sheet, slug, index or workbook)",
+                                &rest[..end]
+                            ));
+                        }
+                    };
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(part);
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err(format!("unmatched '}}' in name template '{}'", s)),
+                _ => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+        if parts.is_empty() {
+            return Err("name template is empty".to_string());
+        }
+        Ok(Self { parts })
+    }
+}
+
+impl NameTemplate {
+    /// Render the file name for a sheet
+    /// index is 1-based; workbook is the workbook file stem.
+    pub fn render(&self, sheet: &str, index: usize, workbook: &str) -> String {
+        let mut name = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(s) => name.push_str(s),
+                TemplatePart::Sheet => name.push_str(&sanitize_filename(sheet)),
+                TemplatePart::Slug => name.push_str(&to_lowercase_filename(sheet)),
+                TemplatePart::Index { width } => {
+                    name.push_str(&format!("{:0width$}", index, width = *width))
+                }
+                TemplatePart::Workbook => name.push_str(&sanitize_filename(workbook)),
+            }
+        }
+        name
+    }
+}
+
+/// Replace characters that are not allowed in file names on common platforms with '_'
+fn sanitize_filename(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_template() {
+        let t: NameTemplate = "{index:02}_{sheet}.csv".parse().unwrap();
+        assert_eq!(t.render("Sales: Q1", 3, "report"), "03_Sales_ Q1.csv");
+
+        let t: NameTemplate = "{workbook}-{slug}-{index}{{x}}.tsv".parse().unwrap();
+        assert_eq!(
+            t.render("Sales Q1", 12, "report"),
+            "report-sales_q1-12{x}.tsv"
+        );
+
+        assert_eq!(NameTemplate::default().render("Data", 1, "wb"), "data.csv");
+        assert!("{name}.csv".parse::<NameTemplate>().is_err());
+        assert!("{index:2}.csv".parse::<NameTemplate>().is_err());
+        assert!("{sheet.csv".parse::<NameTemplate>().is_err());
+        assert!("out/{sheet}.csv".parse::<NameTemplate>().is_err());
+    }
+}
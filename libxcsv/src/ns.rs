@@ -0,0 +1,54 @@
+use quick_xml::events::Event;
+use quick_xml::name::{Namespace, ResolveResult};
+use quick_xml::reader::NsReader;
+use std::io::BufRead;
+
+/// SpreadsheetML main namespaces (workbook, worksheets, styles, shared strings):
+/// transitional and strict OOXML
+pub(crate) const SPREADSHEETML: &[&[u8]] = &[
+    b"http://schemas.openxmlformats.org/spreadsheetml/2006/main",
+    b"http://purl.oclc.org/ooxml/spreadsheetml/main",
+];
+
+/// Namespace of .rels parts (the same in transitional and strict packages)
+pub(crate) const PACKAGE_RELATIONSHIPS: &[&[u8]] =
+    &[b"http://schemas.openxmlformats.org/package/2006/relationships"];
+
+/// Namespace of [Content_Types].xml
+pub(crate) const CONTENT_TYPES: &[&[u8]] =
+    &[b"http://schemas.openxmlformats.org/package/2006/content-types"];
+
+/// Namespaces of relationship id attributes like `r:id`: transitional and strict OOXML
+pub(crate) const OFFICE_RELATIONSHIPS: &[&[u8]] = &[
+    b"http://schemas.openxmlformats.org/officeDocument/2006/relationships",
+    b"http://purl.oclc.org/ooxml/officeDocument/relationships",
+];
+
+/// DrawingML main namespaces (the color scheme of theme parts): transitional and strict OOXML
+pub(crate) const DRAWINGML: &[&[u8]] = &[
+    b"http://schemas.openxmlformats.org/drawingml/2006/main",
+    b"http://purl.oclc.org/ooxml/drawingml/main",
+];
+
+/// Whether a resolved element or attribute name belongs to one of namespaces
+/// Names without any namespace are accepted as well, so minimal or hand written
+/// parts without xmlns declarations still parse.
+pub(crate) fn in_namespace(resolved: &ResolveResult, namespaces: &[&[u8]]) -> bool {
+    match resolved {
+        ResolveResult::Unbound => true,
+        ResolveResult::Bound(Namespace(ns)) => namespaces.contains(ns),
+        ResolveResult::Unknown(_) => false,
+    }
+}
+
+/// Read the next event, flagging whether it is an element of one of namespaces
+/// Match the local name of flagged elements (e.g. `x:row` and `row` are both "row");
+/// elements from other namespaces (extensions, drawings, ...) come back unflagged.
+pub(crate) fn read_event<'b, R: BufRead>(
+    xml: &mut NsReader<R>,
+    buf: &'b mut Vec<u8>,
+    namespaces: &[&[u8]],
+) -> quick_xml::Result<(bool, Event<'b>)> {
+    let (resolved, event) = xml.read_resolved_event_into(buf)?;
+    Ok((in_namespace(&resolved, namespaces), event))
+}
@@ -0,0 +1,1350 @@
+use chrono::{NaiveDate, NaiveDateTime};
+
+/// Decimal and digit grouping symbols of a locale
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberLocale {
+    pub decimal_separator: char,
+    pub group_separator: char,
+}
+
+impl Default for NumberLocale {
+    /// The en-US convention Excel uses when a format carries no locale
+    fn default() -> Self {
+        Self {
+            decimal_separator: '.',
+            group_separator: ',',
+        }
+    }
+}
+
+const NBSP: char = '\u{a0}';
+
+impl NumberLocale {
+    /// Look up the symbols for a Windows LCID (e.g. 0x040C for fr-FR)
+    /// Only the low 16 bits are used; the upper bits select calendars and digit systems.
+    /// Returns None for LCIDs that are not in the table.
+    pub fn from_lcid(lcid: u32) -> Option<Self> {
+        let (decimal_separator, group_separator) = match lcid & 0xFFFF {
+            // English, Chinese, Japanese, Korean, Spanish (Mexico)
+            0x0409 | 0x0809 | 0x0C09 | 0x1009 | 0x1409 | 0x1809 | 0x0804 | 0x0404 | 0x0411
+            | 0x0412 | 0x080A => ('.', ','),
+            // German, Italian, Spanish (Spain), Dutch, Portuguese (Brazil), Danish, Turkish,
+            // Indonesian, Greek, French (Belgium)
+            0x0407 | 0x0410 | 0x0C0A | 0x040A | 0x0413 | 0x0813 | 0x0416 | 0x0406 | 0x041F
+            | 0x0421 | 0x0408 | 0x080C => (',', '.'),
+            // French, Russian, Polish, Swedish, Norwegian, Finnish, Czech, Hungarian,
+            // Portuguese (Portugal), Ukrainian, French (Canada), German (Austria)
+            0x040C | 0x0419 | 0x0415 | 0x041D | 0x0414 | 0x0814 | 0x040B | 0x0405 | 0x040E
+            | 0x0816 | 0x0422 | 0x0C0C | 0x0C07 => (',', NBSP),
+            // German and French (Switzerland)
+            0x0807 | 0x100C => ('.', '\''),
+            _ => return None,
+        };
+        Some(Self {
+            decimal_separator,
+            group_separator,
+        })
+    }
+}
+
+/// Locale information embedded in a number format code via a `[$<currency>-<lcid>]` tag
+/// currency: The currency symbol before the dash (e.g. "€" in `[$€-40C]`), if any
+/// lcid: The hexadecimal locale id after the dash, if any
+/// locale: The decimal/grouping symbols for the lcid, if the lcid is known
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatLocale {
+    pub currency: Option<String>,
+    pub lcid: Option<u32>,
+    pub locale: Option<NumberLocale>,
+}
+
+/// Find the `[$…]` locale tag of a number format code
+/// Quoted literals and backslash-escaped characters are skipped.
+/// Returns None if the format has no locale tag.
+///
+/// Examples:
+///   parse_format_locale("#,##0.00 [$€-40C]") -> currency "€", lcid 0x40C, fr-FR symbols
+///   parse_format_locale("[$-409]d-mmm-yy") -> no currency, lcid 0x409
+///   parse_format_locale("0.00") -> None
+pub fn parse_format_locale(format_code: &str) -> Option<FormatLocale> {
+    let mut chars = format_code.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => {
+                for (_, q) in chars.by_ref() {
+                    if q == '"' {
+                        break;
+                    }
+                }
+            }
+            '\\' => {
+                chars.next();
+            }
+            '[' if format_code[i + 1..].starts_with('$') => {
+                let start = i + 2;
+                let end = start + format_code[start..].find(']')?;
+                return Some(parse_locale_tag(&format_code[start..end]));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parse the inside of a `[$…]` tag (without the leading `$`)
+fn parse_locale_tag(tag: &str) -> FormatLocale {
+    let (currency, lcid) = match tag.rsplit_once('-') {
+        Some((currency, lcid)) => (currency, u32::from_str_radix(lcid, 16).ok()),
+        None => (tag, None),
+    };
+    FormatLocale {
+        currency: Some(currency.to_string()).filter(|c| !c.is_empty()),
+        lcid,
+        locale: lcid.and_then(NumberLocale::from_lcid),
+    }
+}
+
+/// Format code of a built-in number format id (ECMA-376 Part 1, 18.8.30)
+/// Ids whose code depends on the locale (currency formats 5-8, 27-36, 50-81) return None.
+///
+/// Examples:
+///   builtin_format_code(14) -> Some("mm-dd-yy")
+///   builtin_format_code(49) -> Some("@")
+pub fn builtin_format_code(num_fmt_id: u32) -> Option<&'static str> {
+    Some(match num_fmt_id {
+        0 => "General",
+        1 => "0",
+        2 => "0.00",
+        3 => "#,##0",
+        4 => "#,##0.00",
+        9 => "0%",
+        10 => "0.00%",
+        11 => "0.00E+00",
+        12 => "# ?/?",
+        13 => "# ??/??",
+        14 => "mm-dd-yy",
+        15 => "d-mmm-yy",
+        16 => "d-mmm",
+        17 => "mmm-yy",
+        18 => "h:mm AM/PM",
+        19 => "h:mm:ss AM/PM",
+        20 => "h:mm",
+        21 => "h:mm:ss",
+        22 => "m/d/yy h:mm",
+        37 => "#,##0 ;(#,##0)",
+        38 => "#,##0 ;[Red](#,##0)",
+        39 => "#,##0.00;(#,##0.00)",
+        40 => "#,##0.00;[Red](#,##0.00)",
+        45 => "mm:ss",
+        46 => "[h]:mm:ss",
+        47 => "mmss.0",
+        48 => "##0.0E+0",
+        49 => "@",
+        _ => return None,
+    })
+}
+
+/// A token of a date/time number format, see parse_date_format
+/// The count is the number of letters, e.g. Month(3) for "mmm".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DateToken {
+    Year(usize),
+    Month(usize),
+    Day(usize),
+    Hour(usize),
+    Minute(usize),
+    Second(usize),
+    /// Digits after the decimal point of the seconds, e.g. 2 for "ss.00"
+    FractionalSecond(usize),
+    /// Elapsed time in brackets: [h], [mm], [ss]
+    ElapsedHours(usize),
+    ElapsedMinutes(usize),
+    ElapsedSeconds(usize),
+    /// "AM/PM" or "A/P" as written in the format
+    AmPm(String),
+    Literal(String),
+}
+
+impl DateToken {
+    fn is_hour(&self) -> bool {
+        matches!(self, DateToken::Hour(_) | DateToken::ElapsedHours(_))
+    }
+
+    fn is_second(&self) -> bool {
+        matches!(self, DateToken::Second(_) | DateToken::ElapsedSeconds(_))
+    }
+}
+
+/// Tokenize the first section of a number format code as a date/time format
+/// Quoted literals, backslash escapes, `_x` spacing, `*x` fills and bracketed colors,
+/// conditions and locale tags are understood, so letters inside them don't count.
+/// An "m" is a minute when it follows an hour or precedes a second, otherwise a month.
+/// Returns None if the format is not a date/time format (no date/time letters, or it
+/// contains digit placeholders, scientific notation, General or the text placeholder @).
+///
+/// Examples:
+///   parse_date_format("yyyy-mm-dd") -> [Year(4), "-", Month(2), "-", Day(2)]
+///   parse_date_format("h:mm AM/PM") -> [Hour(1), ":", Minute(2), " ", AmPm("AM/PM")]
+///   parse_date_format(r#"0.00 "mm""#) -> None
+pub fn parse_date_format(format_code: &str) -> Option<Vec<DateToken>> {
+    let chars: Vec<char> = format_code.chars().collect();
+    let mut tokens: Vec<DateToken> = Vec::new();
+    // Tokens that are 'm' runs still to be resolved to month or minute
+    let mut ambiguous_m: Vec<usize> = Vec::new();
+    let push_literal = |tokens: &mut Vec<DateToken>, text: &str| {
+        if let Some(DateToken::Literal(last)) = tokens.last_mut() {
+            last.push_str(text);
+        } else {
+            tokens.push(DateToken::Literal(text.to_string()));
+        }
+    };
+    let run_length = |i: usize, c: char| {
+        chars[i..]
+            .iter()
+            .take_while(|x| x.eq_ignore_ascii_case(&c))
+            .count()
+    };
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let rest: String = chars[i..].iter().take(7).collect::<String>().to_lowercase();
+        match c {
+            ';' => break,
+            '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&q| q == '"')
+                    .map_or(chars.len(), |p| i + 1 + p);
+                let text: String = chars[i + 1..end].iter().collect();
+                push_literal(&mut tokens, &text);
+                i = end + 1;
+            }
+            '\\' => {
+                if let Some(next) = chars.get(i + 1) {
+                    push_literal(&mut tokens, &next.to_string());
+                }
+                i += 2;
+            }
+            '_' => {
+                push_literal(&mut tokens, " ");
+                i += 2;
+            }
+            '*' => i += 2,
+            '[' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&b| b == ']')
+                    .map_or(chars.len(), |p| i + 1 + p);
+                let inner: String = chars[i + 1..end].iter().collect::<String>().to_lowercase();
+                let n = inner.len();
+                if n > 0 && inner.chars().all(|x| x == 'h') {
+                    tokens.push(DateToken::ElapsedHours(n));
+                } else if n > 0 && inner.chars().all(|x| x == 'm') {
+                    tokens.push(DateToken::ElapsedMinutes(n));
+                } else if n > 0 && inner.chars().all(|x| x == 's') {
+                    tokens.push(DateToken::ElapsedSeconds(n));
+                }
+                // Anything else is a color, condition or locale tag
+                i = end + 1;
+            }
+            '0' | '#' | '?' | '@' => return None,
+            '.' if tokens.last().is_some_and(DateToken::is_second)
+                && chars.get(i + 1) == Some(&'0') =>
+            {
+                let n = run_length(i + 1, '0');
+                tokens.push(DateToken::FractionalSecond(n));
+                i += 1 + n;
+            }
+            _ if rest.starts_with("am/pm") => {
+                tokens.push(DateToken::AmPm(chars[i..i + 5].iter().collect()));
+                i += 5;
+            }
+            _ if rest.starts_with("a/p") => {
+                tokens.push(DateToken::AmPm(chars[i..i + 3].iter().collect()));
+                i += 3;
+            }
+            _ if rest.starts_with("general") => return None,
+            'e' | 'E' if matches!(chars.get(i + 1), Some('+') | Some('-')) => return None,
+            _ => {
+                let n = run_length(i, c);
+                let token = match c.to_ascii_lowercase() {
+                    'y' | 'e' => DateToken::Year(n),
+                    'd' => DateToken::Day(n),
+                    'h' => DateToken::Hour(n),
+                    's' => DateToken::Second(n),
+                    'm' => {
+                        ambiguous_m.push(tokens.len());
+                        DateToken::Month(n)
+                    }
+                    _ => {
+                        push_literal(&mut tokens, &c.to_string());
+                        i += 1;
+                        continue;
+                    }
+                };
+                tokens.push(token);
+                i += n;
+            }
+        }
+    }
+
+    // Resolve "m": minute right after an hour or right before a second (literals don't count)
+    let is_field = |t: &DateToken| !matches!(t, DateToken::Literal(_) | DateToken::AmPm(_));
+    for idx in ambiguous_m {
+        let prev = tokens[..idx].iter().rev().find(|t| is_field(t));
+        let next = tokens[idx + 1..].iter().find(|t| is_field(t));
+        if (prev.is_some_and(DateToken::is_hour) || next.is_some_and(DateToken::is_second))
+            && let DateToken::Month(n) = tokens[idx]
+        {
+            tokens[idx] = DateToken::Minute(n);
+        }
+    }
+
+    tokens.iter().any(is_field).then_some(tokens)
+}
+
+/// Whether a number format code displays a date and/or time
+pub fn is_date_format(format_code: &str) -> bool {
+    parse_date_format(format_code).is_some()
+}
+
+/// English month names, which the mmm and mmmm parts of a date text are matched against
+const MONTH_NAMES: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+/// The year of a two-digit year yy in the 100 years from window_start, e.g. with Excel's
+/// window of 1930: 29 -> 2029, 30 -> 1930
+pub fn window_year(yy: u32, window_start: i32) -> i32 {
+    let year = window_start - window_start.rem_euclid(100) + (yy % 100) as i32;
+    if year < window_start {
+        year + 100
+    } else {
+        year
+    }
+}
+
+/// Split the leading digits (at most max) off s: their value, how many there were, the rest
+fn take_digits(s: &str, max: usize) -> Option<(u32, usize, &str)> {
+    let n = s.bytes().take(max).take_while(u8::is_ascii_digit).count();
+    let value = s[..n].parse().ok()?;
+    Some((value, n, &s[n..]))
+}
+
+/// Split the leading letters off s
+fn take_letters(s: &str) -> (&str, &str) {
+    let n = s
+        .char_indices()
+        .find(|(_, c)| !c.is_alphabetic())
+        .map_or(s.len(), |(i, _)| i);
+    s.split_at(n)
+}
+
+/// Parse text holding a date as the tokens of its date format display it, e.g. "05/03/34" for
+/// dd/mm/yy, the way Excel reads a date typed into such a cell: two-digit years fall in the
+/// 100 years from window_start (1930 in Excel, see window_year), month names are English
+/// Returns None if the text doesn't match the format or is not a valid date; formats without a
+/// year or a month and elapsed times never match.
+pub fn parse_date_text(
+    text: &str,
+    tokens: &[DateToken],
+    window_start: i32,
+) -> Option<NaiveDateTime> {
+    let mut rest = text.trim();
+    let (mut year, mut month, mut day) = (None, None, 1);
+    let (mut hour, mut minute, mut second) = (0, 0, 0);
+    let mut pm = None;
+    for token in tokens {
+        match token {
+            DateToken::Literal(literal) => {
+                rest = rest.trim_start().strip_prefix(literal.trim())?.trim_start();
+            }
+            DateToken::Year(_) => {
+                let (value, n, r) = take_digits(rest, 4)?;
+                year = Some(match n {
+                    1 | 2 => window_year(value, window_start),
+                    4 => value as i32,
+                    _ => return None,
+                });
+                rest = r;
+            }
+            DateToken::Month(n) if *n >= 3 => {
+                let (name, r) = take_letters(rest);
+                let name = name.to_lowercase();
+                let index = MONTH_NAMES
+                    .iter()
+                    .position(|m| name.len() >= 3 && m.starts_with(&name))?;
+                month = Some(index as u32 + 1);
+                rest = r;
+            }
+            // Day names are only displayed, the date is in the other parts
+            DateToken::Day(n) if *n >= 3 => rest = take_letters(rest).1,
+            DateToken::Month(_)
+            | DateToken::Day(_)
+            | DateToken::Hour(_)
+            | DateToken::Minute(_)
+            | DateToken::Second(_) => {
+                let (value, _, r) = take_digits(rest, 2)?;
+                match token {
+                    DateToken::Month(_) => month = Some(value),
+                    DateToken::Day(_) => day = value,
+                    DateToken::Hour(_) => hour = value,
+                    DateToken::Minute(_) => minute = value,
+                    _ => second = value,
+                }
+                rest = r;
+            }
+            DateToken::FractionalSecond(_) => {
+                rest = rest.trim_start_matches(|c: char| c.is_ascii_digit())
+            }
+            DateToken::AmPm(_) => {
+                let (marker, r) = take_letters(rest);
+                pm = Some(match marker.to_ascii_uppercase().as_str() {
+                    "AM" | "A" => false,
+                    "PM" | "P" => true,
+                    _ => return None,
+                });
+                rest = r;
+            }
+            DateToken::ElapsedHours(_)
+            | DateToken::ElapsedMinutes(_)
+            | DateToken::ElapsedSeconds(_) => return None,
+        }
+    }
+    if !rest.trim().is_empty() {
+        return None;
+    }
+    match pm {
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some(pm) => hour = hour % 12 + if pm { 12 } else { 0 },
+        None => {}
+    }
+    NaiveDate::from_ymd_opt(year?, month?, day)?.and_hms_opt(hour, minute, second)
+}
+
+/// How plain (General formatted) numbers are written
+/// Exact: the value as stored in the sheet XML, e.g. "0.30000000000000004"
+/// Excel: the way Excel's General format shows it, see format_general
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GeneralPrecision {
+    #[default]
+    Exact,
+    Excel,
+}
+
+impl std::str::FromStr for GeneralPrecision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exact" => Ok(Self::Exact),
+            "excel" => Ok(Self::Excel),
+            _ => Err(format!(
+                "invalid precision '{}', expected excel or exact",
+                s
+            )),
+        }
+    }
+}
+
+/// Significant digits kept by Excel's General format
+const GENERAL_DIGITS: i32 = 11;
+
+/// Format a number like Excel's General format
+/// The value is rounded to 11 significant digits and written in its shortest form;
+/// magnitudes from 1E+11 up and below 1E-9 use scientific notation. The magnitude is the
+/// rounded one, so 99999999999.9 is 1E+11 and 9.99999999999E-10 is 0.000000001.
+///
+/// Examples:
+///   format_general(0.30000000000000004) -> "0.3"
+///   format_general(1234.5) -> "1234.5"
+///   format_general(123456789012345.0) -> "1.2345678901E+14"
+///   format_general(0.0000000000123) -> "1.23E-11"
+pub fn format_general(value: f64) -> String {
+    if value == 0.0 || !value.is_finite() {
+        return if value.is_finite() {
+            "0".to_string()
+        } else {
+            value.to_string()
+        };
+    }
+    let scientific = format!("{:.*E}", (GENERAL_DIGITS - 1) as usize, value);
+    let (mantissa, exponent) = scientific.split_once('E').unwrap_or((&scientific, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    if !(-9..GENERAL_DIGITS).contains(&exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        return format!("{}E{}{:02}", trim_fraction(mantissa), sign, exponent.abs());
+    }
+    let decimals = (GENERAL_DIGITS - 1 - exponent).max(0) as usize;
+    let formatted = format!("{:.*}", decimals, value);
+    match trim_fraction(&formatted) {
+        "-0" => "0".to_string(),
+        s => s.to_string(),
+    }
+}
+
+/// How numbers that are not dates are written
+/// Raw: canonical, machine-friendly numbers without grouping or symbols (see GeneralPrecision)
+/// Formatted: as the cell's number format displays them, e.g. "1,234.50 €", see format_number
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NumberOutput {
+    #[default]
+    Raw,
+    Formatted,
+}
+
+impl std::str::FromStr for NumberOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(Self::Raw),
+            "formatted" => Ok(Self::Formatted),
+            _ => Err(format!("invalid mode '{}', expected raw or formatted", s)),
+        }
+    }
+}
+
+/// A token of one section of a number format code, see format_number
+#[derive(Debug, Clone, PartialEq)]
+enum NumberToken {
+    Literal(String),
+    /// A digit placeholder: '0', '#' or '?'
+    Digit(char),
+    Point,
+    /// A comma: grouping between digit placeholders, else scaling by 1000 or a literal
+    Comma,
+    Percent,
+    /// E+ or E-: whether positive exponents get a plus sign
+    Exponent(bool),
+    Slash,
+    General,
+}
+
+/// A section of a number format code (the parts between `;`)
+#[derive(Debug, Default)]
+struct NumberSection {
+    /// A condition like `[>=100]`: its operator and operand
+    condition: Option<(String, f64)>,
+    tokens: Vec<NumberToken>,
+    /// The locale of a `[$€-40C]` tag
+    locale: Option<NumberLocale>,
+}
+
+impl NumberSection {
+    fn matches(&self, value: f64) -> bool {
+        match &self.condition {
+            Some((op, operand)) => match op.as_str() {
+                "<" => value < *operand,
+                "<=" => value <= *operand,
+                ">" => value > *operand,
+                ">=" => value >= *operand,
+                "<>" => value != *operand,
+                _ => value == *operand,
+            },
+            None => true,
+        }
+    }
+
+    /// Whether the section is meant for negative numbers and writes its own sign
+    fn is_negative(&self) -> bool {
+        self.condition
+            .as_ref()
+            .is_some_and(|(op, operand)| op.starts_with('<') && op != "<>" && *operand <= 0.0)
+    }
+}
+
+/// Split a number format code into its sections and tokenize them
+fn parse_number_format(format_code: &str) -> Vec<NumberSection> {
+    let chars: Vec<char> = format_code.chars().collect();
+    let mut sections = vec![NumberSection::default()];
+    let push_literal = |section: &mut NumberSection, text: &str| {
+        if let Some(NumberToken::Literal(last)) = section.tokens.last_mut() {
+            last.push_str(text);
+        } else {
+            section.tokens.push(NumberToken::Literal(text.to_string()));
+        }
+    };
+    let mut i = 0;
+    while i < chars.len() {
+        let section = sections.last_mut().expect("at least one section");
+        let c = chars[i];
+        let rest: String = chars[i..].iter().take(7).collect::<String>().to_lowercase();
+        match c {
+            ';' => sections.push(NumberSection::default()),
+            '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&q| q == '"')
+                    .map_or(chars.len(), |p| i + 1 + p);
+                push_literal(section, &chars[i + 1..end].iter().collect::<String>());
+                i = end;
+            }
+            '\\' => {
+                if let Some(next) = chars.get(i + 1) {
+                    push_literal(section, &next.to_string());
+                }
+                i += 1;
+            }
+            '_' => {
+                push_literal(section, " ");
+                i += 1;
+            }
+            '*' => i += 1,
+            '[' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&b| b == ']')
+                    .map_or(chars.len(), |p| i + 1 + p);
+                let inner: String = chars[i + 1..end].iter().collect();
+                if let Some(tag) = inner.strip_prefix('$') {
+                    let tag = parse_locale_tag(tag);
+                    if let Some(currency) = &tag.currency {
+                        push_literal(section, currency);
+                    }
+                    section.locale = tag.locale;
+                } else if let Some(op) = ["<=", ">=", "<>", "<", ">", "="]
+                    .into_iter()
+                    .find(|op| inner.starts_with(op))
+                    && let Ok(operand) = inner[op.len()..].trim().parse()
+                {
+                    section.condition = Some((op.to_string(), operand));
+                }
+                // Anything else is a color
+                i = end;
+            }
+            '0' | '#' | '?' => section.tokens.push(NumberToken::Digit(c)),
+            '.' => {
+                if section.tokens.contains(&NumberToken::Point)
+                    || section
+                        .tokens
+                        .iter()
+                        .any(|t| matches!(t, NumberToken::Exponent(_)))
+                {
+                    push_literal(section, ".");
+                } else {
+                    section.tokens.push(NumberToken::Point);
+                }
+            }
+            ',' => section.tokens.push(NumberToken::Comma),
+            '%' => section.tokens.push(NumberToken::Percent),
+            '/' => section.tokens.push(NumberToken::Slash),
+            '@' => section.tokens.push(NumberToken::General),
+            'e' | 'E' if matches!(chars.get(i + 1), Some('+') | Some('-')) => {
+                section
+                    .tokens
+                    .push(NumberToken::Exponent(chars[i + 1] == '+'));
+                i += 1;
+            }
+            _ if rest.starts_with("general") => {
+                section.tokens.push(NumberToken::General);
+                i += 6;
+            }
+            _ => push_literal(section, &c.to_string()),
+        }
+        i += 1;
+    }
+    sections
+}
+
+/// Round a non-negative value to decimals places, half away from zero, on its first 15
+/// significant digits as Excel does (so 2.675 rounds to 2.68)
+/// Returns the digits before and after the decimal point; the integer part is "0" below 1.
+fn round_decimal(value: f64, decimals: usize) -> (String, String) {
+    let sci = format!("{:.14e}", value);
+    let (mantissa, exp) = sci.split_once('e').unwrap_or((&sci, "0"));
+    let mut digits: Vec<u8> = mantissa.bytes().filter(u8::is_ascii_digit).collect();
+    // Digits before the decimal point
+    let mut point = exp.parse::<i64>().unwrap_or(0) + 1;
+    if value == 0.0 {
+        point = 1;
+    }
+    let keep = point + decimals as i64;
+    if keep < 0 {
+        return ("0".to_string(), "0".repeat(decimals));
+    }
+    let keep = keep as usize;
+    if keep < digits.len() {
+        let round_up = digits[keep] >= b'5';
+        digits.truncate(keep);
+        if round_up {
+            let mut i = keep;
+            loop {
+                if i == 0 {
+                    digits.insert(0, b'1');
+                    point += 1;
+                    break;
+                }
+                i -= 1;
+                if digits[i] == b'9' {
+                    digits[i] = b'0';
+                } else {
+                    digits[i] += 1;
+                    break;
+                }
+            }
+        }
+    }
+    let keep = (point + decimals as i64).max(0) as usize;
+    digits.resize(keep.max(digits.len()), b'0');
+    let digit = |i: i64| -> char {
+        if i < 0 {
+            '0'
+        } else {
+            digits.get(i as usize).map_or('0', |&d| d as char)
+        }
+    };
+    let integer: String = if point <= 0 {
+        "0".to_string()
+    } else {
+        (0..point).map(digit).collect()
+    };
+    let fraction: String = (point..point + decimals as i64).map(digit).collect();
+    let integer = integer.trim_start_matches('0');
+    (
+        if integer.is_empty() { "0" } else { integer }.to_string(),
+        fraction,
+    )
+}
+
+/// Write the digits of integer (no leading zeros, "0" for zero) into the placeholders of
+/// tokens, right to left: extra digits go to the first placeholder, missing ones are written
+/// as '0' for `0`, a space for `?` and not at all for `#`
+fn fill_integer(tokens: &[NumberToken], integer: &str, grouping: Option<char>, out: &mut String) {
+    let integer = if integer == "0" { "" } else { integer };
+    let placeholders: Vec<char> = tokens
+        .iter()
+        .filter_map(|t| match t {
+            NumberToken::Digit(p) => Some(*p),
+            _ => None,
+        })
+        .collect();
+    // The digits written for each placeholder, the first one taking any extra digits
+    let mut slots = vec![String::new(); placeholders.len()];
+    let mut digits = integer.chars().rev();
+    for (slot, p) in slots.iter_mut().zip(&placeholders).rev() {
+        match digits.next() {
+            Some(d) => slot.push(d),
+            None if *p == '0' => slot.push('0'),
+            None if *p == '?' => slot.push(' '),
+            None => {}
+        }
+    }
+    if let Some(first) = slots.first_mut() {
+        let extra: String = digits.collect::<Vec<_>>().into_iter().rev().collect();
+        first.insert_str(0, &extra);
+    }
+    if let Some(separator) = grouping {
+        // Grouping applies to the digits as a whole, so they are written at the first
+        // placeholder
+        let all: String = slots.concat();
+        let padding = all.len() - all.trim_start().len();
+        let number = all.trim_start();
+        let mut grouped = " ".repeat(padding);
+        for (i, d) in number.chars().enumerate() {
+            if i > 0 && (number.len() - i).is_multiple_of(3) {
+                grouped.push(separator);
+            }
+            grouped.push(d);
+        }
+        slots = vec![String::new(); placeholders.len()];
+        if let Some(first) = slots.first_mut() {
+            *first = grouped;
+        }
+    }
+    let mut slots = slots.into_iter();
+    for token in tokens {
+        match token {
+            NumberToken::Digit(_) => out.push_str(&slots.next().unwrap_or_default()),
+            NumberToken::Literal(text) => out.push_str(text),
+            NumberToken::Percent => out.push('%'),
+            _ => {}
+        }
+    }
+}
+
+/// Write the digits of fraction into the placeholders of tokens, left to right; trailing
+/// zeros are dropped for `#` and written as spaces for `?`
+fn fill_fraction(tokens: &[NumberToken], fraction: &str, out: &mut String) {
+    let placeholders: Vec<char> = tokens
+        .iter()
+        .filter_map(|t| match t {
+            NumberToken::Digit(p) => Some(*p),
+            _ => None,
+        })
+        .collect();
+    // None for a trailing zero left out
+    let mut digits: Vec<Option<char>> = fraction.chars().map(Some).collect();
+    digits.resize(placeholders.len(), Some('0'));
+    for (digit, p) in digits.iter_mut().zip(&placeholders).rev() {
+        match (*digit, p) {
+            (Some('0'), '#') => *digit = None,
+            (Some('0'), '?') => *digit = Some(' '),
+            _ => break,
+        }
+    }
+    let mut digits = digits.into_iter();
+    for token in tokens {
+        match token {
+            NumberToken::Digit(_) => {
+                if let Some(d) = digits.next().flatten() {
+                    out.push(d);
+                }
+            }
+            NumberToken::Literal(text) => out.push_str(text),
+            NumberToken::Percent => out.push('%'),
+            _ => {}
+        }
+    }
+}
+
+/// Render a non-negative value with tokens that have no exponent or fraction slash
+fn format_decimal(value: f64, tokens: &[NumberToken], locale: NumberLocale, out: &mut String) {
+    let point = tokens.iter().position(|t| *t == NumberToken::Point);
+    let (integer_tokens, fraction_tokens) = match point {
+        Some(p) => (&tokens[..p], &tokens[p + 1..]),
+        None => (tokens, &[][..]),
+    };
+    let decimals = fraction_tokens
+        .iter()
+        .filter(|t| matches!(t, NumberToken::Digit(_)))
+        .count();
+    let (integer, fraction) = round_decimal(value, decimals);
+    let grouping = integer_tokens
+        .contains(&NumberToken::Comma)
+        .then_some(locale.group_separator);
+    fill_integer(integer_tokens, &integer, grouping, out);
+    if point.is_some() {
+        out.push(locale.decimal_separator);
+        fill_fraction(fraction_tokens, &fraction, out);
+    }
+}
+
+/// Render a non-negative value in scientific notation, e.g. with `0.00E+00`
+fn format_scientific(
+    value: f64,
+    tokens: &[NumberToken],
+    plus: bool,
+    exponent_tokens: &[NumberToken],
+    locale: NumberLocale,
+    out: &mut String,
+) {
+    let point = tokens.iter().position(|t| *t == NumberToken::Point);
+    let integer_places: Vec<char> = tokens[..point.unwrap_or(tokens.len())]
+        .iter()
+        .filter_map(|t| match t {
+            NumberToken::Digit(p) => Some(*p),
+            _ => None,
+        })
+        .collect();
+    let decimals = point.map_or(0, |p| {
+        tokens[p + 1..]
+            .iter()
+            .filter(|t| matches!(t, NumberToken::Digit(_)))
+            .count()
+    });
+    let places = integer_places.len().max(1) as i32;
+    // With `#` in front (e.g. ##0.0E+0) the exponent is a multiple of the integer places
+    let engineering = places > 1 && integer_places.contains(&'#');
+    let mut exponent = if value == 0.0 {
+        0
+    } else {
+        let magnitude = round_decimal(value, 0).0;
+        let e = if value >= 1.0 {
+            magnitude.len() as i32 - 1
+        } else {
+            value.log10().floor() as i32
+        };
+        if engineering {
+            e - e.rem_euclid(places)
+        } else {
+            e - (places - 1)
+        }
+    };
+    let mut mantissa = value / 10f64.powi(exponent);
+    // Rounding can carry into another digit, e.g. 9.99 with 0.0E+0
+    if !engineering && value != 0.0 && round_decimal(mantissa, decimals).0.len() > places as usize {
+        exponent += 1;
+        mantissa = value / 10f64.powi(exponent);
+    }
+    format_decimal(mantissa, tokens, locale, out);
+    out.push('E');
+    if exponent < 0 {
+        out.push('-');
+    } else if plus {
+        out.push('+');
+    }
+    let width = exponent_tokens
+        .iter()
+        .filter(|t| matches!(t, NumberToken::Digit(_)))
+        .count();
+    let digits = exponent.unsigned_abs().to_string();
+    let mut rest = String::new();
+    fill_integer(exponent_tokens, &digits, None, &mut rest);
+    // An exponent of zero still shows at least one digit
+    if exponent == 0 && width > 0 && !rest.contains('0') {
+        rest.insert(0, '0');
+    }
+    out.push_str(&rest);
+}
+
+/// The numerator and denominator closest to fraction (0 to 1) with a denominator up to max
+fn best_fraction(fraction: f64, max: u64) -> (u64, u64) {
+    let mut best = (0, 1);
+    let mut best_error = f64::INFINITY;
+    for denominator in 1..=max.max(1) {
+        let numerator = (fraction * denominator as f64).round() as u64;
+        let error = (fraction - numerator as f64 / denominator as f64).abs();
+        if error < best_error - 1e-12 {
+            best = (numerator, denominator);
+            best_error = error;
+        }
+    }
+    best
+}
+
+/// Render a non-negative value as a fraction, e.g. with `# ?/?` or `?/8`
+fn format_fraction(value: f64, tokens: &[NumberToken], slash: usize, out: &mut String) {
+    let before = &tokens[..slash];
+    let after = &tokens[slash + 1..];
+    // The numerator is the run of placeholders right before the slash; any placeholders
+    // before that, separated by a literal, take the whole part
+    let numerator_start = before
+        .iter()
+        .rposition(|t| !matches!(t, NumberToken::Digit(_)))
+        .map_or(0, |i| i + 1);
+    let whole_tokens = &before[..numerator_start];
+    let has_whole = whole_tokens
+        .iter()
+        .any(|t| matches!(t, NumberToken::Digit(_)));
+    let numerator_places = before.len() - numerator_start;
+    // A denominator written as a number (e.g. /8 or /10) is fixed, placeholders give its digits
+    let mut denominator_text = String::new();
+    let mut denominator_places = 0;
+    let mut suffix_start = after.len();
+    for (i, token) in after.iter().enumerate() {
+        match token {
+            NumberToken::Digit(p) => {
+                denominator_places += 1;
+                denominator_text.push(*p);
+            }
+            NumberToken::Literal(text)
+                if text.starts_with(|c: char| c.is_ascii_digit()) && i == denominator_places =>
+            {
+                let digits: String = text.chars().take_while(char::is_ascii_digit).collect();
+                denominator_text.push_str(&digits);
+                if digits.len() < text.len() {
+                    suffix_start = i;
+                    break;
+                }
+                continue;
+            }
+            _ => {
+                suffix_start = i;
+                break;
+            }
+        }
+    }
+    let fixed = denominator_text
+        .chars()
+        .any(|c| c.is_ascii_digit() && c != '0')
+        .then(|| {
+            denominator_text
+                .replace(['#', '?'], "0")
+                .parse::<u64>()
+                .ok()
+        })
+        .flatten();
+
+    let (mut whole, fraction) = if has_whole {
+        (value.trunc(), value.fract())
+    } else {
+        (0.0, value)
+    };
+    let (mut numerator, denominator) = match fixed {
+        Some(d) => ((fraction * d as f64).round() as u64, d),
+        None if has_whole => {
+            let max = 10u64.pow(denominator_places.clamp(1, 4) as u32) - 1;
+            best_fraction(fraction, max)
+        }
+        None => {
+            let max = 10u64.pow(denominator_places.clamp(1, 4) as u32) - 1;
+            let (n, d) = best_fraction(fraction.fract(), max);
+            (n + fraction.trunc() as u64 * d, d)
+        }
+    };
+    if has_whole && numerator == denominator {
+        whole += 1.0;
+        numerator = 0;
+    }
+    if has_whole {
+        fill_integer(whole_tokens, &format!("{}", whole as u64), None, out);
+        if numerator == 0 {
+            // Excel leaves the fraction out when it is zero
+            return;
+        }
+    }
+    let pad = |text: String, places: usize, left: bool| {
+        let fill = " ".repeat(places.saturating_sub(text.len()));
+        if left { fill + &text } else { text + &fill }
+    };
+    out.push_str(&pad(numerator.to_string(), numerator_places, true));
+    out.push('/');
+    let denominator_places = denominator_places.max(denominator_text.len());
+    out.push_str(&pad(denominator.to_string(), denominator_places, false));
+    for token in &after[suffix_start..] {
+        if let NumberToken::Literal(text) = token {
+            out.push_str(text);
+        }
+    }
+}
+
+/// Format a number with an Excel number format code, the way Excel displays it
+/// Sections (positive;negative;zero), conditions like `[>=1000]`, digit placeholders
+/// (`0`, `#`, `?`), grouping and scaling commas, percent, scientific notation, fractions,
+/// quoted and escaped literals and `[$€-40C]` currency tags are understood; colors and `*`
+/// fills are left out, and so are the spaces `_x` and `?` pad with at either end.
+/// The decimal and grouping symbols are those of the format's locale tag, else locale.
+/// Date formats are not handled here (see parse_date_format).
+///
+/// Examples:
+///   format_number(1234.5, "#,##0.00", NumberLocale::default()) -> "1,234.50"
+///   format_number(-1234.5, "#,##0.00 [$€-40C];(#,##0.00)", ..) -> "(1 234,50)"
+///   format_number(0.125, "0.0%", ..) -> "12.5%"
+pub fn format_number(value: f64, format_code: &str, locale: NumberLocale) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+    let sections = parse_number_format(format_code);
+    let conditional = sections.iter().any(|s| s.condition.is_some());
+    // The section to use, and whether a minus sign has to be added
+    let (section, signed) = if conditional {
+        match sections
+            .iter()
+            .take(3)
+            .find(|s| s.condition.is_some() && s.matches(value))
+            .or_else(|| sections.iter().take(3).find(|s| s.condition.is_none()))
+        {
+            Some(section) => (section, !section.is_negative()),
+            None => return format_general(value),
+        }
+    } else {
+        match sections.len() {
+            1 => (&sections[0], true),
+            2 => (&sections[usize::from(value < 0.0)], value >= 0.0),
+            _ if value > 0.0 => (&sections[0], true),
+            _ if value < 0.0 => (&sections[1], false),
+            _ => (&sections[2], true),
+        }
+    };
+    let locale = section.locale.unwrap_or(locale);
+    let tokens = &section.tokens;
+    let mut abs = value.abs();
+    let mut out = String::new();
+
+    if tokens.contains(&NumberToken::General) {
+        for token in tokens {
+            match token {
+                NumberToken::General => out.push_str(
+                    &format_general(abs).replace('.', &locale.decimal_separator.to_string()),
+                ),
+                NumberToken::Literal(text) => out.push_str(text),
+                _ => {}
+            }
+        }
+    } else {
+        let mut tokens: Vec<NumberToken> = tokens.clone();
+        let is_digit = |t: &NumberToken| matches!(t, NumberToken::Digit(_));
+        // Commas right after the last placeholder, or right before the decimal point, divide
+        // by 1000 each
+        let number_end = tokens
+            .iter()
+            .position(|t| matches!(t, NumberToken::Exponent(_) | NumberToken::Slash))
+            .unwrap_or(tokens.len());
+        let mut scaling = Vec::new();
+        if let Some(last) = tokens[..number_end].iter().rposition(is_digit) {
+            scaling.push(last + 1);
+        }
+        if let Some(point) = tokens.iter().position(|t| *t == NumberToken::Point) {
+            let start = tokens[..point]
+                .iter()
+                .rposition(|t| *t != NumberToken::Comma)
+                .map_or(0, |i| i + 1);
+            if start > 0 && is_digit(&tokens[start - 1]) {
+                scaling.push(start);
+            }
+        }
+        scaling.sort_unstable();
+        for &start in scaling.iter().rev() {
+            while tokens.get(start) == Some(&NumberToken::Comma) {
+                abs /= 1000.0;
+                tokens.remove(start);
+            }
+        }
+        // Other commas group digits when between integer placeholders, else they are literal
+        let integer_end = tokens
+            .iter()
+            .position(|t| {
+                matches!(
+                    t,
+                    NumberToken::Point | NumberToken::Exponent(_) | NumberToken::Slash
+                )
+            })
+            .unwrap_or(tokens.len());
+        let first = tokens.iter().position(is_digit);
+        let last = tokens[..integer_end].iter().rposition(is_digit);
+        for (i, token) in tokens.iter_mut().enumerate() {
+            let between = first.is_some_and(|f| i > f) && last.is_some_and(|l| i < l);
+            if *token == NumberToken::Comma && !between {
+                *token = NumberToken::Literal(",".to_string());
+            }
+        }
+        for token in &tokens {
+            if *token == NumberToken::Percent {
+                abs *= 100.0;
+            }
+        }
+        if let Some(e) = tokens
+            .iter()
+            .position(|t| matches!(t, NumberToken::Exponent(_)))
+        {
+            let plus = tokens[e] == NumberToken::Exponent(true);
+            format_scientific(abs, &tokens[..e], plus, &tokens[e + 1..], locale, &mut out);
+        } else if let Some(slash) = tokens.iter().position(|t| *t == NumberToken::Slash) {
+            format_fraction(abs, &tokens, slash, &mut out);
+        } else if tokens.iter().any(|t| matches!(t, NumberToken::Digit(_))) {
+            format_decimal(abs, &tokens, locale, &mut out);
+        } else {
+            // Only literals, e.g. "-" for zero in accounting formats
+            for token in &tokens {
+                match token {
+                    NumberToken::Literal(text) => out.push_str(text),
+                    NumberToken::Percent => out.push('%'),
+                    NumberToken::Comma => out.push(','),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    let out = out.trim_matches(' ');
+    // No minus sign for values that round to zero
+    if signed && value < 0.0 && out.chars().any(|c| c.is_ascii_digit() && c != '0') {
+        format!("-{}", out)
+    } else {
+        out.to_string()
+    }
+}
+
+/// Drop trailing zeros of a decimal fraction, and the point if nothing is left
+fn trim_fraction(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_format_locale() {
+        let fr = parse_format_locale("#,##0.00 [$€-40C]").unwrap();
+        assert_eq!(fr.currency.as_deref(), Some("€"));
+        assert_eq!(fr.lcid, Some(0x40C));
+        assert_eq!(fr.locale.unwrap().decimal_separator, ',');
+        assert_eq!(fr.locale.unwrap().group_separator, NBSP);
+
+        let us = parse_format_locale("[$-409]d-mmm-yy").unwrap();
+        assert_eq!(us.currency, None);
+        assert_eq!(us.locale, Some(NumberLocale::default()));
+
+        // Calendar bits in the upper word don't hide the locale
+        let de = parse_format_locale("[$-1010407]dd.mm.yyyy").unwrap();
+        assert_eq!(de.locale.unwrap().decimal_separator, ',');
+
+        assert_eq!(parse_format_locale(r#"0.00 "[$-40C]""#), None);
+        assert_eq!(parse_format_locale("0.00"), None);
+    }
+
+    #[test]
+    fn test_format_general() {
+        assert_eq!(format_general(0.1 + 0.2), "0.3");
+        assert_eq!(format_general(1234.5), "1234.5");
+        assert_eq!(format_general(-2.0), "-2");
+        assert_eq!(format_general(1.0 / 3.0), "0.33333333333");
+        assert_eq!(format_general(12345678901.0), "12345678901");
+        assert_eq!(format_general(123456789012345.0), "1.2345678901E+14");
+        assert_eq!(format_general(0.0000000000123), "1.23E-11");
+        assert_eq!(format_general(-0.00000000000001), "-1E-14");
+        assert_eq!(format_general(0.0), "0");
+
+        // Scientific notation from 1E+11 and below 1E-9, after rounding to 11 digits
+        assert_eq!(format_general(99999999999.0), "99999999999");
+        assert_eq!(format_general(99999999999.4), "99999999999");
+        assert_eq!(format_general(99999999999.9), "1E+11");
+        assert_eq!(format_general(-99999999999.9), "-1E+11");
+        assert_eq!(format_general(100000000000.0), "1E+11");
+        assert_eq!(format_general(0.000000001), "0.000000001");
+        assert_eq!(format_general(0.000000000999999999999), "0.000000001");
+        assert_eq!(format_general(0.000000000999), "9.99E-10");
+    }
+
+    #[test]
+    fn test_format_number() {
+        let us = NumberLocale::default();
+        let f = |value, code| format_number(value, code, us);
+
+        assert_eq!(f(1234.5, "#,##0.00"), "1,234.50");
+        assert_eq!(f(1234567.891, "#,##0"), "1,234,568");
+        assert_eq!(f(2.675, "0.00"), "2.68");
+        assert_eq!(f(0.5, "#.##"), ".5");
+        assert_eq!(f(3.0, "0.0#"), "3.0");
+        assert_eq!(f(-3.5, "0.0"), "-3.5");
+        assert_eq!(f(-0.001, "0.00"), "0.00");
+        assert_eq!(f(42.0, "00000"), "00042");
+        assert_eq!(f(5551234.0, "000-0000"), "555-1234");
+        assert_eq!(f(0.125, "0.0%"), "12.5%");
+        assert_eq!(f(1234567.0, "#,##0.0,,\"M\""), "1.2M");
+        assert_eq!(f(12345.0, "0.00E+00"), "1.23E+04");
+        assert_eq!(f(0.00012, "0.0E+0"), "1.2E-4");
+        assert_eq!(f(12345.0, "##0.0E+0"), "12.3E+3");
+        assert_eq!(f(1.5, "# ?/?"), "1 1/2");
+        assert_eq!(f(0.75, "?/8"), "6/8");
+        assert_eq!(f(3.0, "# ?/?"), "3");
+        assert_eq!(f(2.0, "General"), "2");
+        assert_eq!(f(7.0, "\"#\"0"), "#7");
+
+        // Sections, conditions and accounting formats
+        assert_eq!(f(-1234.5, "#,##0.00;(#,##0.00)"), "(1,234.50)");
+        assert_eq!(f(0.0, "0;-0;\"zero\""), "zero");
+        assert_eq!(f(-5.0, "0;[Red]-0"), "-5");
+        assert_eq!(f(1500.0, "[>=1000]#,##0,\"K\";0"), "2K");
+        assert_eq!(f(150.0, "[>=1000]#,##0,\"K\";0"), "150");
+        assert_eq!(
+            f(
+                1234.5,
+                "_($* #,##0.00_);_($* (#,##0.00);_($* \"-\"??_);_(@_)"
+            ),
+            "$1,234.50"
+        );
+        assert_eq!(
+            f(0.0, "_($* #,##0.00_);_($* (#,##0.00);_($* \"-\"??_);_(@_)"),
+            "$-"
+        );
+
+        // Currency tags bring their locale
+        assert_eq!(f(1234.5, "#,##0.00 [$€-40C]"), "1\u{a0}234,50 €");
+        assert_eq!(f(1234.5, "[$$-409]#,##0.00"), "$1,234.50");
+        let de = NumberLocale {
+            decimal_separator: ',',
+            group_separator: '.',
+        };
+        assert_eq!(format_number(1234.5, "#,##0.00", de), "1.234,50");
+    }
+
+    #[test]
+    fn test_parse_date_text() {
+        let parse = |text: &str, format: &str| {
+            let tokens = parse_date_format(format).unwrap();
+            parse_date_text(text, &tokens, 1930).map(|d| d.to_string())
+        };
+        assert_eq!(window_year(29, 1930), 2029);
+        assert_eq!(window_year(30, 1930), 1930);
+        assert_eq!(window_year(99, 1950), 1999);
+        assert_eq!(window_year(49, 1950), 2049);
+
+        assert_eq!(
+            parse("05/03/34", "dd/mm/yy").as_deref(),
+            Some("1934-03-05 00:00:00")
+        );
+        assert_eq!(
+            parse("5/3/29", "dd/mm/yy").as_deref(),
+            Some("2029-03-05 00:00:00")
+        );
+        assert_eq!(
+            parse(" 05/03/2034 ", "dd/mm/yyyy").as_deref(),
+            Some("2034-03-05 00:00:00")
+        );
+        assert_eq!(
+            parse("12-Mar-45", "d-mmm-yy").as_deref(),
+            Some("1945-03-12 00:00:00")
+        );
+        assert_eq!(
+            parse("March 07", "mmmm yy").as_deref(),
+            Some("2007-03-01 00:00:00")
+        );
+        assert_eq!(
+            parse("1/2/03 1:30 PM", "m/d/yy h:mm AM/PM").as_deref(),
+            Some("2003-01-02 13:30:00")
+        );
+        assert_eq!(parse("31/02/34", "dd/mm/yy"), None);
+        assert_eq!(parse("05/03/341", "dd/mm/yy"), None);
+        assert_eq!(parse("05-03-34", "dd/mm/yy"), None);
+        assert_eq!(parse("soon", "dd/mm/yy"), None);
+        assert_eq!(parse("12:30", "h:mm"), None);
+    }
+
+    #[test]
+    fn test_parse_date_format() {
+        use DateToken::*;
+        let lit = |s: &str| Literal(s.to_string());
+
+        assert_eq!(
+            parse_date_format("yyyy-mm-dd"),
+            Some(vec![Year(4), lit("-"), Month(2), lit("-"), Day(2)])
+        );
+        assert_eq!(
+            parse_date_format("h:mm AM/PM"),
+            Some(vec![
+                Hour(1),
+                lit(":"),
+                Minute(2),
+                lit(" "),
+                AmPm("AM/PM".into())
+            ])
+        );
+        assert_eq!(
+            parse_date_format("[h]:mm:ss.00"),
+            Some(vec![
+                ElapsedHours(1),
+                lit(":"),
+                Minute(2),
+                lit(":"),
+                Second(2),
+                FractionalSecond(2)
+            ])
+        );
+        assert_eq!(
+            parse_date_format(r#"[$-409]mmm\ yyyy;@"#),
+            Some(vec![Month(3), lit(" "), Year(4)])
+        );
+        assert_eq!(
+            parse_date_format(r#""Day "d"#),
+            Some(vec![lit("Day "), Day(1)])
+        );
+
+        assert!(is_date_format("[Red]dd/mm/yy"));
+        assert!(!is_date_format(r#"0.00 "mm""#));
+        assert!(!is_date_format(r#""CHF" #,##0.00"#));
+        assert!(!is_date_format("[$USD] 0"));
+        assert!(!is_date_format("0.00E+00"));
+        assert!(!is_date_format("General"));
+        assert!(!is_date_format("@"));
+        assert!(!is_date_format(r#""Total""#));
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::{Context, Result};
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, WriteMultipart};
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tokio::runtime::Runtime;
+use url::Url;
+
+use crate::input::{WorkbookReader, object_url};
+use crate::remote::{RangeSource, RemoteReader};
+
+/// Size of the parts of a multipart upload
+const UPLOAD_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+/// Parts of an upload in flight at once
+const UPLOAD_CONCURRENCY: usize = 4;
+
+/// A workbook in an object store, read with ranged GETs
+#[derive(Debug)]
+struct ObjectSource {
+    store: Box<dyn ObjectStore>,
+    path: ObjectPath,
+    runtime: Runtime,
+}
+
+impl RangeSource for ObjectSource {
+    fn fetch(&self, range: Range<u64>) -> io::Result<Vec<u8>> {
+        self.runtime
+            .block_on(self.store.get_range(&self.path, range))
+            .map(|bytes| bytes.to_vec())
+            .map_err(io::Error::other)
+    }
+}
+
+/// The store and object path of a URL like s3://bucket/key
+/// Credentials and settings come from the environment like for the cloud CLIs: AWS_*, GOOGLE_*
+/// and AZURE_* variables, e.g. AWS_REGION or AWS_ENDPOINT for S3 compatible stores.
+fn parse(url: &str) -> Result<(Box<dyn ObjectStore>, ObjectPath)> {
+    let parsed = Url::parse(url).with_context(|| format!("Invalid URL {}", url))?;
+    let options = std::env::vars()
+        .filter(|(key, _)| {
+            ["AWS_", "GOOGLE_", "AZURE_"]
+                .iter()
+                .any(|p| key.starts_with(p))
+        })
+        .map(|(key, value)| (key.to_ascii_lowercase(), value));
+    object_store::parse_url_opts(&parsed, options).with_context(|| format!("Cannot open {}", url))
+}
+
+/// A runtime for the async object store calls, blocked on from the calling thread
+fn runtime() -> Result<Runtime> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start async runtime")
+}
+
+/// Open the workbook at an object store URL (see is_object_url) for reading with ranged GETs
+pub fn open_object(url: &str) -> Result<WorkbookReader> {
+    let (store, path) = parse(url)?;
+    let runtime = runtime()?;
+    let meta = runtime
+        .block_on(store.head(&path))
+        .with_context(|| format!("Failed to open {}", url))?;
+    let source = ObjectSource {
+        store,
+        path,
+        runtime,
+    };
+    Ok(WorkbookReader::Remote(RemoteReader::new(
+        Box::new(source),
+        meta.size,
+    )))
+}
+
+/// Upload every file under dir to the object store prefix url (e.g. s3://bucket/out/), at
+/// their paths relative to dir
+/// Returns the URLs of the objects written, in path order.
+pub fn upload_dir(dir: &Path, url: &str) -> Result<Vec<String>> {
+    let (store, prefix) = parse(url)?;
+    let runtime = runtime()?;
+    let mut files = Vec::new();
+    list_files(dir, &mut files)?;
+    files.sort();
+    let mut urls = Vec::with_capacity(files.len());
+    for file in files {
+        let relative = file.strip_prefix(dir).unwrap_or(&file);
+        let location = relative.iter().fold(prefix.clone(), |path, part| {
+            path.child(part.to_string_lossy().as_ref())
+        });
+        let target = object_url(url, relative);
+        runtime
+            .block_on(upload(store.as_ref(), &file, &location))
+            .with_context(|| format!("Failed to upload {} to {}", file.display(), target))?;
+        urls.push(target);
+    }
+    Ok(urls)
+}
+
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir).with_context(|| format!("read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Stream a file into a multipart upload
+async fn upload(store: &dyn ObjectStore, file: &Path, location: &ObjectPath) -> Result<()> {
+    let mut reader = File::open(file)?;
+    let mut upload = WriteMultipart::new_with_chunk_size(
+        store.put_multipart(location).await?,
+        UPLOAD_CHUNK_SIZE,
+    );
+    let mut buf = vec![0; UPLOAD_CHUNK_SIZE];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        upload.wait_for_capacity(UPLOAD_CONCURRENCY).await?;
+        upload.write(&buf[..n]);
+    }
+    upload.finish().await?;
+    Ok(())
+}
@@ -0,0 +1,186 @@
+use anyhow::{Context, Result};
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Compression applied to written CSV files
+/// None: plain CSV
+/// Gzip: gzip at the default level, file extension "gz"
+/// Zstd: zstd at the default level, file extension "zst"
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "gzip" | "gz" => Ok(Self::Gzip),
+            "zstd" | "zst" => Ok(Self::Zstd),
+            _ => Err(format!(
+                "invalid compression '{}', expected gzip, zstd or none",
+                s
+            )),
+        }
+    }
+}
+
+impl Compression {
+    /// Extension appended to the file name (without the dot), e.g. "gz" for sheet.csv.gz
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gz"),
+            Compression::Zstd => Some("zst"),
+        }
+    }
+}
+
+/// The path of part index (1-based) of a split output file
+/// ".part-NNNN" goes in front of the file extension, after stripping a compression extension,
+/// e.g. (out/data.csv.gz, 1) -> out/data.part-0001.csv.gz
+pub fn part_path(path: &Path, index: u32) -> PathBuf {
+    let name = path
+        .file_name()
+        .map_or_else(String::new, |n| n.to_string_lossy().into_owned());
+    let (rest, compressed) = match name.rsplit_once('.') {
+        Some((rest, ext))
+            if ext
+                .parse::<Compression>()
+                .is_ok_and(|c| c.extension() == Some(ext)) =>
+        {
+            (rest, format!(".{}", ext))
+        }
+        _ => (name.as_str(), String::new()),
+    };
+    let file = match rest.rsplit_once('.') {
+        Some((stem, ext)) => format!("{}.part-{:04}.{}{}", stem, index, ext, compressed),
+        None => format!("{}.part-{:04}{}", rest, index, compressed),
+    };
+    path.with_file_name(file)
+}
+
+/// An output file, compressed on the fly according to Compression
+/// Call finish once everything is written; a compressed stream is incomplete without it.
+/// Discard stands in for a file that is not written, e.g. on a dry run, and Memory for one that
+/// is kept in memory (see convert_xlsx_bytes_to_csv).
+pub enum OutputWriter {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+    Discard(io::Sink),
+    Memory(Vec<u8>),
+}
+
+impl OutputWriter {
+    /// Create (or truncate) the file at path
+    pub fn create(path: &Path, compression: Compression) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("create {}", path.display()))?;
+        let file = BufWriter::new(file);
+        Ok(match compression {
+            Compression::None => OutputWriter::Plain(file),
+            Compression::Gzip => {
+                OutputWriter::Gzip(GzEncoder::new(file, flate2::Compression::default()))
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => OutputWriter::Zstd(zstd::Encoder::new(file, 0)?),
+            #[cfg(not(feature = "zstd"))]
+            Compression::Zstd => anyhow::bail!("zstd compression needs the zstd feature"),
+        })
+    }
+
+    /// A writer that throws away everything written to it
+    pub fn discard() -> Self {
+        OutputWriter::Discard(io::sink())
+    }
+
+    /// End the compressed stream and flush everything to disk
+    pub fn finish(self) -> Result<()> {
+        let mut file = match self {
+            OutputWriter::Plain(file) => file,
+            OutputWriter::Gzip(encoder) => encoder.finish()?,
+            #[cfg(feature = "zstd")]
+            OutputWriter::Zstd(encoder) => encoder.finish()?,
+            OutputWriter::Discard(_) | OutputWriter::Memory(_) => return Ok(()),
+        };
+        file.flush()?;
+        Ok(())
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputWriter::Plain(file) => file.write(buf),
+            OutputWriter::Gzip(encoder) => encoder.write(buf),
+            #[cfg(feature = "zstd")]
+            OutputWriter::Zstd(encoder) => encoder.write(buf),
+            OutputWriter::Discard(sink) => sink.write(buf),
+            OutputWriter::Memory(bytes) => bytes.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputWriter::Plain(file) => file.flush(),
+            OutputWriter::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "zstd")]
+            OutputWriter::Zstd(encoder) => encoder.flush(),
+            OutputWriter::Discard(sink) => sink.flush(),
+            OutputWriter::Memory(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_compressed_output_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            let path = dir.path().join("out");
+            let mut out = OutputWriter::create(&path, compression).unwrap();
+            out.write_all(b"a,b\n1,2\n").unwrap();
+            out.finish().unwrap();
+
+            let file = File::open(&path).unwrap();
+            let mut text = String::new();
+            match compression {
+                Compression::None => io::BufReader::new(file).read_to_string(&mut text),
+                Compression::Gzip => flate2::read::GzDecoder::new(file).read_to_string(&mut text),
+                Compression::Zstd => zstd::Decoder::new(file).unwrap().read_to_string(&mut text),
+            }
+            .unwrap();
+            assert_eq!(text, "a,b\n1,2\n", "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn test_part_path() {
+        assert_eq!(
+            part_path(Path::new("out/data.csv"), 1),
+            PathBuf::from("out/data.part-0001.csv")
+        );
+        assert_eq!(
+            part_path(Path::new("out/v1.2.csv.gz"), 12),
+            PathBuf::from("out/v1.2.part-0012.csv.gz")
+        );
+        assert_eq!(
+            part_path(Path::new("data"), 3),
+            PathBuf::from("data.part-0003")
+        );
+    }
+}
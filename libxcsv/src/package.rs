@@ -0,0 +1,357 @@
+use anyhow::{Context, Result};
+use quick_xml::events::Event;
+use quick_xml::reader::{NsReader, Reader};
+use quick_xml::writer::Writer;
+use std::io::{BufRead, BufReader, Read, Seek, Write};
+use zip::ZipArchive;
+
+use super::ns::{CONTENT_TYPES, PACKAGE_RELATIONSHIPS, read_event};
+use crate::{StyleInfo, parse_styles, read_shared_strings};
+
+const CONTENT_TYPES_PART: &str = "[Content_Types].xml";
+const ROOT_RELS_PART: &str = "_rels/.rels";
+const DEFAULT_WORKBOOK_PART: &str = "xl/workbook.xml";
+
+const SHARED_STRINGS_CONTENT_TYPE: &str =
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sharedStrings+xml";
+const STYLES_CONTENT_TYPE: &str =
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml";
+const THEME_CONTENT_TYPE: &str = "application/vnd.openxmlformats-officedocument.theme+xml";
+
+/// Where the parts needed for conversion live inside the package
+/// All names are zip entry names, without a leading '/'.
+/// workbook: The main workbook part, e.g. xl/workbook.xml
+/// workbook_rels: The relationships of the workbook part, e.g. xl/_rels/workbook.xml.rels
+/// shared_strings: The shared string table, if the package has one
+/// styles: The stylesheet, if the package has one
+/// theme: The theme, whose colors styles refer to, if the package has one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageParts {
+    pub workbook: String,
+    pub workbook_rels: String,
+    pub shared_strings: Option<String>,
+    pub styles: Option<String>,
+    pub theme: Option<String>,
+}
+
+/// A relationship from a .rels part
+/// id: The Id attribute (e.g. rId1)
+/// rel_type: The relationship Type URI
+/// target: The Target attribute as written in the part
+/// external: Whether TargetMode="External", i.e. target is a URI outside the package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relationship {
+    pub id: String,
+    pub rel_type: String,
+    pub target: String,
+    pub external: bool,
+}
+
+/// Parse [Content_Types].xml into (part name, content type) pairs of its Override entries
+/// Part names are returned without the leading '/'.
+pub fn parse_content_types<R: BufRead>(reader: R) -> Result<Vec<(String, String)>> {
+    let mut xml = NsReader::from_reader(reader);
+    let mut buf = Vec::new();
+    let mut overrides = Vec::new();
+    loop {
+        match read_event(&mut xml, &mut buf, CONTENT_TYPES) {
+            Ok((true, Event::Empty(e))) | Ok((true, Event::Start(e)))
+                if e.local_name().as_ref() == b"Override" =>
+            {
+                let mut part = None;
+                let mut content_type = None;
+                e.attributes().flatten().for_each(|a| match a.key.as_ref() {
+                    b"PartName" => part = Some(String::from_utf8_lossy(&a.value).into_owned()),
+                    b"ContentType" => {
+                        content_type = Some(String::from_utf8_lossy(&a.value).into_owned())
+                    }
+                    _ => {}
+                });
+                if let (Some(part), Some(content_type)) = (part, content_type) {
+                    overrides.push((part.trim_start_matches('/').to_string(), content_type));
+                }
+            }
+            Ok((_, Event::Eof)) => break,
+            Err(e) => {
+                return Err(anyhow::anyhow!(
+                    "XML error in {}: {}",
+                    CONTENT_TYPES_PART,
+                    e
+                ));
+            }
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(overrides)
+}
+
+/// Parse a .rels part into its relationships
+pub fn parse_relationships<R: BufRead>(reader: R) -> Result<Vec<Relationship>> {
+    let mut xml = NsReader::from_reader(reader);
+    let mut buf = Vec::new();
+    let mut relationships = Vec::new();
+    loop {
+        match read_event(&mut xml, &mut buf, PACKAGE_RELATIONSHIPS) {
+            Ok((true, Event::Empty(e))) | Ok((true, Event::Start(e)))
+                if e.local_name().as_ref() == b"Relationship" =>
+            {
+                let mut id = None;
+                let mut rel_type = None;
+                let mut target = None;
+                let mut external = false;
+                e.attributes().flatten().for_each(|a| match a.key.as_ref() {
+                    b"Id" => id = Some(String::from_utf8_lossy(&a.value).into_owned()),
+                    b"Type" => rel_type = Some(String::from_utf8_lossy(&a.value).into_owned()),
+                    b"Target" => target = Some(String::from_utf8_lossy(&a.value).into_owned()),
+                    b"TargetMode" => external = a.value.as_ref() == b"External",
+                    _ => {}
+                });
+                if let (Some(id), Some(rel_type), Some(target)) = (id, rel_type, target) {
+                    relationships.push(Relationship {
+                        id,
+                        rel_type,
+                        target,
+                        external,
+                    });
+                }
+            }
+            Ok((_, Event::Eof)) => break,
+            Err(e) => return Err(anyhow::anyhow!("XML error in relationships: {}", e)),
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(relationships)
+}
+
+/// The name of the relationships part that belongs to part
+/// e.g. xl/workbook.xml -> xl/_rels/workbook.xml.rels
+pub fn rels_part_name(part: &str) -> String {
+    match part.rsplit_once('/') {
+        Some((dir, file)) => format!("{}/_rels/{}.rels", dir, file),
+        None => format!("_rels/{}.rels", part),
+    }
+}
+
+/// Resolve a relationship target into a zip entry name
+/// Relative targets are resolved against the directory of source_part (the part the .rels
+/// belongs to, "" for the package root); targets starting with '/' are relative to the package
+/// root. "." and ".." segments are normalized, e.g. ("xl/workbook.xml", "worksheets/../a.xml")
+/// resolves to "xl/a.xml".
+pub fn resolve_target(source_part: &str, target: &str) -> String {
+    let mut segments: Vec<&str> = match target.strip_prefix('/') {
+        Some(_) => Vec::new(),
+        None => source_part
+            .rsplit_once('/')
+            .map_or(Vec::new(), |(dir, _)| dir.split('/').collect()),
+    };
+    for segment in target.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+/// Locate the workbook, its rels, shared strings and styles in the package
+/// The workbook is the target of the officeDocument relationship in _rels/.rels, falling back
+/// to the part with a spreadsheet main content type and finally to xl/workbook.xml.
+/// Shared strings and styles are found by content type, falling back to sharedStrings.xml and
+/// styles.xml next to the workbook; parts missing from the package are left out.
+pub fn read_package_parts<R: Read + Seek>(zip: &mut ZipArchive<R>) -> Result<PackageParts> {
+    let overrides = match zip.by_name(CONTENT_TYPES_PART) {
+        Ok(f) => parse_content_types(BufReader::new(f))?,
+        Err(_) => Vec::new(),
+    };
+    let root_rels = match zip.by_name(ROOT_RELS_PART) {
+        Ok(f) => parse_relationships(BufReader::new(f))
+            .with_context(|| format!("invalid {}", ROOT_RELS_PART))?,
+        Err(_) => Vec::new(),
+    };
+
+    let workbook = root_rels
+        .iter()
+        .find(|r| !r.external && r.rel_type.ends_with("/officeDocument"))
+        .map(|r| resolve_target("", &r.target))
+        .or_else(|| {
+            overrides
+                .iter()
+                .find(|(_, content_type)| content_type.ends_with(".main+xml"))
+                .map(|(part, _)| part.clone())
+        })
+        .unwrap_or_else(|| DEFAULT_WORKBOOK_PART.to_string());
+
+    let dir = workbook.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let mut find_part = |content_type: &str, file: &str| {
+        overrides
+            .iter()
+            .find(|(_, ct)| ct == content_type)
+            .map(|(part, _)| part.clone())
+            .filter(|part| {
+                let exists = zip.by_name(part).is_ok();
+                if !exists {
+                    tracing::warn!(
+                        "{} is listed in {} but missing from the package",
+                        part,
+                        CONTENT_TYPES_PART
+                    );
+                }
+                exists
+            })
+            .or_else(|| {
+                let part = if dir.is_empty() {
+                    file.to_string()
+                } else {
+                    format!("{}/{}", dir, file)
+                };
+                zip.by_name(&part).is_ok().then_some(part)
+            })
+    };
+    let shared_strings = find_part(SHARED_STRINGS_CONTENT_TYPE, "sharedStrings.xml");
+    let styles = find_part(STYLES_CONTENT_TYPE, "styles.xml");
+    let theme = find_part(THEME_CONTENT_TYPE, "theme/theme1.xml");
+
+    Ok(PackageParts {
+        workbook_rels: rels_part_name(&workbook),
+        workbook,
+        shared_strings,
+        styles,
+        theme,
+    })
+}
+
+/// Read the shared strings and styles of the package (empty if it has none)
+pub fn read_strings_and_styles<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    parts: &PackageParts,
+) -> Result<(Vec<String>, Vec<StyleInfo>)> {
+    let shared_strings = match &parts.shared_strings {
+        Some(part) => read_shared_strings(BufReader::new(
+            zip.by_name(part)
+                .with_context(|| format!("missing {}", part))?,
+        ))?,
+        None => Vec::new(),
+    };
+    let styles = match &parts.styles {
+        Some(part) => parse_styles(BufReader::new(
+            zip.by_name(part)
+                .with_context(|| format!("missing {}", part))?,
+        ))?,
+        None => Vec::new(),
+    };
+    Ok((shared_strings, styles))
+}
+
+/// Copy the XML document in reader to out, indented by two spaces per level
+/// Text is copied as is; whitespace-only text with a line break is taken for the indentation
+/// of the source and dropped.
+pub fn write_pretty_xml<R: BufRead, W: Write>(reader: R, out: W) -> Result<()> {
+    let mut xml = Reader::from_reader(reader);
+    let mut writer = Writer::new_with_indent(out, b' ', 2);
+    let mut buf = Vec::new();
+    loop {
+        match xml.read_event_into(&mut buf) {
+            Ok(Event::Eof) => break,
+            Ok(Event::Text(t)) if t.iter().all(u8::is_ascii_whitespace) && t.contains(&b'\n') => {}
+            // Keep write errors io::Errors, so callers can tell a closed pipe
+            Ok(event) => writer.write_event(event).map_err(|e| match e {
+                quick_xml::Error::Io(e) => std::io::Error::new(e.kind(), e.to_string()).into(),
+                e => anyhow::Error::from(e),
+            })?,
+            Err(e) => {
+                return Err(anyhow::anyhow!(
+                    "XML error at byte {}: {}",
+                    xml.buffer_position(),
+                    e
+                ));
+            }
+        }
+        buf.clear();
+    }
+    writer.get_mut().write_all(b"\n")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::ZipWriter;
+    use zip::write::FileOptions;
+
+    #[test]
+    fn test_read_package_parts_at_non_standard_paths() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in [
+            (
+                "[Content_Types].xml",
+                r#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+                    <Override PartName="/book/main.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/>
+                    <Override PartName="/book/strings.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sharedStrings+xml"/>
+                </Types>"#,
+            ),
+            (
+                "_rels/.rels",
+                r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+                    <Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="/book/main.xml"/>
+                </Relationships>"#,
+            ),
+            ("book/main.xml", "<workbook/>"),
+            ("book/strings.xml", "<sst/>"),
+            ("book/styles.xml", "<styleSheet/>"),
+        ] {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(data.as_bytes()).unwrap();
+        }
+        let mut zip = ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        let parts = read_package_parts(&mut zip).unwrap();
+        assert_eq!(
+            parts,
+            PackageParts {
+                workbook: "book/main.xml".to_string(),
+                workbook_rels: "book/_rels/main.xml.rels".to_string(),
+                shared_strings: Some("book/strings.xml".to_string()),
+                styles: Some("book/styles.xml".to_string()),
+                theme: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_resolve_target() {
+        assert_eq!(
+            resolve_target("xl/workbook.xml", "worksheets/sheet1.xml"),
+            "xl/worksheets/sheet1.xml"
+        );
+        assert_eq!(
+            resolve_target("xl/workbook.xml", "/xl/worksheets/sheet1.xml"),
+            "xl/worksheets/sheet1.xml"
+        );
+        assert_eq!(
+            resolve_target("xl/workbook.xml", "worksheets/../worksheets/./sheet1.xml"),
+            "xl/worksheets/sheet1.xml"
+        );
+        assert_eq!(
+            resolve_target("xl/workbook.xml", "../customXml/item1.xml"),
+            "customXml/item1.xml"
+        );
+        assert_eq!(resolve_target("", "xl/workbook.xml"), "xl/workbook.xml");
+    }
+
+    #[test]
+    fn test_write_pretty_xml() {
+        let xml = "<?xml version=\"1.0\"?>\n<sst count=\"2\"><si><t xml:space=\"preserve\"> a&amp;b </t></si>\n  <si><t> </t></si><x/></sst>";
+        let mut out = Vec::new();
+        write_pretty_xml(xml.as_bytes(), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "<?xml version=\"1.0\"?>\n<sst count=\"2\">\n  <si>\n    <t xml:space=\"preserve\"> a&amp;b </t>\n  </si>\n  <si>\n    <t> </t>\n  </si>\n  <x/>\n</sst>\n"
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use anyhow::Result;
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender, sync_channel};
+
+use crate::{
+    CellValue, ExportOptions, ExportProgress, ExportSummary, RowTarget, StringTable, StyleInfo,
+    Unit, read_sheet,
+};
+
+/// Rows queued between the parsing and the writing thread; enough to ride out a slow write
+/// without holding much of the sheet in memory
+const PIPELINE_ROWS: usize = 1024;
+
+/// What the parsing thread hands to the writing thread
+enum Message {
+    SheetWidth(usize),
+    UnitColumns(Vec<(usize, Unit)>),
+    Row {
+        row: Vec<String>,
+        present: Vec<bool>,
+        values: Vec<CellValue>,
+        in_unit: Vec<bool>,
+        row_number: u32,
+    },
+}
+
+/// RowTarget of the parsing thread, queueing every row for the target on the writing thread
+/// written: Rows the writing thread has written so far; limit_reached: its limit_reached
+/// in_unit: set_row_units of the next row
+struct QueueTarget<'a> {
+    sender: SyncSender<Message>,
+    typed: bool,
+    in_unit: Vec<bool>,
+    written: &'a AtomicU64,
+    reported: u64,
+    limit_reached: &'a AtomicBool,
+}
+
+impl RowTarget for QueueTarget<'_> {
+    fn typed(&self) -> bool {
+        self.typed
+    }
+
+    fn set_sheet_width(&mut self, width: usize) {
+        // A failed send means the writing thread stopped; its error is reported on joining
+        let _ = self.sender.send(Message::SheetWidth(width));
+    }
+
+    fn set_unit_columns(&mut self, columns: &[(usize, Unit)]) {
+        let _ = self.sender.send(Message::UnitColumns(columns.to_vec()));
+    }
+
+    fn set_row_units(&mut self, in_unit: &[bool]) {
+        self.in_unit = in_unit.to_vec();
+    }
+
+    fn write(
+        &mut self,
+        row: &mut Vec<String>,
+        present: &[bool],
+        values: &mut Vec<CellValue>,
+        row_number: u32,
+    ) -> Result<u64> {
+        let message = Message::Row {
+            row: std::mem::take(row),
+            present: present.to_vec(),
+            values: std::mem::take(values),
+            in_unit: std::mem::take(&mut self.in_unit),
+            row_number,
+        };
+        if self.sender.send(message).is_err() {
+            anyhow::bail!("the writing thread stopped");
+        }
+        // Progress counts the rows written by now, which trail the rows read
+        let written = self.written.load(Ordering::Relaxed);
+        let new = written - self.reported;
+        self.reported = written;
+        Ok(new)
+    }
+
+    fn limit_reached(&self) -> bool {
+        self.limit_reached.load(Ordering::Relaxed)
+    }
+}
+
+/// Write the rows queued by the parsing thread to target until the queue is closed
+fn write_queued<T: RowTarget>(
+    receiver: Receiver<Message>,
+    target: &mut T,
+    written: &AtomicU64,
+    limit_reached: &AtomicBool,
+) -> Result<()> {
+    let result = write_messages(receiver, target, written, limit_reached);
+    target.release();
+    result
+}
+
+fn write_messages<T: RowTarget>(
+    receiver: Receiver<Message>,
+    target: &mut T,
+    written: &AtomicU64,
+    limit_reached: &AtomicBool,
+) -> Result<()> {
+    for message in receiver {
+        match message {
+            Message::SheetWidth(width) => target.set_sheet_width(width),
+            Message::UnitColumns(columns) => target.set_unit_columns(&columns),
+            Message::Row {
+                mut row,
+                present,
+                mut values,
+                in_unit,
+                row_number,
+            } => {
+                if !in_unit.is_empty() {
+                    target.set_row_units(&in_unit);
+                }
+                let rows = target.write(&mut row, &present, &mut values, row_number)?;
+                written.fetch_add(rows, Ordering::Relaxed);
+                if target.limit_reached() {
+                    limit_reached.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// read_sheet with the rows written to target on a thread of their own, connected by a bounded
+/// queue, so parsing the XML overlaps encoding and writing the rows (see ExportOptions::pipeline)
+/// Returns the progress at the end of the sheet, counting every row target wrote.
+pub(crate) fn read_sheet_pipelined<R: BufRead, S: StringTable + ?Sized, T: RowTarget + Send>(
+    reader: R,
+    shared_strings: &S,
+    styles: &[StyleInfo],
+    is_1904: bool,
+    options: &ExportOptions,
+    target: &mut T,
+    summary: &mut ExportSummary,
+) -> Result<ExportProgress> {
+    let written = AtomicU64::new(0);
+    let limit_reached = AtomicBool::new(false);
+    let (sender, receiver) = sync_channel(PIPELINE_ROWS);
+    let mut queue = QueueTarget {
+        sender,
+        typed: target.typed(),
+        in_unit: Vec::new(),
+        written: &written,
+        reported: 0,
+        limit_reached: &limit_reached,
+    };
+    let parent = tracing::Span::current();
+    std::thread::scope(|scope| {
+        let writer = scope.spawn(|| {
+            let _span = parent.enter();
+            write_queued(receiver, target, &written, &limit_reached)
+        });
+        let read = read_sheet(
+            reader,
+            shared_strings,
+            styles,
+            is_1904,
+            options,
+            &mut queue,
+            summary,
+        );
+        drop(queue);
+        let write = writer
+            .join()
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("the writing thread panicked")));
+        // A write error stops reading too; it is the one worth reporting
+        write?;
+        read
+    })
+    .map(|mut progress| {
+        progress.rows_written = written.into_inner();
+        progress
+    })
+}
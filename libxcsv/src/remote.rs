@@ -0,0 +1,138 @@
+use std::fmt::Debug;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+
+/// Size of the ranges requested from a RangeSource; the zip reader reads in much smaller pieces
+const BLOCK_SIZE: u64 = 1024 * 1024;
+/// Number of blocks kept in memory, enough for the central directory and the part being read
+const CACHED_BLOCKS: usize = 16;
+
+/// Where a RemoteReader gets its bytes from: a web server or an object store
+pub(crate) trait RangeSource: Debug + Send {
+    /// The bytes in range, which lies within the file
+    fn fetch(&self, range: Range<u64>) -> io::Result<Vec<u8>>;
+}
+
+/// A remote workbook read one block at a time as the zip reader seeks through it
+/// Opening the archive only fetches its central directory; reading a part then fetches just
+/// that part, so a few sheets of a huge workbook never need the whole file.
+#[derive(Debug)]
+pub struct RemoteReader {
+    source: Box<dyn RangeSource>,
+    len: u64,
+    pos: u64,
+    /// Cached blocks by index, the most recently used last
+    blocks: Vec<(u64, Vec<u8>)>,
+}
+
+impl RemoteReader {
+    pub(crate) fn new(source: Box<dyn RangeSource>, len: u64) -> Self {
+        Self {
+            source,
+            len,
+            pos: 0,
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Size of the remote file in bytes
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The block with this index, fetched unless it is cached
+    fn block(&mut self, index: u64) -> io::Result<&[u8]> {
+        if let Some(i) = self.blocks.iter().position(|(n, _)| *n == index) {
+            let block = self.blocks.remove(i);
+            self.blocks.push(block);
+        } else {
+            let start = index * BLOCK_SIZE;
+            let end = (start + BLOCK_SIZE).min(self.len);
+            tracing::trace!("fetching bytes {}-{} of {:?}", start, end - 1, self.source);
+            let data = self.source.fetch(start..end)?;
+            if self.blocks.len() == CACHED_BLOCKS {
+                self.blocks.remove(0);
+            }
+            self.blocks.push((index, data));
+        }
+        Ok(&self.blocks.last().expect("block was just added").1)
+    }
+}
+
+impl Read for RemoteReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let offset = (self.pos % BLOCK_SIZE) as usize;
+        let block = self.block(self.pos / BLOCK_SIZE)?;
+        let n = buf.len().min(block.len().saturating_sub(offset));
+        buf[..n].copy_from_slice(&block[offset..offset + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for RemoteReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.len.checked_add_signed(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        };
+        self.pos = pos
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start"))?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug)]
+    struct Memory(Vec<u8>, Arc<Mutex<Vec<Range<u64>>>>);
+
+    impl RangeSource for Memory {
+        fn fetch(&self, range: Range<u64>) -> io::Result<Vec<u8>> {
+            self.1.lock().unwrap().push(range.clone());
+            Ok(self.0[range.start as usize..range.end as usize].to_vec())
+        }
+    }
+
+    #[test]
+    fn test_remote_reader_fetches_blocks_once() {
+        let data: Vec<u8> = (0..=255).cycle().take(BLOCK_SIZE as usize + 100).collect();
+        let fetched = Arc::new(Mutex::new(Vec::new()));
+        let source = Memory(data.clone(), fetched.clone());
+        let mut reader = RemoteReader::new(Box::new(source), data.len() as u64);
+
+        reader.seek(SeekFrom::End(-150)).unwrap();
+        let mut tail = Vec::new();
+        reader.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, data[data.len() - 150..]);
+
+        reader.seek(SeekFrom::Start(BLOCK_SIZE - 10)).unwrap();
+        let mut middle = [0; 20];
+        reader.read_exact(&mut middle).unwrap();
+        assert_eq!(
+            middle,
+            data[BLOCK_SIZE as usize - 10..BLOCK_SIZE as usize + 10]
+        );
+        assert!(
+            reader
+                .seek(SeekFrom::Current(-(BLOCK_SIZE as i64) * 2))
+                .is_err()
+        );
+
+        assert_eq!(
+            *fetched.lock().unwrap(),
+            vec![0..BLOCK_SIZE, BLOCK_SIZE..data.len() as u64]
+        );
+    }
+}
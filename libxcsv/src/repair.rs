@@ -0,0 +1,352 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashSet};
+use std::io::{BufReader, Read, Seek};
+use zip::ZipArchive;
+
+use crate::{
+    PackageParts, Relationship, SheetInfo, SheetKind, SheetState, parse_relationships,
+    parse_workbook_rels, parse_workbook_sheets,
+};
+
+const RELATIONSHIP_TYPES: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+
+/// A sheet of workbook.xml with the relationship id it names its part by
+/// info: The sheet; path_in_zip is empty when r_id doesn't resolve to a sheet part
+/// r_id: The r:id attribute, if present
+#[derive(Debug, Clone)]
+pub(crate) struct WorkbookSheet {
+    pub(crate) info: SheetInfo,
+    pub(crate) r_id: Option<String>,
+}
+
+/// The sheet relationships of the workbook rels, or None when the part is missing or broken
+pub(crate) fn read_workbook_rels_lenient<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    parts: &PackageParts,
+) -> Option<BTreeMap<String, (String, SheetKind)>> {
+    let rels = match zip.by_name(&parts.workbook_rels) {
+        Ok(f) => parse_workbook_rels(BufReader::new(f), &parts.workbook),
+        Err(e) => Err(anyhow::anyhow!("missing {}: {}", parts.workbook_rels, e)),
+    };
+    rels.map_err(|e| {
+        tracing::warn!(
+            "{:#}, looking for the sheets in the worksheets directory",
+            e
+        )
+    })
+    .ok()
+}
+
+/// The worksheet parts in the worksheets directory next to workbook_part, ordered by the number
+/// in their name (sheet2.xml before sheet10.xml)
+fn worksheet_parts(names: &[&str], workbook_part: &str) -> Vec<String> {
+    let dir = match workbook_part.rsplit_once('/') {
+        Some((dir, _)) => format!("{}/worksheets/", dir),
+        None => "worksheets/".to_string(),
+    };
+    let mut parts: Vec<(u64, &str)> = names
+        .iter()
+        .filter_map(|name| {
+            let file = name.strip_prefix(dir.as_str())?;
+            if file.contains('/') || !file.ends_with(".xml") {
+                return None;
+            }
+            let stem = &file[..file.len() - ".xml".len()];
+            let digits = stem.len() - stem.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+            let number = stem[stem.len() - digits..].parse().unwrap_or(u64::MAX);
+            Some((number, *name))
+        })
+        .collect();
+    parts.sort();
+    parts
+        .into_iter()
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
+/// Give every sheet whose part is unknown or missing from the package (names) one of the
+/// worksheet parts no other sheet uses: sheets in sheetId order get the parts in the order of
+/// the number in their name, as generators number both the same way
+/// Returns the positions of the sheets that got a part; sheets left over keep an empty
+/// path_in_zip.
+pub(crate) fn assign_orphan_sheets(
+    sheets: &mut [WorkbookSheet],
+    names: &[&str],
+    workbook_part: &str,
+) -> Vec<usize> {
+    let existing: HashSet<&str> = names.iter().copied().collect();
+    let mut orphans: Vec<usize> = (0..sheets.len())
+        .filter(|&i| !existing.contains(sheets[i].info.path_in_zip.as_str()))
+        .collect();
+    if orphans.is_empty() {
+        return orphans;
+    }
+    let used: HashSet<&str> = sheets
+        .iter()
+        .map(|sheet| sheet.info.path_in_zip.as_str())
+        .filter(|path| existing.contains(path))
+        .collect();
+    let free: Vec<String> = worksheet_parts(names, workbook_part)
+        .into_iter()
+        .filter(|part| !used.contains(part.as_str()))
+        .collect();
+    // Sheets without a sheetId last, in workbook order
+    orphans.sort_by_key(|&i| (sheets[i].info.sheet_id.is_none(), sheets[i].info.sheet_id));
+    for &i in &orphans {
+        sheets[i].info.path_in_zip.clear();
+    }
+    orphans.truncate(free.len());
+    for (&i, part) in orphans.iter().zip(free) {
+        sheets[i].info.path_in_zip = part;
+        sheets[i].info.kind = SheetKind::Worksheet;
+    }
+    orphans.sort();
+    orphans
+}
+
+/// Add a sheet for every worksheet part no sheet uses, named after the part (sheet3.xml ->
+/// Sheet3), for packages whose workbook part or rels are missing
+/// Returns the positions of the added sheets.
+pub(crate) fn add_unclaimed_worksheets(
+    sheets: &mut Vec<WorkbookSheet>,
+    names: &[&str],
+    workbook_part: &str,
+) -> Vec<usize> {
+    let first = sheets.len();
+    for part in worksheet_parts(names, workbook_part) {
+        if sheets.iter().any(|sheet| sheet.info.path_in_zip == part) {
+            continue;
+        }
+        let stem = part
+            .rsplit('/')
+            .next()
+            .unwrap_or(&part)
+            .trim_end_matches(".xml");
+        let mut base = stem.to_string();
+        if let Some(first) = base.get_mut(..1) {
+            first.make_ascii_uppercase();
+        }
+        let mut name = base.clone();
+        let mut n = 1;
+        while sheets
+            .iter()
+            .any(|sheet| sheet.info.name.eq_ignore_ascii_case(&name))
+        {
+            n += 1;
+            name = format!("{} ({})", base, n);
+        }
+        sheets.push(WorkbookSheet {
+            info: SheetInfo {
+                name,
+                path_in_zip: part,
+                sheet_id: None,
+                state: SheetState::Visible,
+                kind: SheetKind::Worksheet,
+            },
+            r_id: None,
+        });
+    }
+    (first..sheets.len()).collect()
+}
+
+/// Rebuild the workbook rels of a package whose sheet relationships are missing, broken or
+/// point at parts that don't exist
+/// Sheets are matched with worksheet parts as read_workbook does; the other relationships are
+/// kept when the rels part can still be read, and ones to the styles and shared strings added
+/// when missing. Returns the new rels part, or None when nothing needs repairing, and the names
+/// of the sheets that can't be linked (no r:id in workbook.xml or no worksheet part left).
+pub fn repair_workbook_rels<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    parts: &PackageParts,
+) -> Result<(Option<String>, Vec<String>)> {
+    let rels_map = read_workbook_rels_lenient(zip, parts).unwrap_or_default();
+    let mut relationships = match zip.by_name(&parts.workbook_rels) {
+        Ok(f) => parse_relationships(BufReader::new(f)).unwrap_or_default(),
+        Err(_) => Vec::new(),
+    };
+    let f = zip
+        .by_name(&parts.workbook)
+        .with_context(|| format!("missing {}", parts.workbook))?;
+    let (mut sheets, _) = parse_workbook_sheets(BufReader::new(f), &rels_map)?;
+    let names: Vec<&str> = zip.file_names().collect();
+    let repaired = assign_orphan_sheets(&mut sheets, &names, &parts.workbook);
+
+    let mut unlinked: Vec<String> = sheets
+        .iter()
+        .filter(|sheet| sheet.info.path_in_zip.is_empty())
+        .map(|sheet| sheet.info.name.clone())
+        .collect();
+    let mut changed = false;
+    for sheet in repaired.iter().map(|&i| &sheets[i]) {
+        let Some(r_id) = &sheet.r_id else {
+            unlinked.push(sheet.info.name.clone());
+            continue;
+        };
+        relationships.retain(|r| &r.id != r_id);
+        relationships.push(Relationship {
+            id: r_id.clone(),
+            rel_type: format!("{}/worksheet", RELATIONSHIP_TYPES),
+            target: format!("/{}", sheet.info.path_in_zip),
+            external: false,
+        });
+        changed = true;
+    }
+    // Without its rels part the workbook also lost the links to its styles and shared strings
+    for (part, kind) in [
+        (&parts.styles, "styles"),
+        (&parts.shared_strings, "sharedStrings"),
+    ] {
+        let Some(part) = part.as_ref().filter(|part| names.contains(&part.as_str())) else {
+            continue;
+        };
+        if relationships
+            .iter()
+            .any(|r| r.rel_type.rsplit('/').next() == Some(kind))
+        {
+            continue;
+        }
+        let id = (1..)
+            .map(|n| format!("rId{}", n))
+            .find(|id| {
+                !relationships.iter().any(|r| &r.id == id)
+                    && !sheets.iter().any(|s| s.r_id.as_ref() == Some(id))
+            })
+            .expect("a free relationship id");
+        relationships.push(Relationship {
+            id,
+            rel_type: format!("{}/{}", RELATIONSHIP_TYPES, kind),
+            target: format!("/{}", part),
+            external: false,
+        });
+        changed = true;
+    }
+    Ok((changed.then(|| relationships_xml(&relationships)), unlinked))
+}
+
+/// A .rels part holding relationships
+fn relationships_xml(relationships: &[Relationship]) -> String {
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+        "\n",
+        r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#
+    ));
+    for r in relationships {
+        xml.push_str(&format!(
+            r#"<Relationship Id="{}" Type="{}" Target="{}"{}/>"#,
+            quick_xml::escape::escape(r.id.as_str()),
+            quick_xml::escape::escape(r.rel_type.as_str()),
+            quick_xml::escape::escape(r.target.as_str()),
+            if r.external {
+                r#" TargetMode="External""#
+            } else {
+                ""
+            }
+        ));
+    }
+    xml.push_str("</Relationships>");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read_package_parts, read_workbook};
+    use std::io::{Cursor, Write};
+    use zip::ZipWriter;
+    use zip::write::FileOptions;
+
+    #[test]
+    fn test_sheets_without_relationships_are_matched_by_sheet_id() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in [
+            (
+                "xl/workbook.xml",
+                r#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets>
+                    <sheet name="Third" sheetId="10" r:id="rId3"/>
+                    <sheet name="First" sheetId="1" r:id="rId1"/>
+                    <sheet name="Second" sheetId="2" r:id="rId2"/>
+                </sheets></workbook>"#,
+            ),
+            (
+                "xl/_rels/workbook.xml.rels",
+                r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+                    <Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet2.xml"/>
+                    <Relationship Id="rId3" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/gone.xml"/>
+                </Relationships>"#,
+            ),
+            ("xl/worksheets/sheet10.xml", "<worksheet/>"),
+            ("xl/worksheets/sheet2.xml", "<worksheet/>"),
+            ("xl/worksheets/sheet1.xml", "<worksheet/>"),
+            ("xl/worksheets/_rels/sheet1.xml.rels", "<Relationships/>"),
+            ("xl/styles.xml", "<styleSheet/>"),
+        ] {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(data.as_bytes()).unwrap();
+        }
+        let mut zip = ZipArchive::new(writer.finish().unwrap()).unwrap();
+        let parts = read_package_parts(&mut zip).unwrap();
+
+        let (sheets, _) = read_workbook(&mut zip, &parts).unwrap();
+        let paths: Vec<_> = sheets.iter().map(|s| s.path_in_zip.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "xl/worksheets/sheet10.xml",
+                "xl/worksheets/sheet1.xml",
+                "xl/worksheets/sheet2.xml"
+            ]
+        );
+
+        let (rels, unlinked) = repair_workbook_rels(&mut zip, &parts).unwrap();
+        assert!(unlinked.is_empty());
+        let rels = parse_relationships(rels.unwrap().as_bytes()).unwrap();
+        let targets: Vec<_> = rels
+            .iter()
+            .map(|r| (r.id.as_str(), r.target.as_str()))
+            .collect();
+        assert_eq!(
+            targets,
+            [
+                ("rId2", "worksheets/sheet2.xml"),
+                ("rId3", "/xl/worksheets/sheet10.xml"),
+                ("rId1", "/xl/worksheets/sheet1.xml"),
+                ("rId4", "/xl/styles.xml"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_worksheets_without_workbook_become_sheets() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for name in [
+            "xl/worksheets/sheet2.xml",
+            "xl/worksheets/sheet1.xml",
+            "xl/worksheets/Sheet1.xml",
+        ] {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(b"<worksheet/>").unwrap();
+        }
+        let mut zip = ZipArchive::new(writer.finish().unwrap()).unwrap();
+        let parts = read_package_parts(&mut zip).unwrap();
+
+        let (sheets, is_1904) = read_workbook(&mut zip, &parts).unwrap();
+        assert!(!is_1904);
+        let sheets: Vec<_> = sheets
+            .iter()
+            .map(|s| (s.name.as_str(), s.path_in_zip.as_str()))
+            .collect();
+        assert_eq!(
+            sheets,
+            [
+                ("Sheet1", "xl/worksheets/Sheet1.xml"),
+                ("Sheet1 (2)", "xl/worksheets/sheet1.xml"),
+                ("Sheet2", "xl/worksheets/sheet2.xml"),
+            ]
+        );
+
+        let empty = ZipWriter::new(Cursor::new(Vec::new())).finish().unwrap();
+        let mut zip = ZipArchive::new(empty).unwrap();
+        assert!(read_workbook(&mut zip, &parts).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+use anyhow::{Context, Result};
+use flate2::read::DeflateDecoder;
+use std::io::{BufReader, Read, Seek, Write};
+use zip::ZipArchive;
+use zip::write::{FileOptions, ZipWriter};
+
+use crate::WorkbookReader;
+
+const LOCAL_HEADER_SIGNATURE: &[u8] = b"PK\x03\x04";
+const DATA_DESCRIPTOR_SIGNATURE: &[u8] = b"PK\x07\x08";
+const CENTRAL_HEADER_SIGNATURE: &[u8] = b"PK\x01\x02";
+/// Fixed part of a local file header, up to the file name
+const LOCAL_HEADER_LEN: usize = 30;
+
+/// A part found by salvage_zip
+/// name: The zip entry name
+/// bytes: Number of (uncompressed) bytes recovered
+/// problem: What was wrong with it, e.g. "cut off after 1234 bytes"; None for an intact part
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SalvagedPart {
+    pub name: String,
+    pub bytes: u64,
+    pub problem: Option<String>,
+}
+
+/// Rebuild a zip archive whose central directory is damaged or missing from the local file
+/// headers in front of every part
+/// The input is scanned for local file headers and every part that can be decompressed is
+/// copied into a new archive (in a temp file), including the readable start of a part that is
+/// cut off. Returns that archive and every part found, in archive order; parts that yield no
+/// data (encrypted, unknown compression) are listed but left out.
+/// The damaged input is read into memory.
+pub fn salvage_zip<R: Read>(
+    mut reader: R,
+) -> Result<(ZipArchive<WorkbookReader>, Vec<SalvagedPart>)> {
+    let mut data = Vec::new();
+    reader
+        .read_to_end(&mut data)
+        .context("Failed to read input")?;
+
+    let file = tempfile::tempfile().context("Failed to create temp file for salvaged parts")?;
+    let mut writer = ZipWriter::new(file);
+    let mut found: Vec<SalvagedPart> = Vec::new();
+    let mut pos = 0;
+    while let Some(offset) = find(&data[pos..], LOCAL_HEADER_SIGNATURE) {
+        let start = pos + offset;
+        let Some(entry) = read_entry(&data, start) else {
+            pos = start + LOCAL_HEADER_SIGNATURE.len();
+            continue;
+        };
+        pos = entry.end.max(start + LOCAL_HEADER_SIGNATURE.len());
+        if entry.name.ends_with('/') || found.iter().any(|part| part.name == entry.name) {
+            continue;
+        }
+        if let Some(contents) = &entry.contents {
+            let options =
+                FileOptions::default().large_file(contents.len() as u64 > u32::MAX as u64);
+            writer.start_file(entry.name.as_str(), options)?;
+            writer.write_all(contents)?;
+        }
+        found.push(SalvagedPart {
+            name: entry.name,
+            bytes: entry.contents.as_ref().map_or(0, |c| c.len() as u64),
+            problem: entry.problem,
+        });
+    }
+    if found.is_empty() {
+        anyhow::bail!("no zip parts found");
+    }
+    let mut file = writer.finish()?;
+    file.rewind()?;
+    let zip = ZipArchive::new(WorkbookReader::File(BufReader::new(file)))?;
+    Ok((zip, found))
+}
+
+/// A part read from its local file header
+/// end: Where the part's data (and data descriptor) ends, or the header if it can't be told
+struct Entry {
+    name: String,
+    contents: Option<Vec<u8>>,
+    problem: Option<String>,
+    end: usize,
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn u16_at(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u64_at(data: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(at..at + 8)?.try_into().ok()?))
+}
+
+/// Read the part whose local file header starts at start; None if it isn't a plausible header
+fn read_entry(data: &[u8], start: usize) -> Option<Entry> {
+    let flags = u16_at(data, start + 6)?;
+    let method = u16_at(data, start + 8)?;
+    let mut crc = u32_at(data, start + 14)?;
+    let mut compressed_size = u64::from(u32_at(data, start + 18)?);
+    let mut size = u64::from(u32_at(data, start + 22)?);
+    let name_len = usize::from(u16_at(data, start + 26)?);
+    let extra_len = usize::from(u16_at(data, start + 28)?);
+    let name_start = start + LOCAL_HEADER_LEN;
+    let name = std::str::from_utf8(data.get(name_start..name_start + name_len)?).ok()?;
+    if name.is_empty() {
+        return None;
+    }
+    let extra = data.get(name_start + name_len..name_start + name_len + extra_len)?;
+    let data_start = name_start + name_len + extra_len;
+    let mut entry = Entry {
+        name: name.to_string(),
+        contents: None,
+        problem: None,
+        end: data_start,
+    };
+
+    // Zip64 sizes, present for the sizes set to 0xFFFFFFFF
+    let mut at = 0;
+    while let (Some(id), Some(len)) = (u16_at(extra, at), u16_at(extra, at + 2)) {
+        if id == 0x0001 {
+            let mut field = at + 4;
+            if size == u64::from(u32::MAX) {
+                size = u64_at(extra, field).unwrap_or(size);
+                field += 8;
+            }
+            if compressed_size == u64::from(u32::MAX) {
+                compressed_size = u64_at(extra, field).unwrap_or(compressed_size);
+            }
+        }
+        at += 4 + usize::from(len);
+    }
+
+    if flags & 1 != 0 {
+        entry.problem = Some("encrypted".to_string());
+        return Some(entry);
+    }
+    // With a data descriptor the sizes follow the data, so the data runs to the end of the
+    // deflate stream, or for stored data to the next signature
+    let has_descriptor = flags & 0x08 != 0;
+    let available = data.len() - data_start;
+    let (compressed, cut_off) = if has_descriptor && compressed_size == 0 {
+        match method {
+            8 => (&data[data_start..], false),
+            _ => {
+                let rest = &data[data_start..];
+                let end = [
+                    DATA_DESCRIPTOR_SIGNATURE,
+                    LOCAL_HEADER_SIGNATURE,
+                    CENTRAL_HEADER_SIGNATURE,
+                ]
+                .iter()
+                .filter_map(|signature| find(rest, signature))
+                .min();
+                (&rest[..end.unwrap_or(rest.len())], end.is_none())
+            }
+        }
+    } else {
+        let len = usize::try_from(compressed_size)
+            .unwrap_or(usize::MAX)
+            .min(available);
+        (
+            &data[data_start..data_start + len],
+            (len as u64) < compressed_size,
+        )
+    };
+
+    let (contents, consumed, error) = match method {
+        0 => (compressed.to_vec(), compressed.len(), None),
+        8 => {
+            let mut decoder = DeflateDecoder::new(compressed);
+            let mut contents = Vec::new();
+            let error = decoder.read_to_end(&mut contents).err();
+            let consumed = decoder.total_in() as usize;
+            (contents, consumed, error)
+        }
+        method => {
+            entry.problem = Some(format!("unsupported compression method {}", method));
+            return Some(entry);
+        }
+    };
+    entry.end = data_start + consumed;
+    if has_descriptor {
+        let mut at = entry.end;
+        if data.get(at..at + 4) == Some(DATA_DESCRIPTOR_SIGNATURE) {
+            at += 4;
+        }
+        if let Some(descriptor_crc) = u32_at(data, at) {
+            crc = descriptor_crc;
+            entry.end = at + 12;
+        }
+    }
+
+    entry.problem = if cut_off || (error.is_some() && entry.end >= data.len()) {
+        Some(format!("cut off after {} bytes", contents.len()))
+    } else if let Some(error) = error {
+        Some(format!(
+            "damaged after {} bytes ({})",
+            contents.len(),
+            error
+        ))
+    } else if !has_descriptor && contents.len() as u64 != size {
+        Some(format!("{} of {} bytes", contents.len(), size))
+    } else {
+        let mut hasher = flate2::Crc::new();
+        hasher.update(&contents);
+        (hasher.sum() != crc).then(|| "CRC mismatch".to_string())
+    };
+    if contents.is_empty() && entry.problem.is_some() {
+        return Some(entry);
+    }
+    entry.contents = Some(contents);
+    Some(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_salvage_zip_recovers_parts_of_a_cut_off_archive() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let sheet: String = (1..=2000)
+            .map(|i| format!("<row r=\"{}\"><c r=\"A{}\"><v>{}</v></c></row>", i, i, i))
+            .collect();
+        for (name, data) in [
+            ("a.xml", "<a/>".to_string()),
+            ("stored.xml", "<stored/>".to_string()),
+            ("sheet.xml", sheet.clone()),
+        ] {
+            let options = match name {
+                "stored.xml" => {
+                    FileOptions::default().compression_method(zip::CompressionMethod::Stored)
+                }
+                _ => FileOptions::default(),
+            };
+            writer.start_file(name, options).unwrap();
+            writer.write_all(data.as_bytes()).unwrap();
+        }
+        let data = writer.finish().unwrap().into_inner();
+        // No central directory and the last part cut off halfway
+        let sheet_start = find(&data, b"sheet.xml").unwrap();
+        let cut = sheet_start + (data.len() - sheet_start) / 3;
+
+        let (mut zip, parts) = salvage_zip(&data[..cut]).unwrap();
+        let names: Vec<_> = parts.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["a.xml", "stored.xml", "sheet.xml"]);
+        assert_eq!(parts[0].problem, None);
+        assert_eq!(parts[1].problem, None);
+        assert!(parts[2].problem.as_deref().unwrap().starts_with("cut off"));
+
+        let mut stored = String::new();
+        zip.by_name("stored.xml")
+            .unwrap()
+            .read_to_string(&mut stored)
+            .unwrap();
+        assert_eq!(stored, "<stored/>");
+        let mut partial = String::new();
+        zip.by_name("sheet.xml")
+            .unwrap()
+            .read_to_string(&mut partial)
+            .unwrap();
+        assert!(partial.len() > 100 && sheet.starts_with(&partial));
+
+        assert!(salvage_zip(&b"not a zip"[..]).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+/// A uniform random sample of at most capacity items from a stream of unknown length
+/// (reservoir sampling, algorithm R)
+/// The same seed and the same stream always give the same sample; the generator is part of
+/// this crate so that samples do not change with a dependency update.
+#[derive(Debug, Clone)]
+pub(crate) struct Reservoir<T> {
+    capacity: usize,
+    offered: u64,
+    rng: SplitMix64,
+    items: Vec<T>,
+}
+
+impl<T> Reservoir<T> {
+    pub(crate) fn new(capacity: usize, seed: u64) -> Self {
+        Self {
+            capacity,
+            offered: 0,
+            rng: SplitMix64(seed),
+            items: Vec::new(),
+        }
+    }
+
+    /// Offer the next item of the stream; it replaces a kept item with probability
+    /// capacity / items offered so far
+    pub(crate) fn offer(&mut self, item: T) {
+        self.offered += 1;
+        if self.items.len() < self.capacity {
+            self.items.push(item);
+            return;
+        }
+        let j = self.rng.below(self.offered);
+        if let Some(kept) = self.items.get_mut(j as usize) {
+            *kept = item;
+        }
+    }
+
+    /// The kept items, in no particular order
+    pub(crate) fn into_items(self) -> Vec<T> {
+        self.items
+    }
+}
+
+/// The SplitMix64 generator: tiny, fast and good enough for picking rows
+#[derive(Debug, Clone)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in 0..n (n > 0), by multiply and shift
+    fn below(&mut self, n: u64) -> u64 {
+        ((u128::from(self.next_u64()) * u128::from(n)) >> 64) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reservoir_is_deterministic_and_uniform() {
+        let sample = |seed| {
+            let mut reservoir = Reservoir::new(10, seed);
+            (0..1000).for_each(|i| reservoir.offer(i));
+            let mut items = reservoir.into_items();
+            items.sort();
+            items
+        };
+        assert_eq!(sample(7).len(), 10);
+        assert_eq!(sample(7), sample(7));
+        assert_ne!(sample(7), sample(8));
+
+        let mut short = Reservoir::new(10, 1);
+        (0..3).for_each(|i| short.offer(i));
+        assert_eq!(short.into_items(), vec![0, 1, 2]);
+
+        // Every position is about as likely to be kept
+        let mut counts = [0u32; 10];
+        for seed in 0..2000 {
+            let mut reservoir = Reservoir::new(1, seed);
+            (0..10).for_each(|i| reservoir.offer(i));
+            counts[reservoir.into_items()[0]] += 1;
+        }
+        assert!(
+            counts.iter().all(|&n| (120..280).contains(&n)),
+            "{:?}",
+            counts
+        );
+    }
+}
@@ -0,0 +1,393 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::io::BufRead;
+
+use super::{
+    CellValue, ExportOptions, ExportSummary, ReadCell, RowTarget, StringTable, StyleInfo,
+    parse_iso_datetime, read_sheet,
+};
+
+/// How many distinct example values are kept per column
+const EXAMPLES: usize = 3;
+
+/// The type of a column, inferred from its exported values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    Integer,
+    Float,
+    Bool,
+    Date,
+    Text,
+}
+
+impl std::fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Bool => "bool",
+            ColumnType::Date => "date",
+            ColumnType::Text => "text",
+        })
+    }
+}
+
+impl ColumnType {
+    /// The type of a single non-empty value as written by the export
+    /// Booleans are TRUE/FALSE, dates ISO 8601 (see format_iso_datetime).
+    pub fn of(value: &str) -> Self {
+        if value.parse::<i64>().is_ok() {
+            ColumnType::Integer
+        } else if value.parse::<f64>().is_ok_and(f64::is_finite) {
+            ColumnType::Float
+        } else if value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false") {
+            ColumnType::Bool
+        } else if parse_iso_datetime(value).is_some() {
+            ColumnType::Date
+        } else {
+            ColumnType::Text
+        }
+    }
+
+    /// The narrowest type holding values of both types: integers widen to floats, anything
+    /// else mixed is text
+    pub fn widen(self, other: Self) -> Self {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (ColumnType::Integer, ColumnType::Float) | (ColumnType::Float, ColumnType::Integer) => {
+                ColumnType::Float
+            }
+            _ => ColumnType::Text,
+        }
+    }
+}
+
+/// A column of a SheetSchema
+/// name: the header field, or column_N when it is empty or repeated
+/// column_type: the inferred type; text for a column without values
+/// nulls: rows with an empty (or missing) field in this column
+/// examples: the first few distinct values
+/// number_format: the number format most of its cells have, if their formats were counted (see
+///   SchemaBuilder::add_format and infer_sheet_schema)
+#[derive(Debug, Clone, Serialize)]
+pub struct ColumnSchema {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub column_type: ColumnType,
+    pub nulls: u64,
+    pub examples: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub number_format: Option<DominantFormat>,
+}
+
+/// The most common number format of the cells of a column
+/// code: the format code, e.g. "yyyy-mm-dd"; "General" for cells without one
+/// share: the fraction of the counted cells with it, from 0.0 to 1.0
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DominantFormat {
+    pub code: String,
+    pub share: f64,
+}
+
+/// The inferred schema of a sheet; rows counts the rows below the header
+#[derive(Debug, Clone, Serialize)]
+pub struct SheetSchema {
+    pub rows: u64,
+    pub columns: Vec<ColumnSchema>,
+}
+
+/// Infers a SheetSchema from the rows of a sheet, one row at a time
+#[derive(Debug, Clone)]
+pub struct SchemaBuilder {
+    header: Vec<String>,
+    rows: u64,
+    columns: Vec<ColumnState>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ColumnState {
+    column_type: Option<ColumnType>,
+    /// Rows with a value, the others are nulls
+    values: u64,
+    examples: Vec<String>,
+    /// Cells counted by add_format for every format code, in the order first seen
+    formats: Vec<(String, u64)>,
+}
+
+impl SchemaBuilder {
+    /// Start a schema whose columns are named by header
+    pub fn new(header: Vec<String>) -> Self {
+        let columns = vec![ColumnState::default(); header.len()];
+        Self {
+            header,
+            rows: 0,
+            columns,
+        }
+    }
+
+    /// Add a row below the header; rows wider than the header add columns
+    pub fn add_row<'r>(&mut self, row: impl IntoIterator<Item = &'r str>) {
+        self.rows += 1;
+        for (i, value) in row.into_iter().enumerate() {
+            if value.is_empty() {
+                continue;
+            }
+            if i >= self.columns.len() {
+                self.columns.resize(i + 1, ColumnState::default());
+            }
+            let column = &mut self.columns[i];
+            let value_type = ColumnType::of(value);
+            column.column_type = Some(
+                column
+                    .column_type
+                    .map_or(value_type, |t| t.widen(value_type)),
+            );
+            column.values += 1;
+            if column.examples.len() < EXAMPLES && !column.examples.iter().any(|e| e == value) {
+                column.examples.push(value.to_string());
+            }
+        }
+    }
+
+    /// Count a cell of column (0-based) with this number format for ColumnSchema::number_format
+    pub fn add_format(&mut self, column: usize, format_code: &str) {
+        if column >= self.columns.len() {
+            self.columns.resize(column + 1, ColumnState::default());
+        }
+        let formats = &mut self.columns[column].formats;
+        match formats.iter_mut().find(|(code, _)| code == format_code) {
+            Some((_, count)) => *count += 1,
+            None => formats.push((format_code.to_string(), 1)),
+        }
+    }
+
+    pub fn finish(self) -> SheetSchema {
+        let names = column_names(&self.header, self.columns.len());
+        let columns = names
+            .into_iter()
+            .zip(self.columns)
+            .map(|(name, column)| ColumnSchema {
+                name,
+                column_type: column.column_type.unwrap_or(ColumnType::Text),
+                nulls: self.rows - column.values,
+                number_format: dominant_format(&column.formats),
+                examples: column.examples,
+            })
+            .collect();
+        SheetSchema {
+            rows: self.rows,
+            columns,
+        }
+    }
+}
+
+/// Infer the schema of a worksheet in a single pass: the first row with a value names the
+/// columns, the rows below it (blank ones left out) give the types, and the number formats of
+/// their cells holding a value the ColumnSchema::number_format
+/// Values are read like the cells of an export with options.
+pub fn infer_sheet_schema<R: BufRead, S: StringTable + ?Sized>(
+    reader: R,
+    shared_strings: &S,
+    styles: &[StyleInfo],
+    is_1904: bool,
+    options: &ExportOptions,
+) -> Result<SheetSchema> {
+    let mut target = SchemaTarget {
+        styles,
+        builder: None,
+        formats: Vec::new(),
+        formats_row: 0,
+    };
+    read_sheet(
+        reader,
+        shared_strings,
+        styles,
+        is_1904,
+        options,
+        &mut target,
+        &mut ExportSummary::default(),
+    )?;
+    let builder = target
+        .builder
+        .unwrap_or_else(|| SchemaBuilder::new(Vec::new()));
+    Ok(builder.finish())
+}
+
+/// The RowTarget of infer_sheet_schema
+/// formats: the column and format code of the cells with a value in row formats_row, counted
+///   when that row is written below the header
+struct SchemaTarget<'a> {
+    styles: &'a [StyleInfo],
+    builder: Option<SchemaBuilder>,
+    formats: Vec<(usize, &'a str)>,
+    formats_row: u32,
+}
+
+impl RowTarget for SchemaTarget<'_> {
+    fn set_sheet_width(&mut self, _width: usize) {}
+
+    fn write(
+        &mut self,
+        row: &mut Vec<String>,
+        _present: &[bool],
+        _values: &mut Vec<CellValue>,
+        row_number: u32,
+    ) -> Result<u64> {
+        let formats = std::mem::take(&mut self.formats);
+        if row.iter().all(|field| field.is_empty()) {
+            return Ok(0);
+        }
+        match &mut self.builder {
+            None => self.builder = Some(SchemaBuilder::new(std::mem::take(row))),
+            Some(builder) => {
+                builder.add_row(row.iter().map(String::as_str));
+                if row_number == self.formats_row {
+                    for (column, code) in formats {
+                        builder.add_format(column, code);
+                    }
+                }
+            }
+        }
+        Ok(1)
+    }
+
+    fn limit_reached(&self) -> bool {
+        false
+    }
+
+    fn wants_cells(&self) -> bool {
+        true
+    }
+
+    fn cell(&mut self, cell: &ReadCell) -> Result<()> {
+        if cell.row != self.formats_row {
+            self.formats.clear();
+            self.formats_row = cell.row;
+        }
+        if !cell.field.is_empty() {
+            // A cell without a style has the first one
+            let code = self
+                .styles
+                .get(cell.style.unwrap_or(0) as usize)
+                .and_then(|style| style.format_code.as_deref())
+                .unwrap_or("General");
+            self.formats.push((cell.column as usize - 1, code));
+        }
+        Ok(())
+    }
+}
+
+/// The format of formats counted most often (the first seen of a tie) and its share
+fn dominant_format(formats: &[(String, u64)]) -> Option<DominantFormat> {
+    let total: u64 = formats.iter().map(|(_, count)| count).sum();
+    let (code, count) = formats.iter().rev().max_by_key(|(_, count)| *count)?;
+    Some(DominantFormat {
+        code: code.clone(),
+        share: *count as f64 / total as f64,
+    })
+}
+
+/// Names for width columns from a header row
+/// Fields are trimmed; empty, missing or (case-insensitively) repeated names become column_N.
+pub fn column_names(header: &[String], width: usize) -> Vec<String> {
+    let mut names: Vec<String> = Vec::with_capacity(width);
+    for i in 0..width {
+        let name = header.get(i).map_or("", |h| h.trim());
+        if name.is_empty() || names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+            names.push(format!("column_{}", i + 1));
+        } else {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_builder_infers_types_and_nulls() {
+        let header = vec!["id".to_string(), "amount".to_string(), "id".to_string()];
+        let mut builder = SchemaBuilder::new(header);
+        builder.add_row(["1", "10", "TRUE", "2024-01-01T00:00:00.000Z"]);
+        builder.add_row(["2", "2.5", "false"]);
+        builder.add_row(["3", "", "TRUE", "n/a"]);
+        builder.add_row(["3"]);
+        let schema = builder.finish();
+
+        assert_eq!(schema.rows, 4);
+        let summary: Vec<(&str, ColumnType, u64)> = schema
+            .columns
+            .iter()
+            .map(|c| (c.name.as_str(), c.column_type, c.nulls))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("id", ColumnType::Integer, 0),
+                ("amount", ColumnType::Float, 2),
+                ("column_3", ColumnType::Bool, 1),
+                ("column_4", ColumnType::Text, 2),
+            ]
+        );
+        assert_eq!(schema.columns[0].examples, vec!["1", "2", "3"]);
+        assert_eq!(ColumnType::of("2024-01-01"), ColumnType::Date);
+        assert_eq!(ColumnType::of("NaN"), ColumnType::Text);
+    }
+
+    #[test]
+    fn test_dominant_number_formats() {
+        let xml = r#"
+        <worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">
+            <sheetData>
+                <row r="1"><c r="A1" t="inlineStr"><is><t>Report</t></is></c></row>
+                <row r="2"><c r="A2" s="1"><v>45000</v></c><c r="C2"><v>1</v></c></row>
+                <row r="3"><c r="A3" s="1"><v>45001</v></c><c r="C3" s="2"/></row>
+                <row r="4"><c r="A4" s="1"><v>45002</v></c></row>
+                <row r="5"><c r="A5" t="inlineStr"><is><t>n/a</t></is></c></row>
+            </sheetData>
+        </worksheet>"#;
+        let styles = [
+            StyleInfo {
+                format_code: Some("General".to_string()),
+                ..StyleInfo::default()
+            },
+            StyleInfo {
+                format_code: Some("yyyy-mm-dd".to_string()),
+                is_date: true,
+                ..StyleInfo::default()
+            },
+            StyleInfo {
+                format_code: Some("0.00%".to_string()),
+                ..StyleInfo::default()
+            },
+        ];
+        let schema = infer_sheet_schema(
+            xml.as_bytes(),
+            &Vec::new(),
+            &styles,
+            false,
+            &ExportOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            schema.columns[0].number_format,
+            Some(DominantFormat {
+                code: "yyyy-mm-dd".to_string(),
+                share: 0.75,
+            })
+        );
+        assert_eq!(schema.columns[1].number_format, None);
+        // Empty cells don't count
+        assert_eq!(
+            schema.columns[2].number_format,
+            Some(DominantFormat {
+                code: "General".to_string(),
+                share: 1.0,
+            })
+        );
+    }
+}
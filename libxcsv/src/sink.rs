@@ -0,0 +1,410 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use std::cell::Cell;
+use std::io::BufRead;
+use std::sync::Arc;
+
+use crate::{
+    CellKind, DateSystem, ExportOptions, ExportSummary, GeneralPrecision, RowTarget, SheetInfo,
+    StringTable, StyleInfo, format_general, format_iso_datetime, parse_iso_datetime, read_sheet,
+};
+
+/// A typed cell value, as handed to a RowSink
+/// Dates are numbers with a date format (or `t="d"` cells) converted with the workbook's date
+/// system; text formatted numbers are text, errors keep their code (e.g. "#N/A").
+#[derive(Debug, Clone, PartialEq)]
+pub enum CellValue {
+    Empty,
+    Text(String),
+    Number(f64),
+    Bool(bool),
+    Date(NaiveDateTime),
+    Error(String),
+}
+
+impl CellValue {
+    /// The value of a cell of this kind, exported as field from its raw `<v>` text
+    pub(crate) fn parse(
+        kind: CellKind,
+        field: &str,
+        raw: &str,
+        cell_type: Option<&str>,
+        date_system: &dyn DateSystem,
+    ) -> Self {
+        match kind {
+            CellKind::Empty => CellValue::Empty,
+            CellKind::Text => CellValue::Text(field.to_string()),
+            CellKind::Bool => CellValue::Bool(field == "TRUE"),
+            CellKind::Error => CellValue::Error(raw.to_string()),
+            CellKind::Number => raw
+                .trim()
+                .parse()
+                .map_or_else(|_| CellValue::Text(field.to_string()), CellValue::Number),
+            CellKind::Date => {
+                let date = match cell_type {
+                    Some("d") => parse_iso_datetime(raw.trim()),
+                    _ => raw
+                        .trim()
+                        .parse()
+                        .ok()
+                        .and_then(|serial| date_system.to_datetime(serial)),
+                };
+                // Serials out of the date range are written as the number
+                date.map_or_else(|| CellValue::Text(field.to_string()), CellValue::Date)
+            }
+        }
+    }
+
+    /// Whether the cell has no value; an empty string is a value
+    pub fn is_empty(&self) -> bool {
+        *self == CellValue::Empty
+    }
+
+    /// The CellKind of the value
+    pub fn kind(&self) -> CellKind {
+        match self {
+            CellValue::Empty => CellKind::Empty,
+            CellValue::Text(_) => CellKind::Text,
+            CellValue::Number(_) => CellKind::Number,
+            CellValue::Bool(_) => CellKind::Bool,
+            CellValue::Date(_) => CellKind::Date,
+            CellValue::Error(_) => CellKind::Error,
+        }
+    }
+
+    /// The value as the exporter writes it: numbers with precision, dates in ISO 8601, booleans
+    /// as TRUE/FALSE and errors as #ERROR:<code>
+    pub fn to_field(&self, precision: GeneralPrecision) -> String {
+        match self {
+            CellValue::Empty => String::new(),
+            CellValue::Text(text) => text.clone(),
+            CellValue::Number(n) => match precision {
+                GeneralPrecision::Exact => n.to_string(),
+                GeneralPrecision::Excel => format_general(*n),
+            },
+            CellValue::Bool(true) => "TRUE".to_string(),
+            CellValue::Bool(false) => "FALSE".to_string(),
+            CellValue::Date(date) => format_iso_datetime(date),
+            CellValue::Error(code) => format!("#ERROR:{}", code),
+        }
+    }
+
+    /// The value of a CSV field, the inverse of to_field: TRUE/FALSE, numbers, ISO 8601 dates
+    /// and #ERROR:<code> get their type back, anything else is text
+    /// Numbers with leading zeros (e.g. "00420") and integers of more than 15 digits, which a
+    /// spreadsheet cannot hold exactly, stay text.
+    pub fn from_field(field: &str) -> Self {
+        match field {
+            "" => return CellValue::Empty,
+            "TRUE" => return CellValue::Bool(true),
+            "FALSE" => return CellValue::Bool(false),
+            _ => {}
+        }
+        if let Some(code) = field.strip_prefix("#ERROR:") {
+            return CellValue::Error(code.to_string());
+        }
+        if is_number(field)
+            && let Ok(n) = field.parse::<f64>()
+            && n.is_finite()
+        {
+            return CellValue::Number(n);
+        }
+        // Dates start with the year; parse only what can be one
+        if field.len() >= 10
+            && field.as_bytes()[4] == b'-'
+            && let Some(date) = parse_iso_datetime(field)
+        {
+            return CellValue::Date(date);
+        }
+        CellValue::Text(field.to_string())
+    }
+}
+
+/// Whether s is a plain decimal number like -12, 0.5 or 1.5E-3, without leading zeros and of
+/// at most 15 digits when it is an integer
+fn is_number(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    let (mantissa, exponent) = match digits.find(['e', 'E']) {
+        Some(i) => (&digits[..i], Some(&digits[i + 1..])),
+        None => (digits, None),
+    };
+    let (int, fraction) = match mantissa.split_once('.') {
+        Some((int, fraction)) => (int, Some(fraction)),
+        None => (mantissa, None),
+    };
+    let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    all_digits(int)
+        && (int == "0" || !int.starts_with('0'))
+        && fraction.is_none_or(all_digits)
+        && exponent.is_none_or(|e| all_digits(e.strip_prefix(['+', '-']).unwrap_or(e)))
+        && (fraction.is_some() || exponent.is_some() || int.len() <= 15)
+}
+
+/// Where a cell passed to ExportOptions::cell_transform sits
+/// row, column: 1-based position in the sheet
+/// header: the column's field in the first non-blank row of the sheet, as transformed; None in
+///   that row and those before it, and for columns past its end
+#[derive(Debug, Clone, Copy)]
+pub struct CellContext<'a> {
+    pub row: u32,
+    pub column: u32,
+    pub header: Option<&'a str>,
+    pub(crate) dropped: &'a Cell<bool>,
+}
+
+impl CellContext<'_> {
+    /// Leave out the row of this cell; the rest of its cells are still passed to the transform
+    pub fn drop_row(&self) {
+        self.dropped.set(true);
+    }
+}
+
+/// Callback rewriting every cell value read, before it is written (see ExportOptions)
+/// An error stops the export of the sheet at that cell. Shared by the clones of the
+/// ExportOptions holding it.
+pub type CellTransform = Arc<dyn Fn(&CellContext, CellValue) -> Result<CellValue> + Send + Sync>;
+
+/// Receives the rows of the sheets exported with export_sheet_to_sink, e.g. to insert them into
+/// a database or publish them to a queue without writing CSV first
+/// Rows are passed as read: the first row (usually the header) like any other, and shorter rows
+/// padded with Empty to its width.
+pub trait RowSink {
+    /// Called before the first row of a sheet
+    fn start_sheet(&mut self, _info: &SheetInfo) {}
+
+    /// Called for every row of the sheet, in order
+    fn write_row(&mut self, row: &[CellValue]) -> Result<()>;
+
+    /// Whether the sink needs no more rows; the rest of the sheet is then not read
+    fn done(&self) -> bool {
+        false
+    }
+
+    /// Called after the last row of a sheet
+    fn finish_sheet(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Collects the rows in memory
+impl RowSink for Vec<Vec<CellValue>> {
+    fn write_row(&mut self, row: &[CellValue]) -> Result<()> {
+        self.push(row.to_vec());
+        Ok(())
+    }
+}
+
+/// Export a worksheet into sink instead of a file
+/// reader, shared_strings, styles and is_1904 are as for export_sheet_xml_to_csv; info is passed
+/// to RowSink::start_sheet. Of the options only skip_blank_rows, trim, max_rows, date_system and
+/// the warning and progress callbacks apply.
+/// Returns an ExportSummary without files.
+///
+/// Examples
+/// let mut rows: Vec<Vec<CellValue>> = Vec::new();
+/// export_sheet_to_sink(reader, &info, &shared_strings, &styles, false, &options, &mut rows)?;
+pub fn export_sheet_to_sink<R: BufRead, T: StringTable + ?Sized, S: RowSink>(
+    reader: R,
+    info: &SheetInfo,
+    shared_strings: &T,
+    styles: &[StyleInfo],
+    is_1904: bool,
+    options: &ExportOptions,
+    sink: &mut S,
+) -> Result<ExportSummary> {
+    sink.start_sheet(info);
+    let mut summary = ExportSummary::default();
+    let mut writer = SinkWriter {
+        sink,
+        options,
+        pending_blank_rows: 0,
+        rows: 0,
+        widest: 0,
+    };
+    let mut progress = read_sheet(
+        reader,
+        shared_strings,
+        styles,
+        is_1904,
+        options,
+        &mut writer,
+        &mut summary,
+    )?;
+    summary.columns = writer.widest;
+    progress.rows_written = writer.rows;
+    if let Some(cb) = &options.progress {
+        cb(&progress);
+    }
+    summary.rows_written = writer.rows;
+    sink.finish_sheet()?;
+    Ok(summary)
+}
+
+/// Passes rows on to a RowSink, applying skip_blank_rows, trim and max_rows
+/// Unlike in CSV, a cell holding an empty string keeps its row from being blank.
+struct SinkWriter<'a, S> {
+    sink: &'a mut S,
+    options: &'a ExportOptions,
+    /// Blank rows held back by trim until a non-blank row follows
+    pending_blank_rows: u64,
+    rows: u64,
+    widest: usize,
+}
+
+impl<S: RowSink> SinkWriter<'_, S> {
+    fn write_values(&mut self, values: &[CellValue]) -> Result<u64> {
+        if RowTarget::limit_reached(self) {
+            return Ok(0);
+        }
+        self.sink.write_row(values)?;
+        self.rows += 1;
+        self.widest = self.widest.max(values.len());
+        Ok(1)
+    }
+}
+
+impl<S: RowSink> RowTarget for SinkWriter<'_, S> {
+    fn typed(&self) -> bool {
+        true
+    }
+
+    fn set_sheet_width(&mut self, _width: usize) {}
+
+    fn write(
+        &mut self,
+        _row: &mut Vec<String>,
+        _present: &[bool],
+        values: &mut Vec<CellValue>,
+        _row_number: u32,
+    ) -> Result<u64> {
+        if self.options.trim {
+            let len = values
+                .iter()
+                .rposition(|value| !value.is_empty())
+                .map_or(0, |i| i + 1);
+            values.truncate(len);
+        }
+        if values.iter().all(CellValue::is_empty) {
+            if self.options.skip_blank_rows {
+                return Ok(0);
+            }
+            if self.options.trim {
+                self.pending_blank_rows += 1;
+                return Ok(0);
+            }
+        }
+        let mut written = 0;
+        for _ in 0..std::mem::take(&mut self.pending_blank_rows) {
+            written += self.write_values(&[])?;
+        }
+        written += self.write_values(values)?;
+        Ok(written)
+    }
+
+    fn limit_reached(&self) -> bool {
+        self.sink.done() || self.options.max_rows.is_some_and(|n| self.rows >= n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SheetKind, SheetState};
+    use chrono::NaiveDate;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_cell_value_from_field() {
+        let date = NaiveDate::from_ymd_opt(2023, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        for (field, value) in [
+            ("", CellValue::Empty),
+            ("TRUE", CellValue::Bool(true)),
+            ("-1.5E-3", CellValue::Number(-0.0015)),
+            (
+                "0.30000000000000004",
+                CellValue::Number(0.30000000000000004),
+            ),
+            ("2023-01-01T00:00:00.000Z", CellValue::Date(date)),
+            ("#ERROR:#N/A", CellValue::Error("#N/A".to_string())),
+            ("00420", CellValue::Text("00420".to_string())),
+            (
+                "1234567890123456",
+                CellValue::Text("1234567890123456".to_string()),
+            ),
+            ("1.", CellValue::Text("1.".to_string())),
+            ("inf", CellValue::Text("inf".to_string())),
+            ("2023-13-01", CellValue::Text("2023-13-01".to_string())),
+        ] {
+            assert_eq!(CellValue::from_field(field), value, "{}", field);
+        }
+    }
+
+    #[test]
+    fn test_export_sheet_to_sink() {
+        let xml = r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>
+            <row r="1"><c r="A1" t="s"><v>0</v></c><c r="B1" t="inlineStr"><is><t>when</t></is></c><c r="C1" t="b"><v>1</v></c></row>
+            <row r="2"><c r="A2"><v>1.5</v></c><c r="B2" s="1"><v>45000</v></c><c r="C2" t="e"><v>#N/A</v></c></row>
+            <row r="4"><c r="B4" t="str"><v></v></c></row>
+        </sheetData></worksheet>"#;
+        let styles = vec![
+            StyleInfo::default(),
+            StyleInfo {
+                is_date: true,
+                ..StyleInfo::default()
+            },
+        ];
+        let info = SheetInfo {
+            name: "Data".to_string(),
+            path_in_zip: "xl/worksheets/sheet1.xml".to_string(),
+            sheet_id: Some(1),
+            state: SheetState::Visible,
+            kind: SheetKind::Worksheet,
+        };
+        let options = ExportOptions {
+            skip_blank_rows: true,
+            ..ExportOptions::default()
+        };
+        let mut rows: Vec<Vec<CellValue>> = Vec::new();
+        let summary = export_sheet_to_sink(
+            Cursor::new(xml),
+            &info,
+            &["id".to_string()],
+            &styles,
+            false,
+            &options,
+            &mut rows,
+        )
+        .unwrap();
+
+        let date = NaiveDate::from_ymd_opt(2023, 3, 15)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    CellValue::Text("id".to_string()),
+                    CellValue::Text("when".to_string()),
+                    CellValue::Bool(true),
+                ],
+                vec![
+                    CellValue::Number(1.5),
+                    CellValue::Date(date),
+                    CellValue::Error("#N/A".to_string()),
+                ],
+                vec![
+                    CellValue::Empty,
+                    CellValue::Text(String::new()),
+                    CellValue::Empty
+                ],
+            ]
+        );
+        assert_eq!(summary.rows_written, 3);
+        assert_eq!(summary.columns, 3);
+        assert!(summary.files.is_empty());
+    }
+}
@@ -0,0 +1,205 @@
+use anyhow::Result;
+use std::io::{Read, Seek, SeekFrom};
+
+/// The kind of file detected by looking at its leading bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    /// A zip archive, which is what an .xlsx file is
+    Zip,
+    /// A legacy binary Excel workbook (.xls, BIFF inside an OLE2 compound file)
+    Xls,
+    /// A password protected OOXML workbook (EncryptedPackage inside an OLE2 compound file)
+    EncryptedOoxml,
+    /// Some other OLE2 compound file (e.g. a .doc)
+    OtherCompound,
+    /// Plain delimited text
+    Csv,
+    /// An HTML page, often produced by "export to Excel" features of web apps
+    Html,
+    /// An XML document, e.g. an Excel 2003 XML spreadsheet
+    Xml,
+    /// A zero-length file
+    Empty,
+    /// Anything else
+    Unknown,
+}
+
+impl InputFormat {
+    /// Describe why the format cannot be converted and what to do about it
+    /// Returns None for formats that should be handed to the zip reader
+    pub fn rejection(&self) -> Option<&'static str> {
+        match self {
+            InputFormat::Zip | InputFormat::Unknown => None,
+            InputFormat::Xls => Some(
+                "this is a legacy .xls (BIFF) workbook; only .xlsx is supported, re-save it as .xlsx in Excel or LibreOffice",
+            ),
+            InputFormat::EncryptedOoxml => Some(
+                "this is a password protected (encrypted) workbook; remove the password in Excel and save it again",
+            ),
+            InputFormat::OtherCompound => {
+                Some("this is an OLE2 compound document, not an .xlsx workbook")
+            }
+            InputFormat::Csv => Some("this looks like a CSV/text file, not an .xlsx workbook"),
+            InputFormat::Html => Some(
+                "this is an HTML file with an Excel extension; open it in a browser or re-save it as .xlsx",
+            ),
+            InputFormat::Xml => Some(
+                "this is an XML file (e.g. Excel 2003 XML Spreadsheet), not an .xlsx workbook; re-save it as .xlsx",
+            ),
+            InputFormat::Empty => Some("the file is empty"),
+        }
+    }
+}
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const ZIP_EMPTY_MAGIC: &[u8] = b"PK\x05\x06";
+const CFB_MAGIC: &[u8] = &[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+const SAMPLE_LEN: usize = 8192;
+
+/// Sniff the format of the input by its magic bytes
+/// The reader is rewound to the start before returning.
+///
+/// Examples
+/// let format = sniff_format(&mut File::open("example.xlsx")?)?;
+/// assert_eq!(format, InputFormat::Zip);
+pub fn sniff_format<R: Read + Seek>(reader: &mut R) -> Result<InputFormat> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut sample = Vec::with_capacity(SAMPLE_LEN);
+    reader
+        .by_ref()
+        .take(SAMPLE_LEN as u64)
+        .read_to_end(&mut sample)?;
+
+    let format = if sample.is_empty() {
+        InputFormat::Empty
+    } else if sample.starts_with(ZIP_MAGIC) || sample.starts_with(ZIP_EMPTY_MAGIC) {
+        InputFormat::Zip
+    } else if sample.starts_with(CFB_MAGIC) {
+        sniff_compound(reader, &sample)?
+    } else {
+        sniff_text(&sample)
+    };
+
+    reader.seek(SeekFrom::Start(0))?;
+    Ok(format)
+}
+
+/// Look at the first directory sector of an OLE2 compound file to tell
+/// encrypted OOXML packages apart from legacy workbooks
+fn sniff_compound<R: Read + Seek>(reader: &mut R, header: &[u8]) -> Result<InputFormat> {
+    if header.len() < 52 {
+        return Ok(InputFormat::OtherCompound);
+    }
+    let sector_shift = u16::from_le_bytes([header[30], header[31]]);
+    if !(7..=16).contains(&sector_shift) {
+        return Ok(InputFormat::OtherCompound);
+    }
+    let sector_size = 1u64 << sector_shift;
+    let first_dir_sector = u32::from_le_bytes([header[48], header[49], header[50], header[51]]);
+
+    let mut dir = Vec::new();
+    reader.seek(SeekFrom::Start((first_dir_sector as u64 + 1) * sector_size))?;
+    reader.by_ref().take(sector_size).read_to_end(&mut dir)?;
+
+    // Directory entries are 128 bytes, starting with a UTF-16LE name of up to 64 bytes
+    let names: Vec<String> = dir
+        .chunks_exact(128)
+        .map(|entry| {
+            let units: Vec<u16> = entry[..64]
+                .chunks_exact(2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                .take_while(|&u| u != 0)
+                .collect();
+            String::from_utf16_lossy(&units)
+        })
+        .collect();
+
+    let format = if names
+        .iter()
+        .any(|n| n == "EncryptedPackage" || n == "EncryptionInfo")
+    {
+        InputFormat::EncryptedOoxml
+    } else if names.iter().any(|n| n == "Workbook" || n == "Book") {
+        InputFormat::Xls
+    } else {
+        InputFormat::OtherCompound
+    };
+    Ok(format)
+}
+
+/// Classify a leading sample that is not a known binary container
+fn sniff_text(sample: &[u8]) -> InputFormat {
+    if sample.contains(&0) {
+        return InputFormat::Unknown;
+    }
+    // The sample may end in the middle of a multi-byte character
+    let text = match std::str::from_utf8(sample) {
+        Ok(t) => t,
+        Err(e) if e.valid_up_to() + 4 >= sample.len() => {
+            std::str::from_utf8(&sample[..e.valid_up_to()]).unwrap_or_default()
+        }
+        Err(_) => return InputFormat::Unknown,
+    };
+    let trimmed = text.trim_start_matches('\u{feff}').trim_start();
+    let lower = trimmed
+        .get(..trimmed.len().min(512))
+        .unwrap_or(trimmed)
+        .to_ascii_lowercase();
+
+    if lower.starts_with("<!doctype html") || lower.starts_with("<html") || lower.contains("<table")
+    {
+        InputFormat::Html
+    } else if lower.starts_with("<?xml") || lower.starts_with('<') {
+        InputFormat::Xml
+    } else if text.contains([',', ';', '\t', '\n']) {
+        InputFormat::Csv
+    } else {
+        InputFormat::Unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_sniff_text_and_zip_inputs() {
+        let mut csv = Cursor::new(b"id,name\n1,foo\n".to_vec());
+        assert_eq!(sniff_format(&mut csv).unwrap(), InputFormat::Csv);
+        assert_eq!(csv.position(), 0);
+
+        let mut html = Cursor::new(b"<html><body><table></table></body></html>".to_vec());
+        assert_eq!(sniff_format(&mut html).unwrap(), InputFormat::Html);
+
+        let mut zip = Cursor::new(b"PK\x03\x04rest-of-archive".to_vec());
+        assert_eq!(sniff_format(&mut zip).unwrap(), InputFormat::Zip);
+
+        let mut empty = Cursor::new(Vec::new());
+        assert_eq!(sniff_format(&mut empty).unwrap(), InputFormat::Empty);
+    }
+
+    #[test]
+    fn test_sniff_encrypted_compound_file() {
+        // 512 byte header followed by a single directory sector (sector 0)
+        let mut data = vec![0u8; 1024];
+        data[..8].copy_from_slice(CFB_MAGIC);
+        data[30..32].copy_from_slice(&9u16.to_le_bytes());
+        data[48..52].copy_from_slice(&0u32.to_le_bytes());
+        for (i, name) in ["Root Entry", "EncryptionInfo", "EncryptedPackage"]
+            .iter()
+            .enumerate()
+        {
+            let start = 512 + i * 128;
+            for (j, unit) in name.encode_utf16().enumerate() {
+                data[start + j * 2..start + j * 2 + 2].copy_from_slice(&unit.to_le_bytes());
+            }
+        }
+
+        let mut cursor = Cursor::new(data);
+        assert_eq!(
+            sniff_format(&mut cursor).unwrap(),
+            InputFormat::EncryptedOoxml
+        );
+    }
+}
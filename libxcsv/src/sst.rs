@@ -0,0 +1,360 @@
+use anyhow::{Context, Result};
+use quick_xml::events::Event;
+use quick_xml::reader::NsReader;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+
+use crate::ns::{SPREADSHEETML, read_event};
+
+/// Most shared strings reserved up front from the uniqueCount of `<sst>`; a larger count grows
+/// the table as the strings are read, so a bogus count can't claim much memory
+const MAX_SST_RESERVE: usize = 1 << 20;
+
+/// Shared strings looked up by index while reading a sheet: the Vec<String> of
+/// read_shared_strings or the SharedStrings of read_shared_strings_within
+pub trait StringTable: Sync {
+    /// Number of strings
+    fn string_count(&self) -> usize;
+
+    /// The string at index, None past the end; fails if a spilled string can't be read back
+    fn string(&self, index: usize) -> Result<Option<Cow<'_, str>>>;
+}
+
+impl StringTable for [String] {
+    fn string_count(&self) -> usize {
+        self.len()
+    }
+
+    fn string(&self, index: usize) -> Result<Option<Cow<'_, str>>> {
+        Ok(self.get(index).map(|s| Cow::Borrowed(s.as_str())))
+    }
+}
+
+impl<const N: usize> StringTable for [String; N] {
+    fn string_count(&self) -> usize {
+        N
+    }
+
+    fn string(&self, index: usize) -> Result<Option<Cow<'_, str>>> {
+        StringTable::string(self.as_slice(), index)
+    }
+}
+
+impl StringTable for Vec<String> {
+    fn string_count(&self) -> usize {
+        self.len()
+    }
+
+    fn string(&self, index: usize) -> Result<Option<Cow<'_, str>>> {
+        StringTable::string(self.as_slice(), index)
+    }
+}
+
+/// The shared strings of a workbook, kept in memory up to a budget and spilled to temp files
+/// past it (see read_shared_strings_within)
+#[derive(Debug)]
+pub struct SharedStrings(Storage);
+
+#[derive(Debug)]
+enum Storage {
+    Memory(Vec<String>),
+    Spilled(SpilledStrings),
+}
+
+/// Strings spilled to disk: their bytes back to back in data, and in index the end offset of
+/// every string in data as a u64, so string i is read with two positional reads that threads
+/// can do at once
+#[derive(Debug)]
+struct SpilledStrings {
+    data: File,
+    index: File,
+    len: usize,
+}
+
+/// Fill buf from file at offset without moving (or sharing) a file cursor
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+    while !buf.is_empty() {
+        match std::os::windows::fs::FileExt::seek_read(file, buf, offset)? {
+            0 => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            n => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Default for SharedStrings {
+    fn default() -> Self {
+        SharedStrings(Storage::Memory(Vec::new()))
+    }
+}
+
+impl SharedStrings {
+    /// Whether the strings went over the budget and are read from disk
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.0, Storage::Spilled(_))
+    }
+}
+
+impl StringTable for SharedStrings {
+    fn string_count(&self) -> usize {
+        match &self.0 {
+            Storage::Memory(strings) => strings.len(),
+            Storage::Spilled(spilled) => spilled.len,
+        }
+    }
+
+    fn string(&self, index: usize) -> Result<Option<Cow<'_, str>>> {
+        let spilled = match &self.0 {
+            Storage::Memory(strings) => return StringTable::string(strings, index),
+            Storage::Spilled(spilled) => spilled,
+        };
+        if index >= spilled.len {
+            return Ok(None);
+        }
+        let mut offsets = [0u8; 16];
+        let (start, end) = if index == 0 {
+            read_exact_at(&spilled.index, &mut offsets[8..], 0)?;
+            (0, u64::from_le_bytes(offsets[8..].try_into()?))
+        } else {
+            read_exact_at(&spilled.index, &mut offsets, (index as u64 - 1) * 8)?;
+            (
+                u64::from_le_bytes(offsets[..8].try_into()?),
+                u64::from_le_bytes(offsets[8..].try_into()?),
+            )
+        };
+        let mut bytes = vec![0; usize::try_from(end - start)?];
+        read_exact_at(&spilled.data, &mut bytes, start).context("read spilled shared string")?;
+        Ok(Some(Cow::Owned(String::from_utf8(bytes)?)))
+    }
+}
+
+/// Writes the strings of SpilledStrings
+struct SpillWriter {
+    data: BufWriter<File>,
+    index: BufWriter<File>,
+    end: u64,
+    len: usize,
+}
+
+impl SpillWriter {
+    fn new() -> Result<Self> {
+        let create = || tempfile::tempfile().context("create temp file for shared strings");
+        Ok(Self {
+            data: BufWriter::new(create()?),
+            index: BufWriter::new(create()?),
+            end: 0,
+            len: 0,
+        })
+    }
+
+    fn push(&mut self, s: &str) -> Result<()> {
+        self.data.write_all(s.as_bytes())?;
+        self.end += s.len() as u64;
+        self.index.write_all(&self.end.to_le_bytes())?;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<SpilledStrings> {
+        let data = self.data.into_inner().map_err(|e| e.into_error())?;
+        let index = self.index.into_inner().map_err(|e| e.into_error())?;
+        Ok(SpilledStrings {
+            data,
+            index,
+            len: self.len,
+        })
+    }
+}
+
+/// What parse_shared_strings reads from sharedStrings.xml
+enum SstItem<'a> {
+    /// The uniqueCount of `<sst>`: how many strings follow
+    UniqueCount(usize),
+    String(&'a str),
+}
+
+/// Parse sharedStrings.xml, handing the uniqueCount and then every string to f
+/// Every `<si>` is collected in one reused buffer.
+fn parse_shared_strings<R: BufRead>(
+    reader: R,
+    mut f: impl FnMut(SstItem) -> Result<()>,
+) -> Result<()> {
+    let mut xml = NsReader::from_reader(reader);
+    // xml.config_mut().trim_text(true);
+    let mut buf = Vec::new();
+    let mut in_si = false;
+    // Only the text of <t> is part of the string, all of it (xml:space or not); whitespace
+    // between the elements of <si> and phonetic <rPh> text are not
+    let mut in_t = false;
+    let mut in_phonetic = false;
+    let mut current = String::new();
+    loop {
+        match read_event(&mut xml, &mut buf, SPREADSHEETML) {
+            Ok((true, Event::Start(e))) => match e.local_name().as_ref() {
+                b"si" => {
+                    in_si = true;
+                    current.clear();
+                }
+                b"t" => in_t = in_si && !in_phonetic,
+                b"rPh" => in_phonetic = true,
+                b"sst" => {
+                    let unique_count = e
+                        .attributes()
+                        .flatten()
+                        .find(|a| a.key.as_ref() == b"uniqueCount")
+                        .and_then(|a| String::from_utf8_lossy(&a.value).parse::<usize>().ok());
+                    if let Some(n) = unique_count {
+                        f(SstItem::UniqueCount(n))?;
+                    }
+                }
+                _ => {}
+            },
+            Ok((true, Event::End(e))) => match e.local_name().as_ref() {
+                b"si" => {
+                    f(SstItem::String(&current))?;
+                    in_si = false;
+                }
+                b"t" => in_t = false,
+                b"rPh" => in_phonetic = false,
+                _ => {}
+            },
+            Ok((_, Event::Text(t))) if in_t => {
+                // Due to quick-xml 0.38.3 (i assume 0.37+)
+                // The config is unescaping everything way too early.
+                // So we have reverted to 0.31.0 to have a functioning parser
+                // to show correct characters like angle brackets.
+                current.push_str(&t.unescape()?);
+            }
+            Ok((_, Event::Eof)) => break,
+            Err(e) => return Err(anyhow::anyhow!("XML error in sharedStrings: {}", e)),
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(())
+}
+
+/// Read the shared strings from the excel file
+/// The table is sized from the uniqueCount of `<sst>`, so only the final strings are allocated.
+/// Returns a vector of strings
+pub fn read_shared_strings<R: BufRead>(reader: R) -> Result<Vec<String>> {
+    let mut strings = Vec::new();
+    parse_shared_strings(reader, |item| {
+        match item {
+            SstItem::UniqueCount(n) => strings.reserve_exact(n.min(MAX_SST_RESERVE)),
+            SstItem::String(s) => strings.push(s.to_owned()),
+        }
+        Ok(())
+    })?;
+    Ok(strings)
+}
+
+/// Like read_shared_strings, but once the strings take more than memory_budget bytes (counting
+/// the String of each) they are all moved to temp files and looked up from there, so a string
+/// table larger than RAM can still be read. Lookups of spilled strings are a lot slower.
+pub fn read_shared_strings_within<R: BufRead>(
+    reader: R,
+    memory_budget: usize,
+) -> Result<SharedStrings> {
+    let mut strings: Vec<String> = Vec::new();
+    let mut used = 0usize;
+    let mut spill: Option<SpillWriter> = None;
+    parse_shared_strings(reader, |item| {
+        let s = match item {
+            SstItem::UniqueCount(n) => {
+                let fits = memory_budget / std::mem::size_of::<String>();
+                strings.reserve_exact(n.min(MAX_SST_RESERVE).min(fits));
+                return Ok(());
+            }
+            SstItem::String(s) => s,
+        };
+        if let Some(spill) = &mut spill {
+            return spill.push(s);
+        }
+        used = used.saturating_add(std::mem::size_of::<String>() + s.len());
+        strings.push(s.to_owned());
+        if used > memory_budget {
+            let mut writer = SpillWriter::new()?;
+            for s in strings.drain(..) {
+                writer.push(&s)?;
+            }
+            strings.shrink_to_fit();
+            spill = Some(writer);
+        }
+        Ok(())
+    })?;
+    Ok(SharedStrings(match spill {
+        Some(spill) => Storage::Spilled(spill.finish()?),
+        None => Storage::Memory(strings),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_shared_strings_reserves_unique_count() {
+        let xml = r#"<sst xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" count="5" uniqueCount="3"><si><t>a &amp; b</t></si><si><r><t>rich </t></r><r><t>text</t></r></si><si><t>c</t></si></sst>"#;
+        let strings = read_shared_strings(xml.as_bytes()).unwrap();
+        assert_eq!(strings, ["a & b", "rich text", "c"]);
+        assert_eq!(strings.capacity(), 3);
+
+        // A bogus count only reserves up to the cap
+        let xml = r#"<sst xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" uniqueCount="4000000000"><si><t>x</t></si></sst>"#;
+        let strings = read_shared_strings(xml.as_bytes()).unwrap();
+        assert_eq!(strings, ["x"]);
+        assert!(strings.capacity() <= MAX_SST_RESERVE);
+    }
+
+    #[test]
+    fn test_read_shared_strings_keeps_significant_whitespace() {
+        let xml = r#"<sst xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">
+            <si>
+                <t xml:space="preserve"> padded </t>
+            </si>
+            <si><t xml:space="preserve">   </t></si>
+            <si><t> </t></si>
+            <si>
+                <r><t xml:space="preserve">rich </t></r>
+                <r><rPr><b/></rPr><t>text</t></r>
+                <rPh sb="0" eb="1"><t>ruby</t></rPh>
+            </si>
+        </sst>"#;
+        let strings = read_shared_strings(xml.as_bytes()).unwrap();
+        assert_eq!(strings, [" padded ", "   ", " ", "rich text"]);
+    }
+
+    #[test]
+    fn test_shared_strings_spill_past_the_budget() {
+        let items: String = (0..500)
+            .map(|i| format!("<si><t>string {} ü</t></si>", i))
+            .collect();
+        let xml = format!(
+            r#"<sst xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" uniqueCount="500"><si><t></t></si>{}</sst>"#,
+            items
+        );
+
+        let kept = read_shared_strings_within(xml.as_bytes(), usize::MAX).unwrap();
+        assert!(!kept.is_spilled());
+        let spilled = read_shared_strings_within(xml.as_bytes(), 1000).unwrap();
+        assert!(spilled.is_spilled());
+        assert_eq!(spilled.string_count(), 501);
+        for table in [&kept, &spilled] {
+            assert_eq!(table.string(0).unwrap().as_deref(), Some(""));
+            assert_eq!(table.string(1).unwrap().as_deref(), Some("string 0 ü"));
+            assert_eq!(table.string(500).unwrap().as_deref(), Some("string 499 ü"));
+            assert_eq!(table.string(501).unwrap(), None);
+        }
+    }
+}
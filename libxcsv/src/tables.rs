@@ -0,0 +1,211 @@
+use anyhow::{Context, Result};
+use quick_xml::events::Event;
+use quick_xml::reader::NsReader;
+use std::io::{BufRead, BufReader, Read, Seek};
+use zip::ZipArchive;
+
+use crate::ns::{OFFICE_RELATIONSHIPS, SPREADSHEETML, in_namespace, read_event};
+use crate::{parse_range_ref, parse_relationships, rels_part_name, resolve_target};
+
+/// The ranges of a worksheet that start with a header row
+/// auto_filter: the ref of the sheet's `<autoFilter>`, e.g. "A3:F120"
+/// table_ids: the relationship ids of its `<tablePart>`s, in sheet order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct HeaderRanges {
+    auto_filter: Option<String>,
+    table_ids: Vec<String>,
+}
+
+/// Read the autoFilter and table parts of a worksheet; the cells of `<sheetData>` are skipped
+fn read_header_ranges<R: BufRead>(reader: R) -> Result<HeaderRanges> {
+    let mut xml = NsReader::from_reader(reader);
+    let mut buf = Vec::new();
+    let mut ranges = HeaderRanges::default();
+    loop {
+        match read_event(&mut xml, &mut buf, SPREADSHEETML) {
+            Ok((true, Event::Start(e))) if e.local_name().as_ref() == b"sheetData" => {
+                xml.read_to_end_into(e.name(), &mut Vec::new())?;
+            }
+            Ok((true, Event::Start(e) | Event::Empty(e))) => match e.local_name().as_ref() {
+                b"autoFilter" => {
+                    ranges.auto_filter = e
+                        .attributes()
+                        .flatten()
+                        .find(|a| a.key.as_ref() == b"ref")
+                        .map(|a| String::from_utf8_lossy(&a.value).into_owned());
+                }
+                b"tablePart" => {
+                    let id = e.attributes().flatten().find(|a| {
+                        let (resolved, local) = xml.resolve_attribute(a.key);
+                        local.as_ref() == b"id" && in_namespace(&resolved, OFFICE_RELATIONSHIPS)
+                    });
+                    if let Some(id) = id {
+                        ranges
+                            .table_ids
+                            .push(String::from_utf8_lossy(&id.value).into_owned());
+                    }
+                }
+                _ => {}
+            },
+            Ok((_, Event::Eof)) => break,
+            Err(e) => return Err(anyhow::anyhow!("XML error in worksheet: {}", e)),
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(ranges)
+}
+
+/// The range of a table part (xl/tables/table1.xml) if it has a header row
+/// Tables with headerRowCount="0" start with data and are ignored.
+fn read_table_range<R: BufRead>(reader: R) -> Result<Option<String>> {
+    let mut xml = NsReader::from_reader(reader);
+    let mut buf = Vec::new();
+    loop {
+        match read_event(&mut xml, &mut buf, SPREADSHEETML) {
+            Ok((true, Event::Start(e) | Event::Empty(e)))
+                if e.local_name().as_ref() == b"table" =>
+            {
+                let mut range = None;
+                let mut has_header = true;
+                for a in e.attributes().flatten() {
+                    match a.key.as_ref() {
+                        b"ref" => range = Some(String::from_utf8_lossy(&a.value).into_owned()),
+                        b"headerRowCount" => has_header = a.value.as_ref() != b"0",
+                        _ => {}
+                    }
+                }
+                return Ok(range.filter(|_| has_header));
+            }
+            Ok((_, Event::Eof)) => return Ok(None),
+            Err(e) => return Err(anyhow::anyhow!("XML error in table: {}", e)),
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+/// The row (1-based) holding the header of the worksheet stored at part: the first row of its
+/// autoFilter, else of its topmost table with a header row
+/// None for a sheet with neither, whose header is taken to be its first row as usual. Tables
+/// whose part is missing are skipped.
+pub fn find_header_row<R: Read + Seek>(zip: &mut ZipArchive<R>, part: &str) -> Result<Option<u32>> {
+    let f = zip
+        .by_name(part)
+        .with_context(|| format!("missing {}", part))?;
+    let ranges = read_header_ranges(BufReader::new(f))?;
+    let first_row = |range: &str| parse_range_ref(range).map(|(a, b)| a.row.min(b.row));
+    if let Some(row) = ranges.auto_filter.as_deref().and_then(first_row) {
+        return Ok(Some(row));
+    }
+    if ranges.table_ids.is_empty() {
+        return Ok(None);
+    }
+
+    let rels_part = rels_part_name(part);
+    let relationships = match zip.by_name(&rels_part) {
+        Ok(f) => parse_relationships(BufReader::new(f))
+            .with_context(|| format!("invalid {}", rels_part))?,
+        Err(_) => return Ok(None),
+    };
+    let mut header_row = None;
+    for id in &ranges.table_ids {
+        let Some(rel) = relationships
+            .iter()
+            .find(|r| &r.id == id && r.rel_type.rsplit('/').next() == Some("table") && !r.external)
+        else {
+            continue;
+        };
+        let Ok(f) = zip.by_name(&resolve_target(part, &rel.target)) else {
+            continue;
+        };
+        if let Some(row) = read_table_range(BufReader::new(f))?
+            .as_deref()
+            .and_then(first_row)
+        {
+            header_row = Some(header_row.map_or(row, |r: u32| r.min(row)));
+        }
+    }
+    Ok(header_row)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::ZipWriter;
+    use zip::write::FileOptions;
+
+    #[test]
+    fn test_find_header_row() {
+        let sheet = |tail: &str| {
+            format!(
+                r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"
+                    xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">
+                    <sheetData><row r="1"><c r="A1" t="inlineStr"><is><t>Title</t></is></c></row></sheetData>
+                    {}
+                </worksheet>"#,
+                tail
+            )
+        };
+        let table = |range: &str, header_rows: u32| {
+            format!(
+                r#"<table xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"
+                    id="1" name="T" ref="{}" headerRowCount="{}"/>"#,
+                range, header_rows
+            )
+        };
+        let rels = r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+            <Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/table" Target="../tables/table1.xml"/>
+            <Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/table" Target="../tables/table2.xml"/>
+        </Relationships>"#;
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in [
+            (
+                "xl/worksheets/sheet1.xml",
+                sheet(r#"<autoFilter ref="B4:E40"/>"#),
+            ),
+            (
+                "xl/worksheets/sheet2.xml",
+                sheet(
+                    r#"<tableParts count="2"><tablePart r:id="rId1"/><tablePart r:id="rId2"/></tableParts>"#,
+                ),
+            ),
+            ("xl/worksheets/_rels/sheet2.xml.rels", rels.to_string()),
+            ("xl/tables/table1.xml", table("A12:C20", 1)),
+            ("xl/tables/table2.xml", table("A6:C10", 1)),
+            ("xl/worksheets/sheet3.xml", sheet("")),
+            (
+                "xl/worksheets/sheet4.xml",
+                sheet(r#"<tableParts count="1"><tablePart r:id="rId1"/></tableParts>"#),
+            ),
+            ("xl/worksheets/_rels/sheet4.xml.rels", rels.to_string()),
+        ] {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        let mut zip = ZipArchive::new(writer.finish().unwrap()).unwrap();
+
+        assert_eq!(
+            find_header_row(&mut zip, "xl/worksheets/sheet1.xml").unwrap(),
+            Some(4)
+        );
+        // The topmost table wins
+        assert_eq!(
+            find_header_row(&mut zip, "xl/worksheets/sheet2.xml").unwrap(),
+            Some(6)
+        );
+        assert_eq!(
+            find_header_row(&mut zip, "xl/worksheets/sheet3.xml").unwrap(),
+            None
+        );
+        assert_eq!(
+            find_header_row(&mut zip, "xl/worksheets/sheet4.xml").unwrap(),
+            Some(12)
+        );
+
+        let headerless = table("A2:B5", 0);
+        assert_eq!(read_table_range(headerless.as_bytes()).unwrap(), None);
+    }
+}
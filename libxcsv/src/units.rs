@@ -0,0 +1,186 @@
+/// Symbols that make a number format a currency format when they are displayed
+const CURRENCY_SYMBOLS: &[char] = &[
+    '$', '€', '£', '¥', '¢', '¤', '₹', '₽', '₩', '₪', '₺', '฿', '₫', '₴', '₦', '₱',
+];
+
+/// The unit a currency or percent number format displays its values in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Unit {
+    Percent,
+    /// The currency symbol or code of the format, e.g. "€" or "EUR"
+    Currency(String),
+}
+
+impl Unit {
+    /// How the unit is written: "%" or the currency
+    pub fn symbol(&self) -> &str {
+        match self {
+            Unit::Percent => "%",
+            Unit::Currency(currency) => currency,
+        }
+    }
+}
+
+/// How numbers with a currency or percent format are written
+/// Off: like any other number
+/// Suffix: the value in its unit followed by the unit, "12.5 %" for 0.125 or "1234.5 €"
+/// Column: the value in its unit, and the unit in a column of its own next to every column
+///   whose `<col>` style has a unit; units other than the column's are written as a suffix
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnitOutput {
+    #[default]
+    Off,
+    Suffix,
+    Column,
+}
+
+impl std::str::FromStr for UnitOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "suffix" => Ok(Self::Suffix),
+            "column" => Ok(Self::Column),
+            _ => Err(format!(
+                "invalid mode '{}', expected off, suffix or column",
+                s
+            )),
+        }
+    }
+}
+
+/// The unit of a number format from its first section: Percent if it shows a %, else the
+/// currency of a `[$€-407]` tag or of a currency symbol, quoted or not
+/// Returns None for other formats, including locale tags like `[$-409]`.
+///   format_unit("0.00%") -> Some(Percent)
+///   format_unit("#,##0.00 \"€\"") -> Some(Currency("€"))
+///   format_unit("[$EUR] #,##0") -> Some(Currency("EUR"))
+pub fn format_unit(format_code: &str) -> Option<Unit> {
+    let mut currency: Option<String> = None;
+    let mut chars = format_code.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => break,
+            '%' => return Some(Unit::Percent),
+            '"' => {
+                let literal: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                let literal = literal.trim();
+                if !literal.is_empty() && literal.chars().all(|c| CURRENCY_SYMBOLS.contains(&c)) {
+                    currency.get_or_insert_with(|| literal.to_string());
+                }
+            }
+            '[' => {
+                let tag: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                if let Some(tag) = tag.strip_prefix('$') {
+                    let symbol = tag.split('-').next().unwrap_or_default();
+                    if !symbol.is_empty() {
+                        currency.get_or_insert_with(|| symbol.to_string());
+                    }
+                }
+            }
+            // Padding and fill take the next character, an escape shows it
+            '_' | '*' => {
+                chars.next();
+            }
+            '\\' => {
+                if let Some(c) = chars.next().filter(|c| CURRENCY_SYMBOLS.contains(c)) {
+                    currency.get_or_insert_with(|| c.to_string());
+                }
+            }
+            c if CURRENCY_SYMBOLS.contains(&c) => {
+                currency.get_or_insert_with(|| c.to_string());
+            }
+            _ => {}
+        }
+    }
+    currency.map(Unit::Currency)
+}
+
+/// With UnitOutput::Column: the 0-based columns, up to width, whose `<col>` style has a unit,
+/// and the unit; each is followed by a unit column
+/// col_styles are the (min, max, style) ranges of `<cols>`, the first one holding a column wins.
+pub(crate) fn unit_columns(
+    col_styles: &[(u32, u32, u32)],
+    style_units: &[Option<Unit>],
+    width: usize,
+) -> Vec<(usize, Unit)> {
+    (1..=width as u32)
+        .filter_map(|col| {
+            let (_, _, style) = col_styles
+                .iter()
+                .find(|(min, max, _)| (*min..=*max).contains(&col))?;
+            let unit = style_units.get(*style as usize)?.as_ref()?;
+            Some((col as usize - 1, unit.clone()))
+        })
+        .collect()
+}
+
+/// With UnitOutput::Column: which fields of a row hold a value in the unit of their column,
+/// whose unit goes in the unit column; units is the unit of every field with one
+/// The units of other fields are written as a suffix.
+pub(crate) fn place_units(
+    columns: &[(usize, Unit)],
+    row: &mut [String],
+    units: &[Option<Unit>],
+) -> Vec<bool> {
+    let mut in_column = vec![false; units.len()];
+    for (i, unit) in units.iter().enumerate() {
+        let Some(unit) = unit else {
+            continue;
+        };
+        if columns.iter().any(|(column, u)| *column == i && u == unit) {
+            in_column[i] = true;
+        } else if let Some(field) = row.get_mut(i) {
+            field.push(' ');
+            field.push_str(unit.symbol());
+        }
+    }
+    in_column
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_unit() {
+        let currency = |s: &str| Some(Unit::Currency(s.to_string()));
+        assert_eq!(format_unit("0%"), Some(Unit::Percent));
+        assert_eq!(format_unit("0.00%;[Red]-0.00%"), Some(Unit::Percent));
+        assert_eq!(
+            format_unit("\"$\"#,##0.00_);(\"$\"#,##0.00)"),
+            currency("$")
+        );
+        assert_eq!(format_unit("#,##0.00 \\€"), currency("€"));
+        assert_eq!(format_unit("[$€-407] #,##0.00"), currency("€"));
+        assert_eq!(format_unit("[$EUR] #,##0"), currency("EUR"));
+        assert_eq!(format_unit("_($* #,##0_)"), currency("$"));
+        assert_eq!(format_unit("[$-409]#,##0.00"), None);
+        assert_eq!(format_unit("0.00\" pcs\""), None);
+        assert_eq!(format_unit("0.00\"%\""), None);
+        assert_eq!(format_unit("General"), None);
+        // Only the first section counts
+        assert_eq!(format_unit("0;\"$\"0"), None);
+    }
+
+    #[test]
+    fn test_unit_columns_and_place_units() {
+        let euro = Unit::Currency("€".to_string());
+        let style_units = [None, Some(euro.clone()), Some(Unit::Percent)];
+        // B and C are styled €, D is %; the range past the width is left out
+        let columns = unit_columns(&[(2, 3, 1), (4, 40, 2), (3, 3, 0)], &style_units, 4);
+        assert_eq!(
+            columns,
+            [(1, euro.clone()), (2, euro.clone()), (3, Unit::Percent)]
+        );
+
+        let mut row: Vec<String> = ["12.5", "3", "4", "5"].map(String::from).to_vec();
+        let units = [Some(Unit::Percent), Some(euro), None, Some(Unit::Percent)];
+        assert_eq!(
+            place_units(&columns, &mut row, &units),
+            [false, true, false, true]
+        );
+        assert_eq!(row, ["12.5 %", "3", "4", "5"]);
+    }
+}
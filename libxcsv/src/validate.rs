@@ -0,0 +1,254 @@
+use anyhow::Result;
+use quick_xml::events::Event;
+use quick_xml::reader::NsReader;
+use serde::Serialize;
+use std::io::{BufRead, BufReader, Read, Seek};
+use zip::ZipArchive;
+
+use super::{
+    SPREADSHEETML, parse_styles, parse_workbook, parse_workbook_rels, read_event,
+    read_package_parts, read_shared_strings,
+};
+
+/// A structural problem found by validate_workbook
+/// part: The zip part the problem was found in
+/// position: Cell reference or byte offset inside the part, if applicable
+/// message: Description of the problem
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationIssue {
+    pub part: String,
+    pub position: Option<String>,
+    pub message: String,
+}
+
+impl ValidationIssue {
+    fn new(part: &str, position: Option<String>, message: impl Into<String>) -> Self {
+        Self {
+            part: part.to_string(),
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.position {
+            Some(pos) => write!(f, "{} [{}]: {}", self.part, pos, self.message),
+            None => write!(f, "{}: {}", self.part, self.message),
+        }
+    }
+}
+
+/// Check the package structure without converting anything
+/// Verifies that the required parts exist, that every sheet is well-formed XML,
+/// and that shared string and style indices used by cells are in bounds.
+/// Returns the list of problems found (empty if the workbook is valid).
+pub fn validate_workbook<R: Read + Seek>(zip: &mut ZipArchive<R>) -> Result<Vec<ValidationIssue>> {
+    let mut issues = Vec::new();
+
+    let parts = match read_package_parts(zip) {
+        Ok(parts) => parts,
+        Err(e) => {
+            issues.push(ValidationIssue::new(
+                "[Content_Types].xml",
+                None,
+                format!("{:#}", e),
+            ));
+            return Ok(issues);
+        }
+    };
+
+    for part in ["[Content_Types].xml", &parts.workbook, &parts.workbook_rels] {
+        if zip.by_name(part).is_err() {
+            issues.push(ValidationIssue::new(part, None, "required part is missing"));
+        }
+    }
+
+    let shared_strings = match parts.shared_strings.as_deref().map(|p| (p, zip.by_name(p))) {
+        Some((part, Ok(f))) => read_shared_strings(BufReader::new(f)).unwrap_or_else(|e| {
+            issues.push(ValidationIssue::new(part, None, e.to_string()));
+            Vec::new()
+        }),
+        _ => Vec::new(),
+    };
+    let styles_len = match parts.styles.as_deref().map(|p| (p, zip.by_name(p))) {
+        Some((part, Ok(f))) => match parse_styles(BufReader::new(f)) {
+            Ok(styles) => Some(styles.len()),
+            Err(e) => {
+                issues.push(ValidationIssue::new(part, None, e.to_string()));
+                None
+            }
+        },
+        _ => Some(0),
+    };
+
+    let rels = match zip.by_name(&parts.workbook_rels) {
+        Ok(f) => match parse_workbook_rels(BufReader::new(f), &parts.workbook) {
+            Ok(rels) => rels,
+            Err(e) => {
+                issues.push(ValidationIssue::new(
+                    &parts.workbook_rels,
+                    None,
+                    e.to_string(),
+                ));
+                return Ok(issues);
+            }
+        },
+        Err(_) => return Ok(issues),
+    };
+    let sheets = match zip.by_name(&parts.workbook) {
+        Ok(f) => match parse_workbook(BufReader::new(f), &rels) {
+            Ok((sheets, _)) => sheets,
+            Err(e) => {
+                issues.push(ValidationIssue::new(&parts.workbook, None, e.to_string()));
+                return Ok(issues);
+            }
+        },
+        Err(_) => return Ok(issues),
+    };
+    if sheets.is_empty() {
+        issues.push(ValidationIssue::new(
+            &parts.workbook,
+            None,
+            "workbook declares no sheets",
+        ));
+    }
+
+    for sheet in &sheets {
+        match zip.by_name(&sheet.path_in_zip) {
+            Ok(f) => validate_sheet(
+                BufReader::new(f),
+                &sheet.path_in_zip,
+                shared_strings.len(),
+                styles_len,
+                &mut issues,
+            ),
+            Err(_) => issues.push(ValidationIssue::new(
+                &sheet.path_in_zip,
+                None,
+                format!("part for sheet '{}' is missing", sheet.name),
+            )),
+        }
+    }
+    Ok(issues)
+}
+
+/// Stream a worksheet, checking well-formedness and the indices used by its cells
+/// styles_len is None when styles.xml could not be parsed, which disables the style check.
+fn validate_sheet<R: BufRead>(
+    reader: R,
+    part: &str,
+    shared_strings_len: usize,
+    styles_len: Option<usize>,
+    issues: &mut Vec<ValidationIssue>,
+) {
+    let mut xml = NsReader::from_reader(reader);
+    let mut buf = Vec::new();
+    let mut cell_ref = String::new();
+    let mut is_shared = false;
+    let mut in_v = false;
+    let mut value = String::new();
+
+    loop {
+        match read_event(&mut xml, &mut buf, SPREADSHEETML) {
+            Ok((true, Event::Start(e))) | Ok((true, Event::Empty(e)))
+                if e.local_name().as_ref() == b"c" =>
+            {
+                cell_ref.clear();
+                is_shared = false;
+                let mut style = None;
+                e.attributes().flatten().for_each(|a| match a.key.as_ref() {
+                    b"r" => cell_ref = String::from_utf8_lossy(&a.value).into_owned(),
+                    b"t" => is_shared = a.value.as_ref() == b"s",
+                    b"s" => style = String::from_utf8_lossy(&a.value).parse::<usize>().ok(),
+                    _ => {}
+                });
+                if let (Some(idx), Some(len)) = (style, styles_len)
+                    && idx >= len
+                {
+                    issues.push(ValidationIssue::new(
+                        part,
+                        Some(cell_ref.clone()),
+                        format!("style index {} out of range ({} styles)", idx, len),
+                    ));
+                }
+            }
+            Ok((true, Event::Start(e))) if e.local_name().as_ref() == b"v" => {
+                in_v = true;
+                value.clear();
+            }
+            Ok((_, Event::Text(t))) if in_v => {
+                if let Ok(txt) = t.unescape() {
+                    value.push_str(&txt);
+                }
+            }
+            Ok((true, Event::End(e))) if e.local_name().as_ref() == b"v" => {
+                in_v = false;
+                if is_shared {
+                    match value.trim().parse::<usize>() {
+                        Ok(idx) if idx >= shared_strings_len => issues.push(ValidationIssue::new(
+                            part,
+                            Some(cell_ref.clone()),
+                            format!(
+                                "shared string index {} out of range ({} strings)",
+                                idx, shared_strings_len
+                            ),
+                        )),
+                        Ok(_) => {}
+                        Err(_) => issues.push(ValidationIssue::new(
+                            part,
+                            Some(cell_ref.clone()),
+                            format!("invalid shared string index '{}'", value.trim()),
+                        )),
+                    }
+                }
+            }
+            Ok((_, Event::Eof)) => break,
+            Err(e) => {
+                issues.push(ValidationIssue::new(
+                    part,
+                    Some(format!("byte {}", xml.buffer_position())),
+                    format!("malformed XML: {}", e),
+                ));
+                break;
+            }
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_sheet_reports_bad_indices_and_xml() {
+        let xml_data = r#"
+        <worksheet>
+            <sheetData>
+                <row r="1">
+                    <c r="A1" t="s"><v>0</v></c>
+                    <c r="B1" t="s"><v>5</v></c>
+                    <c r="C1" s="3"><v>1</v></c>
+                </row>
+            </row>
+        </worksheet>
+        "#;
+        let mut issues = Vec::new();
+        validate_sheet(
+            BufReader::new(xml_data.as_bytes()),
+            "xl/worksheets/sheet1.xml",
+            1,
+            Some(2),
+            &mut issues,
+        );
+
+        assert_eq!(issues.len(), 3);
+        assert_eq!(issues[0].position.as_deref(), Some("B1"));
+        assert!(issues[0].message.contains("shared string index 5"));
+        assert_eq!(issues[1].position.as_deref(), Some("C1"));
+        assert!(issues[2].message.starts_with("malformed XML"));
+    }
+}
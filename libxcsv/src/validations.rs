@@ -0,0 +1,223 @@
+use anyhow::Result;
+use quick_xml::events::Event;
+use quick_xml::reader::NsReader;
+use serde::Serialize;
+use std::io::BufRead;
+
+use crate::ns::{SPREADSHEETML, read_event};
+
+/// A data validation of a worksheet: the rule the cells of its ranges are checked against,
+/// e.g. a dropdown list
+/// sqref: the cells it applies to, space separated ranges as written, e.g. "D2:D100 F2"
+/// kind: the type attribute: list, whole, decimal, date, time, textLength, custom, or any
+///   when it is missing (any value goes, the validation only shows a prompt)
+/// operator: how formula1 (and formula2) bound the value, e.g. between or greaterThan; None
+///   for list, custom and any
+/// formula1, formula2: the formulas as written, without '='; for a list either the values
+///   themselves ("\"Yes,No\"") or a range or name holding them
+/// values: the values of a list given in formula1 itself, see list_values
+/// allow_blank: whether empty cells pass
+/// prompt, error: the input message and the message shown for an invalid value, if any
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DataValidation {
+    pub sqref: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub operator: Option<String>,
+    pub formula1: Option<String>,
+    pub formula2: Option<String>,
+    pub values: Vec<String>,
+    pub allow_blank: bool,
+    pub prompt: Option<String>,
+    pub error: Option<String>,
+}
+
+/// The text of a data validation being read
+#[derive(Clone, Copy)]
+enum Field {
+    Formula1,
+    Formula2,
+    Sqref,
+}
+
+/// Read the data validations of a worksheet: those of `<dataValidations>` and those Excel
+/// writes to the x14 extension instead, for lists drawn from another sheet
+/// The cells of `<sheetData>` are skipped.
+pub fn read_data_validations<R: BufRead>(reader: R) -> Result<Vec<DataValidation>> {
+    let mut xml = NsReader::from_reader(reader);
+    let mut buf = Vec::new();
+    let mut validations = Vec::new();
+    let mut current: Option<DataValidation> = None;
+    let mut field: Option<Field> = None;
+    loop {
+        // The x14 elements are in an extension namespace (their formulas and sqref in the xm
+        // one), so they are matched on their local names alone
+        match read_event(&mut xml, &mut buf, SPREADSHEETML) {
+            Ok((true, Event::Start(e))) if e.local_name().as_ref() == b"sheetData" => {
+                xml.read_to_end_into(e.name(), &mut Vec::new())?;
+            }
+            Ok((_, Event::Start(e))) => match e.local_name().as_ref() {
+                b"dataValidation" => current = Some(start_validation(&e)),
+                b"formula1" => field = Some(Field::Formula1),
+                b"formula2" => field = Some(Field::Formula2),
+                b"sqref" => field = Some(Field::Sqref),
+                _ => {}
+            },
+            Ok((_, Event::Empty(e))) if e.local_name().as_ref() == b"dataValidation" => {
+                validations.push(finish_validation(start_validation(&e)));
+            }
+            Ok((_, Event::Text(t))) => {
+                if let (Some(field), Some(validation)) = (field, &mut current) {
+                    let text = t.unescape()?;
+                    match field {
+                        Field::Formula1 => validation.formula1.get_or_insert_default(),
+                        Field::Formula2 => validation.formula2.get_or_insert_default(),
+                        Field::Sqref => &mut validation.sqref,
+                    }
+                    .push_str(&text);
+                }
+            }
+            Ok((_, Event::End(e))) => match e.local_name().as_ref() {
+                b"dataValidation" => {
+                    if let Some(validation) = current.take() {
+                        validations.push(finish_validation(validation));
+                    }
+                }
+                b"formula1" | b"formula2" | b"sqref" => field = None,
+                _ => {}
+            },
+            Ok((_, Event::Eof)) => break,
+            Err(e) => return Err(anyhow::anyhow!("XML error in worksheet: {}", e)),
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(validations)
+}
+
+/// A DataValidation from the attributes of `<dataValidation>`
+fn start_validation(e: &quick_xml::events::BytesStart) -> DataValidation {
+    let mut validation = DataValidation::default();
+    for a in e.attributes().flatten() {
+        let value = a
+            .unescape_value()
+            .map(|v| v.into_owned())
+            .unwrap_or_else(|_| String::from_utf8_lossy(&a.value).into_owned());
+        match a.key.as_ref() {
+            b"type" => validation.kind = value,
+            b"operator" => validation.operator = Some(value),
+            b"sqref" => validation.sqref = value,
+            b"allowBlank" => validation.allow_blank = value == "1" || value == "true",
+            b"prompt" => validation.prompt = Some(value),
+            b"error" => validation.error = Some(value),
+            _ => {}
+        }
+    }
+    validation
+}
+
+/// Fill in the defaults of a validation read and the values of an inline list
+fn finish_validation(mut validation: DataValidation) -> DataValidation {
+    if validation.kind.is_empty() {
+        validation.kind = "any".to_string();
+    }
+    match validation.kind.as_str() {
+        "list" | "custom" | "any" | "none" => validation.operator = None,
+        _ => {
+            validation
+                .operator
+                .get_or_insert_with(|| "between".to_string());
+        }
+    }
+    if validation.kind == "list" {
+        validation.values = validation
+            .formula1
+            .as_deref()
+            .and_then(list_values)
+            .unwrap_or_default();
+    }
+    validation
+}
+
+/// The values of a list validation whose formula1 lists them, e.g. "\"Yes,No,Maybe\""
+/// Returns None for a formula referring to a range or name instead.
+///   list_values("\"Yes,No\"") -> Some(["Yes", "No"])
+///   list_values("$H$1:$H$5") -> None
+pub fn list_values(formula: &str) -> Option<Vec<String>> {
+    let list = formula.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some(
+        list.split(',')
+            .map(|value| value.replace("\"\"", "\""))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_data_validations() {
+        let xml = r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"
+            xmlns:x14="http://schemas.microsoft.com/office/spreadsheetml/2009/9/main"
+            xmlns:xm="http://schemas.microsoft.com/office/excel/2006/main">
+            <sheetData>
+                <row r="1"><c r="A1"><f>SUM(B1:B2)</f><v>3</v></c></row>
+            </sheetData>
+            <dataValidations count="4">
+                <dataValidation type="list" allowBlank="1" showInputMessage="1" sqref="D2:D100 F2">
+                    <formula1>"Open,Closed,On hold"</formula1>
+                </dataValidation>
+                <dataValidation type="whole" operator="greaterThan" error="Too small &amp; wrong" sqref="B2:B100">
+                    <formula1>0</formula1>
+                </dataValidation>
+                <dataValidation type="date" sqref="C2:C100">
+                    <formula1>44927</formula1><formula2>45291</formula2>
+                </dataValidation>
+                <dataValidation allowBlank="1" prompt="Free text" sqref="E2"/>
+            </dataValidations>
+            <extLst><ext uri="{CCE6A557-97BC-4b89-ADB6-D9C93CAAB3DF}">
+                <x14:dataValidations count="1">
+                    <x14:dataValidation type="list" allowBlank="1">
+                        <x14:formula1><xm:f>Lists!$A$1:$A$3</xm:f></x14:formula1>
+                        <xm:sqref>G2:G50</xm:sqref>
+                    </x14:dataValidation>
+                </x14:dataValidations>
+            </ext></extLst>
+        </worksheet>"#;
+        let validations = read_data_validations(xml.as_bytes()).unwrap();
+        assert_eq!(validations.len(), 5);
+        assert_eq!(
+            validations[0],
+            DataValidation {
+                sqref: "D2:D100 F2".to_string(),
+                kind: "list".to_string(),
+                operator: None,
+                formula1: Some("\"Open,Closed,On hold\"".to_string()),
+                formula2: None,
+                values: vec![
+                    "Open".to_string(),
+                    "Closed".to_string(),
+                    "On hold".to_string()
+                ],
+                allow_blank: true,
+                prompt: None,
+                error: None,
+            }
+        );
+        assert_eq!(validations[1].operator.as_deref(), Some("greaterThan"));
+        assert_eq!(validations[1].error.as_deref(), Some("Too small & wrong"));
+        assert_eq!(validations[2].operator.as_deref(), Some("between"));
+        assert_eq!(validations[2].formula2.as_deref(), Some("45291"));
+        assert_eq!(validations[3].kind, "any");
+        assert_eq!(validations[3].prompt.as_deref(), Some("Free text"));
+        assert_eq!(validations[4].sqref, "G2:G50");
+        assert_eq!(validations[4].formula1.as_deref(), Some("Lists!$A$1:$A$3"));
+        assert!(validations[4].values.is_empty());
+
+        assert_eq!(
+            list_values("\"say \"\"hi\"\",bye\""),
+            Some(vec!["say \"hi\"".to_string(), "bye".to_string()])
+        );
+    }
+}
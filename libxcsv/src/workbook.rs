@@ -0,0 +1,383 @@
+use anyhow::{Context, Result};
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+use zip::ZipArchive;
+
+use crate::columns::ColumnsSink;
+use crate::{
+    CellValue, Column, DataValidation, ExportOptions, ExportSummary, GeneralPrecision, RowSink,
+    SheetInfo, SheetKind, StyleInfo, WorkbookReader, export_sheet_to_sink, open_zip,
+    parse_cell_ref, parse_range_ref, read_data_validations, read_package_parts,
+    read_strings_and_styles, read_workbook,
+};
+
+/// An open workbook with the shared strings and styles needed to read its sheets
+/// A convenience over open_zip, read_workbook and export_sheet_to_sink for library users.
+///
+/// Examples
+/// let mut workbook = Workbook::open(Path::new("report.xlsx"))?;
+/// let columns = workbook.sheet("Data")?.to_columns()?;
+pub struct Workbook<R: Read + Seek = WorkbookReader> {
+    zip: ZipArchive<R>,
+    sheets: Vec<SheetInfo>,
+    is_1904: bool,
+    shared_strings: Vec<String>,
+    styles: Vec<StyleInfo>,
+    /// Per sheet, the rows of cells kept for Sheet::build_index
+    indexes: Vec<Index>,
+}
+
+/// The rows of a sheet kept in memory, see Sheet::build_index
+#[derive(Clone)]
+enum Index {
+    Off,
+    /// To be built by the next read of the sheet
+    Wanted,
+    Built(Vec<Vec<CellValue>>),
+}
+
+/// The options every read of a Sheet uses, with or without an index, so that both give the
+/// same values
+fn read_options(max_rows: Option<u64>) -> ExportOptions {
+    ExportOptions {
+        trim: true,
+        max_rows,
+        ..ExportOptions::default()
+    }
+}
+
+impl Workbook {
+    /// Open the workbook at path: a file, an http(s) or object store URL, or "-" for stdin
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::from_zip(open_zip(path)?)
+    }
+}
+
+impl<R: Read + Seek> Workbook<R> {
+    /// Read the sheet list, shared strings and styles of an opened zip
+    pub fn from_zip(mut zip: ZipArchive<R>) -> Result<Self> {
+        let parts = read_package_parts(&mut zip)?;
+        let (sheets, is_1904) = read_workbook(&mut zip, &parts)?;
+        let (shared_strings, styles) = read_strings_and_styles(&mut zip, &parts)?;
+        Ok(Self {
+            zip,
+            indexes: vec![Index::Off; sheets.len()],
+            sheets,
+            is_1904,
+            shared_strings,
+            styles,
+        })
+    }
+
+    /// Every sheet of the workbook, of any kind, in workbook order
+    pub fn sheets(&self) -> &[SheetInfo] {
+        &self.sheets
+    }
+
+    /// Whether the workbook uses the 1904 date system
+    pub fn is_1904(&self) -> bool {
+        self.is_1904
+    }
+
+    /// The worksheet named name, falling back to a case-insensitive match
+    pub fn sheet(&mut self, name: &str) -> Result<Sheet<'_, R>> {
+        let index = self
+            .sheets
+            .iter()
+            .position(|s| s.name == name)
+            .or_else(|| {
+                self.sheets
+                    .iter()
+                    .position(|s| s.name.to_lowercase() == name.to_lowercase())
+            })
+            .with_context(|| {
+                let names: Vec<&str> = self.sheets.iter().map(|s| s.name.as_str()).collect();
+                format!("no sheet named '{}' (sheets: {})", name, names.join(", "))
+            })?;
+        self.sheet_at(index)
+    }
+
+    /// The worksheet at index (from 0) of sheets()
+    pub fn sheet_at(&mut self, index: usize) -> Result<Sheet<'_, R>> {
+        let sheet = self
+            .sheets
+            .get(index)
+            .with_context(|| format!("no sheet at index {}", index))?;
+        if sheet.kind != SheetKind::Worksheet {
+            anyhow::bail!("'{}' is a {} and has no cell data", sheet.name, sheet.kind);
+        }
+        Ok(Sheet {
+            workbook: self,
+            index,
+        })
+    }
+}
+
+/// A worksheet of a Workbook, read on demand
+pub struct Sheet<'a, R: Read + Seek = WorkbookReader> {
+    workbook: &'a mut Workbook<R>,
+    index: usize,
+}
+
+impl<R: Read + Seek> Sheet<'_, R> {
+    pub fn info(&self) -> &SheetInfo {
+        &self.workbook.sheets[self.index]
+    }
+
+    /// Stream the rows of the sheet into sink; see export_sheet_to_sink for the options that apply
+    pub fn read_into<S: RowSink>(
+        &mut self,
+        options: &ExportOptions,
+        sink: &mut S,
+    ) -> Result<ExportSummary> {
+        let workbook = &mut *self.workbook;
+        let info = &workbook.sheets[self.index];
+        let f = workbook
+            .zip
+            .by_name(&info.path_in_zip)
+            .with_context(|| format!("missing {}", info.path_in_zip))?;
+        export_sheet_to_sink(
+            BufReader::new(f),
+            info,
+            &workbook.shared_strings,
+            &workbook.styles,
+            workbook.is_1904,
+            options,
+            sink,
+        )
+        .with_context(|| format!("read {}", info.name))
+    }
+
+    /// The data validations (dropdown lists and other rules) of the sheet; its cells are not read
+    pub fn data_validations(&mut self) -> Result<Vec<DataValidation>> {
+        let info = &self.workbook.sheets[self.index];
+        let f = self
+            .workbook
+            .zip
+            .by_name(&info.path_in_zip)
+            .with_context(|| format!("missing {}", info.path_in_zip))?;
+        read_data_validations(BufReader::new(f)).with_context(|| format!("read {}", info.name))
+    }
+
+    /// The sheet as typed columns named by the first non-blank row
+    /// A column is numbers, booleans or timestamps if all its values are, and text otherwise;
+    /// blank rows at the end are left out.
+    pub fn to_columns(&mut self) -> Result<Vec<Column>> {
+        let mut sink = ColumnsSink::default();
+        self.scan(None, &mut sink)?;
+        Ok(sink.finish())
+    }
+
+    /// The fields of the first non-blank row, without trailing empty fields; the rest of the
+    /// sheet is not read
+    /// Empty for a sheet without values.
+    pub fn headers(&mut self) -> Result<Vec<String>> {
+        let mut sink = HeaderSink(None);
+        self.scan(None, &mut sink)?;
+        Ok(sink.0.unwrap_or_default())
+    }
+
+    /// The position (from 0) of the header field name, falling back to a case-insensitive
+    /// match ignoring surrounding whitespace; None if no header field matches
+    pub fn header_index(&mut self, name: &str) -> Result<Option<usize>> {
+        let headers = self.headers()?;
+        Ok(headers.iter().position(|h| h == name).or_else(|| {
+            headers
+                .iter()
+                .position(|h| h.trim().to_lowercase() == name.trim().to_lowercase())
+        }))
+    }
+
+    /// Keep the cells of the sheet in memory, so that reads after the next one don't read the
+    /// sheet again
+    /// Nothing is read yet: the next read of the sheet (a cell, a range, its columns or
+    /// headers) reads all of it once and keeps its rows.
+    pub fn build_index(&mut self) -> Result<()> {
+        let index = &mut self.workbook.indexes[self.index];
+        if matches!(index, Index::Off) {
+            *index = Index::Wanted;
+        }
+        Ok(())
+    }
+
+    /// The value of the cell at reference (e.g. "B7"); Empty for a cell without a value
+    /// Without build_index the sheet is read up to the row of the cell and no further.
+    pub fn cell(&mut self, reference: &str) -> Result<CellValue> {
+        let cell = parse_cell_ref(reference)
+            .filter(|c| c.col >= 1 && c.row >= 1)
+            .with_context(|| format!("invalid cell reference '{}'", reference))?;
+        let rows = self.read_rows(cell.row, cell.row)?;
+        Ok(rows
+            .first()
+            .and_then(|row| row.get(cell.col as usize - 1))
+            .cloned()
+            .unwrap_or(CellValue::Empty))
+    }
+
+    /// The cells of the range at reference (e.g. "A1:D100"), row by row, every row as wide as
+    /// the range; rows past the end of the sheet are left out
+    /// Without build_index the sheet is read up to the last row of the range and no further.
+    pub fn range(&mut self, reference: &str) -> Result<Vec<Vec<CellValue>>> {
+        let (first, last) = parse_range_ref(reference)
+            .filter(|(a, b)| a.col >= 1 && a.row >= 1 && b.col >= 1 && b.row >= 1)
+            .with_context(|| format!("invalid range reference '{}'", reference))?;
+        let (rows, cols) = (
+            first.row.min(last.row)..=first.row.max(last.row),
+            first.col.min(last.col) as usize - 1..first.col.max(last.col) as usize,
+        );
+        let mut values = self.read_rows(*rows.start(), *rows.end())?;
+        for row in &mut values {
+            let mut cells: Vec<CellValue> = row.drain(cols.start.min(row.len())..).collect();
+            cells.resize(cols.len(), CellValue::Empty);
+            *row = cells;
+        }
+        Ok(values)
+    }
+
+    /// The rows first..=last (1-based) as read
+    fn read_rows(&mut self, first: u32, last: u32) -> Result<Vec<Vec<CellValue>>> {
+        // Every row is passed on, blank or not, so the n-th row written is sheet row n
+        let mut rows: Vec<Vec<CellValue>> = Vec::new();
+        self.scan(Some(u64::from(last)), &mut rows)?;
+        rows.drain(..(first as usize - 1).min(rows.len()));
+        Ok(rows)
+    }
+
+    /// Pass the rows of the sheet, up to max_rows of them, to sink: from the index if it is
+    /// built, else as read from the sheet, first building the index if it is wanted
+    fn scan<S: RowSink>(&mut self, max_rows: Option<u64>, sink: &mut S) -> Result<()> {
+        match &self.workbook.indexes[self.index] {
+            Index::Built(rows) => {
+                sink.start_sheet(&self.workbook.sheets[self.index]);
+                let limit = max_rows.map_or(rows.len(), |n| n as usize);
+                for row in rows.iter().take(limit) {
+                    if sink.done() {
+                        break;
+                    }
+                    sink.write_row(row)?;
+                }
+                sink.finish_sheet()
+            }
+            Index::Wanted => {
+                let mut rows: Vec<Vec<CellValue>> = Vec::new();
+                self.read_into(&read_options(None), &mut rows)?;
+                self.workbook.indexes[self.index] = Index::Built(rows);
+                self.scan(max_rows, sink)
+            }
+            Index::Off => self.read_into(&read_options(max_rows), sink).map(drop),
+        }
+    }
+}
+
+/// Keeps the fields of the first non-blank row and stops
+struct HeaderSink(Option<Vec<String>>);
+
+impl RowSink for HeaderSink {
+    fn write_row(&mut self, row: &[CellValue]) -> Result<()> {
+        if self.0.is_none() && !row.iter().all(CellValue::is_empty) {
+            self.0 = Some(
+                row.iter()
+                    .map(|value| value.to_field(GeneralPrecision::Exact))
+                    .collect(),
+            );
+        }
+        Ok(())
+    }
+
+    fn done(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ColumnData, WorkbookWriter};
+    use std::io::Cursor;
+
+    /// A workbook with the sheets and rows given, as fields typed by CellValue::from_field
+    fn workbook(sheets: &[(&str, &[&str])]) -> Workbook<Cursor<Vec<u8>>> {
+        let mut writer = WorkbookWriter::new(Cursor::new(Vec::new()));
+        for (name, rows) in sheets {
+            writer.start_sheet(name).unwrap();
+            for row in *rows {
+                let values: Vec<CellValue> = row.split(',').map(CellValue::from_field).collect();
+                writer.write_row(&values).unwrap();
+            }
+        }
+        let mut bytes = writer.finish().unwrap();
+        bytes.set_position(0);
+        Workbook::from_zip(ZipArchive::new(bytes).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_sheet_to_columns() {
+        let mut workbook = workbook(&[
+            ("Other", &["x"]),
+            ("Data", &["id,amount", "1,0.5", ",", "2,", ""]),
+        ]);
+        assert!(workbook.sheet("missing").is_err());
+        let columns = workbook.sheet("data").unwrap().to_columns().unwrap();
+        assert_eq!(columns.len(), 2);
+        assert_eq!(columns[0].name, "id");
+        assert_eq!(columns[0].data, ColumnData::Number(vec![1.0, 0.0, 2.0]));
+        assert_eq!(columns[1].validity, [true, false, false]);
+    }
+
+    #[test]
+    fn test_sheet_cell_and_range() {
+        let mut workbook = workbook(&[("Report", &["Title", "", "a,b,c", "1,2,3"])]);
+        let mut sheet = workbook.sheet("Report").unwrap();
+        for indexed in [false, true] {
+            if indexed {
+                sheet.build_index().unwrap();
+            }
+            assert_eq!(sheet.cell("A1").unwrap(), CellValue::Text("Title".into()));
+            assert_eq!(sheet.cell("c4").unwrap(), CellValue::Number(3.0));
+            assert_eq!(sheet.cell("Z99").unwrap(), CellValue::Empty);
+            assert_eq!(
+                sheet.range("D4:B3").unwrap(),
+                [
+                    vec![
+                        CellValue::Text("b".into()),
+                        CellValue::Text("c".into()),
+                        CellValue::Empty
+                    ],
+                    vec![
+                        CellValue::Number(2.0),
+                        CellValue::Number(3.0),
+                        CellValue::Empty
+                    ],
+                ]
+            );
+            assert_eq!(
+                sheet.range("A4:A9").unwrap(),
+                [vec![CellValue::Number(1.0)]]
+            );
+        }
+        assert!(sheet.cell("A0").is_err());
+    }
+
+    #[test]
+    fn test_sheet_headers_stop_at_first_row() {
+        let mut workbook = workbook(&[("Data", &["", ",Id, Amount ,", "1,2,3"]), ("Empty", &[])]);
+        let mut sheet = workbook.sheet("Data").unwrap();
+        assert_eq!(sheet.headers().unwrap(), ["", "Id", " Amount "]);
+        assert_eq!(sheet.header_index("amount").unwrap(), Some(2));
+        assert_eq!(sheet.header_index("Id").unwrap(), Some(1));
+        assert_eq!(sheet.header_index("missing").unwrap(), None);
+        let mut rows = HeaderSink(None);
+        let summary = sheet
+            .read_into(&ExportOptions::default(), &mut rows)
+            .unwrap();
+        assert_eq!(summary.rows_written, 2);
+        assert!(
+            workbook
+                .sheet("Empty")
+                .unwrap()
+                .headers()
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,373 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Seek, Write};
+use std::path::Path;
+use zip::ZipWriter;
+use zip::write::FileOptions;
+
+use crate::{CellValue, MAX_COLUMNS, MAX_ROWS, index_to_col, to_excel_serial};
+
+/// Most characters a cell can hold
+const MAX_CELL_CHARS: usize = 32_767;
+
+/// Styles of styles.xml: 0 General, 1 a date, 2 a date and time
+const DATE_STYLE: u32 = 1;
+const DATETIME_STYLE: u32 = 2;
+
+const STYLES_XML: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><numFmts count="2"><numFmt numFmtId="164" formatCode="yyyy\-mm\-dd"/><numFmt numFmtId="165" formatCode="yyyy\-mm\-dd\ hh:mm:ss"/></numFmts><fonts count="1"><font><sz val="11"/><name val="Calibri"/><family val="2"/></font></fonts><fills count="2"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill></fills><borders count="1"><border><left/><right/><top/><bottom/><diagonal/></border></borders><cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs><cellXfs count="3"><xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/><xf numFmtId="164" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/><xf numFmtId="165" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/></cellXfs><cellStyles count="1"><cellStyle name="Normal" xfId="0" builtinId="0"/></cellStyles></styleSheet>"#;
+
+const ROOT_RELS_XML: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#;
+
+/// Writes a minimal .xlsx workbook: one worksheet per start_sheet, with shared strings and the
+/// styles for dates
+/// Rows are streamed into the zip as they are written; only the shared strings are kept in
+/// memory until finish.
+///
+/// Examples
+/// let mut writer = WorkbookWriter::create(Path::new("out.xlsx"))?;
+/// writer.start_sheet("Orders")?;
+/// writer.write_row(&[CellValue::Text("id".into()), CellValue::Number(7.0)])?;
+/// writer.finish()?;
+pub struct WorkbookWriter<W: Write + Seek> {
+    zip: ZipWriter<W>,
+    /// Names of the sheets started so far; the last one is open
+    sheets: Vec<String>,
+    /// Rows written to the open sheet, including blank ones
+    rows: u32,
+    /// The shared string table and the index of every string in it
+    strings: Vec<String>,
+    string_index: HashMap<String, u32>,
+    /// Number of cells referring to a shared string
+    string_refs: u64,
+}
+
+impl WorkbookWriter<BufWriter<File>> {
+    /// Create the workbook file at path, replacing any existing file
+    pub fn create(path: &Path) -> Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+}
+
+impl<W: Write + Seek> WorkbookWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            zip: ZipWriter::new(writer),
+            sheets: Vec::new(),
+            rows: 0,
+            strings: Vec::new(),
+            string_index: HashMap::new(),
+            string_refs: 0,
+        }
+    }
+
+    /// Finish the open sheet, if any, and start a new one named name
+    /// Sheet names must be 1-31 characters without : \ / ? * [ ], and unique ignoring case.
+    pub fn start_sheet(&mut self, name: &str) -> Result<()> {
+        check_sheet_name(name)?;
+        if self
+            .sheets
+            .iter()
+            .any(|s| s.to_lowercase() == name.to_lowercase())
+        {
+            anyhow::bail!("duplicate sheet name '{}'", name);
+        }
+        self.end_sheet()?;
+        self.sheets.push(name.to_string());
+        self.rows = 0;
+        self.zip.start_file(
+            format!("xl/worksheets/sheet{}.xml", self.sheets.len()),
+            file_options(),
+        )?;
+        self.zip.write_all(
+            concat!(
+                r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+                "\n",
+                r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>"#
+            )
+            .as_bytes(),
+        )?;
+        Ok(())
+    }
+
+    /// Append a row to the open sheet; Empty cells are left out, blank rows take up a row
+    pub fn write_row(&mut self, row: &[CellValue]) -> Result<()> {
+        let Some(sheet) = self.sheets.last().cloned() else {
+            anyhow::bail!("write_row called before start_sheet");
+        };
+        if self.rows == MAX_ROWS {
+            anyhow::bail!("sheet '{}' has more than {} rows", sheet, MAX_ROWS);
+        }
+        if row.len() > MAX_COLUMNS as usize {
+            anyhow::bail!(
+                "row {} of sheet '{}' has {} cells, more than {}",
+                self.rows + 1,
+                sheet,
+                row.len(),
+                MAX_COLUMNS
+            );
+        }
+        self.rows += 1;
+        if row.iter().all(CellValue::is_empty) {
+            return Ok(());
+        }
+        let mut xml = format!(r#"<row r="{}">"#, self.rows);
+        for (i, value) in row.iter().enumerate() {
+            let cell = format!("{}{}", index_to_col(i as u32 + 1), self.rows);
+            match value {
+                CellValue::Empty => continue,
+                CellValue::Text(text) => {
+                    if text.chars().count() > MAX_CELL_CHARS {
+                        anyhow::bail!(
+                            "cell {} of sheet '{}' is longer than {} characters",
+                            cell,
+                            sheet,
+                            MAX_CELL_CHARS
+                        );
+                    }
+                    let index = self.shared_string(text);
+                    xml.push_str(&format!(r#"<c r="{}" t="s"><v>{}</v></c>"#, cell, index));
+                }
+                CellValue::Number(n) if n.is_finite() => {
+                    xml.push_str(&format!(r#"<c r="{}"><v>{}</v></c>"#, cell, n));
+                }
+                // Excel has no NaN or infinity
+                CellValue::Number(n) => {
+                    let index = self.shared_string(&n.to_string());
+                    xml.push_str(&format!(r#"<c r="{}" t="s"><v>{}</v></c>"#, cell, index));
+                }
+                CellValue::Bool(b) => {
+                    xml.push_str(&format!(
+                        r#"<c r="{}" t="b"><v>{}</v></c>"#,
+                        cell,
+                        u8::from(*b)
+                    ));
+                }
+                CellValue::Date(date) => match to_excel_serial(date) {
+                    Some(serial) => {
+                        let style = if serial.fract() == 0.0 {
+                            DATE_STYLE
+                        } else {
+                            DATETIME_STYLE
+                        };
+                        xml.push_str(&format!(
+                            r#"<c r="{}" s="{}"><v>{}</v></c>"#,
+                            cell, style, serial
+                        ));
+                    }
+                    // Before 1900: kept as text
+                    None => {
+                        let index = self.shared_string(&value.to_field(Default::default()));
+                        xml.push_str(&format!(r#"<c r="{}" t="s"><v>{}</v></c>"#, cell, index));
+                    }
+                },
+                CellValue::Error(code) => {
+                    xml.push_str(&format!(
+                        r#"<c r="{}" t="e"><v>{}</v></c>"#,
+                        cell,
+                        escape_xml(code)
+                    ));
+                }
+            }
+        }
+        xml.push_str("</row>");
+        self.zip.write_all(xml.as_bytes())?;
+        Ok(())
+    }
+
+    /// Finish the last sheet and write the workbook, shared string and style parts
+    /// A workbook needs at least one sheet.
+    /// Returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        if self.sheets.is_empty() {
+            anyhow::bail!("a workbook needs at least one sheet");
+        }
+        self.end_sheet()?;
+        let n = self.sheets.len();
+
+        let mut content_types = String::from(concat!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+            "\n",
+            r#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">"#,
+            r#"<Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>"#,
+            r#"<Default Extension="xml" ContentType="application/xml"/>"#,
+            r#"<Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/>"#,
+            r#"<Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/>"#,
+            r#"<Override PartName="/xl/sharedStrings.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sharedStrings+xml"/>"#,
+        ));
+        let mut workbook = String::from(concat!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+            "\n",
+            r#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets>"#,
+        ));
+        let mut rels = String::from(concat!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+            "\n",
+            r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+        ));
+        for (i, name) in self.sheets.iter().enumerate() {
+            let id = i + 1;
+            content_types.push_str(&format!(
+                r#"<Override PartName="/xl/worksheets/sheet{}.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/>"#,
+                id
+            ));
+            workbook.push_str(&format!(
+                r#"<sheet name="{}" sheetId="{}" r:id="rId{}"/>"#,
+                escape_xml(name),
+                id,
+                id
+            ));
+            rels.push_str(&format!(
+                r#"<Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet{}.xml"/>"#,
+                id, id
+            ));
+        }
+        content_types.push_str("</Types>");
+        workbook.push_str("</sheets></workbook>");
+        rels.push_str(&format!(
+            concat!(
+                r#"<Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/>"#,
+                r#"<Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/sharedStrings" Target="sharedStrings.xml"/>"#,
+                "</Relationships>"
+            ),
+            n + 1,
+            n + 2
+        ));
+
+        for (part, xml) in [
+            ("[Content_Types].xml", content_types.as_str()),
+            ("_rels/.rels", ROOT_RELS_XML),
+            ("xl/workbook.xml", workbook.as_str()),
+            ("xl/_rels/workbook.xml.rels", rels.as_str()),
+            ("xl/styles.xml", STYLES_XML),
+        ] {
+            self.zip.start_file(part, file_options())?;
+            self.zip.write_all(xml.as_bytes())?;
+        }
+
+        self.zip
+            .start_file("xl/sharedStrings.xml", file_options())?;
+        write!(
+            self.zip,
+            concat!(
+                r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+                "\n",
+                r#"<sst xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" count="{}" uniqueCount="{}">"#
+            ),
+            self.string_refs,
+            self.strings.len()
+        )?;
+        for s in &self.strings {
+            // Leading or trailing whitespace is dropped by readers unless preserved
+            let space = if s.trim() != s {
+                r#" xml:space="preserve""#
+            } else {
+                ""
+            };
+            write!(self.zip, "<si><t{}>{}</t></si>", space, escape_xml(s))?;
+        }
+        self.zip.write_all(b"</sst>")?;
+        Ok(self.zip.finish()?)
+    }
+
+    /// Close the sheetData of the open sheet, if any
+    fn end_sheet(&mut self) -> Result<()> {
+        if !self.sheets.is_empty() {
+            self.zip.write_all(b"</sheetData></worksheet>")?;
+        }
+        Ok(())
+    }
+
+    /// The index of s in the shared string table, adding it if new
+    fn shared_string(&mut self, s: &str) -> u32 {
+        self.string_refs += 1;
+        if let Some(&index) = self.string_index.get(s) {
+            return index;
+        }
+        let index = self.strings.len() as u32;
+        self.strings.push(s.to_string());
+        self.string_index.insert(s.to_string(), index);
+        index
+    }
+}
+
+fn file_options() -> FileOptions {
+    FileOptions::default().compression_method(zip::CompressionMethod::Deflated)
+}
+
+fn check_sheet_name(name: &str) -> Result<()> {
+    let len = name.chars().count();
+    if len == 0 || len > 31 {
+        anyhow::bail!("invalid sheet name '{}': must be 1 to 31 characters", name);
+    }
+    if let Some(c) = name.chars().find(|c| r"[]:*?/\".contains(*c)) {
+        anyhow::bail!("invalid sheet name '{}': must not contain '{}'", name, c);
+    }
+    if name.starts_with('\'') || name.ends_with('\'') {
+        anyhow::bail!(
+            "invalid sheet name '{}': must not start or end with an apostrophe",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Escape text for XML content and attributes; control characters, which XML cannot hold,
+/// are written in Excel's _xHHHH_ notation
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("_x{:04X}_", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ExportOptions, convert_xlsx_bytes_to_csv};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_written_workbook_round_trips() {
+        let mut writer = WorkbookWriter::new(Cursor::new(Vec::new()));
+        writer.start_sheet("Q1 Sales").unwrap();
+        for row in [
+            "id,name,when,ok,amount",
+            "1, padded ,2023-01-01T00:00:00.000Z,TRUE,0.30000000000000004",
+            "",
+            "00420,a & <b>,2023-01-01T12:30:00.000Z,#ERROR:#N/A,-1.5",
+        ] {
+            let values: Vec<CellValue> = row.split(',').map(CellValue::from_field).collect();
+            writer.write_row(&values).unwrap();
+        }
+        writer.start_sheet("Empty").unwrap();
+        assert!(writer.start_sheet("q1 sales").is_err());
+        assert!(writer.start_sheet("a/b").is_err());
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let files = convert_xlsx_bytes_to_csv(&bytes, &ExportOptions::default()).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].0, "q1_sales.csv");
+        assert_eq!(
+            String::from_utf8(files[0].1.clone()).unwrap(),
+            "id,name,when,ok,amount\n\
+             1, padded ,2023-01-01T00:00:00.000Z,TRUE,0.30000000000000004\n\
+             \"\"\n\
+             00420,a & <b>,2023-01-01T12:30:00.000Z,#ERROR:#N/A,-1.5\n"
+        );
+        assert_eq!(files[1], ("empty.csv".to_string(), Vec::new()));
+    }
+}
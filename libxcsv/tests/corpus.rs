@@ -0,0 +1,146 @@
+//! End-to-end checks against a corpus of real workbooks.
+//!
+//! The corpus is not checked in. Point `XCSV_CORPUS_DIR` at a directory of `.xlsx`
+//! files saved by the producers we care about (Excel, Google Sheets, LibreOffice,
+//! Apache POI, openpyxl, XlsxWriter, ...), e.g. one sub-directory per producer:
+//!
+//!     XCSV_CORPUS_DIR=~/xcsv-corpus cargo test --test corpus -- --nocapture
+//!
+//! Every workbook must validate and export without errors or warnings. If a
+//! directory named `<workbook>.expected/` sits next to `<workbook>.xlsx`, each
+//! `<sheet>.csv` in it is compared byte for byte with the exported sheet.
+
+use std::fs;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use libxcsv::{
+    ExportOptions, StyleInfo, export_sheet_xml_to_csv, open_zip, parse_styles, read_package_parts,
+    read_shared_strings, read_workbook, to_lowercase_filename, validate_workbook,
+};
+
+fn collect_workbooks(dir: &Path, out: &mut Vec<PathBuf>) {
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("read {}: {}", dir.display(), e))
+        .flatten()
+        .map(|e| e.path())
+        .collect();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            collect_workbooks(&path, out);
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("xlsx"))
+        {
+            out.push(path);
+        }
+    }
+}
+
+/// Convert one workbook and check the invariants; returns a description of the first failure
+fn check_workbook(path: &Path) -> Result<usize, String> {
+    let mut zip = open_zip(path).map_err(|e| format!("open: {:#}", e))?;
+
+    let issues = validate_workbook(&mut zip).map_err(|e| format!("validate: {:#}", e))?;
+    if let Some(issue) = issues.first() {
+        return Err(format!("validate: {} ({} issues)", issue, issues.len()));
+    }
+
+    let parts = read_package_parts(&mut zip).map_err(|e| format!("parts: {:#}", e))?;
+    let shared_strings = match parts.shared_strings.as_deref().map(|p| zip.by_name(p)) {
+        Some(Ok(f)) => read_shared_strings(BufReader::new(f)).map_err(|e| format!("{:#}", e))?,
+        _ => Vec::new(),
+    };
+    let styles: Vec<StyleInfo> = match parts.styles.as_deref().map(|p| zip.by_name(p)) {
+        Some(Ok(f)) => parse_styles(BufReader::new(f)).map_err(|e| format!("{:#}", e))?,
+        _ => Vec::new(),
+    };
+    let (sheets, is_1904) = read_workbook(&mut zip, &parts).map_err(|e| format!("{:#}", e))?;
+    if sheets.is_empty() {
+        return Err("no sheets found".to_string());
+    }
+
+    let out_dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+    let expected_dir = path.with_extension("expected");
+    for sheet in &sheets {
+        let file_name = format!("{}.csv", to_lowercase_filename(&sheet.name));
+        let out_path = out_dir.path().join(&file_name);
+        let f = zip
+            .by_name(&sheet.path_in_zip)
+            .map_err(|e| format!("{}: {}", sheet.path_in_zip, e))?;
+        let summary = export_sheet_xml_to_csv(
+            BufReader::new(f),
+            &shared_strings,
+            &styles,
+            is_1904,
+            &out_path,
+            &ExportOptions::default(),
+        )
+        .map_err(|e| format!("export {}: {:#}", sheet.name, e))?;
+        if summary.warning_count() > 0 {
+            return Err(format!(
+                "export {}: {} warning(s) {:?}",
+                sheet.name,
+                summary.warning_count(),
+                summary.warnings
+            ));
+        }
+
+        // The output must be readable CSV again
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_path(&out_path)
+            .map_err(|e| e.to_string())?;
+        let rows = rdr.records().count() as u64;
+        if rows != summary.rows_written {
+            return Err(format!(
+                "{}: read back {} rows, wrote {}",
+                sheet.name, rows, summary.rows_written
+            ));
+        }
+
+        let expected = expected_dir.join(&file_name);
+        if expected.exists() {
+            let want = fs::read(&expected).map_err(|e| e.to_string())?;
+            let got = fs::read(&out_path).map_err(|e| e.to_string())?;
+            if want != got {
+                return Err(format!(
+                    "{}: differs from {}",
+                    sheet.name,
+                    expected.display()
+                ));
+            }
+        }
+    }
+    Ok(sheets.len())
+}
+
+#[test]
+fn corpus_workbooks_convert() {
+    let Some(dir) = std::env::var_os("XCSV_CORPUS_DIR") else {
+        eprintln!("XCSV_CORPUS_DIR not set; skipping corpus tests");
+        return;
+    };
+    let mut workbooks = Vec::new();
+    collect_workbooks(Path::new(&dir), &mut workbooks);
+    assert!(!workbooks.is_empty(), "no .xlsx files in corpus");
+
+    let mut failures = Vec::new();
+    for path in &workbooks {
+        match check_workbook(path) {
+            Ok(sheets) => eprintln!("ok   {} ({} sheets)", path.display(), sheets),
+            Err(e) => {
+                eprintln!("FAIL {}: {}", path.display(), e);
+                failures.push(path);
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} corpus workbooks failed",
+        failures.len(),
+        workbooks.len()
+    );
+}
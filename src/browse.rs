@@ -0,0 +1,454 @@
+use std::collections::HashMap;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::Args;
+use libxcsv::{
+    CellDetails, SheetInfo, SheetKind, StyleInfo, index_to_col, open_zip, read_cell_details,
+    read_package_parts, read_strings_and_styles, read_workbook,
+};
+use ratatui::DefaultTerminal;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Table, Tabs};
+use zip::ZipArchive;
+
+use crate::view::read_sheet_rows;
+
+#[derive(Args, Debug, Clone)]
+pub struct BrowseArgs {
+    /// Load at most N rows of every sheet
+    #[arg(long, value_name = "N", default_value_t = 10_000)]
+    max_rows: u32,
+}
+
+/// Widest a column is drawn, in characters
+const MAX_COLUMN_WIDTH: usize = 30;
+
+/// Browse the workbook in an interactive terminal UI
+/// Sheets are read when their tab is first opened, up to --max-rows rows each.
+pub fn run(xlsx_path: &Path, args: &BrowseArgs) -> Result<()> {
+    let mut zip = open_zip(xlsx_path)?;
+    let parts = read_package_parts(&mut zip)?;
+    let (sheets, is_1904) = read_workbook(&mut zip, &parts)?;
+    if sheets.is_empty() {
+        anyhow::bail!("the workbook has no sheets");
+    }
+    let (shared_strings, styles) = read_strings_and_styles(&mut zip, &parts)?;
+    let mut app = App {
+        zip,
+        views: sheets.iter().map(|_| None).collect(),
+        sheets,
+        shared_strings,
+        styles,
+        is_1904,
+        max_rows: args.max_rows,
+        tab: 0,
+        mode: Mode::Normal,
+        search: String::new(),
+        status: String::new(),
+    };
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+/// A loaded sheet and the position of the cursor in it
+struct SheetView {
+    /// Cell values as exported; rows[r][c] is the cell in sheet row r + 1, column c + 1
+    rows: Vec<Vec<String>>,
+    /// Raw cell details by (row, column), both 1-based
+    details: HashMap<(u32, u32), CellDetails>,
+    widths: Vec<u16>,
+    /// Whether rows past --max-rows were left out
+    truncated: bool,
+    /// Selected (row, column), 0-based
+    cursor: (usize, usize),
+    /// First visible (row, column)
+    offset: (usize, usize),
+}
+
+impl SheetView {
+    fn value(&self, row: usize, col: usize) -> &str {
+        self.rows
+            .get(row)
+            .and_then(|r| r.get(col))
+            .map_or("", String::as_str)
+    }
+
+    fn columns(&self) -> usize {
+        self.widths.len()
+    }
+}
+
+#[derive(PartialEq, Eq)]
+enum Mode {
+    Normal,
+    /// Typing a search term after '/'
+    Search,
+}
+
+struct App<R> {
+    zip: ZipArchive<R>,
+    sheets: Vec<SheetInfo>,
+    /// One entry per sheet, filled in when the sheet is first shown
+    views: Vec<Option<SheetView>>,
+    shared_strings: Vec<String>,
+    styles: Vec<StyleInfo>,
+    is_1904: bool,
+    max_rows: u32,
+    tab: usize,
+    mode: Mode,
+    search: String,
+    status: String,
+}
+
+impl<R: Read + Seek> App<R> {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        self.load_tab();
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && !self.handle_key(key)
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Read the current sheet if it hasn't been yet; failures end up in the status line
+    fn load_tab(&mut self) {
+        if self.views[self.tab].is_some() {
+            return;
+        }
+        match self.read_sheet(self.tab) {
+            Ok(view) => {
+                self.status = if view.truncated {
+                    format!("showing the first {} rows", self.max_rows)
+                } else {
+                    String::new()
+                };
+                self.views[self.tab] = Some(view);
+            }
+            Err(e) => self.status = format!("error: {:#}", e),
+        }
+    }
+
+    fn read_sheet(&mut self, index: usize) -> Result<SheetView> {
+        let sheet = &self.sheets[index];
+        let mut rows = Vec::new();
+        let mut details = HashMap::new();
+        if sheet.kind == SheetKind::Worksheet {
+            rows = read_sheet_rows(
+                &mut self.zip,
+                sheet,
+                &self.shared_strings,
+                &self.styles,
+                self.is_1904,
+                self.max_rows as u64 + 1,
+            )?;
+            let f = self
+                .zip
+                .by_name(&sheet.path_in_zip)
+                .with_context(|| format!("missing {}", sheet.path_in_zip))?;
+            details = read_cell_details(BufReader::new(f), Some(self.max_rows))?
+                .into_iter()
+                .map(|d| ((d.cell.row, d.cell.col), d))
+                .collect();
+        }
+        let truncated = rows.len() > self.max_rows as usize;
+        rows.truncate(self.max_rows as usize);
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let widths = (0..columns)
+            .map(|c| {
+                rows.iter()
+                    .filter_map(|r| r.get(c))
+                    .map(|v| v.chars().count())
+                    .chain([index_to_col(c as u32 + 1).len()])
+                    .max()
+                    .unwrap_or(1)
+                    .clamp(3, MAX_COLUMN_WIDTH) as u16
+            })
+            .collect();
+        Ok(SheetView {
+            rows,
+            details,
+            widths,
+            truncated,
+            cursor: (0, 0),
+            offset: (0, 0),
+        })
+    }
+
+    /// Returns false to quit
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if self.mode == Mode::Search {
+            match key.code {
+                KeyCode::Enter => {
+                    self.mode = Mode::Normal;
+                    self.find(true);
+                }
+                KeyCode::Esc => self.mode = Mode::Normal,
+                KeyCode::Backspace => {
+                    self.search.pop();
+                }
+                KeyCode::Char(c) => self.search.push(c),
+                _ => {}
+            }
+            return true;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Tab | KeyCode::Char(']') => self.switch_tab(1),
+            KeyCode::BackTab | KeyCode::Char('[') => self.switch_tab(self.sheets.len() - 1),
+            KeyCode::Char('/') => {
+                self.mode = Mode::Search;
+                self.search.clear();
+            }
+            KeyCode::Char('n') => self.find(true),
+            KeyCode::Char('N') => self.find(false),
+            KeyCode::Up | KeyCode::Char('k') => self.move_cursor(-1, 0),
+            KeyCode::Down | KeyCode::Char('j') => self.move_cursor(1, 0),
+            KeyCode::Left | KeyCode::Char('h') => self.move_cursor(0, -1),
+            KeyCode::Right | KeyCode::Char('l') => self.move_cursor(0, 1),
+            KeyCode::PageUp => self.move_cursor(-20, 0),
+            KeyCode::PageDown => self.move_cursor(20, 0),
+            KeyCode::Home => self.move_cursor(isize::MIN / 2, isize::MIN / 2),
+            _ => {}
+        }
+        true
+    }
+
+    fn switch_tab(&mut self, step: usize) {
+        self.tab = (self.tab + step) % self.sheets.len();
+        self.status.clear();
+        self.load_tab();
+    }
+
+    fn move_cursor(&mut self, rows: isize, cols: isize) {
+        if let Some(view) = self.views[self.tab].as_mut() {
+            let last_row = view.rows.len().saturating_sub(1);
+            let last_col = view.columns().saturating_sub(1);
+            view.cursor.0 = view.cursor.0.saturating_add_signed(rows).min(last_row);
+            view.cursor.1 = view.cursor.1.saturating_add_signed(cols).min(last_col);
+        }
+    }
+
+    /// Move to the next (or previous) cell containing the search term
+    fn find(&mut self, forward: bool) {
+        let Some(view) = self.views[self.tab].as_mut() else {
+            return;
+        };
+        if self.search.is_empty() {
+            return;
+        }
+        match find_match(&view.rows, view.cursor, &self.search, forward) {
+            Some(cell) => {
+                view.cursor = cell;
+                self.status.clear();
+            }
+            None => self.status = format!("'{}' not found", self.search),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut ratatui::Frame) {
+        let [tabs_area, table_area, details_area, status_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(3),
+            Constraint::Length(4),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let titles = self.sheets.iter().map(|s| match s.kind {
+            SheetKind::Worksheet => s.name.clone(),
+            kind => format!("{} ({})", s.name, kind),
+        });
+        frame.render_widget(
+            Tabs::new(titles)
+                .select(self.tab)
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+            tabs_area,
+        );
+
+        let sheet = &self.sheets[self.tab];
+        let status = match self.mode {
+            Mode::Search => format!("/{}", self.search),
+            Mode::Normal if self.status.is_empty() => {
+                "q quit  tab/[] sheets  arrows/hjkl move  / search  n/N next/previous".to_string()
+            }
+            Mode::Normal => self.status.clone(),
+        };
+        frame.render_widget(Paragraph::new(status), status_area);
+
+        let Some(view) = self.views[self.tab].as_mut() else {
+            return;
+        };
+        if sheet.kind != SheetKind::Worksheet {
+            frame.render_widget(
+                Paragraph::new(format!("This {} has no cell data", sheet.kind))
+                    .block(Block::bordered()),
+                table_area,
+            );
+            return;
+        }
+        draw_table(frame, table_area, view);
+        frame.render_widget(
+            Paragraph::new(describe_cell(view, &self.styles, &self.shared_strings))
+                .block(Block::bordered()),
+            details_area,
+        );
+    }
+}
+
+/// Draw the visible part of the sheet, scrolling so the cursor stays in view
+fn draw_table(frame: &mut ratatui::Frame, area: Rect, view: &mut SheetView) {
+    let (row, col) = view.cursor;
+    // Borders and the column header row
+    let height = (area.height as usize).saturating_sub(3).max(1);
+    let label_width = (view.rows.len().max(1)).to_string().len() as u16;
+    view.offset.0 = view.offset.0.clamp(row.saturating_sub(height - 1), row);
+    view.offset.1 = view.offset.1.min(col);
+    // Scroll right until the cursor column fits
+    let available = area.width.saturating_sub(2 + label_width);
+    let fits =
+        |offset: usize| view.widths[offset..=col].iter().map(|w| w + 1).sum::<u16>() <= available;
+    while view.columns() > 0 && view.offset.1 < col && !fits(view.offset.1) {
+        view.offset.1 += 1;
+    }
+    let mut visible = Vec::new();
+    let mut used = 0;
+    for c in view.offset.1..view.columns() {
+        used += view.widths[c] + 1;
+        if used > available && !visible.is_empty() {
+            break;
+        }
+        visible.push(c);
+    }
+
+    let bold = Style::new().add_modifier(Modifier::BOLD);
+    let header = Row::new(
+        std::iter::once(Cell::from("")).chain(
+            visible
+                .iter()
+                .map(|&c| Cell::from(index_to_col(c as u32 + 1))),
+        ),
+    )
+    .style(bold);
+    let rows = (view.offset.0..view.rows.len().min(view.offset.0 + height)).map(|r| {
+        Row::new(
+            std::iter::once(Cell::from((r + 1).to_string()).style(bold)).chain(visible.iter().map(
+                |&c| {
+                    let cell = Cell::from(view.value(r, c).replace(['\r', '\n'], " "));
+                    if (r, c) == view.cursor {
+                        cell.style(Style::new().add_modifier(Modifier::REVERSED))
+                    } else {
+                        cell
+                    }
+                },
+            )),
+        )
+    });
+    let widths = std::iter::once(Constraint::Length(label_width))
+        .chain(visible.iter().map(|&c| Constraint::Length(view.widths[c])));
+    frame.render_widget(
+        Table::new(rows, widths)
+            .header(header)
+            .block(Block::bordered()),
+        area,
+    );
+}
+
+/// The two detail lines for the selected cell: its value, then type, style and formula
+fn describe_cell<'a>(
+    view: &SheetView,
+    styles: &[StyleInfo],
+    shared_strings: &[String],
+) -> Vec<Line<'a>> {
+    let (row, col) = view.cursor;
+    let reference = format!("{}{}", index_to_col(col as u32 + 1), row + 1);
+    let value = Line::from(format!("{}: {}", reference, view.value(row, col)));
+    let Some(details) = view.details.get(&(row as u32 + 1, col as u32 + 1)) else {
+        return vec![value, Line::from("(empty cell)")];
+    };
+    let mut info = vec![format!(
+        "type: {}",
+        match details.cell_type.as_deref() {
+            None | Some("n") => "number".to_string(),
+            Some("s") => match details.value.parse::<usize>() {
+                Ok(i) if i < shared_strings.len() => format!("shared string #{}", i),
+                _ => format!("shared string #{} (missing)", details.value),
+            },
+            Some("str") => "formula string".to_string(),
+            Some("inlineStr") => "inline string".to_string(),
+            Some("b") => "boolean".to_string(),
+            Some("e") => "error".to_string(),
+            Some("d") => "date (ISO 8601)".to_string(),
+            Some(other) => other.to_string(),
+        }
+    )];
+    if let Some(s) = details.style {
+        let format = styles.get(s as usize).map_or("?".to_string(), |style| {
+            style
+                .format_code
+                .clone()
+                .unwrap_or_else(|| format!("numFmtId {}", style.num_fmt_id))
+        });
+        info.push(format!("style: {} ({})", s, format));
+    }
+    if let Some(formula) = &details.formula {
+        info.push(format!("formula: ={}", formula));
+    }
+    vec![value, Line::from(info.join("  "))]
+}
+
+/// Find the next cell after (or the previous one before) from whose value contains term,
+/// case-insensitively, wrapping around at the end of the sheet
+fn find_match(
+    rows: &[Vec<String>],
+    from: (usize, usize),
+    term: &str,
+    forward: bool,
+) -> Option<(usize, usize)> {
+    let term = term.to_lowercase();
+    let mut cells: Vec<(usize, usize)> = rows
+        .iter()
+        .enumerate()
+        .flat_map(|(r, row)| (0..row.len()).map(move |c| (r, c)))
+        .collect();
+    if !forward {
+        cells.reverse();
+    }
+    let start = cells
+        .iter()
+        .position(|&cell| if forward { cell > from } else { cell < from })
+        .unwrap_or(cells.len());
+    cells[start..]
+        .iter()
+        .chain(&cells[..start])
+        .find(|&&(r, c)| rows[r][c].to_lowercase().contains(&term))
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_match_wraps_around() {
+        let rows = vec![
+            vec!["Apple".to_string(), "pear".to_string()],
+            vec![String::new(), "apple pie".to_string()],
+        ];
+        assert_eq!(find_match(&rows, (0, 0), "APPLE", true), Some((1, 1)));
+        assert_eq!(find_match(&rows, (1, 1), "apple", true), Some((0, 0)));
+        assert_eq!(find_match(&rows, (1, 1), "apple", false), Some((0, 0)));
+        assert_eq!(find_match(&rows, (0, 0), "plum", true), None);
+    }
+}
@@ -0,0 +1,172 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use libxcsv::{Compression, OutputWriter};
+
+/// Name of the column added in front of every combined row
+const SHEET_NAME_COLUMN: &str = "sheet_name";
+
+/// How --combine treats sheets whose header rows differ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CombineMode {
+    /// Every sheet must have the same header row
+    Strict,
+    /// Use the union of all header columns; missing columns are left empty
+    Loose,
+}
+
+fn reader(path: &Path, delimiter: u8) -> Result<csv::Reader<std::fs::File>> {
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_path(path)
+        .with_context(|| format!("read {}", path.display()))
+}
+
+/// Read the first row of a sheet CSV; None for an empty sheet
+fn read_header(path: &Path, delimiter: u8) -> Result<Option<Vec<String>>> {
+    let mut rdr = reader(path, delimiter)?;
+    match rdr.records().next() {
+        Some(record) => Ok(Some(record?.iter().map(str::to_string).collect())),
+        None => Ok(None),
+    }
+}
+
+/// Key a header by name and occurrence so repeated column names stay distinct
+fn header_keys(header: &[String]) -> Vec<(String, usize)> {
+    let mut keys: Vec<(String, usize)> = Vec::with_capacity(header.len());
+    for name in header {
+        let seen = keys.iter().filter(|(n, _)| n == name).count();
+        keys.push((name.clone(), seen));
+    }
+    keys
+}
+
+/// Concatenate per-sheet CSV files (sheet name, path) into out_path
+/// Every row gets a leading sheet_name column and the header is written once.
+/// Columns a sheet doesn't have are filled with empty (the --empty-as marker, if any).
+/// Empty sheets are skipped. Returns the number of data rows written.
+/// out_path is written with the given compression.
+pub fn combine_sheets(
+    parts: &[(String, PathBuf)],
+    mode: CombineMode,
+    delimiter: u8,
+    empty: &str,
+    out_path: &Path,
+    compression: Compression,
+) -> Result<u64> {
+    let mut headers = Vec::with_capacity(parts.len());
+    for (name, path) in parts {
+        if let Some(header) = read_header(path, delimiter)? {
+            headers.push((name, path, header));
+        }
+    }
+
+    // The combined columns, and for every sheet the combined position of each of its columns
+    let mut columns: Vec<(String, usize)> = Vec::new();
+    let mut mappings: Vec<Vec<usize>> = Vec::with_capacity(headers.len());
+    for (name, _, header) in &headers {
+        let keys = header_keys(header);
+        match mode {
+            CombineMode::Strict => {
+                if columns.is_empty() {
+                    columns = keys;
+                } else if keys != columns {
+                    anyhow::bail!(
+                        "header of sheet '{}' differs from sheet '{}'; use --combine loose to merge columns",
+                        name,
+                        headers[0].0
+                    );
+                }
+                mappings.push((0..header.len()).collect());
+            }
+            CombineMode::Loose => {
+                let mapping = keys
+                    .into_iter()
+                    .map(|key| match columns.iter().position(|c| *c == key) {
+                        Some(i) => i,
+                        None => {
+                            columns.push(key);
+                            columns.len() - 1
+                        }
+                    })
+                    .collect();
+                mappings.push(mapping);
+            }
+        }
+    }
+
+    let mut wtr = csv::WriterBuilder::new()
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_writer(OutputWriter::create(out_path, compression)?);
+    wtr.write_record(
+        std::iter::once(SHEET_NAME_COLUMN).chain(columns.iter().map(|(n, _)| n.as_str())),
+    )?;
+
+    let mut rows = 0;
+    for ((name, path, _), mapping) in headers.iter().zip(&mappings) {
+        for record in reader(path, delimiter)?.records().skip(1) {
+            let record = record?;
+            let mut row = vec![empty; columns.len()];
+            for (field, &i) in record.iter().zip(mapping) {
+                row[i] = field;
+            }
+            // Fields beyond the sheet's own header are appended as they are
+            let extra = record.iter().skip(mapping.len());
+            wtr.write_record(std::iter::once(name.as_str()).chain(row).chain(extra))?;
+            rows += 1;
+        }
+    }
+    wtr.into_inner().map_err(|e| e.into_error())?.finish()?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_combine_loose_unions_columns() {
+        let dir = tempfile::tempdir().unwrap();
+        let part = |name: &str, csv: &str| {
+            let path = dir.path().join(format!("{}.csv", name));
+            std::fs::write(&path, csv).unwrap();
+            (name.to_string(), path)
+        };
+        let parts = [
+            part("a", "id,name\n1,x\n"),
+            part("b", "name,id,id\ny,2,3\n"),
+            part("c", ""),
+        ];
+        let out = dir.path().join("out.csv");
+
+        assert!(
+            combine_sheets(
+                &parts,
+                CombineMode::Strict,
+                b',',
+                "",
+                &out,
+                Compression::None
+            )
+            .is_err()
+        );
+        let rows = combine_sheets(
+            &parts,
+            CombineMode::Loose,
+            b',',
+            "",
+            &out,
+            Compression::None,
+        )
+        .unwrap();
+        assert_eq!(rows, 2);
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            "sheet_name,id,name,id\na,1,x,\nb,2,y,3\n"
+        );
+    }
+}
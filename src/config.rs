@@ -0,0 +1,111 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// File looked up in the working directory when --config is not given
+pub const DEFAULT_CONFIG_FILE: &str = "xcsv.toml";
+
+/// Defaults for export/watch read from xcsv.toml; keys use the same names as the CLI flags
+///
+/// Example:
+///   out-dir = "csv"
+///   delimiter = ";"
+///   date-epoch = "1904"
+///   sheets = ["Data*"]
+///   exclude-sheets = ["Scratch"]
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub out_dir: Option<PathBuf>,
+    pub delimiter: Option<String>,
+    pub pad_to_columns: Option<usize>,
+    pub pad_rows: Option<bool>,
+    pub trailing_delimiter: Option<bool>,
+    pub header: Option<String>,
+    pub row_numbers: Option<bool>,
+    pub trim: Option<bool>,
+    pub skip_blank_rows: Option<bool>,
+    pub progress: Option<bool>,
+    pub max_cells: Option<u64>,
+    pub debug_cells: Option<bool>,
+    pub decimal_comma: Option<bool>,
+    pub numbers: Option<String>,
+    pub units: Option<String>,
+    pub on_missing_string: Option<String>,
+    pub on_out_of_bounds: Option<String>,
+    pub date_epoch: Option<String>,
+    pub year_window: Option<i32>,
+    pub leap_bug: Option<String>,
+    pub timezone: Option<String>,
+    pub date_output: Option<String>,
+    pub threads: Option<usize>,
+    pub sheet_threads: Option<usize>,
+    pub sheets: Vec<String>,
+    pub exclude_sheets: Vec<String>,
+    pub combine: Option<String>,
+    pub name_template: Option<String>,
+    pub empty_as: Option<String>,
+    pub newlines: Option<String>,
+    pub r#where: Option<String>,
+    pub types: Option<String>,
+    pub rename: Vec<String>,
+    pub rename_file: Option<PathBuf>,
+    pub script: Option<PathBuf>,
+    pub drop_unmapped: Option<bool>,
+    pub snake_case: Option<bool>,
+    pub dedupe: Option<bool>,
+    pub dedupe_on: Vec<String>,
+    pub sample: Option<u64>,
+    pub seed: Option<u64>,
+    pub keep_going: Option<bool>,
+    pub pipeline: Option<bool>,
+    pub mmap: Option<bool>,
+    pub salvage: Option<bool>,
+    pub general_precision: Option<String>,
+    pub format: Option<String>,
+    pub colors: Option<bool>,
+    pub compress: Option<String>,
+    pub split_rows: Option<u64>,
+    pub split_size: Option<String>,
+    pub strings_memory: Option<String>,
+    pub non_worksheets: Option<String>,
+}
+
+impl Config {
+    /// Load the config from path, or from ./xcsv.toml if present
+    /// An explicitly given path must exist; a missing default file yields an empty config.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => Path::new(DEFAULT_CONFIG_FILE),
+            None => return Ok(Self::default()),
+        };
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("read config {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("invalid config {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config: Config = toml::from_str(
+            r#"
+            delimiter = ";"
+            date-epoch = "1904"
+            sheets = ["Data*"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.delimiter.as_deref(), Some(";"));
+        assert_eq!(config.date_epoch.as_deref(), Some("1904"));
+        assert_eq!(config.sheets, ["Data*"]);
+        assert_eq!(config.threads, None);
+
+        assert!(toml::from_str::<Config>("delimeter = \";\"").is_err());
+    }
+}
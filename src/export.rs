@@ -0,0 +1,1771 @@
+use std::collections::BTreeMap;
+use std::io::{BufReader, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use glob::Pattern;
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
+use libxcsv::{
+    ColumnMapping, ColumnRename, ColumnTypes, Compression, CustomEpoch, DateOutput, DateSystem,
+    Excel1900, Excel1904, ExportOptions, ExportProgress, ExportSummary, ExportWarning,
+    GeneralPrecision, HeaderRow, LeapBug, MissingString, NameTemplate, Newlines, NumberOutput,
+    OutOfBounds, OutputFormat, OutputWriter, ProgressCallback, RowFilter, SalvagedPart,
+    SharedStrings, SheetInfo, SheetKind, SheetMeta, SheetSchema, StringTable, StyleInfo,
+    ThemeColors, Timezone, UnitOutput, WorkbookReader, export_sheet_xml_to_csv, find_header_row,
+    is_object_url, is_url, object_url, open_zip, open_zip_mmap, open_zip_salvage,
+    parse_column_mapping, parse_styles, parse_theme, read_package_parts,
+    read_shared_strings_within, read_sheet_metas, read_sheet_width, read_workbook, upload_dir,
+};
+
+use zip::ZipArchive;
+
+use crate::combine::{CombineMode, combine_sheets};
+use crate::config::Config;
+use crate::log;
+use crate::schema::{create_table_sql, quote_ident, table_name};
+use crate::script::Script;
+use crate::stats::{SheetStats, WorkbookStats, write_stats};
+
+#[derive(Args, Debug, Clone)]
+pub struct ExportArgs {
+    /// Additional .xlsx files or glob patterns to convert; with more than one
+    /// input each workbook is written to <DIR>/<workbook>/<sheet>.csv
+    #[arg(value_name = "XLSX_PATH")]
+    pub inputs: Vec<PathBuf>,
+    /// Write per-sheet row, cell and warning counts and the checksums of the files written to
+    /// FILE as JSON, for checking the conversion in a pipeline
+    #[arg(long, value_name = "FILE", conflicts_with = "dry_run")]
+    pub stats: Option<PathBuf>,
+    #[command(flatten)]
+    pub convert: ConvertArgs,
+}
+
+/// Conversion settings shared by `export` and `watch`
+/// Options left unset fall back to the config file (xcsv.toml) and then to the built-in defaults.
+#[derive(Args, Debug, Clone)]
+pub struct ConvertArgs {
+    /// Output directory, created if missing, or an object store prefix like s3://bucket/out/ (with
+    /// the object-store feature) [default: .]
+    #[arg(short, long, value_name = "DIR")]
+    pub out_dir: Option<PathBuf>,
+    /// CSV delimiter character [default: , or ; with --decimal-comma]
+    #[arg(short, long, value_name = "DELIMITER", value_parser = parse_delimiter)]
+    delimiter: Option<u8>,
+    /// Write numbers with a decimal comma (3,14) instead of a point, as European consumers
+    /// expect; dates and text are left alone
+    #[arg(long, overrides_with = "no_decimal_comma")]
+    decimal_comma: bool,
+    /// Turn off --decimal-comma, e.g. when the config turns it on
+    #[arg(long, overrides_with = "decimal_comma", hide = true)]
+    no_decimal_comma: bool,
+    /// Numbers as raw (the stored value, 1234.5) or formatted (as the cell's number format
+    /// displays them, e.g. 1,234.50 € or 12.5%); dates are unaffected [default: raw]
+    #[arg(long, value_name = "MODE")]
+    numbers: Option<NumberOutput>,
+    /// Currency and percent numbers as their value in the unit (12.5 for 12.5%) followed by
+    /// the unit (suffix) or with the unit in a column of its own (column), named after the
+    /// header; off writes them like any number [default: off]
+    #[arg(long, value_name = "MODE")]
+    units: Option<UnitOutput>,
+    /// Pad or truncate every row to exactly N fields
+    #[arg(long, value_name = "N")]
+    pad_to_columns: Option<usize>,
+    /// Pad or truncate every row to the sheet's width, taken from its <dimension> or, when
+    /// that is missing, a scan of the whole sheet (ignored with --pad-to-columns)
+    #[arg(long, overrides_with = "no_pad_rows")]
+    pad_rows: bool,
+    /// Turn off --pad-rows, e.g. when the config turns it on
+    #[arg(long, overrides_with = "pad_rows", hide = true)]
+    no_pad_rows: bool,
+    /// End every row with a delimiter (requires --pad-to-columns or --pad-rows)
+    #[arg(long, overrides_with = "no_trailing_delimiter")]
+    trailing_delimiter: bool,
+    /// Turn off --trailing-delimiter, e.g. when the config turns it on
+    #[arg(long, overrides_with = "trailing_delimiter", hide = true)]
+    no_trailing_delimiter: bool,
+    /// Write a synthetic first row with the column letters (A,B,C,...) or numbers (1,2,3,...)
+    /// for sheets without a header of their own: letters, index or none; auto starts each sheet
+    /// at its real header, the first row of its autoFilter or table, leaving out title rows
+    /// above it [default: none]
+    #[arg(long, value_name = "MODE")]
+    header: Option<HeaderRow>,
+    /// Prepend the original Excel row number to every row (column "row" in the header)
+    #[arg(long, overrides_with = "no_row_numbers")]
+    row_numbers: bool,
+    /// Turn off --row-numbers, e.g. when the config turns it on
+    #[arg(long, overrides_with = "row_numbers", hide = true)]
+    no_row_numbers: bool,
+    /// Drop trailing empty fields of every row and trailing empty rows at the end of each sheet
+    #[arg(long, overrides_with = "no_trim")]
+    trim: bool,
+    /// Turn off --trim, e.g. when the config turns it on
+    #[arg(long, overrides_with = "trim", hide = true)]
+    no_trim: bool,
+    /// Leave out rows where every cell is empty (including gaps in the row numbers)
+    #[arg(long, overrides_with = "no_skip_blank_rows")]
+    skip_blank_rows: bool,
+    /// Turn off --skip-blank-rows, e.g. when the config turns it on
+    #[arg(long, overrides_with = "skip_blank_rows", hide = true)]
+    no_skip_blank_rows: bool,
+    /// Show per-sheet progress while exporting
+    #[arg(long, overrides_with = "no_progress")]
+    progress: bool,
+    /// Turn off --progress, e.g. when the config turns it on
+    #[arg(long, overrides_with = "progress", hide = true)]
+    no_progress: bool,
+    /// Refuse to export when the sheet dimensions add up to more than N cells
+    #[arg(long, value_name = "N")]
+    max_cells: Option<u64>,
+    /// Write sentinels like ⟨missing-ss:1234⟩ instead of empty cells for broken references
+    #[arg(long, overrides_with = "no_debug_cells")]
+    debug_cells: bool,
+    /// Turn off --debug-cells, e.g. when the config turns it on
+    #[arg(long, overrides_with = "debug_cells", hide = true)]
+    no_debug_cells: bool,
+    /// What to write for cells whose shared string is missing: empty, error (fail the sheet) or
+    /// index (the index as stored); counted as warnings either way [default: empty]
+    #[arg(long, value_name = "MODE")]
+    on_missing_string: Option<MissingString>,
+    /// What to do with cell and row references past column XFD, row 1048576 or the sheet's
+    /// dimension: clamp (skip the cell, read the row as the next one), keep (only clamp at the
+    /// worksheet limits, for stale dimensions) or error (fail the sheet); counted as warnings
+    /// [default: clamp]
+    #[arg(long, value_name = "MODE")]
+    on_out_of_bounds: Option<OutOfBounds>,
+    /// Date system for serial dates: 1900, 1904 or a custom epoch (YYYY-MM-DD[THH:MM:SS]);
+    /// defaults to the workbook's own setting
+    #[arg(long, value_name = "EPOCH", value_parser = parse_date_epoch)]
+    date_epoch: Option<DateEpoch>,
+    /// What serial 60 of the 1900 date system, Excel's nonexistent 1900-02-29, is written as:
+    /// 1900-02-28, 1900-03-01 or error (fail the sheet) [default: 1900-02-28]
+    #[arg(long, value_name = "MODE")]
+    leap_bug: Option<LeapBug>,
+    /// Timezone of the workbook's dates, which Excel stores without one: utc (a Z suffix),
+    /// naive (no suffix), an offset like +02:00 or, built with the tz feature, an IANA zone like
+    /// Europe/Amsterdam, written as the offset in effect at each date [default: utc]
+    #[arg(long, value_name = "TZ")]
+    timezone: Option<Timezone>,
+    /// How dates are written: iso (ISO 8601 in --timezone), unix-seconds, unix-millis (since
+    /// 1970-01-01T00:00:00Z) or serial (Excel serial numbers) [default: iso]
+    #[arg(long, value_name = "MODE")]
+    date_output: Option<DateOutput>,
+    /// Read text typed into date formatted cells (e.g. 05/03/34 in a dd/mm/yy cell) as the date
+    /// Excel displays, two-digit years falling in the 100 years from START [default: 1930]
+    #[arg(long, value_name = "START", num_args = 0..=1, default_missing_value = "1930")]
+    year_window: Option<i32>,
+    /// Number of workbooks to convert in parallel [default: 1]
+    #[arg(long, value_name = "N")]
+    threads: Option<usize>,
+    /// Number of sheets of a workbook to convert in parallel, each thread reading the workbook
+    /// on its own; files are still reported in sheet order. Only for local files and without
+    /// --script [default: 1]
+    #[arg(long, value_name = "N")]
+    sheet_threads: Option<usize>,
+    /// Parse each sheet and encode and write its rows on two threads, connected by a bounded
+    /// queue, so reading and writing overlap; helps with giant sheets on slow disks and network
+    /// filesystems
+    #[arg(long, overrides_with = "no_pipeline")]
+    pipeline: bool,
+    /// Turn off --pipeline, e.g. when the config turns it on
+    #[arg(long, overrides_with = "pipeline", hide = true)]
+    no_pipeline: bool,
+    /// Memory-map local workbook files instead of reading them through a buffer; faster for
+    /// multi-gigabyte workbooks with RAM to spare (built with the mmap feature). The file must
+    /// not change while it is read
+    #[arg(long, overrides_with = "no_mmap")]
+    mmap: bool,
+    /// Turn off --mmap, e.g. when the config turns it on
+    #[arg(long, overrides_with = "mmap", hide = true)]
+    no_mmap: bool,
+    /// Only export sheets whose name matches one of these glob patterns
+    #[arg(long, value_name = "PATTERN", num_args = 1.., value_parser = parse_sheet_pattern)]
+    sheets: Vec<Pattern>,
+    /// Skip sheets whose name matches one of these glob patterns
+    #[arg(long, value_name = "PATTERN", num_args = 1.., value_parser = parse_sheet_pattern)]
+    exclude_sheets: Vec<Pattern>,
+    /// Write all sheets into one <workbook>.csv with a leading sheet_name column;
+    /// headers must match unless MODE is loose
+    #[arg(long, value_name = "MODE", num_args = 0..=1, default_missing_value = "strict")]
+    combine: Option<CombineMode>,
+    /// File name for each sheet; placeholders {sheet}, {slug}, {index} (e.g. {index:02})
+    /// and {workbook} [default: {slug}.csv]
+    #[arg(long, value_name = "TEMPLATE", value_parser = parse_name_template)]
+    pub name_template: Option<NameTemplate>,
+    /// Write STRING (e.g. NULL or \N) for cells without a value, so bulk loaders can
+    /// tell them apart from empty strings
+    #[arg(long, value_name = "STRING")]
+    empty_as: Option<String>,
+    /// Line breaks inside cell values: keep (quoted multi-line fields), space (flatten them) or
+    /// escape (write them as \n and \r, and backslashes as \\) [default: keep]
+    #[arg(long, value_name = "MODE")]
+    newlines: Option<Newlines>,
+    /// Only write rows matching EXPR, e.g. "amount > 100 && status == 'open'"; columns are named
+    /// by the sheet's first row (which is always written), `quote` names with spaces
+    #[arg(long = "where", value_name = "EXPR")]
+    row_filter: Option<RowFilter>,
+    /// Coerce columns to types, e.g. "id:int,price:decimal(2),created:date" (also float, bool,
+    /// datetime, text); rows with a value that does not fit are left out with a warning
+    #[arg(long, value_name = "SPEC")]
+    types: Option<ColumnTypes>,
+    /// Rename a column, e.g. "Order Date=order_date" (repeatable); "Name=" drops it and a bare
+    /// "Name" keeps it. Listed columns come first, in order, followed by the others
+    #[arg(long, value_name = "OLD=NEW")]
+    rename: Vec<ColumnRename>,
+    /// Read --rename entries from FILE, one per line (lines starting with # are comments)
+    #[arg(long, value_name = "FILE")]
+    rename_file: Option<PathBuf>,
+    /// Leave out the columns not listed by --rename or --rename-file
+    #[arg(long, overrides_with = "no_drop_unmapped")]
+    drop_unmapped: bool,
+    /// Turn off --drop-unmapped, e.g. when the config turns it on
+    #[arg(long, overrides_with = "drop_unmapped", hide = true)]
+    no_drop_unmapped: bool,
+    /// Give columns without an explicit new name snake_case names (Order Date -> order_date)
+    #[arg(long, overrides_with = "no_snake_case")]
+    snake_case: bool,
+    /// Turn off --snake-case, e.g. when the config turns it on
+    #[arg(long, overrides_with = "snake_case", hide = true)]
+    no_snake_case: bool,
+    /// Skip rows identical to an earlier row of the same sheet (after --types); only the rows
+    /// seen are hashed, so memory stays small
+    #[arg(long, overrides_with = "no_dedupe")]
+    dedupe: bool,
+    /// Turn off --dedupe, e.g. when the config turns it on
+    #[arg(long, overrides_with = "dedupe", hide = true)]
+    no_dedupe: bool,
+    /// Like --dedupe, but rows count as duplicates when these columns match, e.g. "id,date"
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',')]
+    dedupe_on: Vec<String>,
+    /// Only write N randomly picked rows of each sheet (plus its first row), in sheet order;
+    /// the whole sheet is streamed, but only N rows are held in memory
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    sample: Option<u64>,
+    /// Export the remaining sheets (and workbooks) when one fails; failures are reported at the
+    /// end and the exit code is 2 if only some of them failed
+    #[arg(long, overrides_with = "no_keep_going")]
+    keep_going: bool,
+    /// Turn off --keep-going, e.g. when the config turns it on
+    #[arg(long, overrides_with = "keep_going", hide = true)]
+    no_keep_going: bool,
+    /// Read a workbook whose zip archive is damaged or cut off from the headers of its parts,
+    /// exporting the sheets that can still be decompressed (a cut off sheet up to where it ends)
+    #[arg(long, overrides_with = "no_salvage")]
+    salvage: bool,
+    /// Turn off --salvage, e.g. when the config turns it on
+    #[arg(long, overrides_with = "salvage", hide = true)]
+    no_salvage: bool,
+    /// Read and convert every sheet as usual, but only report the files that would be written
+    /// with their rows and columns; nothing is written, not even the output directory
+    #[arg(long)]
+    dry_run: bool,
+    /// Rewrite cells with a Rhai script defining fn transform(sheet, row, column, header, value),
+    /// which returns the value to write, () to keep it or drop_row() to leave out the row (needs
+    /// the script feature)
+    #[arg(long, value_name = "FILE")]
+    script: Option<PathBuf>,
+    /// Seed for --sample; the same seed picks the same rows from the same sheet [default: 0]
+    #[arg(long, value_name = "S")]
+    seed: Option<u64>,
+    /// How to write plain numbers: exact (as stored, e.g. 0.30000000000000004) or excel
+    /// (General format, 11 significant digits) [default: exact]
+    #[arg(long, value_name = "MODE")]
+    general_precision: Option<GeneralPrecision>,
+    /// Output format: csv, markdown (.md, a GitHub flavored table), html (.html, a <table>) or
+    /// pgcopy (.tsv in PostgreSQL's COPY text format, plus a .sql script that creates the table
+    /// and loads it); the first row of each sheet becomes the table header. cells-json (.jsonl)
+    /// writes a JSON record per cell with a value or formula instead of a table [default: csv]
+    #[arg(long, value_name = "FORMAT")]
+    format: Option<OutputFormat>,
+    /// With --format cells-json, add the fill and font color of every cell as #RRGGBB, theme
+    /// colors resolved with the workbook's theme
+    #[arg(long, overrides_with = "no_colors")]
+    colors: bool,
+    /// Turn off --colors, e.g. when the config turns it on
+    #[arg(long, overrides_with = "colors", hide = true)]
+    no_colors: bool,
+    /// Compress every output file on the fly: gzip (.csv.gz) or zstd (.csv.zst)
+    #[arg(long, value_name = "FORMAT")]
+    compress: Option<Compression>,
+    /// Roll each sheet over into <sheet>.part-0001.csv, <sheet>.part-0002.csv, ... every N rows,
+    /// repeating the sheet's first (header) row at the top of every part
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    split_rows: Option<u64>,
+    /// Like --split-rows, but start a new part before it grows past SIZE (e.g. 500MB, 1GiB) of
+    /// uncompressed CSV
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    split_size: Option<u64>,
+    /// Keep at most SIZE (e.g. 4GiB) of shared strings in memory; a larger string table is
+    /// moved to temp files and read from there, slower but within RAM [default: unlimited]
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    strings_memory: Option<u64>,
+    /// What to do with chartsheets, dialog and macro sheets, which hold no cell data:
+    /// skip them with a warning, or write a placeholder CSV naming the sheet kind [default: skip]
+    #[arg(long, value_name = "MODE")]
+    non_worksheets: Option<NonWorksheets>,
+}
+
+/// How sheets that are not worksheets (chartsheets, dialog and macro sheets) are exported
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum NonWorksheets {
+    /// Leave them out and print a warning
+    #[default]
+    Skip,
+    /// Write a CSV containing just the sheet kind, e.g. "chartsheet"
+    Placeholder,
+}
+
+impl ConvertArgs {
+    /// Fill every option not given on the command line from the config file
+    pub fn apply_config(&mut self, config: &Config) -> Result<()> {
+        let context = |key: &str| format!("invalid {} in config", key);
+        self.out_dir = self.out_dir.take().or_else(|| config.out_dir.clone());
+        if self.delimiter.is_none()
+            && let Some(d) = &config.delimiter
+        {
+            self.delimiter = Some(
+                parse_delimiter(d)
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("delimiter"))?,
+            );
+        }
+        if self.date_epoch.is_none()
+            && let Some(e) = &config.date_epoch
+        {
+            self.date_epoch = Some(
+                parse_date_epoch(e)
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("date-epoch"))?,
+            );
+        }
+        if self.combine.is_none()
+            && let Some(mode) = &config.combine
+        {
+            self.combine = Some(
+                CombineMode::from_str(mode, false)
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("combine"))?,
+            );
+        }
+        if self.name_template.is_none()
+            && let Some(t) = &config.name_template
+        {
+            self.name_template = Some(
+                parse_name_template(t)
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("name-template"))?,
+            );
+        }
+        if self.general_precision.is_none()
+            && let Some(p) = &config.general_precision
+        {
+            self.general_precision = Some(
+                p.parse()
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("general-precision"))?,
+            );
+        }
+        if self.format.is_none()
+            && let Some(f) = &config.format
+        {
+            self.format = Some(
+                f.parse()
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("format"))?,
+            );
+        }
+        if self.compress.is_none()
+            && let Some(c) = &config.compress
+        {
+            self.compress = Some(
+                c.parse()
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("compress"))?,
+            );
+        }
+        if self.non_worksheets.is_none()
+            && let Some(mode) = &config.non_worksheets
+        {
+            self.non_worksheets = Some(
+                NonWorksheets::from_str(mode, false)
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("non-worksheets"))?,
+            );
+        }
+        if self.header.is_none()
+            && let Some(h) = &config.header
+        {
+            self.header = Some(
+                h.parse()
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("header"))?,
+            );
+        }
+        if self.on_missing_string.is_none()
+            && let Some(mode) = &config.on_missing_string
+        {
+            self.on_missing_string = Some(
+                mode.parse()
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("on-missing-string"))?,
+            );
+        }
+        if self.on_out_of_bounds.is_none()
+            && let Some(mode) = &config.on_out_of_bounds
+        {
+            self.on_out_of_bounds = Some(
+                mode.parse()
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("on-out-of-bounds"))?,
+            );
+        }
+        if self.numbers.is_none()
+            && let Some(mode) = &config.numbers
+        {
+            self.numbers = Some(
+                mode.parse()
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("numbers"))?,
+            );
+        }
+        if self.leap_bug.is_none()
+            && let Some(mode) = &config.leap_bug
+        {
+            self.leap_bug = Some(
+                mode.parse()
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("leap-bug"))?,
+            );
+        }
+        if self.timezone.is_none()
+            && let Some(tz) = &config.timezone
+        {
+            self.timezone = Some(
+                tz.parse()
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("timezone"))?,
+            );
+        }
+        if self.date_output.is_none()
+            && let Some(mode) = &config.date_output
+        {
+            self.date_output = Some(
+                mode.parse()
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("date-output"))?,
+            );
+        }
+        if self.units.is_none()
+            && let Some(mode) = &config.units
+        {
+            self.units = Some(
+                mode.parse()
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("units"))?,
+            );
+        }
+        if self.newlines.is_none()
+            && let Some(mode) = &config.newlines
+        {
+            self.newlines = Some(
+                mode.parse()
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("newlines"))?,
+            );
+        }
+        if self.split_size.is_none()
+            && let Some(size) = &config.split_size
+        {
+            self.split_size = Some(
+                parse_size(size)
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("split-size"))?,
+            );
+        }
+        if self.strings_memory.is_none()
+            && let Some(size) = &config.strings_memory
+        {
+            self.strings_memory = Some(
+                parse_size(size)
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("strings-memory"))?,
+            );
+        }
+        if self.row_filter.is_none()
+            && let Some(expr) = &config.r#where
+        {
+            self.row_filter = Some(
+                expr.parse()
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("where"))?,
+            );
+        }
+        if self.types.is_none()
+            && let Some(spec) = &config.types
+        {
+            self.types = Some(
+                spec.parse()
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| context("types"))?,
+            );
+        }
+        if config.split_rows == Some(0) {
+            anyhow::bail!("{}: must be at least 1", context("split-rows"));
+        }
+        self.split_rows = self.split_rows.or(config.split_rows);
+        if config.sample == Some(0) {
+            anyhow::bail!("{}: must be at least 1", context("sample"));
+        }
+        self.sample = self.sample.or(config.sample);
+        self.seed = self.seed.or(config.seed);
+        self.empty_as = self.empty_as.take().or_else(|| config.empty_as.clone());
+        self.pad_to_columns = self.pad_to_columns.or(config.pad_to_columns);
+        self.max_cells = self.max_cells.or(config.max_cells);
+        self.year_window = self.year_window.or(config.year_window);
+        self.threads = self.threads.or(config.threads);
+        self.sheet_threads = self.sheet_threads.or(config.sheet_threads);
+        // A flag or its --no- counterpart wins over the config
+        self.pad_rows = flag(self.pad_rows, self.no_pad_rows, config.pad_rows);
+        self.trailing_delimiter = flag(
+            self.trailing_delimiter,
+            self.no_trailing_delimiter,
+            config.trailing_delimiter,
+        );
+        self.row_numbers = flag(self.row_numbers, self.no_row_numbers, config.row_numbers);
+        self.trim = flag(self.trim, self.no_trim, config.trim);
+        self.skip_blank_rows = flag(
+            self.skip_blank_rows,
+            self.no_skip_blank_rows,
+            config.skip_blank_rows,
+        );
+        self.progress = flag(self.progress, self.no_progress, config.progress);
+        self.debug_cells = flag(self.debug_cells, self.no_debug_cells, config.debug_cells);
+        self.decimal_comma = flag(
+            self.decimal_comma,
+            self.no_decimal_comma,
+            config.decimal_comma,
+        );
+        self.drop_unmapped = flag(
+            self.drop_unmapped,
+            self.no_drop_unmapped,
+            config.drop_unmapped,
+        );
+        self.snake_case = flag(self.snake_case, self.no_snake_case, config.snake_case);
+        self.dedupe = flag(self.dedupe, self.no_dedupe, config.dedupe);
+        self.keep_going = flag(self.keep_going, self.no_keep_going, config.keep_going);
+        self.pipeline = flag(self.pipeline, self.no_pipeline, config.pipeline);
+        self.mmap = flag(self.mmap, self.no_mmap, config.mmap);
+        self.salvage = flag(self.salvage, self.no_salvage, config.salvage);
+        self.colors = flag(self.colors, self.no_colors, config.colors);
+        if self.dedupe_on.is_empty() {
+            self.dedupe_on = config.dedupe_on.clone();
+        }
+        self.rename_file = self
+            .rename_file
+            .take()
+            .or_else(|| config.rename_file.clone());
+        self.script = self.script.take().or_else(|| config.script.clone());
+        if self.rename.is_empty() {
+            self.rename = config
+                .rename
+                .iter()
+                .map(|r| r.parse().map_err(anyhow::Error::msg))
+                .collect::<Result<_>>()
+                .with_context(|| context("rename"))?;
+        }
+        if self.sheets.is_empty() {
+            self.sheets = config
+                .sheets
+                .iter()
+                .map(|p| parse_sheet_pattern(p).map_err(anyhow::Error::msg))
+                .collect::<Result<_>>()
+                .with_context(|| context("sheets"))?;
+        }
+        if self.exclude_sheets.is_empty() {
+            self.exclude_sheets = config
+                .exclude_sheets
+                .iter()
+                .map(|p| parse_sheet_pattern(p).map_err(anyhow::Error::msg))
+                .collect::<Result<_>>()
+                .with_context(|| context("exclude-sheets"))?;
+        }
+        if self.seed.is_some() && self.sample.is_none() {
+            anyhow::bail!("--seed requires --sample");
+        }
+        if self.drop_unmapped && self.rename.is_empty() && self.rename_file.is_none() {
+            anyhow::bail!("--drop-unmapped requires --rename or --rename-file");
+        }
+        if self.trailing_delimiter && self.pad_to_columns.is_none() && !self.pad_rows {
+            anyhow::bail!("--trailing-delimiter requires --pad-to-columns or --pad-rows");
+        }
+        if self.types.as_ref().is_some_and(ColumnTypes::has_dates)
+            && self
+                .date_output
+                .is_some_and(|output| output != DateOutput::Iso)
+        {
+            anyhow::bail!(
+                "--types date and datetime read ISO dates; they cannot be used with --date-output other than iso"
+            );
+        }
+        if self.mmap && self.salvage {
+            anyhow::bail!("--mmap cannot be used with --salvage, which reads the workbook itself");
+        }
+        if self.combine.is_some() && (self.split_rows.is_some() || self.split_size.is_some()) {
+            anyhow::bail!("--split-rows and --split-size cannot be used with --combine");
+        }
+        if self.combine.is_some() && self.format.is_some_and(|f| f != OutputFormat::Csv) {
+            anyhow::bail!("--combine only writes CSV; it cannot be used with --format");
+        }
+        if self.colors && self.format != Some(OutputFormat::CellsJson) {
+            anyhow::bail!("--colors requires --format cells-json");
+        }
+        if self.format == Some(OutputFormat::CellsJson) {
+            let row_options = [
+                ("--delimiter", self.delimiter.is_some()),
+                ("--pad-to-columns", self.pad_to_columns.is_some()),
+                ("--pad-rows", self.pad_rows),
+                ("--trailing-delimiter", self.trailing_delimiter),
+                ("--header", self.header.is_some_and(HeaderRow::is_synthetic)),
+                ("--row-numbers", self.row_numbers),
+                ("--decimal-comma", self.decimal_comma),
+                ("--empty-as", self.empty_as.is_some()),
+                ("--units column", self.units == Some(UnitOutput::Column)),
+                ("--where", self.row_filter.is_some()),
+                ("--types", self.types.is_some()),
+                (
+                    "--rename",
+                    !self.rename.is_empty() || self.rename_file.is_some() || self.snake_case,
+                ),
+                ("--dedupe", self.dedupe || !self.dedupe_on.is_empty()),
+                ("--sample", self.sample.is_some()),
+                ("--split-rows", self.split_rows.is_some()),
+                ("--split-size", self.split_size.is_some()),
+                ("--pipeline", self.pipeline),
+            ];
+            if let Some((name, _)) = row_options.iter().find(|(_, given)| *given) {
+                anyhow::bail!(
+                    "--format cells-json writes cells, not rows; it cannot be used with {}",
+                    name
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The first option given that only changes how or where export writes its files, not what
+    /// they hold; commands that compare with an export rather than write one reject them
+    pub(crate) fn output_only_option(&self) -> Option<&'static str> {
+        [
+            ("--out-dir", self.out_dir.is_some()),
+            ("--combine", self.combine.is_some()),
+            ("--compress", self.compress.is_some()),
+            ("--dry-run", self.dry_run),
+            ("--progress", self.progress),
+            ("--threads", self.threads.is_some()),
+            ("--sheet-threads", self.sheet_threads.is_some()),
+            ("--keep-going", self.keep_going),
+            ("--salvage", self.salvage),
+            ("--script", self.script.is_some()),
+            ("--non-worksheets", self.non_worksheets.is_some()),
+        ]
+        .into_iter()
+        .find(|(_, given)| *given)
+        .map(|(name, _)| name)
+    }
+
+    /// The ColumnMapping of --rename, --rename-file (listed first), --drop-unmapped and
+    /// --snake-case, if any of them is given
+    fn column_mapping(&self) -> Result<Option<ColumnMapping>> {
+        let mut renames = match &self.rename_file {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .with_context(|| format!("read {}", path.display()))?;
+                parse_column_mapping(&text)
+                    .map_err(anyhow::Error::msg)
+                    .with_context(|| format!("invalid rename file {}", path.display()))?
+            }
+            None => Vec::new(),
+        };
+        renames.extend(self.rename.iter().cloned());
+        if renames.is_empty() && !self.snake_case {
+            return Ok(None);
+        }
+        Ok(Some(ColumnMapping {
+            renames,
+            drop_unmapped: self.drop_unmapped,
+            snake_case: self.snake_case,
+        }))
+    }
+
+    pub fn out_dir(&self) -> &Path {
+        self.out_dir.as_deref().unwrap_or(Path::new("."))
+    }
+
+    /// Whether a sheet passes the --sheets/--exclude-sheets filters
+    pub(crate) fn includes_sheet(&self, name: &str) -> bool {
+        (self.sheets.is_empty() || self.sheets.iter().any(|p| p.matches(name)))
+            && !self.exclude_sheets.iter().any(|p| p.matches(name))
+    }
+}
+
+/// A boolean option: on with its flag, off with its --no- flag, else as the config sets it
+fn flag(on: bool, off: bool, config: Option<bool>) -> bool {
+    on || (!off && config.unwrap_or(false))
+}
+
+pub(crate) fn parse_delimiter(s: &str) -> Result<u8, String> {
+    match s {
+        "," => Ok(b','),
+        ";" => Ok(b';'),
+        _ => Err(format!(
+            "Invalid delimiter '{}'. Supported delimiters: ',' (comma) or ';' (semicolon)",
+            s
+        )),
+    }
+}
+
+/// Parse a byte size like 500MB, 1.5GB, 64KiB or 1000000
+/// KB/MB/GB/TB are powers of 1000, KiB/MiB/GiB/TiB powers of 1024; case-insensitive.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid size '{}', expected e.g. 500MB, 1GiB or 1000000", s);
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1000,
+        "m" | "mb" => 1000_u64.pow(2),
+        "g" | "gb" => 1000_u64.pow(3),
+        "t" | "tb" => 1000_u64.pow(4),
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => return Err(invalid()),
+    };
+    let size = (number * multiplier as f64) as u64;
+    if size == 0 {
+        return Err(format!("invalid size '{}', must be at least 1 byte", s));
+    }
+    Ok(size)
+}
+
+fn parse_name_template(s: &str) -> Result<NameTemplate, String> {
+    s.parse()
+}
+
+pub(crate) fn parse_sheet_pattern(s: &str) -> Result<Pattern, String> {
+    Pattern::new(s).map_err(|e| format!("invalid sheet pattern '{}': {}", s, e))
+}
+
+/// Date system selected with --date-epoch
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum DateEpoch {
+    Excel1900,
+    Excel1904,
+    Custom(CustomEpoch),
+}
+
+impl DateEpoch {
+    pub(crate) fn date_system(self) -> Arc<dyn DateSystem> {
+        match self {
+            DateEpoch::Excel1900 => Arc::new(Excel1900),
+            DateEpoch::Excel1904 => Arc::new(Excel1904),
+            DateEpoch::Custom(epoch) => Arc::new(epoch),
+        }
+    }
+}
+
+pub(crate) fn parse_date_epoch(s: &str) -> Result<DateEpoch, String> {
+    match s {
+        "1900" => Ok(DateEpoch::Excel1900),
+        "1904" => Ok(DateEpoch::Excel1904),
+        _ => s.parse().map(DateEpoch::Custom),
+    }
+}
+
+/// Expand glob patterns that don't name an existing file (shells on Windows don't do it for us)
+fn expand_inputs(inputs: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut expanded = Vec::new();
+    for input in inputs {
+        let pattern = input.to_string_lossy();
+        if input.exists()
+            || is_url(&pattern)
+            || is_object_url(&pattern)
+            || !pattern.contains(['*', '?', '['])
+        {
+            expanded.push(input.clone());
+            continue;
+        }
+        let mut matches: Vec<PathBuf> = glob::glob(&pattern)
+            .with_context(|| format!("invalid glob pattern {}", pattern))?
+            .collect::<Result<_, _>>()?;
+        if matches.is_empty() {
+            anyhow::bail!("no files match {}", pattern);
+        }
+        matches.sort();
+        expanded.extend(matches);
+    }
+    Ok(expanded)
+}
+
+/// Export every input workbook; a single input keeps the flat <DIR>/<sheet>.csv layout
+/// With stats, what was written (also for failed workbooks) is recorded in that file. An object
+/// store output directory (s3://bucket/out/) is written to a temp dir and uploaded at the end.
+pub fn run(inputs: Vec<PathBuf>, args: &ConvertArgs, stats: Option<&Path>) -> Result<()> {
+    let inputs = expand_inputs(&inputs)?;
+    if inputs.is_empty() {
+        anyhow::bail!("no input workbook given");
+    }
+    let out_url = args.out_dir().to_string_lossy().into_owned();
+    let staging = if is_object_url(&out_url) && !args.dry_run {
+        if !cfg!(feature = "object-store") {
+            anyhow::bail!(
+                "Cannot write to {}: object store URLs need xcsv built with the object-store feature",
+                out_url
+            );
+        }
+        if args.format == Some(OutputFormat::PgCopy) {
+            anyhow::bail!(
+                "--format pgcopy writes psql scripts with local paths, it cannot be uploaded"
+            );
+        }
+        Some(tempfile::tempdir().context("create temp directory")?)
+    } else {
+        None
+    };
+    let out_dir = staging.as_ref().map_or(args.out_dir(), |dir| dir.path());
+
+    let (workbooks, error) = export_all(&inputs, out_dir, args);
+    // What was written is uploaded even when some of it failed, like it is kept on disk
+    if let Some(dir) = &staging {
+        for url in upload_dir(dir.path(), &out_url)? {
+            tracing::info!("uploaded {}", url);
+        }
+    }
+    if let Some(path) = stats {
+        let location = |file: &Path| match &staging {
+            Some(dir) => object_url(&out_url, file.strip_prefix(dir.path()).unwrap_or(file)),
+            None => file.display().to_string(),
+        };
+        write_stats(path, &workbooks, &location)?;
+    }
+    if let Some(e) = error {
+        return Err(e);
+    }
+    check_failures(&workbooks, args.keep_going)
+}
+
+/// Export the inputs into out_dir, returning what was written for every workbook
+/// The error is that of a single input failing, or why nothing could be exported at all.
+fn export_all(
+    inputs: &[PathBuf],
+    out_dir: &Path,
+    args: &ConvertArgs,
+) -> (Vec<WorkbookStats>, Option<anyhow::Error>) {
+    let failed = |input: &Path, e: &anyhow::Error| WorkbookStats {
+        workbook: input.to_path_buf(),
+        error: Some(format!("{:#}", e)),
+        ..WorkbookStats::default()
+    };
+    if let [input] = inputs {
+        return match export_workbook(input, out_dir, args, &MultiProgress::new()) {
+            Ok(workbook) => (vec![workbook], None),
+            Err(e) => (vec![failed(input, &e)], Some(e)),
+        };
+    }
+
+    let mut targets: Vec<(PathBuf, PathBuf)> = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let Some(stem) = input.file_stem() else {
+            let e = anyhow::anyhow!("{} has no file name", input.display());
+            return (Vec::new(), Some(e));
+        };
+        let workbook_dir = out_dir.join(stem);
+        if let Some((other, _)) = targets.iter().find(|(_, dir)| *dir == workbook_dir) {
+            let e = anyhow::anyhow!(
+                "{} and {} would both be written to {}",
+                other.display(),
+                input.display(),
+                workbook_dir.display()
+            );
+            return (Vec::new(), Some(e));
+        }
+        targets.push((input.clone(), workbook_dir));
+    }
+
+    let multi = MultiProgress::new();
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..args.threads.unwrap_or(1).clamp(1, targets.len()) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some((input, out_dir)) = targets.get(index) else {
+                        break;
+                    };
+                    let workbook = match export_workbook(input, out_dir, args, &multi) {
+                        Ok(workbook) => workbook,
+                        Err(e) => {
+                            log::error("export-failed", format!("{:#}", e))
+                                .workbook(input)
+                                .emit();
+                            failed(input, &e)
+                        }
+                    };
+                    results.lock().unwrap().push((index, workbook));
+                }
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    (results.into_iter().map(|(_, w)| w).collect(), None)
+}
+
+/// Exit code of a --keep-going run where some sheets or workbooks failed and others did not
+pub const PARTIAL_FAILURE_EXIT_CODE: i32 = 2;
+
+/// Error of a --keep-going run that exported some sheets but not all of them; main exits with
+/// PARTIAL_FAILURE_EXIT_CODE on it
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct PartialFailure(String);
+
+/// Open the archive of a workbook, memory-mapped with --mmap
+fn open_workbook(xlsx_path: &Path, args: &ConvertArgs) -> Result<ZipArchive<WorkbookReader>> {
+    if args.mmap {
+        open_zip_mmap(xlsx_path)
+    } else {
+        open_zip(xlsx_path)
+    }
+}
+
+/// Report what --salvage found in a damaged archive; nothing if it opened as is
+fn report_salvaged_parts(xlsx_path: &Path, parts: &[SalvagedPart]) {
+    if parts.is_empty() {
+        return;
+    }
+    let recovered = parts.iter().filter(|p| p.bytes > 0).count();
+    log::warning(
+        "salvage",
+        format!(
+            "damaged zip archive, recovered {} of the {} parts found",
+            recovered,
+            parts.len()
+        ),
+    )
+    .workbook(xlsx_path)
+    .emit();
+    for part in parts.iter().filter(|p| p.bytes == 0) {
+        log::warning(
+            "salvage",
+            format!(
+                "lost {} ({})",
+                part.name,
+                part.problem.as_deref().unwrap_or("empty")
+            ),
+        )
+        .workbook(xlsx_path)
+        .emit();
+    }
+}
+
+/// Report which sheets of a salvaged archive were recovered whole and which only in part
+fn report_salvaged_sheets(xlsx_path: &Path, sheets: &[SheetInfo], parts: &[SalvagedPart]) {
+    for sheet in sheets {
+        match parts
+            .iter()
+            .find(|p| p.name == sheet.path_in_zip)
+            .and_then(|p| p.problem.as_deref())
+        {
+            Some(problem) => {
+                log::warning("salvage", format!("sheet recovered in part: {}", problem))
+                    .workbook(xlsx_path)
+                    .sheet(&sheet.name)
+                    .emit()
+            }
+            None => tracing::info!("recovered sheet {}", sheet.name),
+        }
+    }
+}
+
+/// Fail when a workbook or sheet failed; with keep_going and something exported, with a
+/// PartialFailure
+fn check_failures(workbooks: &[WorkbookStats], keep_going: bool) -> Result<()> {
+    let failed_workbooks = workbooks.iter().filter(|w| w.error.is_some()).count();
+    let failed_sheets: usize = workbooks.iter().map(WorkbookStats::failed_sheets).sum();
+    let mut problems = Vec::new();
+    if failed_workbooks > 0 {
+        problems.push(format!(
+            "{} of {} workbooks failed to convert",
+            failed_workbooks,
+            workbooks.len()
+        ));
+    }
+    if failed_sheets > 0 {
+        let sheets: usize = workbooks.iter().map(|w| w.sheets.len()).sum();
+        problems.push(format!(
+            "{} of {} sheets failed to export",
+            failed_sheets, sheets
+        ));
+    }
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let exported = workbooks
+        .iter()
+        .flat_map(|w| &w.sheets)
+        .any(|s| s.error.is_none());
+    if keep_going && exported {
+        return Err(PartialFailure(problems.join("; ")).into());
+    }
+    anyhow::bail!("{}", problems.join("; "))
+}
+
+/// Export all sheets of one workbook into out_dir, returning what was written
+pub fn export_workbook(
+    xlsx_path: &Path,
+    out_dir: &Path,
+    args: &ConvertArgs,
+    multi: &MultiProgress,
+) -> Result<WorkbookStats> {
+    let _span = tracing::debug_span!("workbook", path = %xlsx_path.display()).entered();
+    let mut stats = WorkbookStats {
+        workbook: xlsx_path.to_path_buf(),
+        ..WorkbookStats::default()
+    };
+    let (mut zip, salvaged) = if args.salvage {
+        let (zip, salvaged) = open_zip_salvage(xlsx_path)?;
+        report_salvaged_parts(xlsx_path, &salvaged);
+        (zip, salvaged)
+    } else {
+        (open_workbook(xlsx_path, args)?, Vec::new())
+    };
+    if !args.dry_run {
+        std::fs::create_dir_all(out_dir).context("create output directory")?;
+    }
+    let mut options = export_options(args)?;
+
+    let script = match &args.script {
+        Some(path) => Some(Arc::new(Script::load(path)?)),
+        None => None,
+    };
+
+    // Locate the workbook parts through the package's content types and relationships
+    let parts = read_package_parts(&mut zip)?;
+
+    // Stream-parse shared strings if present, spilling them to disk past --strings-memory
+    let shared_strings = match &parts.shared_strings {
+        Some(part) => {
+            let f = zip
+                .by_name(part)
+                .with_context(|| format!("missing {}", part))?;
+            let budget = args.strings_memory.map_or(usize::MAX, |size| {
+                usize::try_from(size).unwrap_or(usize::MAX)
+            });
+            read_shared_strings_within(BufReader::new(f), budget)?
+        }
+        None => SharedStrings::default(),
+    };
+    if shared_strings.is_spilled() {
+        tracing::info!(
+            "{} shared strings spilled to disk (over --strings-memory)",
+            shared_strings.string_count()
+        );
+    }
+
+    // Stream-parse styles if present
+    let styles: Vec<StyleInfo> = match &parts.styles {
+        Some(part) => {
+            let f = zip
+                .by_name(part)
+                .with_context(|| format!("missing {}", part))?;
+            parse_styles(BufReader::new(f))?
+        }
+        None => Vec::new(),
+    };
+
+    // The theme colors of fills and fonts are only resolved for --colors
+    let theme = match &parts.theme {
+        _ if !args.colors => None,
+        Some(part) => {
+            let f = zip
+                .by_name(part)
+                .with_context(|| format!("missing {}", part))?;
+            Some(parse_theme(BufReader::new(f))?)
+        }
+        None => Some(ThemeColors::default()),
+    };
+
+    // Workbook rels and sheets, narrowed down by --sheets/--exclude-sheets
+    let (sheets, is_1904) = read_workbook(&mut zip, &parts)?;
+    if !salvaged.is_empty() {
+        report_salvaged_sheets(xlsx_path, &sheets, &salvaged);
+    }
+    let sheet_count = sheets.len();
+    let (positions, sheets): (Vec<usize>, Vec<SheetInfo>) = sheets
+        .into_iter()
+        .enumerate()
+        .filter(|(_, s)| args.includes_sheet(&s.name))
+        .unzip();
+    if sheets.is_empty() && sheet_count > 0 {
+        anyhow::bail!("none of the {} sheets match the sheet filters", sheet_count);
+    }
+
+    // Chartsheets and friends have no cells; with --combine there is nowhere to put a placeholder
+    let keep_placeholders = args.non_worksheets.unwrap_or_default() == NonWorksheets::Placeholder
+        && args.combine.is_none();
+    let (positions, sheets): (Vec<usize>, Vec<SheetInfo>) = positions
+        .into_iter()
+        .zip(sheets)
+        .filter(|(_, sheet)| {
+            let skip = sheet.kind != SheetKind::Worksheet && !keep_placeholders;
+            if skip {
+                log::warning(
+                    "non-worksheet",
+                    format!("skipping {} (no cell data)", sheet.kind),
+                )
+                .sheet(&sheet.name)
+                .emit();
+            }
+            !skip
+        })
+        .unzip();
+
+    if let Some(max) = args.max_cells {
+        check_max_cells(&sheets, &read_sheet_metas(&mut zip, &sheets)?, max)?;
+    }
+
+    let workbook = match xlsx_path.file_stem().and_then(|s| s.to_str()) {
+        Some("-") | None => "stdin".to_string(),
+        Some(stem) => stem.to_string(),
+    };
+    // The default template ends in the extension of the output format (e.g. {slug}.md)
+    let default_template = match options.format {
+        OutputFormat::Csv => NameTemplate::default(),
+        format => format!("{{slug}}.{}", format.extension())
+            .parse()
+            .map_err(anyhow::Error::msg)?,
+    };
+    let file_names = sheet_file_names(
+        &sheets,
+        &positions,
+        args.name_template.as_ref().unwrap_or(&default_template),
+        &workbook,
+    )?
+    .into_iter()
+    .map(|name| compressed_name(name, args.compress.unwrap_or_default()))
+    .collect::<Vec<_>>();
+
+    // With --combine the sheets are written to a temp dir first and merged afterwards
+    let combine_dir = match args.combine {
+        Some(_) => Some(tempfile::tempdir().context("create temp directory")?),
+        None => None,
+    };
+    let mut parts = Vec::new();
+    let compression = options.compression;
+    let jobs: Vec<(SheetInfo, PathBuf)> = sheets
+        .into_iter()
+        .zip(file_names)
+        .enumerate()
+        .map(|(index, (sheet, file_name))| {
+            let out_path = match &combine_dir {
+                Some(dir) => dir.path().join(format!("{}.csv", index)),
+                None => out_dir.join(file_name),
+            };
+            (sheet, out_path)
+        })
+        .collect();
+    let context = SheetContext {
+        xlsx_path,
+        args,
+        shared_strings: &shared_strings,
+        styles: &styles,
+        theme: theme.as_ref(),
+        is_1904,
+        script: script.as_ref(),
+        multi,
+    };
+    // With --keep-going a failed sheet is reported and the others are still exported
+    let mut report = |sheet: &SheetInfo, result: Result<Converted>| -> Result<()> {
+        let _span = tracing::debug_span!("sheet", name = %sheet.name).entered();
+        let result = result.and_then(|converted| {
+            finish_sheet(
+                sheet,
+                converted,
+                args,
+                combine_dir.is_some(),
+                compression,
+                &mut parts,
+            )
+        });
+        match result {
+            Ok(sheet_stats) => stats.sheets.push(sheet_stats),
+            Err(e) if args.keep_going => {
+                log::error("sheet-failed", format!("{:#}", e))
+                    .workbook(xlsx_path)
+                    .sheet(&sheet.name)
+                    .emit();
+                stats.sheets.push(SheetStats {
+                    sheet: sheet.name.clone(),
+                    error: Some(format!("{:#}", e)),
+                    ..SheetStats::default()
+                });
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    };
+
+    // Export each sheet, with --sheet-threads on archives of their own
+    let threads = match args.sheet_threads.unwrap_or(1).min(jobs.len()) {
+        n if n > 1 && script.is_some() => {
+            tracing::debug!("--script runs one sheet at a time, ignoring --sheet-threads");
+            1
+        }
+        n if n > 1 && (args.salvage || !is_local_file(xlsx_path)) => {
+            tracing::debug!("only a local file can be read by several threads");
+            1
+        }
+        n => n.max(1),
+    };
+    if threads > 1 {
+        let mut zips = vec![zip];
+        for _ in 1..threads {
+            zips.push(open_workbook(xlsx_path, args)?);
+        }
+        convert_parallel(&context, &jobs, zips, &mut report)?;
+    } else {
+        for (sheet, out_path) in &jobs {
+            let result = convert_sheet(&context, &mut zip, sheet, out_path, &mut options);
+            report(sheet, result)?;
+        }
+    }
+
+    if let Some(mode) = args.combine {
+        let compression = args.compress.unwrap_or_default();
+        let out_path = out_dir.join(compressed_name(format!("{}.csv", workbook), compression));
+        if args.dry_run {
+            tracing::info!("would write {:?} (from {} sheets)", out_path, parts.len());
+            return Ok(stats);
+        }
+        let rows = combine_sheets(
+            &parts,
+            mode,
+            options.delimiter,
+            options.empty_as.as_deref().unwrap_or(""),
+            &out_path,
+            compression,
+        )?;
+        tracing::info!(
+            "wrote {:?} ({} rows from {} sheets)",
+            out_path,
+            rows,
+            parts.len()
+        );
+        stats.files.push(out_path);
+    }
+    Ok(stats)
+}
+
+/// The ExportOptions of args; the per-sheet callbacks are set by convert_sheet
+pub(crate) fn export_options(args: &ConvertArgs) -> Result<ExportOptions> {
+    Ok(ExportOptions {
+        delimiter: args
+            .delimiter
+            .unwrap_or(if args.decimal_comma { b';' } else { b',' }),
+        pad_to_columns: args.pad_to_columns,
+        trailing_delimiter: args.trailing_delimiter,
+        header: args.header.unwrap_or_default(),
+        row_numbers: args.row_numbers,
+        trim: args.trim,
+        skip_blank_rows: args.skip_blank_rows,
+        debug_cells: args.debug_cells,
+        on_missing_string: args.on_missing_string.unwrap_or_default(),
+        on_out_of_bounds: args.on_out_of_bounds.unwrap_or_default(),
+        numbers: args.numbers.unwrap_or_default(),
+        units: args.units.unwrap_or_default(),
+        year_window: args.year_window,
+        decimal_comma: args.decimal_comma,
+        newlines: args.newlines.unwrap_or_default(),
+        empty_as: args.empty_as.clone(),
+        general_precision: args.general_precision.unwrap_or_default(),
+        // With --combine only the combined file is compressed, not the intermediate sheets
+        compression: match args.combine {
+            Some(_) => Compression::None,
+            None => args.compress.unwrap_or_default(),
+        },
+        format: args.format.unwrap_or_default(),
+        split_rows: args.split_rows,
+        split_size: args.split_size,
+        row_filter: args.row_filter.clone(),
+        column_types: args.types.clone(),
+        column_mapping: args.column_mapping()?,
+        dedupe: (args.dedupe || !args.dedupe_on.is_empty()).then(|| args.dedupe_on.clone()),
+        sample: args.sample.map(|n| n as usize),
+        sample_seed: args.seed.unwrap_or_default(),
+        dry_run: args.dry_run,
+        date_system: args.date_epoch.map(DateEpoch::date_system),
+        leap_bug: args.leap_bug.unwrap_or_default(),
+        timezone: args.timezone.unwrap_or_default(),
+        date_output: args.date_output.unwrap_or_default(),
+        pipeline: args.pipeline,
+        ..ExportOptions::default()
+    })
+}
+
+/// What the sheets of a workbook are converted with
+/// theme: The workbook's theme colors, read for --colors
+/// script: The --script, shared by all sheets
+/// multi: Where the --progress bars go
+struct SheetContext<'a> {
+    xlsx_path: &'a Path,
+    args: &'a ConvertArgs,
+    shared_strings: &'a SharedStrings,
+    styles: &'a [StyleInfo],
+    theme: Option<&'a ThemeColors>,
+    is_1904: bool,
+    script: Option<&'a Arc<Script>>,
+    multi: &'a MultiProgress,
+}
+
+/// What convert_sheet wrote for a sheet
+enum Converted {
+    /// A placeholder naming the kind of a sheet without cells
+    Placeholder(PathBuf),
+    /// The rows of a worksheet, written to out_path (and its split parts)
+    Rows(PathBuf, ExportSummary),
+}
+
+/// Whether xlsx_path is a file that every --sheet-threads thread can open on its own, rather
+/// than stdin or a URL
+fn is_local_file(xlsx_path: &Path) -> bool {
+    let path = xlsx_path.to_string_lossy();
+    xlsx_path != Path::new("-") && !is_url(&path) && !is_object_url(&path)
+}
+
+/// Set the options of args that depend on the sheet: the header row found with --header auto
+/// and the width --pad-rows pads to
+pub(crate) fn set_sheet_layout<R: Read + Seek>(
+    args: &ConvertArgs,
+    zip: &mut ZipArchive<R>,
+    sheet: &SheetInfo,
+    options: &mut ExportOptions,
+) -> Result<()> {
+    if options.header == HeaderRow::Auto {
+        options.header_row = find_header_row(zip, &sheet.path_in_zip)?;
+    }
+    if args.pad_rows && args.pad_to_columns.is_none() {
+        options.pad_to_columns = Some(read_sheet_width(zip, &sheet.path_in_zip)? as usize);
+    }
+    Ok(())
+}
+
+/// Convert one sheet of zip to out_path; reporting what was written is left to finish_sheet
+fn convert_sheet(
+    context: &SheetContext,
+    zip: &mut ZipArchive<WorkbookReader>,
+    sheet: &SheetInfo,
+    out_path: &Path,
+    options: &mut ExportOptions,
+) -> Result<Converted> {
+    let _span = tracing::debug_span!("sheet", name = %sheet.name).entered();
+    let args = context.args;
+    if sheet.kind != SheetKind::Worksheet {
+        if !args.dry_run {
+            let mut out = OutputWriter::create(out_path, options.compression)?;
+            let written = writeln!(out, "{}", sheet.kind)
+                .with_context(|| format!("write {}", out_path.display()))
+                .and_then(|_| out.finish());
+            if let Err(e) = written {
+                remove_outputs(&[out_path.to_path_buf()]);
+                return Err(e);
+            }
+        }
+        return Ok(Converted::Placeholder(out_path.to_path_buf()));
+    }
+    options.colors = context.theme.cloned();
+    set_sheet_layout(args, zip, sheet, options)?;
+    let f = zip
+        .by_name(&sheet.path_in_zip)
+        .with_context(|| format!("missing {}", sheet.path_in_zip))?;
+    let reader = BufReader::new(f);
+    let bar = if args.progress {
+        let (bar, callback) = sheet_progress(&sheet.name);
+        options.progress = Some(callback);
+        Some(context.multi.add(bar))
+    } else {
+        None
+    };
+    let sheet_name = sheet.name.clone();
+    let workbook_path = context.xlsx_path.to_path_buf();
+    options.on_warning = Some(Arc::new(move |w: &ExportWarning| {
+        log::warning(w.kind.code(), w.message.as_str())
+            .workbook(&workbook_path)
+            .sheet(&sheet_name)
+            .cell(w.cell.as_deref())
+            .emit();
+    }));
+    options.cell_transform = context.script.map(|s| s.transform(&sheet.name));
+    let summary = export_sheet_xml_to_csv(
+        reader,
+        context.shared_strings,
+        context.styles,
+        context.is_1904,
+        out_path,
+        options,
+    )
+    .with_context(|| format!("export sheet '{}'", sheet.name))?;
+    if let Some(bar) = bar {
+        bar.finish_and_clear();
+    }
+    Ok(Converted::Rows(out_path.to_path_buf(), summary))
+}
+
+/// Convert the sheets of jobs on a thread per archive in zips (each opened from the same
+/// workbook), handing every result to report in sheet order as soon as the sheets before it
+/// are done. No further sheets are started once report fails.
+fn convert_parallel(
+    context: &SheetContext,
+    jobs: &[(SheetInfo, PathBuf)],
+    zips: Vec<ZipArchive<WorkbookReader>>,
+    report: &mut dyn FnMut(&SheetInfo, Result<Converted>) -> Result<()>,
+) -> Result<()> {
+    let options = export_options(context.args)?;
+    let workbook_span = tracing::Span::current();
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for mut zip in zips {
+            let mut options = options.clone();
+            let sender = sender.clone();
+            let (workbook_span, next, stop) = (&workbook_span, &next, &stop);
+            scope.spawn(move || {
+                let _span = workbook_span.enter();
+                while !stop.load(Ordering::Relaxed) {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some((sheet, out_path)) = jobs.get(index) else {
+                        break;
+                    };
+                    let result = convert_sheet(context, &mut zip, sheet, out_path, &mut options);
+                    if sender.send((index, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let mut done = BTreeMap::new();
+        let mut reported = 0;
+        for (index, result) in receiver {
+            done.insert(index, result);
+            while let Some(result) = done.remove(&reported) {
+                if let Err(e) = report(&jobs[reported].0, result) {
+                    stop.store(true, Ordering::Relaxed);
+                    return Err(e);
+                }
+                reported += 1;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Log what convert_sheet wrote for sheet and return its stats; with combining the rows are
+/// added to parts for merging instead
+fn finish_sheet(
+    sheet: &SheetInfo,
+    converted: Converted,
+    args: &ConvertArgs,
+    combining: bool,
+    compression: Compression,
+    parts: &mut Vec<(String, PathBuf)>,
+) -> Result<SheetStats> {
+    let (out_path, summary) = match converted {
+        Converted::Placeholder(out_path) => {
+            tracing::info!(
+                "{} {:?} (placeholder for {} '{}')",
+                if args.dry_run { "would write" } else { "wrote" },
+                out_path,
+                sheet.kind,
+                sheet.name
+            );
+            return Ok(SheetStats {
+                sheet: sheet.name.clone(),
+                files: vec![out_path],
+                ..SheetStats::default()
+            });
+        }
+        Converted::Rows(out_path, summary) => (out_path, summary),
+    };
+    let mut sheet_stats = SheetStats::new(&sheet.name, &summary);
+    if combining {
+        // Only the combined file is left once the sheets are merged
+        sheet_stats.files.clear();
+        if args.dry_run {
+            tracing::info!(
+                "would combine {} ({} rows, {} columns)",
+                sheet.name,
+                summary.rows_written,
+                summary.columns
+            );
+        }
+        parts.push((sheet.name.clone(), out_path));
+    } else if args.dry_run {
+        let files = match summary.files.as_slice() {
+            [file] => format!("{:?}", file),
+            files => format!("{:?} and {} more part(s)", files[0], files.len() - 1),
+        };
+        tracing::info!(
+            "would write {} ({} rows, {} columns)",
+            files,
+            summary.rows_written,
+            summary.columns
+        );
+        if summary.schema.is_some() {
+            let script = pgcopy_script_path(&out_path, compression);
+            tracing::info!("would write {:?}", script);
+        }
+    } else {
+        for file in &summary.files {
+            tracing::info!("wrote {:?}", file);
+        }
+        if let Some(schema) = &summary.schema {
+            let script =
+                write_pgcopy_script(&out_path, &sheet.name, schema, &summary.files, compression)
+                    .inspect_err(|_| {
+                        let script = pgcopy_script_path(&out_path, compression);
+                        remove_outputs(&summary.files);
+                        remove_outputs(&[script]);
+                    })?;
+            tracing::info!("wrote {:?}", script);
+            sheet_stats.files.push(script);
+        }
+    }
+    if summary.duplicates > 0 {
+        tracing::info!(
+            "  {} duplicate row(s) skipped in {}",
+            summary.duplicates,
+            sheet.name
+        );
+    }
+    if summary.warning_count() > 0 {
+        let counts: Vec<String> = summary
+            .warnings
+            .iter()
+            .map(|(kind, n)| format!("{} {}", n, kind.code()))
+            .collect();
+        tracing::info!(
+            "  {} warning(s) in {}: {}",
+            summary.warning_count(),
+            sheet.name,
+            counts.join(", ")
+        );
+    }
+    Ok(sheet_stats)
+}
+
+/// Remove the files of a sheet that failed after writing them, so it leaves no partial output
+fn remove_outputs(files: &[PathBuf]) {
+    for file in files {
+        match std::fs::remove_file(file) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                tracing::warn!("could not remove {}: {}", file.display(), e);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Write a psql script next to the pgcopy output of a sheet (out/orders.tsv -> out/orders.sql)
+/// It creates the table inferred from the rows and loads every file written with \copy, so
+/// `psql -f out/orders.sql` loads the sheet from any working directory.
+fn write_pgcopy_script(
+    out_path: &Path,
+    sheet: &str,
+    schema: &SheetSchema,
+    files: &[PathBuf],
+    compression: Compression,
+) -> Result<PathBuf> {
+    let script_path = pgcopy_script_path(out_path, compression);
+    let table = quote_ident(&table_name(sheet));
+    let mut script = format!(
+        "-- Sheet '{}', exported by xcsv\n{}",
+        sheet,
+        create_table_sql(&table_name(sheet), schema)
+    );
+    for file in files {
+        let file = std::fs::canonicalize(file)
+            .with_context(|| format!("resolve {}", file.display()))?
+            .display()
+            .to_string();
+        let source = match compression {
+            Compression::None => sql_literal(&file),
+            Compression::Gzip => format!(
+                "PROGRAM {}",
+                sql_literal(&format!("gzip -dc {}", shell_quote(&file)))
+            ),
+            Compression::Zstd => format!(
+                "PROGRAM {}",
+                sql_literal(&format!("zstd -dc {}", shell_quote(&file)))
+            ),
+        };
+        script.push_str(&format!("\\copy {} FROM {}\n", table, source));
+    }
+    std::fs::write(&script_path, script)
+        .with_context(|| format!("write {}", script_path.display()))?;
+    Ok(script_path)
+}
+
+/// The psql script of a pgcopy output, e.g. out/orders.tsv.gz -> out/orders.sql
+fn pgcopy_script_path(out_path: &Path, compression: Compression) -> PathBuf {
+    let base = match compression.extension() {
+        Some(_) => out_path.with_extension(""),
+        None => out_path.to_path_buf(),
+    };
+    base.with_extension("sql")
+}
+
+/// A single quoted SQL string literal
+fn sql_literal(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// A single quoted shell word
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Append the extension of compression (e.g. ".gz") to a file name
+fn compressed_name(name: String, compression: Compression) -> String {
+    match compression.extension() {
+        Some(ext) => format!("{}.{}", name, ext),
+        None => name,
+    }
+}
+
+/// Render the output file name of every sheet and make sure no two sheets share one
+/// Names are compared case-insensitively since common file systems are.
+/// positions are the 0-based positions of the sheets in the workbook.
+fn sheet_file_names(
+    sheets: &[SheetInfo],
+    positions: &[usize],
+    template: &NameTemplate,
+    workbook: &str,
+) -> Result<Vec<String>> {
+    let names: Vec<String> = sheets
+        .iter()
+        .zip(positions)
+        .map(|(sheet, &pos)| template.render(&sheet.name, pos + 1, workbook))
+        .collect();
+    for (i, name) in names.iter().enumerate() {
+        if let Some(j) = names[..i]
+            .iter()
+            .position(|other| other.to_lowercase() == name.to_lowercase())
+        {
+            anyhow::bail!(
+                "sheets '{}' and '{}' would both be written to {}; use a --name-template with {{index}} or {{sheet}} to tell them apart",
+                sheets[j].name,
+                sheets[i].name,
+                name
+            );
+        }
+    }
+    Ok(names)
+}
+
+/// Create a progress bar for a sheet together with the callback that drives it
+fn sheet_progress(sheet_name: &str) -> (ProgressBar, ProgressCallback) {
+    let bar = ProgressBar::new_spinner().with_prefix(sheet_name.to_string());
+    bar.set_style(
+        ProgressStyle::with_template(
+            "{spinner} {prefix} [{elapsed_precise}] {bar:30} {pos}/{len} rows, {msg}",
+        )
+        .expect("valid progress template"),
+    );
+    let cb_bar = bar.clone();
+    let callback: ProgressCallback = Arc::new(move |p: &ExportProgress| {
+        if let Some(total) = p.estimated_rows
+            && cb_bar.length().is_none()
+        {
+            cb_bar.set_length(total as u64);
+        }
+        cb_bar.set_position(p.rows_written);
+        match p.percent() {
+            Some(percent) => cb_bar.set_message(format!(
+                "{:.0}%, {} read",
+                percent,
+                HumanBytes(p.bytes_read)
+            )),
+            None => cb_bar.set_message(format!("{} read", HumanBytes(p.bytes_read))),
+        }
+    });
+    (bar, callback)
+}
+
+/// Estimate the total cell count from the sheet dimensions and fail if it exceeds max
+/// Sheets without a dimension can't be estimated and are left out of the total.
+fn check_max_cells(sheets: &[SheetInfo], metas: &[SheetMeta], max: u64) -> Result<()> {
+    let mut total: u64 = 0;
+    let mut unknown = Vec::new();
+    for (sheet, meta) in sheets.iter().zip(metas) {
+        match meta.cell_count() {
+            Some(cells) => total = total.saturating_add(cells),
+            None => unknown.push(sheet.name.as_str()),
+        }
+    }
+    if !unknown.is_empty() {
+        log::warning(
+            "no-dimension",
+            format!(
+                "no dimension for sheet(s) {}; not included in the cell estimate",
+                unknown.join(", ")
+            ),
+        )
+        .emit();
+    }
+    if total > max {
+        anyhow::bail!(
+            "workbook has an estimated {} cells, which exceeds --max-cells {}",
+            total,
+            max
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        convert: ConvertArgs,
+    }
+
+    #[test]
+    fn test_flags_win_over_config() {
+        let config: Config = toml::from_str("trim = true\nrow-numbers = false").unwrap();
+        let args = |flags: &[&str]| {
+            let mut cli = Cli::try_parse_from([&["xcsv"], flags].concat()).unwrap();
+            cli.convert.apply_config(&config).unwrap();
+            (cli.convert.trim, cli.convert.row_numbers)
+        };
+        assert_eq!(args(&[]), (true, false));
+        assert_eq!(args(&["--no-trim", "--row-numbers"]), (false, true));
+        // The last of a flag and its --no- flag wins
+        assert_eq!(args(&["--no-trim", "--trim"]), (true, false));
+        assert_eq!(args(&["--row-numbers", "--no-row-numbers"]), (true, false));
+    }
+}
@@ -0,0 +1,160 @@
+use std::io::{self, BufReader, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::Args;
+use libxcsv::{
+    DateOutput, ExportOptions, SheetKind, Timezone, for_each_formula, index_to_col, open_zip,
+    read_package_parts, read_strings_and_styles, read_workbook,
+};
+
+use crate::export::{DateEpoch, parse_date_epoch};
+use crate::grep::quote_sheet_name;
+use crate::until_broken_pipe;
+use crate::view::find_sheet;
+
+#[derive(Args, Debug, Clone)]
+pub struct FormulasArgs {
+    /// Only list the formulas of this sheet [default: every worksheet]
+    #[arg(value_name = "SHEET")]
+    sheet: Option<String>,
+    /// Date system for cached dates, like for export [default: the workbook's own]
+    #[arg(long, value_name = "EPOCH", value_parser = parse_date_epoch)]
+    date_epoch: Option<DateEpoch>,
+    /// Timezone of cached dates, like for export [default: utc]
+    #[arg(long, value_name = "TZ")]
+    timezone: Option<Timezone>,
+    /// How cached dates are written, like for export [default: iso]
+    #[arg(long, value_name = "MODE")]
+    date_output: Option<DateOutput>,
+}
+
+/// Print every formula cell as CSV: cell (as Sheet!A1), formula and cached_value, the result
+/// Excel stored when the workbook was last calculated, written like export writes the cell
+/// Formulas are written without the leading '='; cells filled from a shared formula get it
+/// translated to their position. Cells holding a plain value among formulas are the
+/// hard-coded overrides auditors look for: they are simply absent from the list.
+pub fn run(xlsx_path: &Path, args: &FormulasArgs) -> Result<()> {
+    until_broken_pipe(list(xlsx_path, args, io::stdout().lock()))?;
+    Ok(())
+}
+
+fn list(xlsx_path: &Path, args: &FormulasArgs, out: impl Write) -> Result<()> {
+    let mut zip = open_zip(xlsx_path)?;
+    let parts = read_package_parts(&mut zip)?;
+    let (sheets, is_1904) = read_workbook(&mut zip, &parts)?;
+    let selected: Vec<_> = match &args.sheet {
+        Some(name) => {
+            let sheet = find_sheet(&sheets, name)?;
+            if sheet.kind != SheetKind::Worksheet {
+                anyhow::bail!("'{}' is a {} and has no cells", sheet.name, sheet.kind);
+            }
+            vec![sheet]
+        }
+        None => sheets
+            .iter()
+            .filter(|s| s.kind == SheetKind::Worksheet)
+            .collect(),
+    };
+    let (shared_strings, styles) = read_strings_and_styles(&mut zip, &parts)?;
+    let options = ExportOptions {
+        date_system: args.date_epoch.map(DateEpoch::date_system),
+        timezone: args.timezone.unwrap_or_default(),
+        date_output: args.date_output.unwrap_or_default(),
+        ..ExportOptions::default()
+    };
+
+    let mut wtr = csv::Writer::from_writer(out);
+    wtr.write_record(["cell", "formula", "cached_value"])?;
+    for sheet in selected {
+        let f = zip
+            .by_name(&sheet.path_in_zip)
+            .with_context(|| format!("missing {}", sheet.path_in_zip))?;
+        let prefix = quote_sheet_name(&sheet.name);
+        for_each_formula(
+            BufReader::new(f),
+            &shared_strings,
+            &styles,
+            is_1904,
+            &options,
+            |cell| {
+                let reference = format!(
+                    "{}!{}{}",
+                    prefix,
+                    index_to_col(cell.cell.col),
+                    cell.cell.row
+                );
+                wtr.write_record([reference.as_str(), &cell.formula, &cell.value])?;
+                Ok(())
+            },
+        )
+        .with_context(|| format!("read {}", sheet.name))?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use libxcsv::{FormulaCell, StyleInfo};
+
+    use super::*;
+
+    #[test]
+    fn test_cached_value_renders_like_export() {
+        let xml = r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>
+            <row r="1">
+                <c r="A1" t="s"><f>B1</f><v>0</v></c>
+                <c r="B1" t="b"><f>FALSE()</f><v>0</v></c>
+                <c r="C1" t="str"><f>" padded "</f><v> padded </v></c>
+                <c r="D1" s="1"><f>DATE(2023,3,15)</f><v>45000.5</v></c>
+                <c r="E1"><v>45000</v></c>
+                <c r="F1" s="0"><f>E1</f><v>45000</v></c>
+            </row>
+        </sheetData></worksheet>"#;
+        let date_style = StyleInfo {
+            num_fmt_id: 22,
+            is_date: true,
+            ..StyleInfo::default()
+        };
+        let styles = [StyleInfo::default(), date_style];
+        let formulas = |options: &ExportOptions| {
+            let mut cells = Vec::new();
+            for_each_formula(
+                xml.as_bytes(),
+                &vec!["shared".to_string()],
+                &styles,
+                false,
+                options,
+                |cell: FormulaCell| {
+                    cells.push((cell.formula, cell.value));
+                    Ok(())
+                },
+            )
+            .unwrap();
+            cells
+        };
+        let pair = |formula: &str, value: &str| (formula.to_string(), value.to_string());
+        assert_eq!(
+            formulas(&ExportOptions::default()),
+            [
+                pair("B1", "shared"),
+                pair("FALSE()", "FALSE"),
+                pair("\" padded \"", " padded "),
+                pair("DATE(2023,3,15)", "2023-03-15T12:00:00.000Z"),
+                pair("E1", "45000"),
+            ]
+        );
+        // Dates follow the options like in an export
+        let options = ExportOptions {
+            timezone: "+02:00".parse().unwrap(),
+            ..ExportOptions::default()
+        };
+        assert_eq!(formulas(&options)[3].1, "2023-03-15T12:00:00.000+02:00");
+        let options = ExportOptions {
+            date_output: DateOutput::UnixSeconds,
+            ..ExportOptions::default()
+        };
+        assert_eq!(formulas(&options)[3].1, "1678881600");
+    }
+}
@@ -0,0 +1,137 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::Args;
+use libxcsv::{
+    ExportOptions, SheetKind, index_to_col, open_zip, read_package_parts, read_strings_and_styles,
+    read_workbook,
+};
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+
+use crate::until_broken_pipe;
+use crate::view::{export_to_temp, sheet_records};
+
+#[derive(Args, Debug, Clone)]
+pub struct GrepArgs {
+    /// Text to look for in cell values (a regular expression with --regex)
+    #[arg(value_name = "PATTERN")]
+    pattern: String,
+    /// Treat PATTERN as a regular expression
+    #[arg(short = 'E', long)]
+    regex: bool,
+    /// Match case-insensitively
+    #[arg(short, long)]
+    ignore_case: bool,
+    /// Print one JSON object per match (sheet, cell, row, column, value)
+    #[arg(long)]
+    json: bool,
+}
+
+/// One match of `grep --json`; row and column are 1-based
+#[derive(Serialize)]
+struct GrepMatch<'a> {
+    sheet: &'a str,
+    cell: String,
+    row: usize,
+    column: usize,
+    value: &'a str,
+}
+
+/// Search the values of every worksheet, printing matches as `Sheet!A5: value`
+/// Values are matched as they would be exported (dates converted, shared strings resolved).
+/// Exits with status 1 when nothing matches, like grep.
+pub fn run(xlsx_path: &Path, args: &GrepArgs) -> Result<()> {
+    let found = until_broken_pipe(search(xlsx_path, args, &mut io::stdout().lock()))?;
+    if found == Some(false) {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Write the matches to out; returns whether there were any
+fn search(xlsx_path: &Path, args: &GrepArgs, out: &mut impl Write) -> Result<bool> {
+    let matcher = build_matcher(args)?;
+    let mut zip = open_zip(xlsx_path)?;
+    let parts = read_package_parts(&mut zip)?;
+    let (sheets, is_1904) = read_workbook(&mut zip, &parts)?;
+    let (shared_strings, styles) = read_strings_and_styles(&mut zip, &parts)?;
+
+    let mut found = false;
+    for sheet in sheets.iter().filter(|s| s.kind == SheetKind::Worksheet) {
+        let tmp = export_to_temp(
+            &mut zip,
+            sheet,
+            &shared_strings,
+            &styles,
+            is_1904,
+            &ExportOptions::default(),
+        )?;
+        for (r, record) in sheet_records(&tmp)?.enumerate() {
+            let record = record.with_context(|| format!("read {}", sheet.name))?;
+            for (c, value) in record.iter().enumerate() {
+                if !matcher.is_match(value) {
+                    continue;
+                }
+                found = true;
+                let cell = format!("{}{}", index_to_col(c as u32 + 1), r + 1);
+                if args.json {
+                    let m = GrepMatch {
+                        sheet: &sheet.name,
+                        cell,
+                        row: r + 1,
+                        column: c + 1,
+                        value,
+                    };
+                    writeln!(out, "{}", serde_json::to_string(&m)?)?;
+                } else {
+                    writeln!(
+                        out,
+                        "{}!{}: {}",
+                        quote_sheet_name(&sheet.name),
+                        cell,
+                        value.replace('\n', "\\n")
+                    )?;
+                }
+            }
+        }
+    }
+    Ok(found)
+}
+
+fn build_matcher(args: &GrepArgs) -> Result<Regex> {
+    let pattern = if args.regex {
+        args.pattern.clone()
+    } else {
+        regex::escape(&args.pattern)
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(args.ignore_case)
+        .build()
+        .with_context(|| format!("invalid pattern '{}'", args.pattern))
+}
+
+/// Quote a sheet name the way Excel does in references: 'Big Sheet'!A1, 'It''s'!A1
+pub fn quote_sheet_name(name: &str) -> String {
+    let plain = name.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && !name.starts_with(|c: char| c.is_ascii_digit());
+    if plain {
+        name.to_string()
+    } else {
+        format!("'{}'", name.replace('\'', "''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_sheet_name() {
+        assert_eq!(quote_sheet_name("Data"), "Data");
+        assert_eq!(quote_sheet_name("Big Sheet"), "'Big Sheet'");
+        assert_eq!(quote_sheet_name("It's"), "'It''s'");
+        assert_eq!(quote_sheet_name("2024"), "'2024'");
+    }
+}
@@ -0,0 +1,232 @@
+use std::io::{BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Args;
+use libxcsv::{CellValue, WorkbookWriter};
+
+use crate::export::parse_delimiter;
+use crate::slim::temp_output;
+
+#[derive(Args, Debug, Clone)]
+pub struct ImportArgs {
+    /// CSV file to import, - for stdin, or a directory whose .csv files each become a sheet
+    #[arg(value_name = "CSV_PATH", required_unless_present = "sheet")]
+    input: Option<PathBuf>,
+    /// Path of the .xlsx workbook to write
+    #[arg(short, long, value_name = "XLSX_PATH")]
+    out: PathBuf,
+    /// NAME=PATH adds the CSV at PATH as sheet NAME, in the order given (repeatable); a plain
+    /// NAME names the sheet of CSV_PATH [default: the CSV file name without extension]
+    #[arg(long, value_name = "NAME[=PATH]")]
+    sheet: Vec<String>,
+    /// Field delimiter of the CSVs: ',' or ';'
+    #[arg(short, long, default_value = ",", value_parser = parse_delimiter)]
+    delimiter: u8,
+    /// Store every field as text instead of detecting numbers, booleans and dates
+    #[arg(long)]
+    text: bool,
+}
+
+/// Build a workbook from CSV files, one sheet per file
+/// Fields are typed like export writes them: numbers, TRUE/FALSE, ISO 8601 dates and
+/// #ERROR:<code> become numbers, booleans, dates and errors; anything else is text.
+/// The workbook is written to a temp file next to the output and only moved there once
+/// complete, so a failed import leaves no partial workbook behind.
+pub fn run(args: &ImportArgs) -> Result<()> {
+    let sources = sources(args)?;
+    let tmp = temp_output(&args.out)?;
+    let mut writer = WorkbookWriter::new(BufWriter::new(tmp.reopen()?));
+    for (sheet, path) in &sources {
+        let rows = if path.as_os_str() == "-" {
+            write_sheet(&mut writer, sheet, std::io::stdin().lock(), args)
+        } else {
+            let file = std::fs::File::open(path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            write_sheet(&mut writer, sheet, file, args)
+        }
+        .with_context(|| format!("import {}", path.display()))?;
+        tracing::debug!("added {:?} as '{}' ({} rows)", path, sheet, rows);
+    }
+    writer
+        .finish()?
+        .flush()
+        .with_context(|| format!("write {}", args.out.display()))?;
+    tmp.persist(&args.out)
+        .with_context(|| format!("write {}", args.out.display()))?;
+    tracing::info!("wrote {:?} ({} sheets)", args.out, sources.len());
+    Ok(())
+}
+
+/// The sheets to write as (sheet name, CSV path): CSV_PATH or the CSVs in it, then every
+/// --sheet NAME=PATH
+fn sources(args: &ImportArgs) -> Result<Vec<(String, PathBuf)>> {
+    let (pairs, names): (Vec<&String>, Vec<&String>) =
+        args.sheet.iter().partition(|s| s.contains('='));
+    let mut sources = Vec::new();
+    match &args.input {
+        Some(dir) if dir.is_dir() => {
+            if !names.is_empty() {
+                anyhow::bail!("--sheet NAME names a single CSV file, not a directory");
+            }
+            let mut files = Vec::new();
+            for entry in std::fs::read_dir(dir)
+                .with_context(|| format!("Failed to read directory {}", dir.display()))?
+            {
+                let path = entry?.path();
+                if path.is_file()
+                    && path
+                        .extension()
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+                {
+                    files.push(path);
+                }
+            }
+            if files.is_empty() {
+                anyhow::bail!("no .csv files in {}", dir.display());
+            }
+            files.sort();
+            sources.extend(
+                files
+                    .into_iter()
+                    .map(|path| (default_sheet_name(&path), path)),
+            );
+        }
+        Some(file) => {
+            let name = match names.as_slice() {
+                [] => default_sheet_name(file),
+                [name] => name.to_string(),
+                _ => anyhow::bail!("--sheet NAME can be given once, for CSV_PATH"),
+            };
+            sources.push((name, file.clone()));
+        }
+        None if !names.is_empty() => {
+            anyhow::bail!(
+                "--sheet {} needs a CSV_PATH, or use --sheet NAME=PATH",
+                names[0]
+            );
+        }
+        None => {}
+    }
+    for pair in pairs {
+        let (name, path) = pair.split_once('=').expect("partitioned on '='");
+        sources.push((name.to_string(), PathBuf::from(path)));
+    }
+    if sources
+        .iter()
+        .filter(|(_, path)| path.as_os_str() == "-")
+        .count()
+        > 1
+    {
+        anyhow::bail!("stdin (-) can only be imported once");
+    }
+    Ok(sources)
+}
+
+/// The file stem made a valid sheet name: characters Excel forbids become _ and it is cut to
+/// 31 characters; Sheet1 for stdin
+fn default_sheet_name(input: &Path) -> String {
+    match input.file_stem().and_then(|s| s.to_str()) {
+        Some(stem) if input.as_os_str() != "-" => stem
+            .chars()
+            .map(|c| if r"[]:*?/\".contains(c) { '_' } else { c })
+            .take(31)
+            .collect(),
+        _ => "Sheet1".to_string(),
+    }
+}
+
+/// Add a sheet named name with the rows of the CSV in reader; returns the number of rows
+fn write_sheet<W: Write + Seek>(
+    writer: &mut WorkbookWriter<W>,
+    name: &str,
+    reader: impl Read,
+    args: &ImportArgs,
+) -> Result<u64> {
+    writer.start_sheet(name)?;
+    let mut csv = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(args.delimiter)
+        .from_reader(reader);
+    let mut rows = 0;
+    let mut values = Vec::new();
+    for record in csv.records() {
+        let record = record?;
+        values.clear();
+        values.extend(record.iter().map(|field| {
+            if args.text && !field.is_empty() {
+                CellValue::Text(field.to_string())
+            } else {
+                CellValue::from_field(field)
+            }
+        }));
+        writer.write_row(&values)?;
+        rows += 1;
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libxcsv::{ExportOptions, convert_xlsx_bytes_to_csv};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_import_types_fields_unless_text() {
+        // 1e3 is a number unless everything is stored as text
+        let csv = "id;n;when\n007;1e3;2024-02-29T00:00:00.000Z\n";
+        for (text, expected) in [
+            (false, "id;n;when\n007;1000;2024-02-29T00:00:00.000Z\n"),
+            (true, "id;n;when\n007;1e3;2024-02-29T00:00:00.000Z\n"),
+        ] {
+            let args = ImportArgs {
+                input: Some(PathBuf::from("data.csv")),
+                out: PathBuf::from("data.xlsx"),
+                sheet: Vec::new(),
+                delimiter: b';',
+                text,
+            };
+            let mut writer = WorkbookWriter::new(Cursor::new(Vec::new()));
+            let rows = write_sheet(&mut writer, "data", csv.as_bytes(), &args).unwrap();
+            assert_eq!(rows, 2);
+            let bytes = writer.finish().unwrap().into_inner();
+            let options = ExportOptions {
+                delimiter: b';',
+                ..ExportOptions::default()
+            };
+            let files = convert_xlsx_bytes_to_csv(&bytes, &options).unwrap();
+            assert_eq!(String::from_utf8(files[0].1.clone()).unwrap(), expected);
+        }
+        assert_eq!(default_sheet_name(Path::new("-")), "Sheet1");
+        assert_eq!(default_sheet_name(Path::new("dir/q1.csv")), "q1");
+    }
+
+    #[test]
+    fn test_import_sources_from_directory_and_pairs() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b.csv", "a.CSV", "notes.txt"] {
+            std::fs::write(dir.path().join(name), "x\n").unwrap();
+        }
+        let args = ImportArgs {
+            input: Some(dir.path().to_path_buf()),
+            out: PathBuf::from("out.xlsx"),
+            sheet: vec!["Extra=more.csv".to_string()],
+            delimiter: b',',
+            text: false,
+        };
+        let names: Vec<String> = sources(&args)
+            .unwrap()
+            .into_iter()
+            .map(|(n, _)| n)
+            .collect();
+        assert_eq!(names, ["a", "b", "Extra"]);
+
+        let args = ImportArgs {
+            sheet: vec!["Name".to_string()],
+            ..args
+        };
+        assert!(sources(&args).is_err());
+    }
+}
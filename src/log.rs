@@ -0,0 +1,222 @@
+use std::path::Path;
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+
+/// How warnings and errors are written to stderr
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Lines like `warning: Data!B7: message`
+    #[default]
+    Text,
+    /// One JSON object per line with level and message, plus code, workbook, sheet and cell for
+    /// warnings and errors
+    Json,
+}
+
+static FORMAT: OnceLock<LogFormat> = OnceLock::new();
+
+/// Set up stderr output for the run; the first call wins
+/// verbosity: -1 only shows errors, 0 warnings and what was written (tracing info events),
+/// 1 adds the debug spans of workbooks, sheets and output parts with their timing, 2 everything.
+pub fn init(format: LogFormat, verbosity: i8) {
+    let _ = FORMAT.set(format);
+    let level = match verbosity {
+        ..0 => LevelFilter::ERROR,
+        0 => LevelFilter::INFO,
+        1 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    // Closed spans report time.busy and time.idle
+    let spans = if verbosity > 0 {
+        FmtSpan::CLOSE
+    } else {
+        FmtSpan::NONE
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(level)
+        .with_target(false)
+        .with_span_events(spans);
+    let _ = match (format, verbosity > 0) {
+        // Every line is an object with level and message, and the fields of the event
+        (LogFormat::Json, true) => builder.json().flatten_event(true).try_init(),
+        (LogFormat::Json, false) => builder.json().flatten_event(true).without_time().try_init(),
+        (LogFormat::Text, true) => builder.try_init(),
+        // An info line is just its message, like `wrote "out/data.csv"`
+        (LogFormat::Text, false) => builder.without_time().with_level(false).try_init(),
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    Warning,
+    Error,
+}
+
+/// A warning or error about a workbook, sheet or cell, written by emit
+/// code: a stable kebab-case identifier, like the codes of ExportWarning
+#[derive(Debug, Clone)]
+pub struct Log<'a> {
+    level: Level,
+    code: &'a str,
+    workbook: Option<&'a Path>,
+    sheet: Option<&'a str>,
+    cell: Option<&'a str>,
+    message: String,
+}
+
+pub fn warning(code: &str, message: impl Into<String>) -> Log<'_> {
+    Log::new(Level::Warning, code, message.into())
+}
+
+pub fn error(code: &str, message: impl Into<String>) -> Log<'_> {
+    Log::new(Level::Error, code, message.into())
+}
+
+impl<'a> Log<'a> {
+    fn new(level: Level, code: &'a str, message: String) -> Self {
+        Self {
+            level,
+            code,
+            workbook: None,
+            sheet: None,
+            cell: None,
+            message,
+        }
+    }
+
+    pub fn workbook(mut self, workbook: &'a Path) -> Self {
+        self.workbook = Some(workbook);
+        self
+    }
+
+    pub fn sheet(mut self, sheet: &'a str) -> Self {
+        self.sheet = Some(sheet);
+        self
+    }
+
+    pub fn cell(mut self, cell: Option<&'a str>) -> Self {
+        self.cell = cell;
+        self
+    }
+
+    /// Emit the line as a tracing event, so it goes to stderr like every other line and
+    /// warnings are left out when quiet
+    /// In text the event's message is the whole line; in JSON it is just the message, the rest
+    /// being fields of the event.
+    pub fn emit(&self) {
+        let message = match FORMAT.get().copied().unwrap_or_default() {
+            LogFormat::Text => self.line(),
+            LogFormat::Json => self.message.clone(),
+        };
+        let workbook = self.workbook.map(|w| tracing::field::display(w.display()));
+        match self.level {
+            Level::Warning => tracing::warn!(
+                code = self.code,
+                workbook,
+                sheet = self.sheet,
+                cell = self.cell,
+                "{}",
+                message
+            ),
+            Level::Error => tracing::error!(
+                code = self.code,
+                workbook,
+                sheet = self.sheet,
+                cell = self.cell,
+                "{}",
+                message
+            ),
+        }
+    }
+
+    /// The text line, e.g. `warning: in.xlsx: Data!B7: message`
+    fn line(&self) -> String {
+        let level = match self.level {
+            Level::Warning => "warning",
+            Level::Error => "error",
+        };
+        let mut location = String::new();
+        if let Some(workbook) = self.workbook {
+            location = format!("{}: ", workbook.display());
+        }
+        match (self.sheet, self.cell) {
+            (Some(sheet), Some(cell)) => location += &format!("{}!{}: ", sheet, cell),
+            (Some(sheet), None) => location += &format!("{}: ", sheet),
+            _ => {}
+        }
+        format!("{}: {}{}", level, location, self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_log_line_formats() {
+        let log = warning("missing-shared-string", "index out of range")
+            .sheet("Data")
+            .cell(Some("B7"));
+        assert_eq!(log.line(), "warning: Data!B7: index out of range");
+        assert_eq!(
+            error("export-failed", "not a zip file")
+                .workbook(Path::new("in.xlsx"))
+                .line(),
+            "error: in.xlsx: not a zip file"
+        );
+
+        // In JSON, log lines and info lines share the subscriber's schema
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_target(false)
+            .json()
+            .flatten_event(true)
+            .without_time()
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            let _ = FORMAT.set(LogFormat::Json);
+            log.emit();
+            tracing::info!("wrote {:?}", "out/data.csv");
+        });
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines[0],
+            serde_json::json!({
+                "level": "WARN",
+                "message": "index out of range",
+                "code": "missing-shared-string",
+                "sheet": "Data",
+                "cell": "B7",
+            })
+        );
+        assert_eq!(
+            lines[1],
+            serde_json::json!({"level": "INFO", "message": "wrote \"out/data.csv\""})
+        );
+    }
+}
@@ -1,19 +1,81 @@
-use std::io::BufReader;
 use std::path::PathBuf;
 
-use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use anyhow::Result;
+use clap::error::ErrorKind;
+use clap::{ArgAction, CommandFactory, Parser, Subcommand};
 use libxcsv::{
-    StyleInfo, export_sheet_xml_to_csv, open_zip, parse_styles, parse_workbook,
-    parse_workbook_rels, read_shared_strings, to_lowercase_filename,
+    DocProperties, SheetInfo, SheetMeta, open_zip, read_doc_properties, read_package_parts,
+    read_sheet_metas, read_workbook, validate_workbook,
 };
+use serde::Serialize;
+
+#[cfg(feature = "tui")]
+mod browse;
+mod combine;
+mod config;
+mod export;
+mod formulas;
+mod grep;
+mod import;
+mod log;
+mod part;
+#[cfg(feature = "query")]
+mod query;
+mod repair;
+mod schema;
+mod script;
+mod slim;
+mod stats;
+mod strings;
+mod validations;
+mod verify;
+mod view;
+mod watch;
+
+#[cfg(feature = "tui")]
+use browse::BrowseArgs;
+use config::Config;
+use export::ExportArgs;
+use formulas::FormulasArgs;
+use grep::GrepArgs;
+use import::ImportArgs;
+use log::LogFormat;
+use part::PartArgs;
+#[cfg(feature = "query")]
+use query::QueryArgs;
+use repair::RepairArgs;
+use schema::SchemaArgs;
+use slim::SlimArgs;
+use strings::StringsArgs;
+use validations::ValidationsArgs;
+use verify::VerifyArgs;
+use view::ViewArgs;
+use watch::WatchArgs;
 
 #[derive(Parser, Debug)]
 #[command(name = "xcsv", author, version, about = "Convert XLSX sheets to CSV", long_about = None)]
 struct Cli {
-    /// Path to the .xlsx file
+    /// Path to the .xlsx file, - to read it from stdin, an http(s) URL (with the http feature) or
+    /// an s3://, gs:// or az:// URL (with the object-store feature)
     #[arg(value_name = "XLSX_PATH")]
-    xlsx_path: PathBuf,
+    xlsx_path: Option<PathBuf>,
+
+    /// Read export/watch defaults from this file instead of ./xcsv.toml
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Write warnings and errors to stderr as text or as JSON lines (level, code, workbook,
+    /// sheet, cell, message)
+    #[arg(long, global = true, value_name = "FORMAT", default_value = "text")]
+    log_format: LogFormat,
+
+    /// Print more: -v adds the time spent per workbook, sheet and output file, -vv everything
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Only print errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
 
     #[command(subcommand)]
     command: Command,
@@ -22,113 +84,311 @@ struct Cli {
 #[derive(Subcommand, Debug, Clone)]
 enum Command {
     /// List sheet names in the workbook
-    List,
-    /// Export all sheets to CSV files in output directory
-    Export {
-        /// Output directory (created if missing)
-        #[arg(short, long, value_name = "DIR", default_value = ".")]
-        out_dir: PathBuf,
-        /// CSV delimiter character
-        #[arg(short, long, value_name = "DELIMITER", default_value = ",", value_parser = parse_delimiter)]
-        delimiter: u8,
+    List {
+        /// Emit sheet metadata (index, sheet_id, state, path, dimension, tab color) as JSON
+        #[arg(long)]
+        json: bool,
     },
+    /// Show workbook metadata (author, timestamps, application) and per-sheet sizes
+    Info {
+        /// Emit the metadata as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check the workbook structure (required parts, XML, string/style indices) without exporting
+    Validate,
+    /// Show a sheet as an aligned table in the terminal, without exporting it
+    View(ViewArgs),
+    /// List every formula as CSV: cell, formula and its cached value
+    Formulas(FormulasArgs),
+    /// List the data validations (dropdown lists and other rules) as CSV: range, type and allowed values
+    Validations(ValidationsArgs),
+    /// Print the shared string table with the number of cells of each sheet referring to every string
+    Strings(StringsArgs),
+    /// Print a part of the workbook package (e.g. xl/worksheets/sheet1.xml) as stored, or list the parts
+    Part(PartArgs),
+    /// Search every sheet for a value and print where it is, e.g. `Sheet1!A5: value`
+    Grep(GrepArgs),
+    /// Infer column types, null counts and examples of a sheet; print them or as SQL/JSON Schema
+    Schema(SchemaArgs),
+    /// Export in memory and compare with the CSV files in a directory, reporting every changed cell
+    Verify(VerifyArgs),
+    /// Run SQL over the sheets, each one a table named after the sheet
+    #[cfg(feature = "query")]
+    Query(QueryArgs),
+    /// Browse the workbook interactively: sheet tabs, scrolling, cell details and search
+    #[cfg(feature = "tui")]
+    Browse(BrowseArgs),
+    /// Export all sheets to CSV files in output directory
+    Export(ExportArgs),
+    /// Watch a directory and convert new or changed .xlsx files into <DIR>/<workbook>/
+    Watch(WatchArgs),
+    /// Build an .xlsx workbook from a CSV file
+    Import(ImportArgs),
+    /// Write a smaller copy with only the selected sheets and ranges, as plain values without
+    /// styles or defined names
+    Slim(SlimArgs),
+    /// Rebuild missing or broken sheet relationships (workbook.xml.rels) from xl/worksheets/
+    Repair(RepairArgs),
+}
+
+/// One entry of `list --json`
+#[derive(Serialize)]
+struct SheetListing<'a> {
+    index: usize,
+    #[serde(flatten)]
+    sheet: &'a SheetInfo,
+    #[serde(flatten)]
+    meta: SheetMeta,
+}
+
+/// Output of `info`
+#[derive(Serialize)]
+struct WorkbookInfo<'a> {
+    #[serde(flatten)]
+    properties: DocProperties,
+    date1904: bool,
+    sheet_count: usize,
+    sheets: Vec<SheetSummary<'a>>,
+}
+
+/// Per-sheet part of `info`; rows/columns come from the sheet dimension
+#[derive(Serialize)]
+struct SheetSummary<'a> {
+    #[serde(flatten)]
+    sheet: &'a SheetInfo,
+    #[serde(flatten)]
+    meta: SheetMeta,
+    rows: Option<u32>,
+    columns: Option<u32>,
+}
+
+/// The result of a command printing to stdout, None when stdout was closed early, e.g. piped
+/// into head, which has seen enough
+pub(crate) fn until_broken_pipe<T>(result: Result<T>) -> Result<Option<T>> {
+    match result {
+        Err(e)
+            if e.downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(None)
+        }
+        result => result.map(Some),
+    }
 }
 
 fn parse_args() -> Cli {
     Cli::parse()
 }
 
-fn parse_delimiter(s: &str) -> Result<u8, String> {
-    match s {
-        "," => Ok(b','),
-        ";" => Ok(b';'),
-        _ => Err(format!(
-            "Invalid delimiter '{}'. Supported delimiters: ',' (comma) or ';' (semicolon)",
-            s
-        )),
+/// Print `info` output in a human readable form
+fn print_info(info: &WorkbookInfo) {
+    let field = |label: &str, value: &Option<String>| {
+        println!("{:<18}{}", label, value.as_deref().unwrap_or("-"));
+    };
+    let props = &info.properties;
+    field("Title:", &props.title);
+    field("Author:", &props.creator);
+    field("Last modified by:", &props.last_modified_by);
+    field("Created:", &props.created);
+    field("Modified:", &props.modified);
+    field("Application:", &props.application);
+    field("App version:", &props.app_version);
+    field("Company:", &props.company);
+    println!(
+        "{:<18}{}",
+        "Date system:",
+        if info.date1904 { "1904" } else { "1900" }
+    );
+    println!("{:<18}{}", "Sheets:", info.sheet_count);
+    for s in &info.sheets {
+        let size = match (s.rows, s.columns) {
+            (Some(rows), Some(cols)) => format!("{} rows x {} columns", rows, cols),
+            _ => "unknown size".to_string(),
+        };
+        println!("  {} ({:?}): {}", s.sheet.name, s.sheet.state, size);
     }
 }
 
 fn main() -> Result<()> {
     let cli = parse_args();
-    let mut zip = open_zip(&cli.xlsx_path)?;
+    let verbosity = if cli.quiet {
+        -1
+    } else {
+        cli.verbose.min(2) as i8
+    };
+    log::init(cli.log_format, verbosity);
+    let json = cli.log_format == LogFormat::Json;
+    match run(cli) {
+        Err(e) if e.is::<export::PartialFailure>() => {
+            log::error("partial-failure", e.to_string()).emit();
+            std::process::exit(export::PARTIAL_FAILURE_EXIT_CODE);
+        }
+        Err(e) if json => {
+            log::error("fatal", format!("{:#}", e)).emit();
+            std::process::exit(1);
+        }
+        result => result,
+    }
+}
+
+fn run(mut cli: Cli) -> Result<()> {
+    if let Command::Export(args) = &mut cli.command {
+        args.convert
+            .apply_config(&Config::load(cli.config.as_deref())?)?;
+        let inputs = cli
+            .xlsx_path
+            .into_iter()
+            .chain(args.inputs.clone())
+            .collect();
+        return export::run(inputs, &args.convert, args.stats.as_deref());
+    }
+    if let Command::Watch(args) = &mut cli.command {
+        if cli.xlsx_path.is_some() {
+            anyhow::bail!("watch takes a directory: xcsv watch <DIR> -o <DIR>");
+        }
+        args.convert
+            .apply_config(&Config::load(cli.config.as_deref())?)?;
+        return watch::run(args);
+    }
+    if let Command::Import(args) = &cli.command {
+        if cli.xlsx_path.is_some() {
+            anyhow::bail!("import takes a CSV file: xcsv import <CSV_PATH> -o <XLSX_PATH>");
+        }
+        return import::run(args);
+    }
+    let Some(xlsx_path) = cli.xlsx_path else {
+        Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "the following required argument was not provided: <XLSX_PATH>",
+            )
+            .exit();
+    };
+    if let Command::View(args) = &cli.command {
+        return view::run(&xlsx_path, args);
+    }
+    if let Command::Grep(args) = &cli.command {
+        return grep::run(&xlsx_path, args);
+    }
+    if let Command::Formulas(args) = &cli.command {
+        return formulas::run(&xlsx_path, args);
+    }
+    if let Command::Validations(args) = &cli.command {
+        return validations::run(&xlsx_path, args);
+    }
+    if let Command::Strings(args) = &cli.command {
+        return strings::run(&xlsx_path, args);
+    }
+    if let Command::Part(args) = &cli.command {
+        return part::run(&xlsx_path, args);
+    }
+    if let Command::Verify(args) = &mut cli.command {
+        // Checked before the config, whose output settings are shared with export
+        if let Some(option) = args.convert.output_only_option() {
+            anyhow::bail!("verify compares the export in memory; {option} does not apply");
+        }
+        args.convert
+            .apply_config(&Config::load(cli.config.as_deref())?)?;
+        return verify::run(&xlsx_path, args);
+    }
+    if let Command::Schema(args) = &cli.command {
+        return schema::run(&xlsx_path, args);
+    }
+    if let Command::Repair(args) = &cli.command {
+        return repair::run(&xlsx_path, args);
+    }
+    if let Command::Slim(args) = &cli.command {
+        return slim::run(&xlsx_path, args);
+    }
+    #[cfg(feature = "tui")]
+    if let Command::Browse(args) = &cli.command {
+        return browse::run(&xlsx_path, args);
+    }
+    #[cfg(feature = "query")]
+    if let Command::Query(args) = &cli.command {
+        return query::run(&xlsx_path, args);
+    }
+    let mut zip = open_zip(&xlsx_path)?;
 
     match cli.command {
-        Command::List => {
-            // Stream-parse workbook rels
-            let rels_map = {
-                let f = zip
-                    .by_name("xl/_rels/workbook.xml.rels")
-                    .context("missing xl/_rels/workbook.xml.rels")?;
-                let reader = BufReader::new(f);
-                parse_workbook_rels(reader)?
-            };
-            // Stream-parse workbook
-            let (sheets, _) = {
-                let f = zip
-                    .by_name("xl/workbook.xml")
-                    .context("missing xl/workbook.xml")?;
-                let reader = BufReader::new(f);
-                parse_workbook(reader, &rels_map)?
-            };
+        Command::List { json } => {
+            // Stream-parse workbook rels and workbook
+            let parts = read_package_parts(&mut zip)?;
+            let (sheets, _) = read_workbook(&mut zip, &parts)?;
 
-            for s in sheets {
-                println!("{}", s.name);
+            if json {
+                let metas = read_sheet_metas(&mut zip, &sheets)?;
+                let listing: Vec<SheetListing> = sheets
+                    .iter()
+                    .zip(metas)
+                    .enumerate()
+                    .map(|(index, (sheet, meta))| SheetListing { index, sheet, meta })
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&listing)?);
+            } else {
+                for s in sheets {
+                    println!("{}", s.name);
+                }
             }
         }
-        Command::Export { out_dir, delimiter } => {
-            std::fs::create_dir_all(&out_dir).context("create output directory")?;
-
-            // Stream-parse shared strings if present
-            let shared_strings: Vec<String> = if let Ok(f) = zip.by_name("xl/sharedStrings.xml") {
-                let reader = BufReader::new(f);
-                read_shared_strings(reader)?
-            } else {
-                Vec::new()
+        Command::Info { json } => {
+            let properties = read_doc_properties(&mut zip)?;
+            let parts = read_package_parts(&mut zip)?;
+            let (sheets, is_1904) = read_workbook(&mut zip, &parts)?;
+            let metas = read_sheet_metas(&mut zip, &sheets)?;
+            let summaries = sheets
+                .iter()
+                .zip(metas)
+                .map(|(sheet, meta)| {
+                    let size = meta.dimension_size();
+                    SheetSummary {
+                        sheet,
+                        meta,
+                        rows: size.map(|(rows, _)| rows),
+                        columns: size.map(|(_, cols)| cols),
+                    }
+                })
+                .collect();
+            let info = WorkbookInfo {
+                properties,
+                date1904: is_1904,
+                sheet_count: sheets.len(),
+                sheets: summaries,
             };
 
-            // Stream-parse styles if present
-            let styles: Vec<StyleInfo> = if let Ok(f) = zip.by_name("xl/styles.xml") {
-                let reader = BufReader::new(f);
-                parse_styles(reader)?
+            if json {
+                println!("{}", serde_json::to_string_pretty(&info)?);
             } else {
-                Vec::new()
-            };
-
-            // Workbook rels and sheets
-            let rels_map = {
-                let f = zip
-                    .by_name("xl/_rels/workbook.xml.rels")
-                    .context("missing xl/_rels/workbook.xml.rels")?;
-                let reader = BufReader::new(f);
-                parse_workbook_rels(reader)?
-            };
-            let (sheets, is_1904) = {
-                let f = zip
-                    .by_name("xl/workbook.xml")
-                    .context("missing xl/workbook.xml")?;
-                let reader = BufReader::new(f);
-                parse_workbook(reader, &rels_map)?
-            };
-
-            // Export each sheet
-            for sheet in sheets {
-                let filename = format!("{}.csv", to_lowercase_filename(&sheet.name));
-                let out_path = out_dir.join(filename);
-                let f = zip
-                    .by_name(&sheet.path_in_zip)
-                    .with_context(|| format!("missing {}", sheet.path_in_zip))?;
-                let reader = BufReader::new(f);
-                export_sheet_xml_to_csv(
-                    reader,
-                    &shared_strings,
-                    &styles,
-                    is_1904,
-                    &out_path,
-                    delimiter,
-                )?;
-                eprintln!("wrote {:?}", out_path);
+                print_info(&info);
+            }
+        }
+        Command::Export(_)
+        | Command::Watch(_)
+        | Command::Import(_)
+        | Command::View(_)
+        | Command::Grep(_)
+        | Command::Formulas(_)
+        | Command::Validations(_)
+        | Command::Strings(_)
+        | Command::Part(_)
+        | Command::Schema(_)
+        | Command::Slim(_)
+        | Command::Repair(_)
+        | Command::Verify(_) => unreachable!("handled above"),
+        #[cfg(feature = "tui")]
+        Command::Browse(_) => unreachable!("handled above"),
+        #[cfg(feature = "query")]
+        Command::Query(_) => unreachable!("handled above"),
+        Command::Validate => {
+            let issues = validate_workbook(&mut zip)?;
+            if !issues.is_empty() {
+                for issue in &issues {
+                    println!("{}", issue);
+                }
+                anyhow::bail!("{} problem(s) found", issues.len());
             }
+            println!("OK: no problems found");
         }
     }
     Ok(())
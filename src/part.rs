@@ -0,0 +1,63 @@
+use std::io::{self, BufReader, Write};
+use std::path::Path;
+
+use anyhow::Result;
+use clap::Args;
+use libxcsv::{open_zip, write_pretty_xml};
+
+use crate::until_broken_pipe;
+
+#[derive(Args, Debug, Clone)]
+pub struct PartArgs {
+    /// Name of the part in the package, e.g. xl/worksheets/sheet1.xml [default: list the parts]
+    #[arg(value_name = "PART")]
+    part: Option<String>,
+    /// Indent the XML, one element per line
+    #[arg(long)]
+    pretty: bool,
+}
+
+/// Print a part of the workbook package as stored, or list every part with its size
+/// Useful to see the XML behind a conversion that looks wrong without unzipping the workbook.
+pub fn run(xlsx_path: &Path, args: &PartArgs) -> Result<()> {
+    until_broken_pipe(print(xlsx_path, args, &mut io::stdout().lock()))?;
+    Ok(())
+}
+
+fn print(xlsx_path: &Path, args: &PartArgs, out: &mut impl Write) -> Result<()> {
+    let mut zip = open_zip(xlsx_path)?;
+    let Some(name) = &args.part else {
+        for i in 0..zip.len() {
+            let entry = zip.by_index(i)?;
+            writeln!(out, "{:>10}  {}", entry.size(), entry.name())?;
+        }
+        return Ok(());
+    };
+    // Content types and relationships name parts with a leading '/'
+    let name = name.strip_prefix('/').unwrap_or(name);
+    if !zip.file_names().any(|n| n == name) {
+        let similar: Vec<&str> = zip
+            .file_names()
+            .filter(|n| n.eq_ignore_ascii_case(name) || n.ends_with(&format!("/{}", name)))
+            .collect();
+        if similar.is_empty() {
+            anyhow::bail!(
+                "no part {} in the workbook (run without PART to list them)",
+                name
+            );
+        }
+        anyhow::bail!(
+            "no part {} in the workbook, did you mean {}?",
+            name,
+            similar.join(", ")
+        );
+    }
+    let mut entry = zip.by_name(name)?;
+    if args.pretty {
+        write_pretty_xml(BufReader::new(entry), &mut *out)?;
+    } else {
+        io::copy(&mut entry, out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use datafusion::arrow::array::{
+    Array, ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray,
+    TimestampMicrosecondArray,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::arrow::util::display::{ArrayFormatter, FormatOptions};
+use datafusion::datasource::MemTable;
+use datafusion::prelude::{SessionConfig, SessionContext};
+use libxcsv::{Column, ColumnData, SheetKind, Workbook};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::schema::table_name;
+use crate::view::render_table;
+
+#[derive(Args, Debug, Clone)]
+pub struct QueryArgs {
+    /// SQL query; every sheet is a table named like its default file name without .csv
+    /// (e.g. "Big Sheet" -> big_sheet) whose columns are named by its header row
+    #[arg(value_name = "SQL")]
+    sql: String,
+    /// How to print the result [default: table]
+    #[arg(long, value_name = "FORMAT")]
+    format: Option<QueryFormat>,
+    /// Write the result to FILE as CSV instead of printing it
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// How `query` prints its result
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum QueryFormat {
+    /// An aligned table
+    #[default]
+    Table,
+    /// CSV with a header row
+    Csv,
+    /// A JSON array with one object per row
+    Json,
+}
+
+/// Widest a column of the result table is printed
+const MAX_COLUMN_WIDTH: usize = 40;
+
+/// Run SQL over the sheets of the workbook with DataFusion
+/// Only the sheets whose table name occurs in the query are loaded, each into an in-memory
+/// table typed from its cells: columns of whole numbers are BIGINT, other numbers DOUBLE,
+/// booleans BOOLEAN, dates TIMESTAMP and anything else (or a mix) VARCHAR; empty cells are NULL.
+/// Identifiers keep their case, so a header "Amount" is the column Amount.
+pub fn run(xlsx_path: &Path, args: &QueryArgs) -> Result<()> {
+    let mut workbook = Workbook::open(xlsx_path)?;
+    let config =
+        SessionConfig::new().set_bool("datafusion.sql_parser.enable_ident_normalization", false);
+    let ctx = SessionContext::new_with_config(config);
+    let sql = args.sql.to_lowercase();
+    let mut tables: HashMap<String, String> = HashMap::new();
+    let names: Vec<String> = workbook
+        .sheets()
+        .iter()
+        .filter(|s| s.kind == SheetKind::Worksheet)
+        .map(|s| s.name.clone())
+        .collect();
+    for name in names {
+        let table = table_name(&name);
+        if let Some(other) = tables.insert(table.clone(), name.clone()) {
+            anyhow::bail!(
+                "sheets '{}' and '{}' would both be table {}",
+                other,
+                name,
+                table
+            );
+        }
+        if !mentions(&sql, &table) {
+            continue;
+        }
+        let columns = workbook.sheet(&name)?.to_columns()?;
+        let batch = record_batch(&columns).with_context(|| format!("load {}", name))?;
+        let mem = MemTable::try_new(batch.schema(), vec![vec![batch]])?;
+        ctx.register_table(table.as_str(), Arc::new(mem))?;
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let batches = runtime.block_on(async {
+        let frame = ctx.sql(&args.sql).await.context("invalid query")?;
+        anyhow::Ok(frame.collect().await?)
+    })?;
+    let header: Vec<String> = match batches.first() {
+        Some(batch) => batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| f.name().clone())
+            .collect(),
+        None => Vec::new(),
+    };
+    let mut rows: Vec<Vec<String>> = vec![header.clone()];
+    let mut values: Vec<Vec<serde_json::Value>> = Vec::new();
+    let options = FormatOptions::default();
+    for batch in &batches {
+        let formatters = batch
+            .columns()
+            .iter()
+            .map(|c| ArrayFormatter::try_new(c.as_ref(), &options))
+            .collect::<Result<Vec<_>, _>>()?;
+        for i in 0..batch.num_rows() {
+            rows.push(formatters.iter().map(|f| f.value(i).to_string()).collect());
+            values.push(
+                batch
+                    .columns()
+                    .iter()
+                    .zip(&formatters)
+                    .map(|(column, f)| json_value(column.as_ref(), i, &f.value(i).to_string()))
+                    .collect(),
+            );
+        }
+    }
+
+    if let Some(path) = &args.output {
+        write_csv(csv::Writer::from_path(path)?, &rows)?;
+        tracing::info!("wrote {:?} ({} rows)", path, rows.len() - 1);
+        return Ok(());
+    }
+    match args.format.unwrap_or_default() {
+        QueryFormat::Table => print!("{}", render_table(&rows, MAX_COLUMN_WIDTH)),
+        QueryFormat::Csv => write_csv(csv::Writer::from_writer(std::io::stdout()), &rows)?,
+        QueryFormat::Json => {
+            let objects: Vec<JsonRow> = values
+                .iter()
+                .map(|row| JsonRow {
+                    header: &header,
+                    values: row,
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&objects)?);
+        }
+    }
+    Ok(())
+}
+
+/// Whether the (lowercased) query mentions table as a whole word
+fn mentions(sql: &str, table: &str) -> bool {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    sql.match_indices(table)
+        .any(|(i, _)| !sql[..i].ends_with(is_word) && !sql[i + table.len()..].starts_with(is_word))
+}
+
+/// The columns of a sheet as an Arrow batch; Number columns holding only whole numbers that
+/// fit become Int64
+fn record_batch(columns: &[Column]) -> Result<RecordBatch> {
+    if columns.is_empty() {
+        let schema = Schema::new(vec![Field::new("column_1", DataType::Utf8, true)]);
+        return Ok(RecordBatch::new_empty(Arc::new(schema)));
+    }
+    let mut fields = Vec::new();
+    let mut arrays: Vec<ArrayRef> = Vec::new();
+    for column in columns {
+        let valid = |i: usize| column.validity[i];
+        let array: ArrayRef = match &column.data {
+            ColumnData::Text(v) => Arc::new(StringArray::from_iter(
+                v.iter()
+                    .enumerate()
+                    .map(|(i, s)| valid(i).then_some(s.as_str())),
+            )),
+            ColumnData::Number(v)
+                if v.iter()
+                    .enumerate()
+                    .all(|(i, n)| !valid(i) || (n.fract() == 0.0 && n.abs() < 9.0e15)) =>
+            {
+                Arc::new(Int64Array::from_iter(
+                    v.iter()
+                        .enumerate()
+                        .map(|(i, n)| valid(i).then_some(*n as i64)),
+                ))
+            }
+            ColumnData::Number(v) => Arc::new(Float64Array::from_iter(
+                v.iter().enumerate().map(|(i, n)| valid(i).then_some(*n)),
+            )),
+            ColumnData::Bool(v) => Arc::new(BooleanArray::from_iter(
+                v.iter().enumerate().map(|(i, b)| valid(i).then_some(*b)),
+            )),
+            ColumnData::Timestamp(v) => Arc::new(TimestampMicrosecondArray::from_iter(
+                v.iter()
+                    .enumerate()
+                    .map(|(i, d)| valid(i).then_some(d.and_utc().timestamp_micros())),
+            )),
+        };
+        fields.push(Field::new(&column.name, array.data_type().clone(), true));
+        arrays.push(array);
+    }
+    Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)?)
+}
+
+/// Numbers and booleans stay what they are in JSON, nulls are null and the rest is text as
+/// printed
+fn json_value(array: &dyn Array, i: usize, text: &str) -> serde_json::Value {
+    if array.is_null(i) {
+        return serde_json::Value::Null;
+    }
+    match array.data_type() {
+        DataType::Boolean => (text == "true").into(),
+        t if t.is_integer() => text
+            .parse::<i64>()
+            .map_or_else(|_| text.into(), serde_json::Value::from),
+        t if t.is_floating() => text
+            .parse::<f64>()
+            .map_or_else(|_| text.into(), serde_json::Value::from),
+        _ => text.into(),
+    }
+}
+
+/// A result row as a JSON object, its keys in column order
+struct JsonRow<'a> {
+    header: &'a [String],
+    values: &'a [serde_json::Value],
+}
+
+impl Serialize for JsonRow<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.header.len()))?;
+        for (key, value) in self.header.iter().zip(self.values) {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+fn write_csv<W: std::io::Write>(mut wtr: csv::Writer<W>, rows: &[Vec<String>]) -> Result<()> {
+    for row in rows {
+        wtr.write_record(row)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_batch_types_columns() {
+        let column = |name: &str, data, validity: Vec<bool>| Column {
+            name: name.to_string(),
+            data,
+            validity,
+        };
+        let columns = vec![
+            column(
+                "region",
+                ColumnData::Text(vec!["north".into(), "north".into(), "south".into()]),
+                vec![true, true, true],
+            ),
+            column(
+                "amount",
+                ColumnData::Number(vec![10.0, 2.5, 0.0]),
+                vec![true, true, false],
+            ),
+            column(
+                "count",
+                ColumnData::Number(vec![1.0, 0.0, 3.0]),
+                vec![true, false, true],
+            ),
+        ];
+        let batch = record_batch(&columns).unwrap();
+        let schema = batch.schema();
+        assert_eq!(schema.field(1).data_type(), &DataType::Float64);
+        assert_eq!(schema.field(2).data_type(), &DataType::Int64);
+        assert_eq!(batch.column(1).null_count(), 1);
+
+        let ctx = SessionContext::new();
+        let mem = MemTable::try_new(schema, vec![vec![batch]]).unwrap();
+        ctx.register_table("sales", Arc::new(mem)).unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let result = runtime.block_on(async {
+            ctx.sql("SELECT SUM(amount) AS total FROM sales WHERE region = 'north'")
+                .await
+                .unwrap()
+                .collect()
+                .await
+                .unwrap()
+        });
+        let total = result[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap()
+            .value(0);
+        assert_eq!(total, 12.5);
+        assert!(mentions("select * from sales", "sales"));
+        assert!(!mentions("select * from sales_2024", "sales"));
+    }
+}
@@ -0,0 +1,68 @@
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Args;
+use libxcsv::{open_zip, read_package_parts, repair_workbook_rels};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::slim::create_output;
+
+#[derive(Args, Debug, Clone)]
+pub struct RepairArgs {
+    /// Path of the repaired .xlsx workbook; may be the input workbook
+    #[arg(short, long, value_name = "XLSX_PATH")]
+    out: PathBuf,
+}
+
+/// Rebuild the sheet relationships of a workbook whose workbook.xml.rels is missing, broken or
+/// points at parts that don't exist
+/// Sheets are matched with the parts in xl/worksheets/ in sheetId order, as export does when it
+/// meets such a workbook. Every other part is copied unchanged.
+pub fn run(xlsx_path: &Path, args: &RepairArgs) -> Result<()> {
+    let mut zip = open_zip(xlsx_path)?;
+    let parts = read_package_parts(&mut zip)?;
+    let (rels, unlinked) = repair_workbook_rels(&mut zip, &parts)?;
+    for name in &unlinked {
+        tracing::warn!(
+            "could not find the part of sheet '{}', it stays unlinked",
+            name
+        );
+    }
+    let Some(rels) = rels else {
+        tracing::info!(
+            "{:?}: the sheet relationships are fine, nothing to repair",
+            xlsx_path
+        );
+        return Ok(());
+    };
+
+    let (file, tmp) = create_output(xlsx_path, &args.out)?;
+    let mut writer = ZipWriter::new(BufWriter::new(file));
+    for i in 0..zip.len() {
+        let entry = zip.by_index_raw(i)?;
+        if entry.name() != parts.workbook_rels {
+            writer.raw_copy_file(entry)?;
+        }
+    }
+    writer.start_file(
+        parts.workbook_rels.as_str(),
+        FileOptions::default().compression_method(CompressionMethod::Deflated),
+    )?;
+    writer.write_all(rels.as_bytes())?;
+    writer
+        .finish()?
+        .flush()
+        .with_context(|| format!("write {}", args.out.display()))?;
+    if let Some(tmp) = tmp {
+        tmp.persist(&args.out)
+            .with_context(|| format!("write {}", args.out.display()))?;
+    }
+    tracing::info!(
+        "wrote {:?} with a rebuilt {}",
+        args.out,
+        parts.workbook_rels
+    );
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use libxcsv::{
+    ColumnType, ExportOptions, SheetKind, SheetSchema, infer_sheet_schema, open_zip,
+    read_package_parts, read_strings_and_styles, read_workbook, to_lowercase_filename,
+};
+use serde_json::json;
+
+use crate::view::{find_sheet, render_table};
+
+#[derive(Args, Debug, Clone)]
+pub struct SchemaArgs {
+    /// Name of the sheet to describe (case-insensitive if there is no exact match)
+    #[arg(value_name = "SHEET")]
+    sheet: String,
+    /// How to print the schema [default: table]
+    #[arg(long, value_name = "FORMAT")]
+    format: Option<SchemaFormat>,
+}
+
+/// How `schema` prints the inferred schema
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SchemaFormat {
+    /// Column names, types, null counts and example values as a table
+    #[default]
+    Table,
+    /// A PostgreSQL CREATE TABLE statement
+    Sql,
+    /// A JSON Schema for an array of row objects
+    Jsonschema,
+}
+
+/// Widest a cell of the schema table is printed
+const MAX_COLUMN_WIDTH: usize = 40;
+
+/// Infer the column types of a sheet from all of its rows
+/// The first row names the columns; blank rows are left out. See infer_sheet_schema for how
+/// types are inferred. The table also shows the number format most cells of each column have.
+pub fn run(xlsx_path: &Path, args: &SchemaArgs) -> Result<()> {
+    let mut zip = open_zip(xlsx_path)?;
+    let parts = read_package_parts(&mut zip)?;
+    let (sheets, is_1904) = read_workbook(&mut zip, &parts)?;
+    let sheet = find_sheet(&sheets, &args.sheet)?;
+    if sheet.kind != SheetKind::Worksheet {
+        anyhow::bail!("'{}' is a {} and has no cell data", sheet.name, sheet.kind);
+    }
+    let (shared_strings, styles) = read_strings_and_styles(&mut zip, &parts)?;
+    let f = zip
+        .by_name(&sheet.path_in_zip)
+        .with_context(|| format!("missing {}", sheet.path_in_zip))?;
+    let schema = infer_sheet_schema(
+        BufReader::new(f),
+        &shared_strings,
+        &styles,
+        is_1904,
+        &ExportOptions::default(),
+    )
+    .with_context(|| format!("read {}", sheet.name))?;
+
+    match args.format.unwrap_or_default() {
+        SchemaFormat::Table => {
+            println!("{}: {} rows", sheet.name, schema.rows);
+            let rows: Vec<Vec<String>> = std::iter::once(
+                ["column", "type", "nulls", "format", "examples"]
+                    .map(String::from)
+                    .to_vec(),
+            )
+            .chain(schema.columns.iter().map(|c| {
+                vec![
+                    c.name.clone(),
+                    c.column_type.to_string(),
+                    c.nulls.to_string(),
+                    c.number_format.as_ref().map_or(String::new(), |f| {
+                        format!("{} ({:.0}%)", f.code, f.share * 100.0)
+                    }),
+                    c.examples.join(", "),
+                ]
+            }))
+            .collect();
+            print!("{}", render_table(&rows, MAX_COLUMN_WIDTH));
+        }
+        SchemaFormat::Sql => print!("{}", create_table_sql(&table_name(&sheet.name), &schema)),
+        SchemaFormat::Jsonschema => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&json_schema(&sheet.name, &schema))?
+            )
+        }
+    }
+    Ok(())
+}
+
+/// The SQL table name of a sheet: its default file name stem with '-' replaced by '_'
+pub fn table_name(sheet: &str) -> String {
+    to_lowercase_filename(sheet).replace('-', "_")
+}
+
+/// Quote a SQL identifier: "name", with embedded quotes doubled
+pub fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// A PostgreSQL CREATE TABLE statement for schema; columns without nulls are NOT NULL
+pub fn create_table_sql(table: &str, schema: &SheetSchema) -> String {
+    let columns: Vec<String> = schema
+        .columns
+        .iter()
+        .map(|c| {
+            let sql_type = match c.column_type {
+                ColumnType::Integer => "BIGINT",
+                ColumnType::Float => "DOUBLE PRECISION",
+                ColumnType::Bool => "BOOLEAN",
+                ColumnType::Date => "TIMESTAMP",
+                ColumnType::Text => "TEXT",
+            };
+            let not_null = if c.nulls == 0 && schema.rows > 0 {
+                " NOT NULL"
+            } else {
+                ""
+            };
+            format!("    {} {}{}", quote_ident(&c.name), sql_type, not_null)
+        })
+        .collect();
+    format!(
+        "CREATE TABLE {} (\n{}\n);\n",
+        quote_ident(table),
+        columns.join(",\n")
+    )
+}
+
+/// A JSON Schema (draft 2020-12) for the sheet as an array of objects keyed by column name
+/// Columns with nulls also allow null; the others are required.
+fn json_schema(title: &str, schema: &SheetSchema) -> serde_json::Value {
+    let mut properties = serde_json::Map::new();
+    let mut required = Vec::new();
+    for c in &schema.columns {
+        let (json_type, format) = match c.column_type {
+            ColumnType::Integer => ("integer", None),
+            ColumnType::Float => ("number", None),
+            ColumnType::Bool => ("boolean", None),
+            ColumnType::Date => ("string", Some("date-time")),
+            ColumnType::Text => ("string", None),
+        };
+        let mut property = if c.nulls > 0 {
+            json!({ "type": [json_type, "null"] })
+        } else {
+            required.push(c.name.clone());
+            json!({ "type": json_type })
+        };
+        if let Some(format) = format {
+            property["format"] = format.into();
+        }
+        properties.insert(c.name.clone(), property);
+    }
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": title,
+        "type": "array",
+        "items": {
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libxcsv::SchemaBuilder;
+
+    #[test]
+    fn test_create_table_sql() {
+        let mut builder = SchemaBuilder::new(vec!["id".to_string(), "say \"hi\"".to_string()]);
+        builder.add_row(["1", "hello"]);
+        builder.add_row(["2"]);
+        assert_eq!(
+            create_table_sql(&table_name("Big Sheet"), &builder.finish()),
+            "CREATE TABLE \"big_sheet\" (\n    \"id\" BIGINT NOT NULL,\n    \"say \"\"hi\"\"\" TEXT\n);\n"
+        );
+    }
+}
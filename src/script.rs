@@ -0,0 +1,205 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Result;
+use libxcsv::CellTransform;
+
+/// A --script: a Rhai file defining `fn transform(sheet, row, column, header, value)`
+/// It is called for every cell read with the sheet name, the 1-based row number, the column
+/// letter, the column's header (or () before and in the header row) and the value (a string,
+/// number or bool, or () for an empty cell; dates as ISO 8601 strings). It returns the value
+/// to write, () to keep the value, or drop_row() to leave out the whole row.
+///
+/// Example:
+///   fn transform(sheet, row, column, header, value) {
+///       if header == "email" { return "<redacted>"; }
+///       if header == "status" && value == "test" { return drop_row(); }
+///   }
+#[cfg(feature = "script")]
+pub struct Script {
+    engine: rhai::Engine,
+    ast: rhai::AST,
+}
+
+/// Returned by the script's drop_row() to leave out the row of the cell
+#[cfg(feature = "script")]
+#[derive(Debug, Clone, Copy)]
+struct DropRow;
+
+#[cfg(feature = "script")]
+impl Script {
+    /// Compile the script at path
+    pub fn load(path: &Path) -> Result<Self> {
+        use anyhow::Context;
+
+        let mut engine = rhai::Engine::new();
+        // Keep stdout clean for the output of other commands
+        engine.on_print(|text| eprintln!("{}", text));
+        engine.register_type_with_name::<DropRow>("DropRow");
+        engine.register_fn("drop_row", || DropRow);
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| format!("Failed to load script {}", path.display()))?;
+        if !ast
+            .iter_functions()
+            .any(|f| f.name == "transform" && f.params.len() == 5)
+        {
+            anyhow::bail!(
+                "script {} does not define fn transform(sheet, row, column, header, value)",
+                path.display()
+            );
+        }
+        Ok(Self { engine, ast })
+    }
+
+    /// The cell_transform running the script on the cells of sheet; an error raised by the
+    /// script stops the export at that cell
+    pub fn transform(self: &Arc<Self>, sheet: &str) -> CellTransform {
+        use libxcsv::{CellValue, index_to_col};
+        use rhai::Dynamic;
+
+        let script = Arc::clone(self);
+        let sheet = sheet.to_string();
+        Arc::new(move |cell, value| {
+            let column = index_to_col(cell.column);
+            let arg = match &value {
+                CellValue::Empty => Dynamic::UNIT,
+                CellValue::Text(text) => text.clone().into(),
+                CellValue::Number(n) => (*n).into(),
+                CellValue::Bool(b) => (*b).into(),
+                CellValue::Date(_) | CellValue::Error(_) => {
+                    value.to_field(Default::default()).into()
+                }
+            };
+            let header = cell.header.map_or(Dynamic::UNIT, |h| h.to_string().into());
+            let result = script.engine.call_fn::<Dynamic>(
+                &mut rhai::Scope::new(),
+                &script.ast,
+                "transform",
+                (sheet.clone(), i64::from(cell.row), column, header, arg),
+            );
+            let returned = result.map_err(|e| anyhow::anyhow!("script error: {}", e))?;
+            Ok(if returned.is_unit() {
+                value
+            } else if returned.is::<DropRow>() {
+                cell.drop_row();
+                value
+            } else if returned.is_string() {
+                CellValue::Text(returned.cast::<String>())
+            } else if let Ok(n) = returned.as_float() {
+                CellValue::Number(n)
+            } else if let Ok(n) = returned.as_int() {
+                CellValue::Number(n as f64)
+            } else if let Ok(b) = returned.as_bool() {
+                CellValue::Bool(b)
+            } else {
+                anyhow::bail!(
+                    "script error: transform returned a {}, expected a string, number, bool, () or drop_row()",
+                    returned.type_name()
+                );
+            })
+        })
+    }
+}
+
+/// Stand-in for builds without the script feature, which refuse --script
+#[cfg(not(feature = "script"))]
+pub struct Script;
+
+#[cfg(not(feature = "script"))]
+impl Script {
+    pub fn load(path: &Path) -> Result<Self> {
+        anyhow::bail!(
+            "Cannot run script {}: --script needs xcsv built with the script feature",
+            path.display()
+        )
+    }
+
+    pub fn transform(self: &Arc<Self>, _sheet: &str) -> CellTransform {
+        Arc::new(|_, value| Ok(value))
+    }
+}
+
+#[cfg(all(test, feature = "script"))]
+mod tests {
+    use super::*;
+    use libxcsv::{ExportOptions, export_sheet_xml_to_csv};
+    use std::io::{BufReader, Write};
+
+    #[test]
+    fn test_script_transforms_and_drops_rows() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            file,
+            r#"
+            fn transform(sheet, row, column, header, value) {{
+                if header == "email" {{ return sheet + "-" + column + row; }}
+                if header == "status" && value == "test" {{ return drop_row(); }}
+                if type_of(value) == "f64" {{ return value * 2; }}
+            }}
+            "#
+        )
+        .unwrap();
+        let script = Arc::new(Script::load(file.path()).unwrap());
+        let xml = r#"<worksheet><sheetData>
+            <row r="1"><c r="A1" t="inlineStr"><is><t>email</t></is></c><c r="B1" t="inlineStr"><is><t>status</t></is></c><c r="C1" t="inlineStr"><is><t>n</t></is></c></row>
+            <row r="2"><c r="A2" t="inlineStr"><is><t>a@b.c</t></is></c><c r="B2" t="inlineStr"><is><t>ok</t></is></c><c r="C2"><v>1.5</v></c></row>
+            <row r="3"><c r="A3" t="inlineStr"><is><t>x@y.z</t></is></c><c r="B3" t="inlineStr"><is><t>test</t></is></c></row>
+        </sheetData></worksheet>"#;
+        let out = tempfile::NamedTempFile::new().unwrap();
+        let options = ExportOptions {
+            cell_transform: Some(script.transform("Users")),
+            ..ExportOptions::default()
+        };
+        export_sheet_xml_to_csv(
+            BufReader::new(xml.as_bytes()),
+            &[],
+            &[],
+            false,
+            out.path(),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(out.path()).unwrap(),
+            "email,status,n\nUsers-A2,ok,3\n"
+        );
+    }
+
+    #[test]
+    fn test_script_error_stops_export_and_removes_output() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            file,
+            r#"
+            fn transform(sheet, row, column, header, value) {{
+                if row == 3 {{ throw "bad row"; }}
+            }}
+            "#
+        )
+        .unwrap();
+        let script = Arc::new(Script::load(file.path()).unwrap());
+        let rows: String = (1..=5)
+            .map(|r| format!(r#"<row r="{r}"><c r="A{r}"><v>{r}</v></c></row>"#))
+            .collect();
+        let xml = format!("<worksheet><sheetData>{}</sheetData></worksheet>", rows);
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out.csv");
+        let options = ExportOptions {
+            cell_transform: Some(script.transform("Users")),
+            ..ExportOptions::default()
+        };
+        let err = export_sheet_xml_to_csv(
+            BufReader::new(xml.as_bytes()),
+            &[],
+            &[],
+            false,
+            &out,
+            &options,
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("cell A3: script error"));
+        assert!(!out.exists());
+    }
+}
@@ -0,0 +1,274 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Args;
+use glob::Pattern;
+use libxcsv::{
+    CellRef, CellValue, ExportOptions, PackageParts, RowSink, SheetKind, StyleInfo, WorkbookWriter,
+    export_sheet_to_sink, open_zip, parse_defined_names, parse_range_ref, read_package_parts,
+    read_strings_and_styles, read_workbook,
+};
+use tempfile::NamedTempFile;
+use zip::ZipArchive;
+
+use crate::export::parse_sheet_pattern;
+
+#[derive(Args, Debug, Clone)]
+pub struct SlimArgs {
+    /// Path of the .xlsx workbook to write; may be the input workbook
+    #[arg(short, long, value_name = "XLSX_PATH")]
+    out: PathBuf,
+    /// Only keep sheets whose name matches one of these glob patterns
+    #[arg(long, value_name = "PATTERN", num_args = 1.., value_parser = parse_sheet_pattern)]
+    sheets: Vec<Pattern>,
+    /// Drop sheets whose name matches one of these glob patterns
+    #[arg(long, value_name = "PATTERN", num_args = 1.., value_parser = parse_sheet_pattern)]
+    exclude_sheets: Vec<Pattern>,
+    /// Only keep the cells in RANGE, e.g. A1:F40 for every sheet or 'Q1 Sales'!A1:F40 for one
+    /// (repeatable; a sheet's own range wins); cells keep their position
+    #[arg(long, value_name = "RANGE", value_parser = parse_sheet_range)]
+    range: Vec<SheetRange>,
+}
+
+/// A --range: the cells first..=last, of the named sheet or of every sheet
+#[derive(Debug, Clone)]
+struct SheetRange {
+    sheet: Option<String>,
+    first: CellRef,
+    last: CellRef,
+}
+
+fn parse_sheet_range(s: &str) -> Result<SheetRange, String> {
+    let (sheet, range) = match s.rsplit_once('!') {
+        Some((sheet, range)) => {
+            let sheet = match sheet.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+                Some(quoted) => quoted.replace("''", "'"),
+                None => sheet.to_string(),
+            };
+            (Some(sheet), range)
+        }
+        None => (None, s),
+    };
+    match parse_range_ref(range) {
+        Some((first, last)) if first.col >= 1 && first.row >= 1 => Ok(SheetRange {
+            sheet,
+            first: CellRef {
+                col: first.col.min(last.col),
+                row: first.row.min(last.row),
+            },
+            last: CellRef {
+                col: first.col.max(last.col),
+                row: first.row.max(last.row),
+            },
+        }),
+        _ => Err(format!(
+            "invalid range '{}', expected e.g. A1:F40 or Sheet1!A1:F40",
+            s
+        )),
+    }
+}
+
+/// Passes the cells of a range on to the writer, blanking the others
+struct RangeSink<'a, W: Write + Seek> {
+    writer: &'a mut WorkbookWriter<W>,
+    range: Option<(CellRef, CellRef)>,
+    row: u32,
+}
+
+impl<W: Write + Seek> RowSink for RangeSink<'_, W> {
+    fn write_row(&mut self, values: &[CellValue]) -> Result<()> {
+        self.row += 1;
+        let Some((first, last)) = self.range else {
+            return self.writer.write_row(values);
+        };
+        if self.row < first.row {
+            return self.writer.write_row(&[]);
+        }
+        if self.row > last.row {
+            return Ok(());
+        }
+        let end = values.len().min(last.col as usize);
+        let start = (first.col as usize - 1).min(end);
+        let mut kept = vec![CellValue::Empty; start];
+        kept.extend_from_slice(&values[start..end]);
+        self.writer.write_row(&kept)
+    }
+}
+
+/// Create the workbook file at out; when out is the input workbook, a temp file next to it with
+/// the same permissions, to be persisted over it once written
+pub(crate) fn create_output(xlsx_path: &Path, out: &Path) -> Result<(File, Option<NamedTempFile>)> {
+    let in_place =
+        out.exists() && std::fs::canonicalize(xlsx_path).ok() == std::fs::canonicalize(out).ok();
+    if !in_place {
+        let file =
+            File::create(out).with_context(|| format!("Failed to create {}", out.display()))?;
+        return Ok((file, None));
+    }
+    let tmp = temp_output(out)?;
+    Ok((tmp.reopen()?, Some(tmp)))
+}
+
+/// A temp file next to out, with the permissions of out if it exists or those of a new file, to
+/// be persisted over it once written so that a failed write leaves out as it was
+pub(crate) fn temp_output(out: &Path) -> Result<NamedTempFile> {
+    let dir = match out.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut builder = tempfile::Builder::new();
+    if let Ok(metadata) = std::fs::metadata(out) {
+        builder.permissions(metadata.permissions());
+    } else {
+        // Like File::create, not the owner-only mode of temp files; the umask still applies
+        #[cfg(unix)]
+        builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o666));
+    }
+    builder
+        .tempfile_in(dir)
+        .with_context(|| format!("create temp file in {}", dir.display()))
+}
+
+/// Warn about what the copy cannot keep: the styles with a number format other than a date, a
+/// fill or a font color, and the defined names
+fn warn_dropped<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    parts: &PackageParts,
+    styles: &[StyleInfo],
+) -> Result<()> {
+    let formatted = styles
+        .iter()
+        .filter(|s| (s.num_fmt_id != 0 && !s.is_date) || s.fill.is_some() || s.font_color.is_some())
+        .count();
+    if formatted > 0 {
+        tracing::warn!(
+            "dropping {} cell style(s) with number formats, fills or font colors; only dates keep their format",
+            formatted
+        );
+    }
+    let names = match zip.by_name(&parts.workbook) {
+        Ok(f) => parse_defined_names(BufReader::new(f))?,
+        Err(_) => Vec::new(),
+    };
+    if !names.is_empty() {
+        tracing::warn!(
+            "dropping {} defined name(s): {}",
+            names.len(),
+            names.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Rewrite the workbook with only the selected sheets and ranges
+/// The copy holds cell values only: formulas are replaced by their last computed value, and
+/// styles and number formats (except for dates), defined names, images, charts, pivot caches,
+/// macros and other non-worksheet sheets are left out. Dropped formatting and defined names
+/// are logged as warnings.
+pub fn run(xlsx_path: &Path, args: &SlimArgs) -> Result<()> {
+    let mut zip = open_zip(xlsx_path)?;
+    let parts = read_package_parts(&mut zip)?;
+    let (sheets, is_1904) = read_workbook(&mut zip, &parts)?;
+    for range in &args.range {
+        if let Some(name) = &range.sheet
+            && !sheets.iter().any(|s| &s.name == name)
+        {
+            anyhow::bail!("--range: no sheet named '{}'", name);
+        }
+    }
+    let kept: Vec<_> = sheets
+        .iter()
+        .filter(|s| s.kind == SheetKind::Worksheet)
+        .filter(|s| args.sheets.is_empty() || args.sheets.iter().any(|p| p.matches(&s.name)))
+        .filter(|s| !args.exclude_sheets.iter().any(|p| p.matches(&s.name)))
+        .collect();
+    if kept.is_empty() {
+        anyhow::bail!("no worksheets left to keep");
+    }
+    let (shared_strings, styles) = read_strings_and_styles(&mut zip, &parts)?;
+    warn_dropped(&mut zip, &parts, &styles)?;
+
+    let (file, tmp) = create_output(xlsx_path, &args.out)?;
+    let mut writer = WorkbookWriter::new(BufWriter::new(file));
+    for sheet in &kept {
+        let range = args
+            .range
+            .iter()
+            .find(|r| r.sheet.as_deref() == Some(sheet.name.as_str()))
+            .or_else(|| args.range.iter().find(|r| r.sheet.is_none()));
+        let options = ExportOptions {
+            // Rows are written one per sheet row, so nothing past the range is read
+            max_rows: range.map(|r| u64::from(r.last.row)),
+            ..ExportOptions::default()
+        };
+        writer.start_sheet(&sheet.name)?;
+        let f = zip
+            .by_name(&sheet.path_in_zip)
+            .with_context(|| format!("missing {}", sheet.path_in_zip))?;
+        let mut sink = RangeSink {
+            writer: &mut writer,
+            range: range.map(|r| (r.first, r.last)),
+            row: 0,
+        };
+        export_sheet_to_sink(
+            BufReader::new(f),
+            sheet,
+            &shared_strings,
+            &styles,
+            is_1904,
+            &options,
+            &mut sink,
+        )
+        .with_context(|| format!("copy {}", sheet.name))?;
+    }
+    writer
+        .finish()?
+        .flush()
+        .with_context(|| format!("write {}", args.out.display()))?;
+    if let Some(tmp) = tmp {
+        tmp.persist(&args.out)
+            .with_context(|| format!("write {}", args.out.display()))?;
+    }
+
+    let size = |path: &Path| std::fs::metadata(path).map(|m| m.len()).ok();
+    tracing::info!(
+        "wrote {:?} ({} of {} sheets, {} bytes)",
+        args.out,
+        kept.len(),
+        sheets.len(),
+        size(&args.out).unwrap_or(0)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range_sink_blanks_cells_outside_range() {
+        let range = parse_sheet_range("'Bob''s'!C2:B3").unwrap();
+        assert_eq!(range.sheet.as_deref(), Some("Bob's"));
+        assert!(parse_sheet_range("Sheet1!nope").is_err());
+
+        let mut writer = WorkbookWriter::new(std::io::Cursor::new(Vec::new()));
+        writer.start_sheet("S").unwrap();
+        let mut sink = RangeSink {
+            writer: &mut writer,
+            range: Some((range.first, range.last)),
+            row: 0,
+        };
+        for row in ["a,b,c,d", "e,f,g,h", "i", "m,n,o,p"] {
+            let values: Vec<CellValue> = row.split(',').map(CellValue::from_field).collect();
+            sink.write_row(&values).unwrap();
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+        let files = libxcsv::convert_xlsx_bytes_to_csv(&bytes, &ExportOptions::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(files[0].1.clone()).unwrap(),
+            "\"\"\n,f,g\n"
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use libxcsv::{CellKind, ExportSummary, WarningKind};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// What `export` did with one workbook, written to the --stats file
+/// error: why the workbook failed to convert; its sheets are then left out
+/// files: files written for the workbook as a whole (the --combine output)
+#[derive(Debug, Clone, Default)]
+pub struct WorkbookStats {
+    pub workbook: PathBuf,
+    pub error: Option<String>,
+    pub sheets: Vec<SheetStats>,
+    pub files: Vec<PathBuf>,
+}
+
+/// What `export` did with one sheet; see ExportSummary for the counts
+/// error: why the sheet failed to export with --keep-going
+#[derive(Debug, Clone, Default)]
+pub struct SheetStats {
+    pub sheet: String,
+    pub error: Option<String>,
+    pub rows: u64,
+    pub duplicates: u64,
+    pub cells: BTreeMap<CellKind, u64>,
+    pub warnings: BTreeMap<WarningKind, u64>,
+    pub files: Vec<PathBuf>,
+}
+
+impl WorkbookStats {
+    /// Number of sheets that failed to export with --keep-going
+    pub fn failed_sheets(&self) -> usize {
+        self.sheets.iter().filter(|s| s.error.is_some()).count()
+    }
+}
+
+impl SheetStats {
+    pub fn new(sheet: &str, summary: &ExportSummary) -> Self {
+        Self {
+            sheet: sheet.to_string(),
+            error: None,
+            rows: summary.rows_written,
+            duplicates: summary.duplicates,
+            cells: summary.cells.clone(),
+            warnings: summary.warnings.clone(),
+            files: summary.files.clone(),
+        }
+    }
+}
+
+/// An output file in the stats: where it ended up, its size and SHA-256 checksum (lowercase hex)
+#[derive(Serialize)]
+struct FileStats {
+    path: String,
+    bytes: u64,
+    sha256: String,
+}
+
+#[derive(Serialize)]
+struct WorkbookEntry<'a> {
+    workbook: &'a Path,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+    sheets: Vec<SheetEntry<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    files: Vec<FileStats>,
+}
+
+#[derive(Serialize)]
+struct SheetEntry<'a> {
+    sheet: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+    rows: u64,
+    duplicates: u64,
+    cells: &'a BTreeMap<CellKind, u64>,
+    warnings: &'a BTreeMap<WarningKind, u64>,
+    files: Vec<FileStats>,
+}
+
+/// Write the stats of every workbook to path as JSON, checksumming the files written
+/// location: where a file written ended up, e.g. its URL after an upload
+pub fn write_stats(
+    path: &Path,
+    workbooks: &[WorkbookStats],
+    location: &dyn Fn(&Path) -> String,
+) -> Result<()> {
+    let entries = workbooks
+        .iter()
+        .map(|w| {
+            Ok(WorkbookEntry {
+                workbook: &w.workbook,
+                error: w.error.as_deref(),
+                sheets: w
+                    .sheets
+                    .iter()
+                    .map(|s| {
+                        Ok(SheetEntry {
+                            sheet: &s.sheet,
+                            error: s.error.as_deref(),
+                            rows: s.rows,
+                            duplicates: s.duplicates,
+                            cells: &s.cells,
+                            warnings: &s.warnings,
+                            files: files(&s.files, location)?,
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+                files: files(&w.files, location)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let json = serde_json::to_string_pretty(&serde_json::json!({ "workbooks": entries }))?;
+    std::fs::write(path, json + "\n").with_context(|| format!("write {}", path.display()))
+}
+
+fn files(paths: &[PathBuf], location: &dyn Fn(&Path) -> String) -> Result<Vec<FileStats>> {
+    paths.iter().map(|p| file_stats(p, location)).collect()
+}
+
+fn file_stats(path: &Path, location: &dyn Fn(&Path) -> String) -> Result<FileStats> {
+    let mut file = std::fs::File::open(path).with_context(|| format!("read {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    let mut bytes = 0;
+    loop {
+        let n = file
+            .read(&mut buf)
+            .with_context(|| format!("read {}", path.display()))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        bytes += n as u64;
+    }
+    Ok(FileStats {
+        path: location(path),
+        bytes,
+        sha256: hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_stats_checksum() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(tmp.path(), "abc").unwrap();
+        let stats = file_stats(tmp.path(), &|_| "s3://bucket/out.csv".to_string()).unwrap();
+        assert_eq!(stats.path, "s3://bucket/out.csv");
+        assert_eq!(stats.bytes, 3);
+        assert_eq!(
+            stats.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
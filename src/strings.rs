@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::Args;
+use libxcsv::{
+    SheetKind, for_each_cell_details, open_zip, read_package_parts, read_shared_strings,
+    read_workbook,
+};
+use serde::Serialize;
+
+use crate::until_broken_pipe;
+
+#[derive(Args, Debug, Clone)]
+pub struct StringsArgs {
+    /// Print one JSON object per string (index, string, refs, sheets)
+    #[arg(long)]
+    json: bool,
+    /// Only list strings no cell refers to
+    #[arg(long)]
+    unused: bool,
+}
+
+/// One entry of `strings --json`
+#[derive(Serialize)]
+struct SharedString<'a> {
+    index: usize,
+    string: &'a str,
+    refs: u64,
+    /// Cells referring to the string, per sheet in workbook order
+    sheets: Vec<SheetRefs<'a>>,
+}
+
+#[derive(Serialize)]
+struct SheetRefs<'a> {
+    sheet: &'a str,
+    refs: u64,
+}
+
+/// Print the shared string table: every string with its index and how many cells of each
+/// sheet refer to it
+/// Strings are printed quoted and escaped (e.g. "a\tb"), so stray whitespace, control
+/// characters and markup-like text such as "<b>" are visible as stored.
+pub fn run(xlsx_path: &Path, args: &StringsArgs) -> Result<()> {
+    until_broken_pipe(list(xlsx_path, args, &mut io::stdout().lock()))?;
+    Ok(())
+}
+
+fn list(xlsx_path: &Path, args: &StringsArgs, out: &mut impl Write) -> Result<()> {
+    let mut zip = open_zip(xlsx_path)?;
+    let parts = read_package_parts(&mut zip)?;
+    let (sheets, _) = read_workbook(&mut zip, &parts)?;
+    let strings = match &parts.shared_strings {
+        Some(part) => read_shared_strings(BufReader::new(
+            zip.by_name(part)
+                .with_context(|| format!("missing {}", part))?,
+        ))?,
+        None => Vec::new(),
+    };
+
+    // refs[string][sheet]: cells of the sheet referring to the string
+    let mut refs: Vec<BTreeMap<usize, u64>> = vec![BTreeMap::new(); strings.len()];
+    let mut out_of_range = 0;
+    for (position, sheet) in sheets.iter().enumerate() {
+        if sheet.kind != SheetKind::Worksheet {
+            continue;
+        }
+        let f = zip
+            .by_name(&sheet.path_in_zip)
+            .with_context(|| format!("missing {}", sheet.path_in_zip))?;
+        out_of_range += count_refs(BufReader::new(f), position, &mut refs)
+            .with_context(|| format!("read {}", sheet.name))?;
+    }
+
+    for (index, (string, sheet_refs)) in strings.iter().zip(&refs).enumerate() {
+        let total: u64 = sheet_refs.values().sum();
+        if args.unused && total > 0 {
+            continue;
+        }
+        if args.json {
+            let entry = SharedString {
+                index,
+                string,
+                refs: total,
+                sheets: sheet_refs
+                    .iter()
+                    .map(|(&i, &refs)| SheetRefs {
+                        sheet: &sheets[i].name,
+                        refs,
+                    })
+                    .collect(),
+            };
+            writeln!(out, "{}", serde_json::to_string(&entry)?)?;
+        } else {
+            let per_sheet: Vec<String> = sheet_refs
+                .iter()
+                .map(|(&i, n)| format!("{}: {}", sheets[i].name, n))
+                .collect();
+            writeln!(
+                out,
+                "{:>6}  {:>6}  {:?}{}",
+                index,
+                total,
+                string,
+                if per_sheet.is_empty() {
+                    String::new()
+                } else {
+                    format!("  ({})", per_sheet.join(", "))
+                }
+            )?;
+        }
+    }
+    if out_of_range > 0 {
+        tracing::warn!(
+            "{} cells refer to a shared string past the end of the table ({} strings)",
+            out_of_range,
+            strings.len()
+        );
+    }
+    Ok(())
+}
+
+/// Add the cells of the worksheet at position referring to each string to refs[string]
+/// Returns the number of cells referring to a string past the end of the table.
+fn count_refs<R: BufRead>(
+    reader: R,
+    position: usize,
+    refs: &mut [BTreeMap<usize, u64>],
+) -> Result<u64> {
+    let mut out_of_range = 0;
+    for_each_cell_details(reader, None, |details| {
+        if details.cell_type.as_deref() == Some("s") {
+            match details.value.trim().parse::<usize>().ok() {
+                Some(i) if i < refs.len() => *refs[i].entry(position).or_default() += 1,
+                _ => out_of_range += 1,
+            }
+        }
+        Ok(())
+    })?;
+    Ok(out_of_range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_refs_per_sheet() {
+        let sheet = |cells: &str| {
+            format!(
+                r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData><row r="1">{}</row></sheetData></worksheet>"#,
+                cells
+            )
+        };
+        let first = sheet(
+            r#"<c r="A1" t="s"><v>0</v></c><c r="B1" t="s"><v> 0 </v></c><c r="C1" t="s"><v>2</v></c><c r="D1"><v>1</v></c>"#,
+        );
+        let second = sheet(r#"<c r="A1" t="s"><v>0</v></c><c r="B1" t="s"><v>7</v></c>"#);
+        let mut refs = vec![BTreeMap::new(); 3];
+        assert_eq!(count_refs(first.as_bytes(), 0, &mut refs).unwrap(), 0);
+        // Sheet 1 is a chartsheet, skipped by list
+        assert_eq!(count_refs(second.as_bytes(), 2, &mut refs).unwrap(), 1);
+        assert_eq!(refs[0], BTreeMap::from([(0, 2), (2, 1)]));
+        // A number cell holding 1 is no reference to string 1
+        assert!(refs[1].is_empty());
+        assert_eq!(refs[2], BTreeMap::from([(0, 1)]));
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::path::Path;
+
+use anyhow::Result;
+use clap::Args;
+use libxcsv::{CellValue, DataValidation, GeneralPrecision, SheetKind, Workbook};
+use serde::Serialize;
+
+use crate::grep::quote_sheet_name;
+use crate::until_broken_pipe;
+
+#[derive(Args, Debug, Clone)]
+pub struct ValidationsArgs {
+    /// Only list the validations of this sheet [default: every worksheet]
+    #[arg(value_name = "SHEET")]
+    sheet: Option<String>,
+    /// Print the validations as a JSON array, with their allowBlank, prompt and error message
+    #[arg(long)]
+    json: bool,
+}
+
+/// One validation of `validations --json`
+#[derive(Serialize)]
+struct SheetValidation {
+    sheet: String,
+    #[serde(flatten)]
+    validation: DataValidation,
+}
+
+/// Print the data validations of the worksheets as CSV: sheet, range, type, operator,
+/// formula1, formula2 and values, the allowed values of a list joined with ','
+/// A list drawn from a range (e.g. Lists!$A$1:$A$5) gets the values of its non-empty cells;
+/// one drawn from a defined name keeps just its formula.
+pub fn run(xlsx_path: &Path, args: &ValidationsArgs) -> Result<()> {
+    until_broken_pipe(list(xlsx_path, args, io::stdout().lock()))?;
+    Ok(())
+}
+
+fn list(xlsx_path: &Path, args: &ValidationsArgs, mut out: impl Write) -> Result<()> {
+    let mut workbook = Workbook::open(xlsx_path)?;
+    let names: Vec<String> = match &args.sheet {
+        Some(name) => vec![workbook.sheet(name)?.info().name.clone()],
+        None => workbook
+            .sheets()
+            .iter()
+            .filter(|s| s.kind == SheetKind::Worksheet)
+            .map(|s| s.name.clone())
+            .collect(),
+    };
+
+    let mut found = Vec::new();
+    let mut indexed = HashSet::new();
+    for name in names {
+        for mut validation in workbook.sheet(&name)?.data_validations()? {
+            if validation.kind == "list" && validation.values.is_empty() {
+                validation.values = range_values(&mut workbook, &mut indexed, &name, &validation)?;
+            }
+            found.push(SheetValidation {
+                sheet: name.clone(),
+                validation,
+            });
+        }
+    }
+
+    if args.json {
+        serde_json::to_writer_pretty(&mut out, &found)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+    let mut wtr = csv::Writer::from_writer(out);
+    wtr.write_record([
+        "sheet", "range", "type", "operator", "formula1", "formula2", "values",
+    ])?;
+    for SheetValidation { sheet, validation } in &found {
+        wtr.write_record([
+            quote_sheet_name(sheet).as_str(),
+            &validation.sqref,
+            &validation.kind,
+            validation.operator.as_deref().unwrap_or(""),
+            validation.formula1.as_deref().unwrap_or(""),
+            validation.formula2.as_deref().unwrap_or(""),
+            &validation.values.join(","),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// The values of the non-empty cells of the range a list validation refers to, on the sheet
+/// of the validation unless the reference names another; empty if formula1 is not a range, or
+/// one in another workbook (e.g. [1]Lists!$A$1:$A$3) or on a sheet that doesn't exist
+/// Every sheet read is kept in memory (its name added to indexed), as a sheet's validations
+/// mostly draw from the same one.
+fn range_values(
+    workbook: &mut Workbook,
+    indexed: &mut HashSet<String>,
+    sheet: &str,
+    validation: &DataValidation,
+) -> Result<Vec<String>> {
+    let Some((target, range)) = validation.formula1.as_deref().and_then(split_reference) else {
+        return Ok(Vec::new());
+    };
+    let target = target.as_deref().unwrap_or(sheet);
+    if target.starts_with('[') {
+        return Ok(Vec::new());
+    }
+    let Ok(mut target_sheet) = workbook.sheet(target) else {
+        tracing::debug!(
+            "list validation of {} refers to missing sheet {}",
+            sheet,
+            target
+        );
+        return Ok(Vec::new());
+    };
+    if indexed.insert(target.to_string()) {
+        target_sheet.build_index()?;
+    }
+    let rows = target_sheet.range(&range)?;
+    Ok(rows
+        .iter()
+        .flatten()
+        .filter(|value| !matches!(value, CellValue::Empty))
+        .map(|value| value.to_field(GeneralPrecision::Exact))
+        .collect())
+}
+
+/// Split a range reference into its sheet, if it names one, and the range without '$'
+/// Returns None for anything else, e.g. a defined name or a formula.
+///   split_reference("'Q1 ''24'!$A$1:$A$5") -> Some((Some("Q1 '24"), "A1:A5"))
+///   split_reference("H1:H3") -> Some((None, "H1:H3"))
+fn split_reference(formula: &str) -> Option<(Option<String>, String)> {
+    let (sheet, range) = match formula.rsplit_once('!') {
+        Some((sheet, range)) => {
+            let sheet = match sheet.strip_prefix('\'') {
+                Some(quoted) => quoted.strip_suffix('\'')?.replace("''", "'"),
+                None => sheet.to_string(),
+            };
+            (Some(sheet), range)
+        }
+        None => (None, formula),
+    };
+    let range = range.trim().replace('$', "");
+    let is_reference = range.split(':').all(|cell| {
+        let digits = cell.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+        digits.len() < cell.len()
+            && !digits.is_empty()
+            && digits.chars().all(|c| c.is_ascii_digit())
+    }) && range.split(':').count() <= 2;
+    is_reference.then_some((sheet, range))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libxcsv::WorkbookReader;
+
+    #[test]
+    fn test_split_reference() {
+        assert_eq!(
+            split_reference("Lists!$A$1:$A$5"),
+            Some((Some("Lists".to_string()), "A1:A5".to_string()))
+        );
+        assert_eq!(
+            split_reference("'Q1 ''24'!B2:B4"),
+            Some((Some("Q1 '24".to_string()), "B2:B4".to_string()))
+        );
+        assert_eq!(split_reference("$H$1"), Some((None, "H1".to_string())));
+        assert_eq!(
+            split_reference("[1]Lists!$A$1:$A$3"),
+            Some((Some("[1]Lists".to_string()), "A1:A3".to_string()))
+        );
+        assert_eq!(split_reference("StatusList"), None);
+        assert_eq!(split_reference("INDIRECT(A1)"), None);
+    }
+
+    #[test]
+    fn test_range_values_reads_lists_and_skips_what_it_cannot() {
+        let parts = [
+            (
+                "_rels/.rels",
+                r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#,
+            ),
+            (
+                "xl/workbook.xml",
+                r#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="Lists" sheetId="1" r:id="rId1"/></sheets></workbook>"#,
+            ),
+            (
+                "xl/_rels/workbook.xml.rels",
+                r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/></Relationships>"#,
+            ),
+            (
+                "xl/worksheets/sheet1.xml",
+                r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData><row r="1"><c r="A1" t="inlineStr"><is><t>open</t></is></c></row><row r="3"><c r="A3" t="inlineStr"><is><t>closed</t></is></c></row></sheetData></worksheet>"#,
+            ),
+        ];
+        let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        for (name, contents) in parts {
+            writer
+                .start_file(name, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+        let zip = zip::ZipArchive::new(WorkbookReader::Memory(io::Cursor::new(bytes))).unwrap();
+        let mut workbook = Workbook::from_zip(zip).unwrap();
+
+        let list = |formula1: &str| DataValidation {
+            kind: "list".to_string(),
+            formula1: Some(formula1.to_string()),
+            ..DataValidation::default()
+        };
+        let mut indexed = HashSet::new();
+        let mut values = |formula1: &str| {
+            range_values(&mut workbook, &mut indexed, "Lists", &list(formula1)).unwrap()
+        };
+        assert_eq!(values("$A$1:$A$3"), ["open", "closed"]);
+        assert_eq!(values("Lists!$A$2:$A$3"), ["closed"]);
+        assert!(values("[1]Lists!$A$1:$A$3").is_empty());
+        assert!(values("Missing!$A$1:$A$3").is_empty());
+    }
+}
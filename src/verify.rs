@@ -0,0 +1,281 @@
+use std::collections::HashSet;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Args;
+use libxcsv::{
+    OutputFormat, SheetKind, export_sheet_xml_to_memory, index_to_col, open_zip,
+    read_package_parts, read_strings_and_styles, read_workbook,
+};
+
+use crate::export::{ConvertArgs, export_options, set_sheet_layout};
+use crate::grep::quote_sheet_name;
+
+#[derive(Args, Debug, Clone)]
+pub struct VerifyArgs {
+    /// Directory with the expected CSV files, as written by `export` with the same options
+    #[arg(value_name = "DIR")]
+    expected: PathBuf,
+    /// Report at most N differences per file
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    max_diffs: usize,
+    /// The options the expected files were exported with, like for export (--delimiter,
+    /// --header, --types, --name-template, ...); those only about writing files, like
+    /// --out-dir or --compress, are rejected
+    #[command(flatten)]
+    pub convert: ConvertArgs,
+}
+
+/// How an exported sheet differs from its expected CSV; row and column are 1-based
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Difference {
+    Cell {
+        row: usize,
+        column: usize,
+        expected: String,
+        actual: String,
+    },
+    /// A row the sheet has but the expected file does not
+    ExtraRow(usize),
+    /// A row of the expected file past the end of the sheet
+    MissingRow(usize),
+}
+
+/// Export every worksheet in memory with the export options of args and compare the files with
+/// the CSVs in the expected directory, printing a cell-level report of the differences
+/// Nothing is written next to the workbook; exits with status 1 on any difference, a missing
+/// expected file or a CSV in the directory that no sheet exports to.
+pub fn run(xlsx_path: &Path, args: &VerifyArgs) -> Result<()> {
+    if !args.expected.is_dir() {
+        anyhow::bail!("{} is not a directory", args.expected.display());
+    }
+    let mut options = export_options(&args.convert)?;
+    if options.format != OutputFormat::Csv {
+        anyhow::bail!("verify compares CSV files; it cannot be used with --format");
+    }
+    let mut zip = open_zip(xlsx_path)?;
+    let parts = read_package_parts(&mut zip)?;
+    let (sheets, is_1904) = read_workbook(&mut zip, &parts)?;
+    let (shared_strings, styles) = read_strings_and_styles(&mut zip, &parts)?;
+    let template = args.convert.name_template.clone().unwrap_or_default();
+    let workbook = xlsx_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("stdin");
+
+    let mut failed = false;
+    let mut verified = 0;
+    let mut expected_files = HashSet::new();
+    for (position, sheet) in sheets.iter().enumerate() {
+        if sheet.kind != SheetKind::Worksheet || !args.convert.includes_sheet(&sheet.name) {
+            continue;
+        }
+        let file_name = template.render(&sheet.name, position + 1, workbook);
+        set_sheet_layout(&args.convert, &mut zip, sheet, &mut options)?;
+        let f = zip
+            .by_name(&sheet.path_in_zip)
+            .with_context(|| format!("missing {}", sheet.path_in_zip))?;
+        // Named like the files export writes, the parts of a split sheet too
+        let (summary, contents) = export_sheet_xml_to_memory(
+            BufReader::new(f),
+            &shared_strings,
+            &styles,
+            is_1904,
+            Path::new(&file_name),
+            &options,
+        )
+        .with_context(|| format!("export sheet '{}'", sheet.name))?;
+        for (file, contents) in summary.files.iter().zip(contents) {
+            let file_name = file.to_string_lossy().into_owned();
+            let expected_path = args.expected.join(&file_name);
+            expected_files.insert(file_name.to_lowercase());
+            if !expected_path.is_file() {
+                println!("{}: {} is missing", sheet.name, expected_path.display());
+                failed = true;
+                continue;
+            }
+            let actual = read_records(
+                csv_reader(options.delimiter)
+                    .from_reader(&contents[..])
+                    .into_records(),
+            )
+            .with_context(|| format!("read exported {}", sheet.name))?;
+            let expected = read_records(
+                csv_reader(options.delimiter)
+                    .from_path(&expected_path)
+                    .with_context(|| format!("open {}", expected_path.display()))?
+                    .into_records(),
+            )
+            .with_context(|| format!("read {}", expected_path.display()))?;
+            verified += 1;
+            if !report(
+                &sheet.name,
+                &expected_path,
+                &actual,
+                &expected,
+                args.max_diffs,
+            ) {
+                failed = true;
+            }
+        }
+    }
+
+    // CSVs no sheet exports to anymore, e.g. of a removed or renamed sheet
+    let mut orphans: Vec<String> = std::fs::read_dir(&args.expected)
+        .with_context(|| format!("read {}", args.expected.display()))?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.to_lowercase().ends_with(".csv"))
+        .filter(|name| !expected_files.contains(&name.to_lowercase()))
+        .collect();
+    orphans.sort();
+    for name in &orphans {
+        println!("{}: no sheet is exported to this file", name);
+        failed = true;
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+    tracing::info!(
+        "verified {} file(s) against {}",
+        verified,
+        args.expected.display()
+    );
+    Ok(())
+}
+
+/// A reader of headerless CSV records of any length
+fn csv_reader(delimiter: u8) -> csv::ReaderBuilder {
+    let mut builder = csv::ReaderBuilder::new();
+    builder
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter);
+    builder
+}
+
+/// Print the differences of a sheet's file with the expected one, at most max_diffs of them
+/// Returns whether there were none.
+fn report(
+    sheet: &str,
+    expected_path: &Path,
+    actual: &[Vec<String>],
+    expected: &[Vec<String>],
+    max_diffs: usize,
+) -> bool {
+    let differences = compare(actual, expected);
+    if differences.is_empty() {
+        return true;
+    }
+    let sheet_ref = quote_sheet_name(sheet);
+    println!(
+        "{}: {} difference(s) with {}",
+        sheet,
+        differences.len(),
+        expected_path.display()
+    );
+    for difference in differences.iter().take(max_diffs) {
+        match difference {
+            Difference::Cell {
+                row,
+                column,
+                expected,
+                actual,
+            } => println!(
+                "  {}!{}{}: expected {:?}, got {:?}",
+                sheet_ref,
+                index_to_col(*column as u32),
+                row,
+                expected,
+                actual
+            ),
+            Difference::ExtraRow(row) => {
+                println!("  {} row {}: not in the expected file", sheet, row)
+            }
+            Difference::MissingRow(row) => {
+                println!("  {} row {}: missing from the sheet", sheet, row)
+            }
+        }
+    }
+    if differences.len() > max_diffs {
+        println!("  ... and {} more", differences.len() - max_diffs);
+    }
+    false
+}
+
+fn read_records(
+    records: impl Iterator<Item = csv::Result<csv::StringRecord>>,
+) -> Result<Vec<Vec<String>>> {
+    records
+        .map(|r| Ok(r?.iter().map(String::from).collect()))
+        .collect()
+}
+
+/// The differences between the exported rows and the expected ones, in sheet order
+/// A missing field equals an empty one, so padding differences are not reported.
+fn compare(actual: &[Vec<String>], expected: &[Vec<String>]) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    for row in 0..actual.len().max(expected.len()) {
+        let (a, e) = match (actual.get(row), expected.get(row)) {
+            (Some(a), Some(e)) => (a, e),
+            (Some(_), None) => {
+                differences.push(Difference::ExtraRow(row + 1));
+                continue;
+            }
+            (None, _) => {
+                differences.push(Difference::MissingRow(row + 1));
+                continue;
+            }
+        };
+        for column in 0..a.len().max(e.len()) {
+            let actual = a.get(column).map_or("", String::as_str);
+            let expected = e.get(column).map_or("", String::as_str);
+            if actual != expected {
+                differences.push(Difference::Cell {
+                    row: row + 1,
+                    column: column + 1,
+                    expected: expected.to_string(),
+                    actual: actual.to_string(),
+                });
+            }
+        }
+    }
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter()
+            .map(|r| r.iter().map(|f| f.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_compare_reports_cells_and_rows() {
+        let expected = rows(&[&["id", "amount"], &["1", "0.3"]]);
+        assert!(compare(&rows(&[&["id", "amount", ""], &["1", "0.3"]]), &expected).is_empty());
+        assert_eq!(
+            compare(
+                &rows(&[&["id", "amount"], &["1", "0.30000000000000004"], &["2"]]),
+                &expected
+            ),
+            vec![
+                Difference::Cell {
+                    row: 2,
+                    column: 2,
+                    expected: "0.3".to_string(),
+                    actual: "0.30000000000000004".to_string(),
+                },
+                Difference::ExtraRow(3),
+            ]
+        );
+        assert_eq!(
+            compare(&expected[..1], &expected),
+            vec![Difference::MissingRow(2)]
+        );
+    }
+}
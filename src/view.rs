@@ -0,0 +1,193 @@
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::Args;
+use libxcsv::{
+    ExportOptions, SheetInfo, SheetKind, StyleInfo, export_sheet_xml_to_csv, open_zip,
+    read_package_parts, read_strings_and_styles, read_workbook,
+};
+use zip::ZipArchive;
+
+#[derive(Args, Debug, Clone)]
+pub struct ViewArgs {
+    /// Name of the sheet to show (case-insensitive if there is no exact match)
+    #[arg(value_name = "SHEET")]
+    sheet: String,
+    /// Show at most N rows below the first (header) row
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    max_rows: usize,
+    /// Truncate cells wider than N characters
+    #[arg(long, value_name = "N", default_value_t = 30, value_parser = clap::value_parser!(u16).range(2..))]
+    max_width: u16,
+}
+
+/// Print a sheet as an aligned table
+/// Only the rows that are shown (plus one, to tell whether there are more) are read.
+pub fn run(xlsx_path: &Path, args: &ViewArgs) -> Result<()> {
+    let mut zip = open_zip(xlsx_path)?;
+    let parts = read_package_parts(&mut zip)?;
+    let (sheets, is_1904) = read_workbook(&mut zip, &parts)?;
+    let sheet = find_sheet(&sheets, &args.sheet)?;
+    if sheet.kind != SheetKind::Worksheet {
+        anyhow::bail!("'{}' is a {} and has no cell data", sheet.name, sheet.kind);
+    }
+    let (shared_strings, styles) = read_strings_and_styles(&mut zip, &parts)?;
+
+    // The header row, max_rows rows and one more to know whether anything was left out
+    let mut rows = read_sheet_rows(
+        &mut zip,
+        sheet,
+        &shared_strings,
+        &styles,
+        is_1904,
+        args.max_rows as u64 + 2,
+    )?;
+    let more = rows.len() > args.max_rows + 1;
+    rows.truncate(args.max_rows + 1);
+
+    print!("{}", render_table(&rows, args.max_width as usize));
+    if more {
+        println!("... (showing the first {} rows)", args.max_rows);
+    }
+    Ok(())
+}
+
+/// Find a sheet by name, falling back to a case-insensitive match
+pub fn find_sheet<'a>(sheets: &'a [SheetInfo], name: &str) -> Result<&'a SheetInfo> {
+    sheets
+        .iter()
+        .find(|s| s.name == name)
+        .or_else(|| {
+            sheets
+                .iter()
+                .find(|s| s.name.to_lowercase() == name.to_lowercase())
+        })
+        .with_context(|| {
+            let names: Vec<&str> = sheets.iter().map(|s| s.name.as_str()).collect();
+            format!("no sheet named '{}' (sheets: {})", name, names.join(", "))
+        })
+}
+
+/// Read the first max_rows rows of a worksheet as they would be exported
+/// Row i holds sheet row i + 1: gaps in the sheet come back as empty rows.
+pub fn read_sheet_rows<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    sheet: &SheetInfo,
+    shared_strings: &[String],
+    styles: &[StyleInfo],
+    is_1904: bool,
+    max_rows: u64,
+) -> Result<Vec<Vec<String>>> {
+    let options = ExportOptions {
+        max_rows: Some(max_rows),
+        ..ExportOptions::default()
+    };
+    let tmp = export_to_temp(zip, sheet, shared_strings, styles, is_1904, &options)?;
+    sheet_records(&tmp)?
+        .map(|r| Ok(r?.iter().map(String::from).collect()))
+        .collect()
+}
+
+/// Export a worksheet with options into a temp file, deleted when it is dropped
+pub fn export_to_temp<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    sheet: &SheetInfo,
+    shared_strings: &[String],
+    styles: &[StyleInfo],
+    is_1904: bool,
+    options: &ExportOptions,
+) -> Result<tempfile::NamedTempFile> {
+    let tmp = tempfile::NamedTempFile::new().context("create temp file")?;
+    let f = zip
+        .by_name(&sheet.path_in_zip)
+        .with_context(|| format!("missing {}", sheet.path_in_zip))?;
+    export_sheet_xml_to_csv(
+        BufReader::new(f),
+        shared_strings,
+        styles,
+        is_1904,
+        tmp.path(),
+        options,
+    )?;
+    Ok(tmp)
+}
+
+/// The records of a sheet exported by export_to_temp
+pub fn sheet_records(
+    tmp: &tempfile::NamedTempFile,
+) -> Result<csv::StringRecordsIntoIter<std::fs::File>> {
+    Ok(csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(tmp.path())?
+        .into_records())
+}
+
+/// Render rows as columns separated by " | ", with a rule below the first row
+/// Cells are cut to max_width characters (ending in '…'); line breaks are shown as spaces.
+pub fn render_table(rows: &[Vec<String>], max_width: usize) -> String {
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(|cell| truncate(cell, max_width)).collect())
+        .collect();
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| {
+            rows.iter()
+                .filter_map(|row| row.get(i))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let mut out = String::new();
+    for (index, row) in rows.iter().enumerate() {
+        let cells: Vec<String> = widths
+            .iter()
+            .enumerate()
+            .map(|(i, width)| {
+                let cell = row.get(i).map_or("", String::as_str);
+                format!("{}{}", cell, " ".repeat(width - cell.chars().count()))
+            })
+            .collect();
+        out.push_str(cells.join(" | ").trim_end());
+        out.push('\n');
+        if index == 0 {
+            let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+            out.push_str(&rule.join("-+-"));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Cut s to at most max_width characters on a single line
+fn truncate(s: &str, max_width: usize) -> String {
+    let s = s.replace("\r\n", " ").replace(['\r', '\n'], " ");
+    if s.chars().count() <= max_width {
+        return s;
+    }
+    let mut cut: String = s.chars().take(max_width - 1).collect();
+    cut.push('…');
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_table_aligns_and_truncates() {
+        let rows = vec![
+            vec!["id".to_string(), "name".to_string()],
+            vec!["1".to_string(), "a very long name".to_string()],
+            vec!["22".to_string()],
+        ];
+        assert_eq!(
+            render_table(&rows, 8),
+            "id | name\n---+---------\n1  | a very …\n22 |\n"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use clap::Args;
+use indicatif::MultiProgress;
+use libxcsv::is_object_url;
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::export::{ConvertArgs, export_workbook};
+use crate::log;
+
+/// How long a file has to stay unchanged before it is converted
+/// Excel and most copy tools write a workbook in several steps.
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+#[derive(Args, Debug, Clone)]
+pub struct WatchArgs {
+    /// Directory to watch for new or changed .xlsx files
+    #[arg(value_name = "DIR")]
+    dir: PathBuf,
+    /// Also convert the workbooks already in the directory on startup
+    #[arg(long)]
+    existing: bool,
+    #[command(flatten)]
+    pub convert: ConvertArgs,
+}
+
+/// True for .xlsx files, skipping Office lock files (~$name.xlsx) and hidden temp files
+fn is_workbook(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    !name.starts_with("~$")
+        && !name.starts_with('.')
+        && path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("xlsx"))
+}
+
+/// Convert one workbook into <out_dir>/<workbook>/; failures are reported but don't stop watching
+fn convert(path: &Path, args: &ConvertArgs) {
+    let Some(stem) = path.file_stem() else {
+        return;
+    };
+    let out_dir = args.out_dir().join(stem);
+    match export_workbook(path, &out_dir, args, &MultiProgress::new()) {
+        Ok(_) => tracing::info!("converted {}", path.display()),
+        Err(e) => log::error("export-failed", format!("{:#}", e))
+            .workbook(path)
+            .emit(),
+    }
+}
+
+/// Watch a drop folder and convert every workbook that is created or modified in it
+/// Runs until interrupted.
+pub fn run(args: &WatchArgs) -> Result<()> {
+    if !args.dir.is_dir() {
+        anyhow::bail!("{} is not a directory", args.dir.display());
+    }
+    if is_object_url(&args.convert.out_dir().to_string_lossy()) {
+        anyhow::bail!("watch writes to a local directory, use export for object store outputs");
+    }
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("create file watcher")?;
+    watcher
+        .watch(&args.dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("watch {}", args.dir.display()))?;
+
+    if args.existing {
+        let mut existing: Vec<PathBuf> = std::fs::read_dir(&args.dir)?
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.is_file() && is_workbook(p))
+            .collect();
+        existing.sort();
+        for path in existing {
+            convert(&path, &args.convert);
+        }
+    }
+    tracing::info!(
+        "watching {} for .xlsx files (Ctrl-C to stop)",
+        args.dir.display()
+    );
+
+    // Last change per file; a file is converted once it has settled
+    let mut pending: BTreeMap<PathBuf, Instant> = BTreeMap::new();
+    loop {
+        match rx.recv_timeout(SETTLE_TIME) {
+            Ok(Ok(event)) => {
+                if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    for path in event.paths.into_iter().filter(|p| is_workbook(p)) {
+                        pending.insert(path, Instant::now());
+                    }
+                }
+            }
+            Ok(Err(e)) => log::warning("watch", format!("watch error: {}", e)).emit(),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => anyhow::bail!("file watcher stopped"),
+        }
+
+        let settled: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, changed)| changed.elapsed() >= SETTLE_TIME)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in settled {
+            pending.remove(&path);
+            if path.is_file() {
+                convert(&path, &args.convert);
+            }
+        }
+    }
+}